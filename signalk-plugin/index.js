@@ -0,0 +1,418 @@
+/**
+ * Signal K server plugin for `mayara-server --signalk-provider`.
+ *
+ * mayara connects to this plugin with a WebSocket at `/plugins/mayara/provider`
+ * and the plugin makes its radars part of the Signal K server:
+ *
+ * - control values, control metadata and targets go into the data model with
+ *   `app.handleMessage`;
+ * - every `radars.<radar>.controls.<control>` path gets a PUT handler that
+ *   forwards the PUT to mayara and returns mayara's status code;
+ * - on servers with the Radar API (`/signalk/v2/api/vessels/self/radars`),
+ *   the plugin registers as its radar provider and maps the radar info,
+ *   control and stream calls of that API to mayara;
+ * - spokes are served at `/plugins/mayara/radars/<radar>/spokes`, and only
+ *   relayed by mayara while someone is listening;
+ * - `GET /plugins/mayara/radars` lists the radars that mayara advertised.
+ *
+ * The protocol is described in docs/api/README.md of mayara-server.
+ */
+
+const WebSocket = require("ws");
+
+const PLUGIN_ID = "mayara";
+const PROVIDER_PATH = `/plugins/${PLUGIN_ID}/provider`;
+const SPOKES_PATH = new RegExp(`^/plugins/${PLUGIN_ID}/radars/([^/]+)/spokes$`);
+const CONTROL_PATH = /^radars\.[^.]+\.controls\.[^.]+$/;
+
+// The Radar API status for each value of mayara's `power` control
+const POWER_STATUS = ["off", "standby", "transmit", "warming"];
+
+// A PUT fails when mayara does not reply within this time
+const PUT_TIMEOUT_MS = 10000;
+
+module.exports = function (app) {
+  let wss = null;
+  let onUpgrade = null;
+  // The WebSocket of mayara; there is at most one provider
+  let provider = null;
+  // The radars that mayara advertised, by radar id
+  let radars = {};
+  // The latest control values by radar id and control id, as `{ value, auto }`
+  let controlValues = new Map();
+  // Control paths that have a PUT handler
+  const putPaths = new Set();
+  // PUT callbacks waiting for a reply, by request id
+  const pending = new Map();
+  // Spoke clients by radar id
+  const spokeClients = new Map();
+  let nextRequestId = 1;
+
+  const plugin = {
+    id: PLUGIN_ID,
+    name: "mayara radar provider",
+    description: "Radars of mayara-server in the Signal K server",
+    schema: { type: "object", properties: {} },
+  };
+
+  function send(message) {
+    if (provider && provider.readyState === WebSocket.OPEN) {
+      provider.send(JSON.stringify(message));
+      return true;
+    }
+    return false;
+  }
+
+  /**
+   * Check the token of a WebSocket request, the same way the server checks
+   * its own stream. The provider needs write access.
+   */
+  function authorize(req, write) {
+    const strategy = app.securityStrategy;
+    if (!strategy || typeof strategy.authorizeWS !== "function") {
+      return;
+    }
+    strategy.authorizeWS(req);
+    const isDummy =
+      typeof strategy.isDummy === "function" && strategy.isDummy();
+    const permissions = req.skPrincipal && req.skPrincipal.permissions;
+    if (
+      write &&
+      !isDummy &&
+      permissions !== "readwrite" &&
+      permissions !== "admin"
+    ) {
+      throw new Error("Providing radars needs write access");
+    }
+  }
+
+  /**
+   * Forward a control PUT to mayara. `body` is the body of mayara's control
+   * PUT; `callback` gets the reply, unless mayara is not connected.
+   */
+  function requestPut(radarId, controlId, body, callback) {
+    const requestId = String(nextRequestId++);
+    if (!send({ type: "put", requestId, radarId, controlId, value: body })) {
+      return {
+        state: "COMPLETED",
+        statusCode: 503,
+        message: "mayara is not connected",
+      };
+    }
+    const timer = setTimeout(() => {
+      pending.delete(requestId);
+      callback({
+        state: "COMPLETED",
+        statusCode: 504,
+        message: "No reply from mayara",
+      });
+    }, PUT_TIMEOUT_MS);
+    pending.set(requestId, (reply) => {
+      clearTimeout(timer);
+      callback(reply);
+    });
+    return { state: "PENDING" };
+  }
+
+  /** A plain value is the `value` of the control, an object the whole body */
+  function controlBody(value) {
+    return value !== null && typeof value === "object" && !Array.isArray(value)
+      ? value
+      : { value };
+  }
+
+  /** Reply to a PUT of `radars.<radar>.controls.<control>` */
+  function putControl(path, value, callback) {
+    const [, radarId, , controlId] = path.split(".");
+    return requestPut(radarId, controlId, controlBody(value), callback);
+  }
+
+  /** Set a control for the Radar API, as `{ success, error }` */
+  function setControl(radarId, controlId, value) {
+    return new Promise((resolve) => {
+      const done = (reply) =>
+        resolve(
+          reply.statusCode === 200
+            ? { success: true }
+            : { success: false, error: reply.message },
+        );
+      const reply = requestPut(radarId, controlId, controlBody(value), done);
+      if (reply.state === "COMPLETED") {
+        done(reply);
+      }
+    });
+  }
+
+  /** Set a control for the Radar API calls that only report success */
+  async function setControlOk(radarId, controlId, value) {
+    return (await setControl(radarId, controlId, value)).success;
+  }
+
+  function getControlValue(radarId, controlId) {
+    const values = controlValues.get(radarId);
+    return values ? values[controlId] : undefined;
+  }
+
+  /** The Radar API `RadarInfo` of a radar that mayara advertised */
+  function radarInfo(radarId) {
+    const radar = radars[radarId];
+    if (!radar) {
+      return null;
+    }
+    const power = getControlValue(radarId, "power");
+    const range = getControlValue(radarId, "range");
+    const controls = {};
+    for (const controlId of ["gain", "sea", "rain"]) {
+      const control = getControlValue(radarId, controlId);
+      if (control) {
+        controls[controlId] = { auto: !!control.auto, value: control.value };
+      }
+    }
+    return {
+      id: radarId,
+      name: radar.name,
+      brand: radar.brand,
+      model: radar.model,
+      status: (power && POWER_STATUS[power.value]) || "off",
+      spokesPerRevolution: radar.spokesPerRevolution,
+      maxSpokeLen: radar.maxSpokeLen,
+      range: range ? range.value : 0,
+      controls,
+    };
+  }
+
+  /** Register with the Radar API of the server, when it has one */
+  function registerRadarProvider() {
+    if (typeof app.registerRadarProvider !== "function") {
+      app.debug("This Signal K server has no Radar API");
+      return;
+    }
+    app.registerRadarProvider({
+      name: plugin.name,
+      methods: {
+        pluginId: PLUGIN_ID,
+        getRadars: async () => Object.keys(radars),
+        getRadarInfo: async (radarId) => radarInfo(radarId),
+        setPower: (radarId, state) => setControlOk(radarId, "power", state),
+        setRange: (radarId, range) => setControlOk(radarId, "range", range),
+        setGain: (radarId, gain) => setControlOk(radarId, "gain", gain),
+        setSea: (radarId, sea) => setControlOk(radarId, "sea", sea),
+        setRain: (radarId, rain) => setControlOk(radarId, "rain", rain),
+        setControls: async (radarId, controls) => {
+          let success = true;
+          for (const [controlId, value] of Object.entries(controls || {})) {
+            success = (await setControlOk(radarId, controlId, value)) && success;
+          }
+          return success;
+        },
+        getControl: async (radarId, controlId) =>
+          getControlValue(radarId, controlId) || null,
+        setControl: (radarId, controlId, value) =>
+          setControl(radarId, controlId, value),
+        handleStreamConnection: (radarId, ws) =>
+          acceptSpokeClient(ws, radarId),
+      },
+    });
+  }
+
+  /** Remember the control values in a delta, for the Radar API */
+  function storeControlValues(delta) {
+    for (const update of delta.updates || []) {
+      for (const entry of update.values || []) {
+        if (!CONTROL_PATH.test(entry.path)) {
+          continue;
+        }
+        const [, radarId, , controlId] = entry.path.split(".");
+        if (!controlValues.has(radarId)) {
+          controlValues.set(radarId, {});
+        }
+        controlValues.get(radarId)[controlId] = {
+          value: entry.value,
+          auto: entry.auto,
+        };
+      }
+    }
+  }
+
+  function registerPutHandlers(delta) {
+    for (const update of delta.updates || []) {
+      const entries = (update.values || []).concat(update.meta || []);
+      for (const { path } of entries) {
+        if (!CONTROL_PATH.test(path) || putPaths.has(path)) {
+          continue;
+        }
+        putPaths.add(path);
+        app.registerPutHandler(
+          "vessels.self",
+          path,
+          (context, path, value, callback) => putControl(path, value, callback),
+          PLUGIN_ID,
+        );
+      }
+    }
+  }
+
+  function subscribeSpokes(radarId, subscribe) {
+    send({ type: "spokes", radarId, subscribe });
+  }
+
+  /** Binary frame: radar id length, radar id, `RadarMessage` */
+  function relaySpokes(frame) {
+    const len = frame[0];
+    const radarId = frame.subarray(1, 1 + len).toString();
+    const message = frame.subarray(1 + len);
+    for (const client of spokeClients.get(radarId) || []) {
+      if (client.readyState === WebSocket.OPEN) {
+        client.send(message);
+      }
+    }
+  }
+
+  function handleProviderMessage(data, isBinary) {
+    if (isBinary) {
+      relaySpokes(data);
+      return;
+    }
+    let message;
+    try {
+      message = JSON.parse(data.toString());
+    } catch (e) {
+      app.debug(`Ignoring message from mayara: ${e}`);
+      return;
+    }
+    switch (message.type) {
+      case "radars": {
+        radars = message.radars || {};
+        const count = Object.keys(radars).length;
+        app.setPluginStatus(
+          `${message.provider} ${message.version}: ${count} radars`,
+        );
+        break;
+      }
+      case "delta":
+        registerPutHandlers(message.delta);
+        storeControlValues(message.delta);
+        app.handleMessage(PLUGIN_ID, message.delta);
+        break;
+      case "reply": {
+        const callback = pending.get(message.requestId);
+        if (callback) {
+          pending.delete(message.requestId);
+          callback({
+            state: "COMPLETED",
+            statusCode: message.statusCode,
+            message: message.message,
+          });
+        }
+        break;
+      }
+      default:
+        app.debug(`Ignoring message of type ${message.type} from mayara`);
+    }
+  }
+
+  function acceptProvider(ws) {
+    if (provider) {
+      provider.close(1000, "Replaced by a new provider connection");
+    }
+    provider = ws;
+    app.setPluginStatus("mayara connected");
+    // Spoke clients that are still connected want their spokes again
+    for (const [radarId, clients] of spokeClients) {
+      if (clients.size > 0) {
+        subscribeSpokes(radarId, true);
+      }
+    }
+    ws.on("message", handleProviderMessage);
+    ws.on("close", () => {
+      if (provider !== ws) {
+        return;
+      }
+      provider = null;
+      radars = {};
+      controlValues = new Map();
+      app.setPluginStatus("Waiting for mayara to connect");
+    });
+  }
+
+  function acceptSpokeClient(ws, radarId) {
+    if (!spokeClients.has(radarId)) {
+      spokeClients.set(radarId, new Set());
+    }
+    const clients = spokeClients.get(radarId);
+    clients.add(ws);
+    if (clients.size === 1) {
+      subscribeSpokes(radarId, true);
+    }
+    ws.on("close", () => {
+      clients.delete(ws);
+      if (clients.size === 0) {
+        subscribeSpokes(radarId, false);
+      }
+    });
+  }
+
+  plugin.start = function () {
+    wss = new WebSocket.Server({ noServer: true });
+    onUpgrade = (req, socket, head) => {
+      const { pathname } = new URL(req.url, "http://localhost");
+      const spokes = pathname.match(SPOKES_PATH);
+      if (pathname !== PROVIDER_PATH && !spokes) {
+        // For the server's own streams
+        return;
+      }
+      try {
+        authorize(req, !spokes);
+      } catch (e) {
+        app.debug(`Refused WebSocket to ${pathname}: ${e.message}`);
+        socket.write("HTTP/1.1 401 Unauthorized\r\n\r\n");
+        socket.destroy();
+        return;
+      }
+      wss.handleUpgrade(req, socket, head, (ws) => {
+        if (spokes) {
+          acceptSpokeClient(ws, decodeURIComponent(spokes[1]));
+        } else {
+          acceptProvider(ws);
+        }
+      });
+    };
+    app.server.on("upgrade", onUpgrade);
+    registerRadarProvider();
+    app.setPluginStatus("Waiting for mayara to connect");
+  };
+
+  plugin.stop = function () {
+    if (onUpgrade) {
+      app.server.removeListener("upgrade", onUpgrade);
+      onUpgrade = null;
+    }
+    if (wss) {
+      for (const client of wss.clients) {
+        client.terminate();
+      }
+      wss.close();
+      wss = null;
+    }
+    for (const callback of pending.values()) {
+      callback({
+        state: "COMPLETED",
+        statusCode: 503,
+        message: "mayara plugin stopped",
+      });
+    }
+    pending.clear();
+    spokeClients.clear();
+    provider = null;
+    radars = {};
+    controlValues = new Map();
+  };
+
+  plugin.registerWithRouter = function (router) {
+    router.get("/radars", (req, res) => {
+      res.json(radars);
+    });
+  };
+
+  return plugin;
+};
@@ -23,6 +23,17 @@ async fn main() -> Result<()> {
         return Ok(());
     }
 
+    // Handle --hash-password flag: hash the password on stdin and exit
+    if args.hash_password {
+        let mut password = String::new();
+        std::io::stdin()
+            .read_line(&mut password)
+            .into_diagnostic()?;
+        let password = password.trim_end_matches(['\r', '\n']);
+        println!("{}", mayara::auth::hash_password(password));
+        return Ok(());
+    }
+
     let log_level = args.verbose.log_level_filter();
     env_logger::Builder::from_env(Env::default())
         // Only log mayara and mayara_server modules at the selected level
@@ -56,12 +67,20 @@ async fn main() -> Result<()> {
     if args.output {
         warn!("Output mode activated; 'protobuf' formatted RadarMessage sent to stdout");
     }
-    if args.nmea0183 {
+    if args.nmea0183 || args.nmea2000 {
+        let what = if args.nmea0183 {
+            "NMEA0183"
+        } else {
+            "NMEA 2000"
+        };
         warn!(
-            "NMEA0183 mode activated; will load GPS position, heading and date/time from {}",
-            args.navigation_address
-                .as_ref()
-                .unwrap_or(&"MDNS".to_string())
+            "{} mode activated; will load GPS position, heading and date/time from {}",
+            what,
+            if args.navigation_address.is_empty() {
+                "MDNS".to_string()
+            } else {
+                args.navigation_address.join(", ")
+            }
         );
     }
 
@@ -1,8 +1,9 @@
 use axum::{
     Json, Router, debug_handler,
     extract::{Path, State},
+    middleware,
     response::{IntoResponse, Redirect, Response},
-    routing::get,
+    routing::{get, post, put},
 };
 use axum_embed::ServeEmbed;
 use http::Uri;
@@ -27,6 +28,7 @@ use tokio_rustls::{TlsAcceptor, server::TlsStream};
 use tower_http::trace::TraceLayer;
 use utoipa::ToSchema;
 
+mod auth;
 mod axum_fix;
 mod recordings;
 mod signalk;
@@ -36,6 +38,8 @@ pub use signalk::v2::generate_openapi_json;
 use axum_fix::{Message, WebSocket, WebSocketUpgrade};
 use mayara::{
     Cli, InterfaceApi, PACKAGE, VERSION,
+    auth::Authenticator,
+    metrics,
     radar::{RadarError, SharedRadars},
     start_session,
 };
@@ -111,6 +115,8 @@ pub struct Web {
     radars: SharedRadars,
     args: Cli,
     tls: bool,
+    /// `None` when authentication is disabled
+    auth: Option<Arc<Authenticator>>,
     shutdown_tx: broadcast::Sender<()>,
     tx_interface_request: broadcast::Sender<Option<mpsc::Sender<InterfaceApi>>>,
     recording_state: recordings::RecordingState,
@@ -127,6 +133,7 @@ impl Web {
             radars,
             args,
             tls,
+            auth: Authenticator::load().map(Arc::new),
             shutdown_tx,
             tx_interface_request,
             recording_state: recordings::RecordingState::new(),
@@ -172,7 +179,10 @@ impl Web {
         let router = Router::new()
             .route("/", get(root_redirect))
             .route("/signalk", get(endpoints))
-            .route("/quit", get(quit_handler));
+            .route("/quit", get(quit_handler))
+            .route("/metrics", get(metrics_handler))
+            .route(auth::LOGIN_URI, post(auth::login))
+            .route(auth::LOGOUT_URI, put(auth::logout));
         let router = signalk::v2::routes(router);
         let router = recordings::routes(router).route(
             "/signalk/{*rest}",
@@ -184,6 +194,10 @@ impl Web {
 
         let router = router
             .fallback_service(serve_assets)
+            .layer(middleware::from_fn_with_state(
+                self.clone(),
+                auth::require_role,
+            ))
             .layer(TraceLayer::new_for_http())
             .with_state(self);
 
@@ -287,6 +301,17 @@ async fn quit_handler(State(state): State<Web>) -> &'static str {
     "bye\n"
 }
 
+async fn metrics_handler(State(state): State<Web>) -> Response {
+    (
+        [(
+            http::header::CONTENT_TYPE,
+            "text/plain; version=0.0.4; charset=utf-8",
+        )],
+        metrics::render(&state.radars),
+    )
+        .into_response()
+}
+
 async fn endpoints(State(state): State<Web>, headers: hyper::header::HeaderMap) -> Response {
     let host: String = match headers.get(axum::http::header::HOST) {
         Some(host) => host.to_str().unwrap_or("localhost").to_string(),
@@ -346,7 +371,9 @@ async fn spokes_handler(
             let radar_message_rx = radar.message_tx.subscribe();
             // finalize the upgrade process by returning upgrade callback.
             // we can customize the callback by sending additional info such as address.
-            ws.on_upgrade(move |socket| spokes_stream(socket, radar_message_rx, shutdown_rx))
+            ws.on_upgrade(move |socket| {
+                spokes_stream(socket, params.id, radar_message_rx, shutdown_rx)
+            })
         }
         None => RadarError::NoSuchRadar(params.id).into_response(),
     }
@@ -356,9 +383,11 @@ async fn spokes_handler(
 
 async fn spokes_stream(
     mut socket: WebSocket,
+    key: String,
     mut radar_message_rx: tokio::sync::broadcast::Receiver<Vec<u8>>,
     mut shutdown_rx: tokio::sync::broadcast::Receiver<()>,
 ) {
+    let _client = metrics::WebSocketClient::new(metrics::WebSocketKind::Spokes);
     loop {
         tokio::select! {
             _ = shutdown_rx.recv() => {
@@ -378,6 +407,7 @@ async fn spokes_stream(
                     },
                     Err(tokio::sync::broadcast::error::RecvError::Lagged(n)) => {
                         debug!("Spoke stream lagged by {} messages, resuming", n);
+                        metrics::lagged("spokes", &key, n);
                     },
                     Err(e) => {
                         debug!("Error on RadarMessage channel: {}", e);
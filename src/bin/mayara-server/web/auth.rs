@@ -0,0 +1,374 @@
+//! Authentication of web clients, when `auth.json` is present.
+//!
+//! Every request passes `require_role`, which works out the role the request
+//! needs from its method and path, and the role of the client from its token.
+//! The token comes from an `Authorization: Bearer` header, the session cookie
+//! that login sets, or a `token` query parameter on WebSocket upgrades, as
+//! browsers cannot set headers on those. Anything that is not a file of the
+//! web GUI needs a role, so new endpoints are protected without listing them
+//! here. The role and `Identity` of the client are added to the request
+//! extensions for handlers that need to check more, like the control stream
+//! and control locks.
+
+use axum::{
+    Json,
+    extract::{Request, State},
+    http::{HeaderMap, Method, StatusCode, Uri, header},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use mayara::{auth::Role, radar::RadarError};
+
+use super::{Assets, Web};
+
+pub(super) const LOGIN_URI: &str = "/signalk/v1/auth/login";
+pub(super) const LOGOUT_URI: &str = "/signalk/v1/auth/logout";
+
+/// Same cookie name as the Signal K server uses
+const SESSION_COOKIE: &str = "JAUTHENTICATION";
+
+#[derive(Deserialize, ToSchema)]
+pub(super) struct LoginRequest {
+    username: String,
+    password: String,
+}
+
+#[derive(Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub(super) struct LoginResponse {
+    token: String,
+    role: Role,
+    /// Seconds until the token expires
+    time_to_live: u64,
+}
+
+///
+/// Who sent a request, as far as authentication can tell. Control locks are
+/// held under this name, so that a client cannot take over the lock of
+/// another one by using its name.
+///
+#[derive(Clone, Debug, PartialEq)]
+pub(super) enum Identity {
+    /// Authentication is disabled, clients name themselves
+    Unchecked,
+    /// A client without a token
+    Anonymous,
+    /// The name of the user or API token
+    Authenticated(String),
+}
+
+impl Identity {
+    ///
+    /// The name under which the client holds control locks, given the name
+    /// it claims in a header, parameter or body. With authentication a client
+    /// is always its user or token name, and anonymous clients have none.
+    ///
+    pub(super) fn client_name(&self, claimed: Option<&str>) -> Result<Option<String>, RadarError> {
+        match (self, claimed) {
+            (Identity::Unchecked, claimed) => Ok(claimed.map(str::to_string)),
+            (Identity::Authenticated(name), Some(claimed)) if claimed != name => Err(
+                RadarError::ClientMismatch(claimed.to_string(), name.clone()),
+            ),
+            (Identity::Authenticated(name), _) => Ok(Some(name.clone())),
+            (Identity::Anonymous, Some(claimed)) => {
+                Err(RadarError::ClientNotAuthenticated(claimed.to_string()))
+            }
+            (Identity::Anonymous, None) => Ok(None),
+        }
+    }
+}
+
+/// Whether `path` is a file of the web GUI, including the login page
+fn is_asset(path: &str) -> bool {
+    let file = path.trim_start_matches('/');
+    if file.is_empty() || file.ends_with('/') {
+        Assets::get(&format!("{}index.html", file)).is_some()
+    } else {
+        Assets::get(file).is_some()
+    }
+}
+
+///
+/// The role needed for a request: none to log in and out and to read the web
+/// GUI and the Signal K discovery document, operator for `/quit` and anything
+/// that changes something, and viewer for everything else.
+///
+fn required_role(method: &Method, path: &str) -> Option<Role> {
+    if path == LOGIN_URI || path == LOGOUT_URI {
+        return None;
+    }
+    let read = method == Method::GET || method == Method::HEAD;
+    if read && (path == "/" || path == "/signalk" || is_asset(path)) {
+        return None;
+    }
+    if read && path != "/quit" {
+        Some(Role::Viewer)
+    } else {
+        Some(Role::Operator)
+    }
+}
+
+fn is_websocket_upgrade(headers: &HeaderMap) -> bool {
+    headers
+        .get(header::UPGRADE)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.eq_ignore_ascii_case("websocket"))
+}
+
+///
+/// The token of the client, if it sent one. The query parameter is only
+/// looked at for WebSocket upgrades, elsewhere it would end up in logs.
+///
+fn token(headers: &HeaderMap, uri: &Uri) -> Option<String> {
+    if let Some(token) = headers
+        .get(header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "))
+    {
+        return Some(token.trim().to_string());
+    }
+    for cookies in headers.get_all(header::COOKIE) {
+        let Ok(cookies) = cookies.to_str() else {
+            continue;
+        };
+        for cookie in cookies.split(';') {
+            if let Some((name, value)) = cookie.trim().split_once('=')
+                && name == SESSION_COOKIE
+            {
+                return Some(value.to_string());
+            }
+        }
+    }
+    if !is_websocket_upgrade(headers) {
+        return None;
+    }
+    uri.query()?
+        .split('&')
+        .find_map(|p| p.strip_prefix("token="))
+        .map(|t| t.to_string())
+}
+
+pub(super) async fn require_role(
+    State(state): State<Web>,
+    mut request: Request,
+    next: Next,
+) -> Response {
+    let Some(auth) = &state.auth else {
+        request.extensions_mut().insert(Role::Operator);
+        request.extensions_mut().insert(Identity::Unchecked);
+        return next.run(request).await;
+    };
+
+    let token = token(request.headers(), request.uri());
+    let principal = auth.identify(token.as_deref());
+    let role = principal.as_ref().map(|p| p.role);
+    match (required_role(request.method(), request.uri().path()), role) {
+        (None, _) => {}
+        (Some(needed), Some(role)) if role >= needed => {}
+        (Some(_), None) => {
+            return (
+                StatusCode::UNAUTHORIZED,
+                [(header::WWW_AUTHENTICATE, "Bearer")],
+                "Authentication required\n",
+            )
+                .into_response();
+        }
+        (Some(needed), Some(role)) => {
+            return (
+                StatusCode::FORBIDDEN,
+                format!("Role '{}' cannot do this, it needs '{}'\n", role, needed),
+            )
+                .into_response();
+        }
+    }
+    if let Some(role) = role {
+        request.extensions_mut().insert(role);
+    }
+    let identity = match principal.and_then(|p| p.name) {
+        Some(name) => Identity::Authenticated(name),
+        None => Identity::Anonymous,
+    };
+    request.extensions_mut().insert(identity);
+    next.run(request).await
+}
+
+fn session_cookie(token: &str, max_age: u64) -> String {
+    format!(
+        "{}={}; Path=/; Max-Age={}; HttpOnly; SameSite=Strict",
+        SESSION_COOKIE, token, max_age
+    )
+}
+
+#[utoipa::path(
+    post,
+    path = "/signalk/v1/auth/login",
+    summary = "Log in",
+    description = "Starts a session for a user from `auth.json`. The token in the response can be \
+                   sent as `Authorization: Bearer <token>`, and is also set as a cookie so that \
+                   browsers send it automatically.",
+    request_body = LoginRequest,
+    responses(
+        (status = 200, body = LoginResponse, description = "Logged in"),
+        (status = 401, description = "Unknown user or wrong password"),
+        (status = 404, description = "Authentication is not enabled")
+    ),
+    tag = "Configuration"
+)]
+pub(super) async fn login(State(state): State<Web>, Json(req): Json<LoginRequest>) -> Response {
+    let Some(auth) = state.auth.clone() else {
+        return (StatusCode::NOT_FOUND, "Authentication is not enabled\n").into_response();
+    };
+    let login = tokio::task::spawn_blocking(move || auth.login(&req.username, &req.password))
+        .await
+        .unwrap_or(None);
+    match login {
+        Some(login) => {
+            let time_to_live = login.time_to_live.as_secs();
+            (
+                [(
+                    header::SET_COOKIE,
+                    session_cookie(&login.token, time_to_live),
+                )],
+                Json(LoginResponse {
+                    token: login.token,
+                    role: login.role,
+                    time_to_live,
+                }),
+            )
+                .into_response()
+        }
+        None => (StatusCode::UNAUTHORIZED, "Invalid username or password\n").into_response(),
+    }
+}
+
+#[utoipa::path(
+    put,
+    path = "/signalk/v1/auth/logout",
+    summary = "Log out",
+    description = "Ends the session of the token in the request and clears the session cookie.",
+    responses(
+        (status = 200, description = "Logged out")
+    ),
+    tag = "Configuration"
+)]
+pub(super) async fn logout(State(state): State<Web>, request: Request) -> Response {
+    if let Some(auth) = &state.auth
+        && let Some(token) = token(request.headers(), request.uri())
+    {
+        auth.logout(&token);
+    }
+    (
+        [(header::SET_COOKIE, session_cookie("", 0))],
+        "Logged out\n",
+    )
+        .into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RADARS: &str = "/signalk/v2/api/vessels/self/radars";
+    const RECORDINGS: &str = "/v2/api/vessels/self/radars/recordings";
+
+    fn role(method: Method, path: &str) -> Option<Role> {
+        required_role(&method, path)
+    }
+
+    #[test]
+    fn test_required_role_public() {
+        assert_eq!(role(Method::POST, LOGIN_URI), None);
+        assert_eq!(role(Method::PUT, LOGOUT_URI), None);
+        assert_eq!(role(Method::GET, "/"), None);
+        assert_eq!(role(Method::GET, "/signalk"), None);
+        assert_eq!(role(Method::GET, "/gui/"), None);
+        assert_eq!(role(Method::GET, "/gui/login.html"), None);
+        assert_eq!(role(Method::HEAD, "/gui/mayara.js"), None);
+        assert_eq!(role(Method::GET, "/favicon.ico"), None);
+
+        // Only reading the GUI is public
+        assert_eq!(role(Method::PUT, "/gui/mayara.js"), Some(Role::Operator));
+        assert_eq!(role(Method::POST, "/signalk"), Some(Role::Operator));
+    }
+
+    #[test]
+    fn test_required_role_api() {
+        for path in [
+            RADARS.to_string(),
+            format!("{}/nav1/controls/gain", RADARS),
+            "/signalk/v1/stream".to_string(),
+            "/signalk/v2/api/vessels/self/navigation".to_string(),
+            "/metrics".to_string(),
+            "/swagger-ui/".to_string(),
+            format!("{}/files", RECORDINGS),
+            format!("{}/playback/status", RECORDINGS),
+        ] {
+            assert_eq!(role(Method::GET, &path), Some(Role::Viewer), "{}", path);
+            assert_eq!(role(Method::HEAD, &path), Some(Role::Viewer), "{}", path);
+            for method in [Method::PUT, Method::POST, Method::DELETE, Method::PATCH] {
+                assert_eq!(role(method, &path), Some(Role::Operator), "{}", path);
+            }
+        }
+        assert_eq!(role(Method::GET, "/quit"), Some(Role::Operator));
+    }
+
+    #[test]
+    fn test_required_role_default() {
+        // Unknown paths are not public
+        assert_eq!(
+            role(Method::GET, "/gui/no-such-file.js"),
+            Some(Role::Viewer)
+        );
+        assert_eq!(role(Method::GET, "/v3/something"), Some(Role::Viewer));
+        assert_eq!(role(Method::POST, "/v3/something"), Some(Role::Operator));
+        assert_eq!(role(Method::OPTIONS, "/gui/"), Some(Role::Operator));
+    }
+
+    #[test]
+    fn test_client_name() {
+        let helm = Identity::Authenticated("helm".to_string());
+        assert_eq!(helm.client_name(None).unwrap().as_deref(), Some("helm"));
+        assert_eq!(
+            helm.client_name(Some("helm")).unwrap().as_deref(),
+            Some("helm")
+        );
+        assert!(matches!(
+            helm.client_name(Some("nav")),
+            Err(RadarError::ClientMismatch(_, _))
+        ));
+
+        assert_eq!(Identity::Anonymous.client_name(None).unwrap(), None);
+        assert!(matches!(
+            Identity::Anonymous.client_name(Some("helm")),
+            Err(RadarError::ClientNotAuthenticated(_))
+        ));
+
+        assert_eq!(
+            Identity::Unchecked
+                .client_name(Some("helm"))
+                .unwrap()
+                .as_deref(),
+            Some("helm")
+        );
+        assert_eq!(Identity::Unchecked.client_name(None).unwrap(), None);
+    }
+
+    #[test]
+    fn test_token() {
+        let uri: Uri = "/signalk/v1/stream?client=helm&token=abc".parse().unwrap();
+        let mut headers = HeaderMap::new();
+        assert_eq!(token(&headers, &uri), None);
+
+        headers.insert(header::UPGRADE, "websocket".parse().unwrap());
+        assert_eq!(token(&headers, &uri).as_deref(), Some("abc"));
+
+        headers.insert(header::COOKIE, "a=b; JAUTHENTICATION=def".parse().unwrap());
+        assert_eq!(token(&headers, &uri).as_deref(), Some("def"));
+
+        headers.insert(header::AUTHORIZATION, "Bearer ghi".parse().unwrap());
+        assert_eq!(token(&headers, &uri).as_deref(), Some("ghi"));
+    }
+}
@@ -1,5 +1,5 @@
 use axum::{
-    Error, Json,
+    Error, Extension, Json,
     extract::{self, Path, Query, State},
     http::Uri,
     response::{IntoResponse, Response},
@@ -12,7 +12,7 @@ use hyper;
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
 use std::{
-    collections::{HashMap, HashSet},
+    collections::{BTreeMap, HashMap, HashSet},
     net::Ipv4Addr,
     str::FromStr,
 };
@@ -25,14 +25,29 @@ use utoipa::OpenApi;
 use utoipa::ToSchema;
 use utoipa_swagger_ui::{Config as SwaggerConfig, SwaggerUi};
 
-use crate::web::spokes_handler;
+use crate::web::{
+    auth::{self, Identity},
+    spokes_handler,
+};
 
 use super::super::{Message, Web, WebSocket, WebSocketUpgrade};
 use mayara::{
-    InterfaceApi, navdata,
+    InterfaceApi,
+    auth::Role,
+    metrics, navdata,
     radar::{
         GeoPosition, Legend, RadarError, RadarInfo, SharedRadars,
-        settings::{BareControlValue, Control, ControlId, ControlValue, RadarControlValue},
+        batch::{BatchControlValue, BatchReport, BatchRequest, BatchResult, BatchStatus},
+        diagnostics::DiagnosticsApi,
+        lock::{ControlLock, DEFAULT_LEASE},
+        preset::{Preset, PresetReport},
+        settings::{
+            BareControlValue, Category, Control, ControlId, ControlValue, RadarControlValue,
+        },
+        snapshot::{
+            DEFAULT_IMAGE_SIZE, ImageOptions, MAX_IMAGE_SIZE, MAX_ZOOM, Orientation,
+            wmts_capabilities,
+        },
         target::{ArpaTargetApi, MarpaRequest, TrackerCommand},
     },
     stream::{ActiveSubscriptions, Desubscription, SignalKDelta, Subscribe, Subscription},
@@ -45,12 +60,35 @@ pub(crate) const CONTROL_URI: &str = "/signalk/v1/stream";
 pub(crate) const SPOKES_URI: &str = "/signalk/v2/api/vessels/self/radars/{id}/spokes"; // plus radar_id
 const OPENAPI_URI: &str = "/signalk/v2/api/vessels/self/radars/resources/openapi.json";
 const RADAR_CAPABILITIES_URI: &str = "/signalk/v2/api/vessels/self/radars/{radar_id}/capabilities";
+const RADAR_DIAGNOSTICS_URI: &str = "/signalk/v2/api/vessels/self/radars/{radar_id}/diagnostics";
+const RADAR_IMAGE_URI: &str = "/signalk/v2/api/vessels/self/radars/{radar_id}/image.png";
+// The router cannot match "{y}.png", so the handler strips the extension
+const RADAR_TILE_URI: &str = "/signalk/v2/api/vessels/self/radars/{radar_id}/tiles/{z}/{x}/{y}";
+const RADAR_WMTS_URI: &str = "/signalk/v2/api/vessels/self/radars/{radar_id}/wmts";
 const INTERFACES_URI: &str = "/signalk/v2/api/vessels/self/radars/interfaces";
 const RADAR_CONTROLS_URI: &str = "/signalk/v2/api/vessels/self/radars/{radar_id}/controls";
 const RADAR_CONTROL_URI: &str =
     "/signalk/v2/api/vessels/self/radars/{radar_id}/controls/{control_id}";
+const RADAR_LOCKS_URI: &str = "/signalk/v2/api/vessels/self/radars/{radar_id}/locks";
+const RADAR_PRESETS_URI: &str = "/signalk/v2/api/vessels/self/radars/{radar_id}/presets";
+const RADAR_PRESET_URI: &str = "/signalk/v2/api/vessels/self/radars/{radar_id}/presets/{name}";
+const RADAR_PRESET_APPLY_URI: &str =
+    "/signalk/v2/api/vessels/self/radars/{radar_id}/presets/{name}/apply";
 const RADAR_TARGETS_URI: &str = "/signalk/v2/api/vessels/self/radars/{radar_id}/targets";
 const RADAR_TARGET_URI: &str = "/signalk/v2/api/vessels/self/radars/{radar_id}/targets/{target_id}";
+const NAVIGATION_URI: &str = "/signalk/v2/api/vessels/self/navigation";
+const NAVIGATION_SOURCES_URI: &str = "/signalk/v2/api/vessels/self/navigation/sources";
+
+/// Header with the name of a client, for control locks
+const CLIENT_HEADER: &str = "x-mayara-client";
+
+/// The name of the client for control locks, see `Identity::client_name`
+fn request_client(
+    identity: &Identity,
+    headers: &hyper::header::HeaderMap,
+) -> Result<Option<String>, RadarError> {
+    identity.client_name(headers.get(CLIENT_HEADER).and_then(|v| v.to_str().ok()))
+}
 
 #[derive(OpenApi)]
 #[openapi(
@@ -66,29 +104,65 @@ const RADAR_TARGET_URI: &str = "/signalk/v2/api/vessels/self/radars/{radar_id}/t
         (name = "Controls", description = "Read and modify radar control settings"),
         (name = "Targets", description = "ARPA target acquisition and tracking"),
         (name = "Configuration", description = "Server and network configuration"),
+        (name = "Navigation", description = "Own-ship navigation data"),
         (name = "Stream", description = "Real-time WebSocket stream for control updates")
     ),
     paths(
         get_radars,
         get_interfaces,
         get_radar,
+        get_diagnostics,
+        get_image,
+        get_tile,
+        get_wmts_capabilities,
         get_control_values,
         get_control_value,
         set_control_value,
+        set_control_values,
+        get_locks,
+        claim_lock,
+        release_lock,
+        get_presets,
+        set_preset,
+        delete_preset,
+        apply_preset,
         get_targets,
         acquire_target,
         delete_target,
+        get_navigation,
+        get_navigation_sources,
+        auth::login,
+        auth::logout,
         control_stream_docs,
     ),
     components(schemas(
         RadarControlIdParam,
         RadarApiV3,
         Capabilities,
+        DiagnosticsApi,
+        Orientation,
         BareControlValue,
+        BatchControlValue,
+        BatchRequest,
+        BatchResult,
+        BatchStatus,
+        BatchReport,
+        ControlLock,
+        ClaimLockRequest,
+        Category,
+        Preset,
+        SavePresetRequest,
+        PresetReport,
         // Target types
         ArpaTargetApi,
         AcquireTargetRequest,
         AcquireTargetResponse,
+        navdata::NavigationValueApi,
+        navdata::NavigationSourceApi,
+        // Authentication
+        Role,
+        auth::LoginRequest,
+        auth::LoginResponse,
         // WebSocket message types
         SignalKDelta,
         Subscription,
@@ -104,13 +178,32 @@ pub(crate) fn routes(axum: axum::Router<Web>) -> axum::Router<Web> {
         .route(CONTROL_URI, get(control_stream_handler))
         .route(SPOKES_URI, get(spokes_handler))
         .route(RADAR_CAPABILITIES_URI, get(get_radar))
-        .route(RADAR_CONTROLS_URI, get(get_control_values))
+        .route(RADAR_DIAGNOSTICS_URI, get(get_diagnostics))
+        .route(RADAR_IMAGE_URI, get(get_image))
+        .route(RADAR_TILE_URI, get(get_tile))
+        .route(RADAR_WMTS_URI, get(get_wmts_capabilities))
+        .route(
+            RADAR_CONTROLS_URI,
+            get(get_control_values).put(set_control_values),
+        )
         .route(
             RADAR_CONTROL_URI,
             get(get_control_value).put(set_control_value),
         )
+        .route(
+            RADAR_LOCKS_URI,
+            get(get_locks).put(claim_lock).delete(release_lock),
+        )
+        .route(RADAR_PRESETS_URI, get(get_presets))
+        .route(
+            RADAR_PRESET_URI,
+            axum::routing::put(set_preset).delete(delete_preset),
+        )
+        .route(RADAR_PRESET_APPLY_URI, axum::routing::post(apply_preset))
         .route(RADAR_TARGETS_URI, get(get_targets).post(acquire_target))
         .route(RADAR_TARGET_URI, axum::routing::delete(delete_target))
+        .route(NAVIGATION_URI, get(get_navigation))
+        .route(NAVIGATION_SOURCES_URI, get(get_navigation_sources))
         .route(OPENAPI_URI, get(openapi_json))
         .merge(SwaggerUi::new("/swagger-ui").config(SwaggerConfig::new([OPENAPI_URI])))
 }
@@ -277,6 +370,39 @@ async fn get_interfaces(State(state): State<Web>, headers: hyper::header::Header
     }
 }
 
+#[utoipa::path(
+    get,
+    path = "/signalk/v2/api/vessels/self/navigation",
+    summary = "Get own-ship navigation data",
+    description = "Returns the current heading, position, COG and SOG by Signal K path, each with \
+                   the source that last set it, its age in seconds and whether it is valid. A value \
+                   that was not updated within the navigation timeout is not valid, and is not used \
+                   for radar stabilisation or target tracking.",
+    responses(
+        (status = 200, body = HashMap<String, navdata::NavigationValueApi>, description = "Navigation value, source, age and validity per navigation path")
+    ),
+    tag = "Navigation"
+)]
+async fn get_navigation() -> Response {
+    Json(navdata::get_navigation_state()).into_response()
+}
+
+#[utoipa::path(
+    get,
+    path = "/signalk/v2/api/vessels/self/navigation/sources",
+    summary = "List navigation sources",
+    description = "Returns, for each navigation quantity by Signal K path, the source that currently \
+                   provides it and the configured source priority. The source is absent when no \
+                   source provides the quantity, or when it went stale.",
+    responses(
+        (status = 200, body = HashMap<String, navdata::NavigationSourceApi>, description = "Active source and priority per navigation path")
+    ),
+    tag = "Navigation"
+)]
+async fn get_navigation_sources() -> Response {
+    Json(navdata::get_navigation_sources()).into_response()
+}
+
 /// Static capabilities and configuration of a radar unit
 #[derive(Serialize, ToSchema)]
 #[serde(rename_all = "camelCase")]
@@ -442,6 +568,295 @@ async fn get_radar(
     }
 }
 
+#[utoipa::path(
+    get,
+    path = "/signalk/v2/api/vessels/self/radars/{radar_id}/diagnostics",
+    summary = "Get radar diagnostics",
+    description = "Returns what the server received from the radar and what went wrong: packets \
+                   per socket and message type, parse failures, when the last report and spokes \
+                   came in, the measured rotation period and jitter, missing spokes, commands \
+                   that could not be sent, and the state of the connection. Counters start at \
+                   zero when the radar is found.",
+    params(
+        ("radar_id" = String, Path, description = "Radar identifier (e.g., 'nav1034A')", example = "nav1034A")
+    ),
+    responses(
+        (status = 200, body = DiagnosticsApi, description = "Radar diagnostics"),
+        (status = 404, description = "Radar not found")
+    ),
+    tag = "Radars"
+)]
+async fn get_diagnostics(Path(radar_id): Path<String>, State(state): State<Web>) -> Response {
+    match state.radars.get_by_key(&radar_id) {
+        Some(info) => Json(
+            info.diagnostics
+                .to_api(info.spokes_per_revolution, info.sparse_spokes),
+        )
+        .into_response(),
+        None => no_such_radar(&radar_id, &state.radars),
+    }
+}
+
+/// Query parameters for the radar image
+#[derive(Deserialize, Debug, ToSchema)]
+struct RadarImageQuery {
+    /// Width and height in pixels
+    #[schema(example = 512)]
+    size: Option<u32>,
+    orientation: Option<Orientation>,
+    /// Range at the edge of the image in meters
+    #[schema(example = 1852)]
+    range: Option<f64>,
+    /// Comma separated list of `targets`, `guardzones` and `rings`
+    #[schema(example = "targets,rings")]
+    overlays: Option<String>,
+}
+
+#[utoipa::path(
+    get,
+    path = "/signalk/v2/api/vessels/self/radars/{radar_id}/image.png",
+    summary = "Get radar image",
+    description = "Renders the latest full revolution of the radar as a PNG image, using the \
+                   colors of the legend. The image is north up when the radar has a heading, \
+                   otherwise head up. Without `range` the image shows the current range of the \
+                   radar. Overlays can show ARPA targets, the guard zones and four range rings.",
+    params(
+        ("radar_id" = String, Path, description = "Radar identifier (e.g., 'nav1034A')", example = "nav1034A"),
+        ("size" = Option<u32>, Query, description = "Width and height in pixels, 512 by default, at most 2048"),
+        ("orientation" = Option<Orientation>, Query, description = "'north' (default) or 'head' up"),
+        ("range" = Option<f64>, Query, description = "Range at the edge of the image in meters"),
+        ("overlays" = Option<String>, Query, description = "Comma separated list of 'targets', 'guardzones' and 'rings'")
+    ),
+    responses(
+        (status = 200, content_type = "image/png", description = "Radar image"),
+        (status = 400, description = "Invalid size or overlay"),
+        (status = 404, description = "Radar not found"),
+        (status = 503, description = "No spokes received yet")
+    ),
+    tag = "Radars"
+)]
+async fn get_image(
+    Path(radar_id): Path<String>,
+    Query(query): Query<RadarImageQuery>,
+    State(state): State<Web>,
+) -> Response {
+    let Some(info) = state.radars.get_by_key(&radar_id) else {
+        return no_such_radar(&radar_id, &state.radars);
+    };
+
+    let size = query.size.unwrap_or(DEFAULT_IMAGE_SIZE);
+    if size == 0 || size > MAX_IMAGE_SIZE {
+        return (
+            StatusCode::BAD_REQUEST,
+            format!("Size must be between 1 and {}", MAX_IMAGE_SIZE),
+        )
+            .into_response();
+    }
+
+    let mut options = ImageOptions {
+        size,
+        orientation: query.orientation.unwrap_or_default(),
+        range: query
+            .range
+            .or_else(|| info.controls.get(&ControlId::Range)?.value),
+        range_rings: false,
+        guard_zones: Vec::new(),
+        targets: Vec::new(),
+    };
+    for overlay in query.overlays.iter().flat_map(|o| o.split(',')) {
+        match overlay.trim() {
+            "" => {}
+            "rings" => options.range_rings = true,
+            "guardzones" => {
+                options.guard_zones = [ControlId::GuardZone1, ControlId::GuardZone2]
+                    .iter()
+                    .filter_map(|id| info.controls.guard_zone(id))
+                    .collect();
+            }
+            "targets" => options.targets = get_target_positions(&state, &radar_id).await,
+            other => {
+                return (
+                    StatusCode::BAD_REQUEST,
+                    format!(
+                        "Unknown overlay '{}' -- use targets, guardzones or rings",
+                        other
+                    ),
+                )
+                    .into_response();
+            }
+        }
+    }
+
+    match info
+        .snapshot
+        .render(info.get_legend().palette(), options)
+        .await
+    {
+        Some(png) => (
+            [
+                (http::header::CONTENT_TYPE, "image/png"),
+                (http::header::CACHE_CONTROL, "no-store"),
+            ],
+            png,
+        )
+            .into_response(),
+        None => (
+            StatusCode::SERVICE_UNAVAILABLE,
+            format!("Radar '{}' has not sent any spokes yet", radar_id),
+        )
+            .into_response(),
+    }
+}
+
+/// Bearing and distance of the tracked targets, if target tracking is enabled
+async fn get_target_positions(state: &Web, radar_id: &str) -> Vec<(f64, f64)> {
+    let Some(command_tx) = state.radars.get_tracker_command_tx() else {
+        return Vec::new();
+    };
+    let (response_tx, response_rx) = tokio::sync::oneshot::channel();
+    if command_tx
+        .send(TrackerCommand::GetTargets {
+            radar_key: Some(radar_id.to_string()),
+            radar_position: navdata::get_radar_position(),
+            response_tx,
+        })
+        .await
+        .is_err()
+    {
+        return Vec::new();
+    }
+    response_rx
+        .await
+        .unwrap_or_default()
+        .into_iter()
+        .filter(|t| t.status != "lost")
+        .map(|t| (t.position.bearing, t.position.distance as f64))
+        .collect()
+}
+
+#[utoipa::path(
+    get,
+    path = "/signalk/v2/api/vessels/self/radars/{radar_id}/tiles/{z}/{x}/{y}.png",
+    summary = "Get radar chart tile",
+    description = "Renders the latest full revolution of the radar as a 256 x 256 pixel Web \
+                   Mercator (XYZ) tile, placed at the radar position using the spoke bearings. \
+                   Pixels without a radar return are transparent. Tiles are cached until the \
+                   next revolution.",
+    params(
+        ("radar_id" = String, Path, description = "Radar identifier (e.g., 'nav1034A')", example = "nav1034A"),
+        ("z" = u8, Path, description = "Zoom level, at most 22"),
+        ("x" = u32, Path, description = "Tile column"),
+        ("y" = u32, Path, description = "Tile row")
+    ),
+    responses(
+        (status = 200, content_type = "image/png", description = "Radar tile"),
+        (status = 400, description = "Invalid tile coordinates"),
+        (status = 404, description = "Radar not found"),
+        (status = 503, description = "No radar position or no spokes with a heading received yet")
+    ),
+    tag = "Radars"
+)]
+async fn get_tile(
+    Path((radar_id, z, x, y)): Path<(String, u8, u32, String)>,
+    State(state): State<Web>,
+) -> Response {
+    let Some(info) = state.radars.get_by_key(&radar_id) else {
+        return no_such_radar(&radar_id, &state.radars);
+    };
+
+    let y = y.strip_suffix(".png").unwrap_or(&y).parse::<u32>();
+    let y = match y {
+        Ok(y) if z <= MAX_ZOOM && x < 1 << z && y < 1 << z => y,
+        _ => {
+            return (
+                StatusCode::BAD_REQUEST,
+                format!("Invalid tile -- zoom is at most {}", MAX_ZOOM),
+            )
+                .into_response();
+        }
+    };
+
+    let Some(position) = navdata::get_radar_position() else {
+        return (
+            StatusCode::SERVICE_UNAVAILABLE,
+            "No radar position available".to_string(),
+        )
+            .into_response();
+    };
+
+    match info
+        .snapshot
+        .render_tile(info.get_legend().palette(), position, z, x, y)
+        .await
+    {
+        Some(png) => (
+            [
+                (http::header::CONTENT_TYPE, "image/png"),
+                (http::header::CACHE_CONTROL, "no-cache"),
+            ],
+            png,
+        )
+            .into_response(),
+        None => (
+            StatusCode::SERVICE_UNAVAILABLE,
+            format!(
+                "Radar '{}' has not sent any spokes with a heading yet",
+                radar_id
+            ),
+        )
+            .into_response(),
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/signalk/v2/api/vessels/self/radars/{radar_id}/wmts",
+    summary = "Get WMTS capabilities",
+    description = "A minimal OGC WMTS capabilities document for the radar tiles, in the \
+                   GoogleMapsCompatible tile matrix set, so that chart plotters and GIS \
+                   applications can add the radar as a layer.",
+    params(
+        ("radar_id" = String, Path, description = "Radar identifier (e.g., 'nav1034A')", example = "nav1034A")
+    ),
+    responses(
+        (status = 200, content_type = "application/xml", description = "WMTS capabilities"),
+        (status = 404, description = "Radar not found")
+    ),
+    tag = "Radars"
+)]
+async fn get_wmts_capabilities(
+    Path(radar_id): Path<String>,
+    State(state): State<Web>,
+    headers: hyper::header::HeaderMap,
+) -> Response {
+    let Some(info) = state.radars.get_by_key(&radar_id) else {
+        return no_such_radar(&radar_id, &state.radars);
+    };
+
+    let host = headers
+        .get(axum::http::header::HOST)
+        .and_then(|h| h.to_str().ok())
+        .map(|h| h.to_string())
+        .unwrap_or_else(|| format!("localhost:{}", state.args.port));
+    let scheme = if state.tls { "https" } else { "http" };
+    let tile_url = format!(
+        "{}://{}{}",
+        scheme,
+        host,
+        RADAR_TILE_URI
+            .replace("{radar_id}", &radar_id)
+            .replace("/{z}/{x}/{y}", "")
+    );
+
+    let xml = wmts_capabilities(
+        &radar_id,
+        &tile_url,
+        navdata::get_radar_position(),
+        info.snapshot.range(),
+    );
+    ([(http::header::CONTENT_TYPE, "application/xml")], xml).into_response()
+}
+
 // =============================================================================
 // Control Value REST API Handler
 // =============================================================================
@@ -467,7 +882,8 @@ struct RadarControlIdParam {
                    guard zones use 'value', 'endValue', 'startDistance', 'endDistance', and 'enabled'.",
     params(
         ("radar_id" = String, Path, description = "Radar identifier", example = "nav1034A"),
-        ("control_id" = String, Path, description = "Control identifier (e.g., gain, range, sea, guardZone1, ...)", example = "gain")
+        ("control_id" = String, Path, description = "Control identifier (e.g., gain, range, sea, guardZone1, ...)", example = "gain"),
+        ("X-Mayara-Client" = Option<String>, Header, description = "Client name, needed to change controls that this client has locked", example = "helm")
     ),
     request_body(
         content = BareControlValue,
@@ -477,15 +893,22 @@ struct RadarControlIdParam {
     responses(
         (status = 200, description = "Control value set successfully"),
         (status = 400, description = "Value out of range or invalid"),
-        (status = 404, description = "Radar or control not found")
+        (status = 404, description = "Radar or control not found"),
+        (status = 409, description = "Control is locked by another client")
     ),
     tag = "Controls"
 )]
 async fn set_control_value(
     Path(params): Path<RadarControlIdParam>,
     State(state): State<Web>,
+    Extension(identity): Extension<Identity>,
+    headers: hyper::header::HeaderMap,
     extract::Json(request): extract::Json<BareControlValue>,
 ) -> Response {
+    let client = match request_client(&identity, &headers) {
+        Ok(client) => client,
+        Err(e) => return e.into_response(),
+    };
     let (radar_id, control_id) = (params.radar_id, params.control_id);
     log::info!(
         "PUT control {} = {:?} for radar {}",
@@ -526,24 +949,15 @@ async fn set_control_value(
     let (reply_tx, mut reply_rx) = tokio::sync::mpsc::channel(1);
 
     // Check if this control should trigger persistence save
-    let needs_persistence = matches!(
-        control_value.id,
-        ControlId::GuardZone1
-            | ControlId::GuardZone2
-            | ControlId::ExclusionZone1
-            | ControlId::ExclusionZone2
-            | ControlId::ExclusionZone3
-            | ControlId::ExclusionZone4
-            | ControlId::ExclusionRect1
-            | ControlId::ExclusionRect2
-            | ControlId::ExclusionRect3
-            | ControlId::ExclusionRect4
-            | ControlId::UserName
-    );
+    let needs_persistence = control_value.id.needs_persistence();
 
     // Send the control request
-    if let Err(e) = controls.process_client_request(control_value, reply_tx) {
-        return (StatusCode::BAD_REQUEST, e.to_string()).into_response();
+    if let Err(e) = controls.process_client_request(control_value, client.as_deref(), reply_tx) {
+        let status = match e {
+            RadarError::ControlLocked(_, _) => StatusCode::CONFLICT,
+            _ => StatusCode::BAD_REQUEST,
+        };
+        return (status, e.to_string()).into_response();
     }
 
     // Save persistence for controls that need it
@@ -570,6 +984,348 @@ async fn set_control_value(
     StatusCode::OK.into_response()
 }
 
+#[utoipa::path(
+    put,
+    path = "/signalk/v2/api/vessels/self/radars/{radar_id}/controls",
+    summary = "Set several control values",
+    description = "Sets a list of controls as one operation. All values are checked against their \
+                   control definitions first; if any is invalid or locked, nothing is sent. The \
+                   values are then sent in the order given. With `rollback`, the controls that \
+                   were changed are set back to their previous values when the radar rejects one \
+                   of them. The response has the result for each control.",
+    params(
+        ("radar_id" = String, Path, description = "Radar identifier", example = "nav1034A"),
+        ("X-Mayara-Client" = Option<String>, Header, description = "Client name, needed to change controls that this client has locked", example = "helm")
+    ),
+    request_body = BatchRequest,
+    responses(
+        (status = 200, body = BatchReport, description = "Controls sent; see `success` for whether the radar accepted all of them"),
+        (status = 400, body = BatchReport, description = "A value is invalid or a control is given more than once, nothing was sent"),
+        (status = 404, description = "Radar not found"),
+        (status = 409, body = BatchReport, description = "Controls are locked by another client, nothing was sent")
+    ),
+    tag = "Controls"
+)]
+async fn set_control_values(
+    Path(radar_id): Path<String>,
+    State(state): State<Web>,
+    Extension(identity): Extension<Identity>,
+    headers: hyper::header::HeaderMap,
+    Json(request): Json<BatchRequest>,
+) -> Response {
+    let client = match request_client(&identity, &headers) {
+        Ok(client) => client,
+        Err(e) => return e.into_response(),
+    };
+    let Some(info) = state.radars.get_by_key(&radar_id) else {
+        return no_such_radar(&radar_id, &state.radars);
+    };
+    log::info!(
+        "PUT {} controls for radar {}",
+        request.controls.len(),
+        radar_id
+    );
+
+    match request.apply(&info.controls, client.as_deref()).await {
+        Ok(report) => {
+            // Guard zones, user name and the like are persisted
+            state.radars.save_persistence(&info.key());
+            Json(report).into_response()
+        }
+        Err((status, report)) => (status, Json(report)).into_response(),
+    }
+}
+
+// =============================================================================
+// Control Lock REST API Handlers
+// =============================================================================
+
+/// Request body to claim a control lock
+#[derive(Deserialize, Debug, ToSchema)]
+#[serde(rename_all = "camelCase")]
+struct ClaimLockRequest {
+    /// Name of the client, also sent in the X-Mayara-Client header and the `client`
+    /// parameter of the stream when setting controls. With authentication this
+    /// is the user or token name, and can be left out.
+    #[schema(example = "helm")]
+    client: Option<String>,
+    /// Category of controls to lock, all controls when absent
+    category: Option<Category>,
+    /// Lease in seconds, 300 by default, at most 3600
+    #[schema(example = 300)]
+    lease: Option<u64>,
+}
+
+/// Query parameters to release a control lock
+#[derive(Deserialize, Debug, ToSchema)]
+struct ReleaseLockQuery {
+    client: Option<String>,
+    category: Option<Category>,
+}
+
+/// The name under which a client holds locks, which it must have
+fn lock_holder(identity: &Identity, claimed: Option<&str>) -> Result<String, Response> {
+    match identity.client_name(claimed) {
+        Ok(Some(client)) => Ok(client),
+        Ok(None) => Err((
+            StatusCode::BAD_REQUEST,
+            "A client name is needed to hold a lock\n",
+        )
+            .into_response()),
+        Err(e) => Err(e.into_response()),
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/signalk/v2/api/vessels/self/radars/{radar_id}/locks",
+    summary = "Get control locks",
+    description = "Lists the locks that clients hold on the controls of the radar.",
+    params(
+        ("radar_id" = String, Path, description = "Radar identifier", example = "nav1034A")
+    ),
+    responses(
+        (status = 200, body = Vec<ControlLock>, description = "Current locks"),
+        (status = 404, description = "Radar not found")
+    ),
+    tag = "Controls"
+)]
+async fn get_locks(Path(radar_id): Path<String>, State(state): State<Web>) -> Response {
+    match state.radars.get_by_key(&radar_id) {
+        Some(info) => Json(info.controls.get_locks()).into_response(),
+        None => no_such_radar(&radar_id, &state.radars),
+    }
+}
+
+#[utoipa::path(
+    put,
+    path = "/signalk/v2/api/vessels/self/radars/{radar_id}/locks",
+    summary = "Claim a control lock",
+    description = "Claims all controls of the radar, or one category of them, for the client. \
+                   Until the lease runs out or the lock is released, changes from other clients \
+                   are rejected. Claim the lock again to renew it. Lock changes are sent to \
+                   stream clients as `radars.<id>.locks`.",
+    params(
+        ("radar_id" = String, Path, description = "Radar identifier", example = "nav1034A")
+    ),
+    request_body = ClaimLockRequest,
+    responses(
+        (status = 200, body = ControlLock, description = "Lock claimed or renewed"),
+        (status = 400, description = "No client name"),
+        (status = 403, description = "The client name is not the authenticated user or token"),
+        (status = 404, description = "Radar not found"),
+        (status = 409, description = "Another client holds an overlapping lock")
+    ),
+    tag = "Controls"
+)]
+async fn claim_lock(
+    Path(radar_id): Path<String>,
+    State(state): State<Web>,
+    Extension(identity): Extension<Identity>,
+    Json(request): Json<ClaimLockRequest>,
+) -> Response {
+    let Some(info) = state.radars.get_by_key(&radar_id) else {
+        return no_such_radar(&radar_id, &state.radars);
+    };
+    let client = match lock_holder(&identity, request.client.as_deref()) {
+        Ok(client) => client,
+        Err(response) => return response,
+    };
+    let lease = request
+        .lease
+        .map(std::time::Duration::from_secs)
+        .unwrap_or(DEFAULT_LEASE);
+    match info.controls.claim_lock(&client, request.category, lease) {
+        Ok(lock) => Json(lock).into_response(),
+        Err(e) => e.into_response(),
+    }
+}
+
+#[utoipa::path(
+    delete,
+    path = "/signalk/v2/api/vessels/self/radars/{radar_id}/locks",
+    summary = "Release a control lock",
+    params(
+        ("radar_id" = String, Path, description = "Radar identifier", example = "nav1034A"),
+        ("client" = Option<String>, Query, description = "Name of the client that holds the lock, the authenticated user or token when left out", example = "helm"),
+        ("category" = Option<Category>, Query, description = "Category of the lock, all controls when absent")
+    ),
+    responses(
+        (status = 200, description = "Lock released, or there was none"),
+        (status = 400, description = "No client name"),
+        (status = 403, description = "The client name is not the authenticated user or token"),
+        (status = 404, description = "Radar not found"),
+        (status = 409, description = "The lock is held by another client")
+    ),
+    tag = "Controls"
+)]
+async fn release_lock(
+    Path(radar_id): Path<String>,
+    Query(query): Query<ReleaseLockQuery>,
+    State(state): State<Web>,
+    Extension(identity): Extension<Identity>,
+) -> Response {
+    let Some(info) = state.radars.get_by_key(&radar_id) else {
+        return no_such_radar(&radar_id, &state.radars);
+    };
+    let client = match lock_holder(&identity, query.client.as_deref()) {
+        Ok(client) => client,
+        Err(response) => return response,
+    };
+    match info.controls.release_lock(&client, query.category) {
+        Ok(()) => StatusCode::OK.into_response(),
+        Err(e) => e.into_response(),
+    }
+}
+
+// =============================================================================
+// Control Preset REST API Handlers
+// =============================================================================
+
+#[derive(Deserialize)]
+struct RadarPresetParam {
+    radar_id: String,
+    name: String,
+}
+
+/// Request body to store a preset
+#[derive(Deserialize, Debug, Default, ToSchema)]
+#[serde(rename_all = "camelCase")]
+struct SavePresetRequest {
+    /// Control values by control id, like the body of a PUT on the control
+    #[serde(default)]
+    #[schema(example = json!({"gain": {"value": 40, "auto": false}, "rain": {"value": 0}}))]
+    controls: BTreeMap<String, BareControlValue>,
+    /// Controls to store with their current value
+    #[serde(default)]
+    #[schema(example = json!(["range", "sea"]))]
+    capture: Vec<String>,
+}
+
+#[utoipa::path(
+    get,
+    path = "/signalk/v2/api/vessels/self/radars/{radar_id}/presets",
+    summary = "Get control presets",
+    description = "Lists the named control presets of the radar.",
+    params(
+        ("radar_id" = String, Path, description = "Radar identifier", example = "nav1034A")
+    ),
+    responses(
+        (status = 200, body = BTreeMap<String, Preset>, description = "Presets by name"),
+        (status = 404, description = "Radar not found")
+    ),
+    tag = "Controls"
+)]
+async fn get_presets(Path(radar_id): Path<String>, State(state): State<Web>) -> Response {
+    match state.radars.get_by_key(&radar_id) {
+        Some(info) => Json(state.radars.get_presets(&info.key())).into_response(),
+        None => no_such_radar(&radar_id, &state.radars),
+    }
+}
+
+#[utoipa::path(
+    put,
+    path = "/signalk/v2/api/vessels/self/radars/{radar_id}/presets/{name}",
+    summary = "Store a control preset",
+    description = "Creates or replaces a named preset. The preset holds the values in `controls` \
+                   and the current values of the controls in `capture`. Values are stored in SI \
+                   units.",
+    params(
+        ("radar_id" = String, Path, description = "Radar identifier", example = "nav1034A"),
+        ("name" = String, Path, description = "Preset name", example = "harbour")
+    ),
+    request_body = SavePresetRequest,
+    responses(
+        (status = 200, body = Preset, description = "Preset stored"),
+        (status = 400, description = "Control cannot be set"),
+        (status = 404, description = "Radar or control not found")
+    ),
+    tag = "Controls"
+)]
+async fn set_preset(
+    Path(params): Path<RadarPresetParam>,
+    State(state): State<Web>,
+    Json(request): Json<SavePresetRequest>,
+) -> Response {
+    let Some(info) = state.radars.get_by_key(&params.radar_id) else {
+        return no_such_radar(&params.radar_id, &state.radars);
+    };
+    let mut preset = match Preset::capture(&info.controls, &request.capture) {
+        Ok(preset) => preset,
+        Err(e) => return e.into_response(),
+    };
+    preset.controls.extend(request.controls);
+    match state.radars.set_preset(&info.key(), &params.name, preset) {
+        Ok(preset) => Json(preset).into_response(),
+        Err(e) => e.into_response(),
+    }
+}
+
+#[utoipa::path(
+    delete,
+    path = "/signalk/v2/api/vessels/self/radars/{radar_id}/presets/{name}",
+    summary = "Delete a control preset",
+    params(
+        ("radar_id" = String, Path, description = "Radar identifier", example = "nav1034A"),
+        ("name" = String, Path, description = "Preset name", example = "harbour")
+    ),
+    responses(
+        (status = 200, description = "Preset deleted"),
+        (status = 404, description = "Radar or preset not found")
+    ),
+    tag = "Controls"
+)]
+async fn delete_preset(Path(params): Path<RadarPresetParam>, State(state): State<Web>) -> Response {
+    let Some(info) = state.radars.get_by_key(&params.radar_id) else {
+        return no_such_radar(&params.radar_id, &state.radars);
+    };
+    match state.radars.delete_preset(&info.key(), &params.name) {
+        Ok(()) => StatusCode::OK.into_response(),
+        Err(e) => e.into_response(),
+    }
+}
+
+#[utoipa::path(
+    post,
+    path = "/signalk/v2/api/vessels/self/radars/{radar_id}/presets/{name}/apply",
+    summary = "Apply a control preset",
+    description = "Sets the controls of the preset: the range first, then gain, sea and rain, \
+                   then the rest. Controls that fail do not stop the others; the response lists \
+                   which controls were applied and which failed, and why.",
+    params(
+        ("radar_id" = String, Path, description = "Radar identifier", example = "nav1034A"),
+        ("name" = String, Path, description = "Preset name", example = "harbour"),
+        ("X-Mayara-Client" = Option<String>, Header, description = "Client name, needed to change controls that this client has locked", example = "helm")
+    ),
+    responses(
+        (status = 200, body = PresetReport, description = "Preset applied, possibly in part"),
+        (status = 404, description = "Radar or preset not found")
+    ),
+    tag = "Controls"
+)]
+async fn apply_preset(
+    Path(params): Path<RadarPresetParam>,
+    State(state): State<Web>,
+    Extension(identity): Extension<Identity>,
+    headers: hyper::header::HeaderMap,
+) -> Response {
+    let client = match request_client(&identity, &headers) {
+        Ok(client) => client,
+        Err(e) => return e.into_response(),
+    };
+    let Some(info) = state.radars.get_by_key(&params.radar_id) else {
+        return no_such_radar(&params.radar_id, &state.radars);
+    };
+    match state
+        .radars
+        .apply_preset(&info.key(), &params.name, client.as_deref())
+        .await
+    {
+        Ok(report) => Json(report).into_response(),
+        Err(e) => e.into_response(),
+    }
+}
+
 // =============================================================================
 // Target Acquisition REST API Handler
 // =============================================================================
@@ -995,6 +1751,17 @@ struct SignalKWebSocket {
     /// Send cached control values on connect: 'true' (default) or 'false'
     #[schema(example = "true")]
     send_cached_values: Option<String>,
+    /// Name of the client, needed to change controls that this client has locked
+    #[schema(example = "helm")]
+    client: Option<String>,
+}
+
+/// Who is on the other end of a stream
+#[derive(Clone, Debug)]
+struct StreamClient {
+    role: Role,
+    /// Name for control locks
+    name: Option<String>,
 }
 
 /// Documentation endpoint for the WebSocket stream (not actually called)
@@ -1102,12 +1869,19 @@ async fn control_stream_docs() {}
 async fn control_stream_handler(
     State(state): State<Web>,
     Query(params): Query<SignalKWebSocket>,
+    Extension(role): Extension<Role>,
+    Extension(identity): Extension<Identity>,
     ws: WebSocketUpgrade,
 ) -> Response {
     log::debug!(
-        "stream request for \"/signalk/v1/stream\" params={:?}",
-        params
+        "stream request for \"/signalk/v1/stream\" params={:?} role={}",
+        params,
+        role
     );
+    let client = match identity.client_name(params.client.as_deref()) {
+        Ok(name) => StreamClient { role, name },
+        Err(e) => return e.into_response(),
+    };
 
     let subscribe = match params.subscribe.as_deref() {
         None | Some("self") | Some("all") => Subscribe::All,
@@ -1146,7 +1920,14 @@ async fn control_stream_handler(
     // finalize the upgrade process by returning upgrade callback.
     // we can customize the callback by sending additional info such as address.
     ws.on_upgrade(move |socket| {
-        ws_signalk_delta_shim(socket, subscribe, send_cached_values, radars, shutdown_tx)
+        ws_signalk_delta_shim(
+            socket,
+            subscribe,
+            send_cached_values,
+            client,
+            radars,
+            shutdown_tx,
+        )
     })
 }
 
@@ -1154,13 +1935,16 @@ async fn ws_signalk_delta_shim(
     mut socket: WebSocket,
     subscribe: Subscribe,
     send_cached_values: bool,
+    client: StreamClient,
     radars: SharedRadars,
     shutdown_tx: broadcast::Sender<()>,
 ) {
+    let _client = metrics::WebSocketClient::new(metrics::WebSocketKind::Stream);
     if let Err(e) = ws_signalk_delta(
         &mut socket,
         subscribe,
         send_cached_values,
+        client,
         radars,
         shutdown_tx,
     )
@@ -1179,6 +1963,7 @@ async fn ws_signalk_delta(
     mut socket: &mut WebSocket,
     subscribe: Subscribe,
     send_cached_values: bool,
+    client: StreamClient,
     radars: SharedRadars,
     shutdown_tx: broadcast::Sender<()>,
 ) -> Result<(), RadarError> {
@@ -1211,6 +1996,8 @@ async fn ws_signalk_delta(
             sk_delta.add_updates(rcvs);
         }
 
+        add_navigation_snapshot(&mut sk_delta, &mut subscriptions);
+
         // Note: ARPA target tracking not currently implemented
 
         // AIS vessels are NOT sent on initial connection.
@@ -1274,7 +2061,7 @@ async fn ws_signalk_delta(
                     Some(Ok(message)) => {
                         match message {
                             Message::Text(message) => {
-                                handle_client_request(&mut socket, message.as_str(), &mut subscriptions, &radars, reply_tx.clone()).await;
+                                handle_client_request(&mut socket, message.as_str(), &mut subscriptions, &radars, &client, reply_tx.clone()).await;
                             },
                             _ => {
                                 log::debug!("Dropping unexpected message {:?}", message);
@@ -1333,6 +2120,14 @@ enum StreamRequest {
     RadarControlValue(RadarControlValue),
     Subscription(Subscription),
     Desubscription(Desubscription),
+    ApplyPreset(ApplyPresetRequest),
+}
+
+/// Apply a control preset: `{"radar": "nav1034A", "preset": "harbour"}`
+#[derive(Deserialize, Debug)]
+struct ApplyPresetRequest {
+    radar: String,
+    preset: String,
 }
 
 //
@@ -1367,6 +2162,7 @@ async fn handle_client_request(
     message: &str,
     subscriptions: &mut ActiveSubscriptions,
     radars: &SharedRadars,
+    client: &StreamClient,
     reply_tx: mpsc::Sender<ControlValue>,
 ) {
     log::info!("Stream request: {}", message);
@@ -1383,8 +2179,17 @@ async fn handle_client_request(
             StreamRequest::Desubscription(desubscription) => {
                 subscriptions.desubscribe(desubscription)
             }
+            StreamRequest::RadarControlValue(_) if client.role < Role::Operator => {
+                Err(RadarError::Forbidden(client.role))
+            }
             StreamRequest::RadarControlValue(rcv) => {
-                handle_control_request(message, radars, reply_tx, rcv).await
+                handle_control_request(message, radars, client.name.as_deref(), reply_tx, rcv).await
+            }
+            StreamRequest::ApplyPreset(_) if client.role < Role::Operator => {
+                Err(RadarError::Forbidden(client.role))
+            }
+            StreamRequest::ApplyPreset(request) => {
+                handle_preset_request(socket, radars, client.name.as_deref(), request).await
             }
         };
         match r {
@@ -1395,7 +2200,7 @@ async fn handle_client_request(
                 log::debug!("stream error {}", str_message);
                 let ws_message = Message::Text(str_message.into());
 
-                let _ = socket.send(ws_message);
+                let _ = socket.send(ws_message).await;
             }
         }
     }
@@ -1404,23 +2209,20 @@ async fn handle_client_request(
 async fn handle_control_request(
     message: &str,
     radars: &SharedRadars,
+    client: Option<&str>,
     reply_tx: mpsc::Sender<ControlValue>,
     mut rcv: RadarControlValue,
 ) -> Result<(), RadarError> {
     if let Some(radar_id) = rcv.parse_path() {
         if let Some(radar) = radars.get_by_key(&radar_id) {
             let control_value: ControlValue = rcv.into();
-            let result = radar
-                .controls
-                .process_client_request(control_value.clone(), reply_tx);
+            let result =
+                radar
+                    .controls
+                    .process_client_request(control_value.clone(), client, reply_tx);
 
             // Save persistence for controls that need it
-            if result.is_ok()
-                && matches!(
-                    control_value.id,
-                    ControlId::GuardZone1 | ControlId::GuardZone2 | ControlId::UserName
-                )
-            {
+            if result.is_ok() && control_value.id.needs_persistence() {
                 radars.save_persistence(&radar.key());
             }
 
@@ -1439,6 +2241,31 @@ async fn handle_control_request(
     }
 }
 
+///
+/// Apply a preset and send the report to the client, as
+/// `{"radar": .., "preset": .., "applied": [..], "failed": {..}}`
+///
+async fn handle_preset_request(
+    socket: &mut WebSocket,
+    radars: &SharedRadars,
+    client: Option<&str>,
+    request: ApplyPresetRequest,
+) -> Result<(), RadarError> {
+    let report = radars
+        .apply_preset(&request.radar, &request.preset, client)
+        .await?;
+    let message = serde_json::json!({
+        "radar": request.radar,
+        "preset": request.preset,
+        "applied": report.applied,
+        "failed": report.failed,
+    });
+    socket
+        .send(Message::Text(message.to_string().into()))
+        .await?;
+    Ok(())
+}
+
 async fn handle_subscription(
     socket: &mut WebSocket,
     radars: &SharedRadars,
@@ -1447,6 +2274,7 @@ async fn handle_subscription(
 ) -> Result<(), RadarError> {
     let ais_subscribed = subscriptions.subscribe(subscription)?;
     send_all_subscribed(socket, radars, subscriptions).await?;
+    send_navigation_snapshot(socket, subscriptions).await?;
 
     // If AIS was just subscribed, send all known AIS vessels
     if ais_subscribed {
@@ -1479,6 +2307,30 @@ async fn send_all_subscribed(
     Ok(())
 }
 
+/// Add the valid own-ship navigation values that the client is subscribed to
+fn add_navigation_snapshot(sk_delta: &mut SignalKDelta, subscriptions: &mut ActiveSubscriptions) {
+    for (path, navigation) in navdata::get_navigation_state() {
+        if navigation.valid && subscriptions.is_subscribed_path(path, true) {
+            let source = navigation.source.as_deref().unwrap_or_default();
+            sk_delta.add_navigation_value(path, navigation.value, source);
+        }
+    }
+}
+
+/// Send the current own-ship navigation data, so the client does not have
+/// to wait for the next change
+async fn send_navigation_snapshot(
+    socket: &mut WebSocket,
+    subscriptions: &mut ActiveSubscriptions,
+) -> Result<(), RadarError> {
+    let mut sk_delta = SignalKDelta::new();
+    add_navigation_snapshot(&mut sk_delta, subscriptions);
+    if let Some(delta) = sk_delta.build() {
+        send_message(socket, delta).await?;
+    }
+    Ok(())
+}
+
 /// Send all known AIS vessels to the client
 async fn send_all_ais_vessels(socket: &mut WebSocket) -> Result<(), RadarError> {
     if let Some(ais_store) = navdata::get_ais_store() {
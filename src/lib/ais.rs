@@ -2,9 +2,11 @@
 //!
 //! This module maintains a store of AIS vessels received from Signal K,
 //! accumulating data over time and broadcasting updates to WebSocket clients.
+//! AIS messages decoded from NMEA 0183 `!AIVDM` sentences are converted to
+//! Signal K values first, so they take the same route into the store.
 
 use serde::Serialize;
-use serde_json::Value;
+use serde_json::{Value, json};
 use std::collections::HashMap;
 use std::sync::{Arc, RwLock};
 use std::time::{Duration, Instant};
@@ -221,6 +223,21 @@ impl AisVessel {
     }
 }
 
+/// Signal K context for the vessel with the given MMSI, in the form that
+/// `AisVesselStore::update` expects.
+pub fn mmsi_context(mmsi: u32) -> String {
+    format!("vessels.urn:mrn:imo:mmsi:{:09}", mmsi)
+}
+
+/// Wrap (path, value) pairs in a Signal K `updates` array for `AisVesselStore::update`
+pub fn signalk_updates(values: Vec<(&str, Value)>) -> Value {
+    let values: Vec<Value> = values
+        .into_iter()
+        .map(|(path, value)| json!({ "path": path, "value": value }))
+        .collect();
+    json!([{ "values": values }])
+}
+
 /// Delay before broadcasting vessel updates to coalesce rapid updates
 const BROADCAST_DELAY: Duration = Duration::from_millis(100);
 
@@ -444,6 +461,49 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_mmsi_context() {
+        assert_eq!(
+            mmsi_context(227334400),
+            "vessels.urn:mrn:imo:mmsi:227334400"
+        );
+        // Coast stations have leading zeros, which must survive the round trip
+        assert_eq!(mmsi_context(2442000), "vessels.urn:mrn:imo:mmsi:002442000");
+        assert_eq!(
+            AisVesselStore::extract_mmsi(&mmsi_context(2442000)),
+            Some("002442000".to_string())
+        );
+    }
+
+    #[test]
+    fn test_store_update_from_signalk_updates() {
+        let (tx, _rx) = broadcast::channel(16);
+        let store = AisVesselStore::new(tx);
+
+        let updates = signalk_updates(vec![
+            (
+                "navigation.position",
+                json!({ "latitude": 52.5, "longitude": 4.25 }),
+            ),
+            ("navigation.speedOverGround", json!(5.0)),
+            ("", json!({ "name": "VESSEL" })),
+        ]);
+        assert!(store.update(&mmsi_context(244060807), &updates));
+
+        let vessels = store.get_all_active();
+        assert_eq!(vessels.len(), 1);
+        assert_eq!(vessels[0].mmsi, "244060807");
+        assert_eq!(vessels[0].name, Some("VESSEL".to_string()));
+        assert_eq!(vessels[0].sog, Some(5.0));
+        assert_eq!(
+            vessels[0].position,
+            Some(Position {
+                latitude: 52.5,
+                longitude: 4.25
+            })
+        );
+    }
+
     #[test]
     fn test_dimensions_has_any() {
         let empty = Dimensions::default();
@@ -0,0 +1,243 @@
+//! Alarms raised by tracked targets.
+//!
+//! Watches the target updates that the `TrackerManager` sends to Signal K
+//! clients and raises two kinds of alarms, which the outputs send on in their
+//! own format:
+//! - `navigation.closestApproach.<urn>` when a target comes closer than
+//!   `CPA_ALARM_DISTANCE` within `CPA_ALARM_TIME`;
+//! - `radar.<radar>.guardZone<n>` while targets acquired by guard zone `n`
+//!   are being tracked.
+
+use std::collections::{HashMap, HashSet};
+
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::radar::NAUTICAL_MILE_F64;
+use crate::stream::opt_f64;
+
+/// A target that comes closer than this (in meters) raises a CPA alarm...
+const CPA_ALARM_DISTANCE: f64 = 0.5 * NAUTICAL_MILE_F64;
+/// ... when it gets there within this many seconds
+const CPA_ALARM_TIME: f64 = 12. * 60.;
+
+#[derive(Serialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub(crate) enum AlarmState {
+    Normal,
+    Alarm,
+}
+
+impl AlarmState {
+    pub(crate) fn as_str(&self) -> &'static str {
+        match self {
+            AlarmState::Normal => "normal",
+            AlarmState::Alarm => "alarm",
+        }
+    }
+}
+
+///
+/// A change of an alarm. The path is the Signal K notification path without
+/// the leading `notifications.`.
+///
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub(crate) struct Alarm {
+    pub path: String,
+    pub state: AlarmState,
+    pub message: String,
+}
+
+/// The URN we use for target `target_id` of radar `radar_id`
+pub(crate) fn target_urn(radar_id: &str, target_id: u64) -> String {
+    format!("urn:mrn:radar:{}:{}", radar_id, target_id)
+}
+
+///
+/// Keeps the state needed to raise each alarm once and clear it again.
+///
+#[derive(Default)]
+pub(crate) struct TargetAlarms {
+    /// Targets that have a CPA alarm raised, by (radar, target id)
+    cpa_alarms: HashSet<(String, u64)>,
+    /// Targets acquired by a guard zone that are still tracked, by (radar, zone)
+    zone_targets: HashMap<(String, u8), HashSet<u64>>,
+}
+
+impl TargetAlarms {
+    pub(crate) fn new() -> Self {
+        Self::default()
+    }
+
+    /// Return the alarm changes caused by one target update; `Null` means deleted
+    pub(crate) fn process_target(
+        &mut self,
+        radar_id: &str,
+        target_id: u64,
+        value: &Value,
+    ) -> Vec<Alarm> {
+        let mut alarms = Vec::new();
+        self.cpa_alarm(radar_id, target_id, value, &mut alarms);
+        self.guard_zone_alarm(radar_id, target_id, value, &mut alarms);
+        alarms
+    }
+
+    fn cpa_alarm(
+        &mut self,
+        radar_id: &str,
+        target_id: u64,
+        value: &Value,
+        alarms: &mut Vec<Alarm>,
+    ) {
+        let key = (radar_id.to_string(), target_id);
+        let path = format!(
+            "navigation.closestApproach.{}",
+            target_urn(radar_id, target_id)
+        );
+
+        // Danger is omitted entirely when the vessels are diverging
+        let cpa = opt_f64(value, "danger", "cpa");
+        let tcpa = opt_f64(value, "danger", "tcpa");
+        let tracking = value.get("status").and_then(Value::as_str) == Some("tracking");
+        match (cpa, tcpa) {
+            (Some(cpa), Some(tcpa))
+                if tracking
+                    && cpa < CPA_ALARM_DISTANCE
+                    && (0. ..=CPA_ALARM_TIME).contains(&tcpa) =>
+            {
+                // Repeat while the alarm is active, so the message shows the latest CPA
+                self.cpa_alarms.insert(key);
+                alarms.push(Alarm {
+                    path,
+                    state: AlarmState::Alarm,
+                    message: format!(
+                        "Radar {} target {}: CPA {:.2} NM in {:.1} min",
+                        radar_id,
+                        target_id,
+                        cpa / NAUTICAL_MILE_F64,
+                        tcpa / 60.
+                    ),
+                });
+            }
+            _ => {
+                if self.cpa_alarms.remove(&key) {
+                    alarms.push(Alarm {
+                        path,
+                        state: AlarmState::Normal,
+                        message: format!("Radar {} target {}: no danger", radar_id, target_id),
+                    });
+                }
+            }
+        }
+    }
+
+    fn guard_zone_alarm(
+        &mut self,
+        radar_id: &str,
+        target_id: u64,
+        value: &Value,
+        alarms: &mut Vec<Alarm>,
+    ) {
+        let active = value.get("status").and_then(Value::as_str) != Some("lost");
+
+        // A deleted or lost target is removed from whichever zone it was in
+        if value.is_null() || !active {
+            for ((radar, zone), targets) in self.zone_targets.iter_mut() {
+                if radar == radar_id && targets.remove(&target_id) && targets.is_empty() {
+                    alarms.push(Alarm {
+                        path: guard_zone_path(radar_id, *zone),
+                        state: AlarmState::Normal,
+                        message: format!("Radar {} guard zone {}: clear", radar_id, zone),
+                    });
+                }
+            }
+            self.zone_targets.retain(|_, targets| !targets.is_empty());
+            return;
+        }
+
+        let Some(zone) = value
+            .get("sourceZone")
+            .and_then(Value::as_u64)
+            .filter(|z| *z > 0)
+            .map(|z| z as u8)
+        else {
+            return;
+        };
+        let targets = self
+            .zone_targets
+            .entry((radar_id.to_string(), zone))
+            .or_default();
+        if targets.is_empty() {
+            alarms.push(Alarm {
+                path: guard_zone_path(radar_id, zone),
+                state: AlarmState::Alarm,
+                message: format!(
+                    "Radar {} guard zone {}: target {}",
+                    radar_id, zone, target_id
+                ),
+            });
+        }
+        targets.insert(target_id);
+    }
+}
+
+fn guard_zone_path(radar_id: &str, zone: u8) -> String {
+    format!("radar.{}.guardZone{}", radar_id, zone)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stream::test_target as target;
+
+    #[test]
+    fn test_cpa_alarm() {
+        let mut alarms = TargetAlarms::new();
+        let path = "navigation.closestApproach.urn:mrn:radar:nav1:7";
+
+        // Far away: nothing to report
+        let a = alarms.process_target("nav1", 7, &target("tracking", 2000., 600., 0));
+        assert!(a.is_empty());
+
+        let a = alarms.process_target("nav1", 7, &target("tracking", 300., 600., 0));
+        assert_eq!(a.len(), 1);
+        assert_eq!(a[0].path, path);
+        assert_eq!(a[0].state, AlarmState::Alarm);
+
+        // Passing more than 12 minutes from now clears the alarm
+        let a = alarms.process_target("nav1", 7, &target("tracking", 300., 1200., 0));
+        assert_eq!(a[0].state, AlarmState::Normal);
+
+        // As does deleting the target
+        alarms.process_target("nav1", 7, &target("tracking", 300., 60., 0));
+        let a = alarms.process_target("nav1", 7, &Value::Null);
+        assert_eq!(a[0].state, AlarmState::Normal);
+        assert!(alarms.cpa_alarms.is_empty());
+    }
+
+    #[test]
+    fn test_guard_zone_alarm() {
+        let mut alarms = TargetAlarms::new();
+        let path = "radar.nav1.guardZone1";
+
+        let a = alarms.process_target("nav1", 7, &target("acquiring", 2000., 600., 1));
+        assert_eq!(a.len(), 1);
+        assert_eq!(a[0].path, path);
+        assert_eq!(a[0].state, AlarmState::Alarm);
+
+        // A second target in the same zone does not raise it again
+        let a = alarms.process_target("nav1", 8, &target("acquiring", 2000., 600., 1));
+        assert!(a.is_empty());
+
+        // The alarm clears once neither target is tracked anymore
+        let a = alarms.process_target("nav1", 7, &target("lost", 2000., 600., 1));
+        assert!(a.is_empty());
+        let a = alarms.process_target("nav1", 8, &Value::Null);
+        assert_eq!(a[0].path, path);
+        assert_eq!(a[0].state, AlarmState::Normal);
+
+        // Manually acquired targets have zone 0
+        let a = alarms.process_target("nav1", 9, &target("tracking", 2000., 600., 0));
+        assert!(a.is_empty());
+    }
+}
@@ -0,0 +1,255 @@
+//! ASTERIX CAT010 monosensor surface movement target reports.
+//!
+//! Tracked targets come from the target updates that the `TrackerManager`
+//! sends to Signal K clients. We keep the latest state of each target and
+//! send all of them once per antenna revolution, each as a primary radar
+//! target report with track number, position, velocity and track status.
+//! A target that has been deleted is sent one last time with the TRE
+//! (track end) bit set.
+
+use std::collections::BTreeMap;
+use std::f64::consts::TAU;
+
+use chrono::DateTime;
+use serde_json::Value;
+
+use super::{Record, data_blocks, time_of_day};
+use crate::radar::NAUTICAL_MILE_F64;
+
+pub(crate) const CATEGORY: u8 = 10;
+
+/// I010/000 message type: target report
+const MESSAGE_TYPE_TARGET_REPORT: u8 = 1;
+
+/// Field reference numbers in the CAT010 UAP
+const FRN_DATA_SOURCE: usize = 1; // I010/010
+const FRN_MESSAGE_TYPE: usize = 2; // I010/000
+const FRN_DESCRIPTOR: usize = 3; // I010/020
+const FRN_TIME_OF_DAY: usize = 4; // I010/140
+const FRN_POSITION_WGS84: usize = 5; // I010/041
+const FRN_POSITION_POLAR: usize = 6; // I010/040
+const FRN_VELOCITY_POLAR: usize = 8; // I010/200
+const FRN_TRACK_NUMBER: usize = 10; // I010/161
+const FRN_TRACK_STATUS: usize = 11; // I010/170
+
+/// I010/020 TYP = 011: primary surveillance radar
+const DESCRIPTOR_PSR: u8 = 0b0110_0000;
+
+/// I010/170 track status bits
+const STATUS_IN_INITIATION: u8 = 0x80; // CNF
+const STATUS_TRACK_END: u8 = 0x40; // TRE
+const STATUS_COASTING: u8 = 0x30; // CST = 11, no detection
+const STATUS_SMOOTHED: u8 = 0x02; // STH
+
+/// I010/161 track numbers are 12 bits
+const TRACK_NUMBER_MASK: u64 = 0x0fff;
+
+/// Keep data blocks within a single unfragmented UDP datagram
+const MAX_BLOCK_LEN: usize = 1400;
+
+struct TrackState {
+    value: Value,
+    ended: bool,
+}
+
+///
+/// The targets of one radar, sent as CAT010 data blocks.
+///
+pub(crate) struct TrackReports {
+    sac: u8,
+    sic: u8,
+    tracks: BTreeMap<u64, TrackState>,
+}
+
+impl TrackReports {
+    pub(crate) fn new(sac: u8, sic: u8) -> Self {
+        TrackReports {
+            sac,
+            sic,
+            tracks: BTreeMap::new(),
+        }
+    }
+
+    /// Process a target update; a `Null` value means the target was deleted
+    pub(crate) fn update(&mut self, target_id: u64, value: &Value) {
+        if value.is_null() {
+            if let Some(track) = self.tracks.get_mut(&target_id) {
+                track.ended = true;
+            }
+        } else {
+            self.tracks.insert(
+                target_id,
+                TrackState {
+                    value: value.clone(),
+                    ended: false,
+                },
+            );
+        }
+    }
+
+    ///
+    /// Encode all tracks, to be called once per revolution. Tracks that
+    /// ended are sent for the last time and then forgotten.
+    ///
+    pub(crate) fn encode_revolution(&mut self, now: u64) -> Vec<Vec<u8>> {
+        let records: Vec<Vec<u8>> = self
+            .tracks
+            .iter()
+            .map(|(id, track)| self.target_record(*id, &track.value, track.ended, now))
+            .collect();
+        self.tracks.retain(|_, track| !track.ended);
+        data_blocks(CATEGORY, records, MAX_BLOCK_LEN)
+    }
+
+    fn target_record(&self, target_id: u64, value: &Value, ended: bool, now: u64) -> Vec<u8> {
+        let time = value
+            .get("lastSeen")
+            .and_then(Value::as_str)
+            .and_then(|s| DateTime::parse_from_rfc3339(s).ok())
+            .map(|t| t.timestamp_millis() as u64)
+            .unwrap_or(now);
+
+        let mut record = Record::new();
+        record.add(FRN_DATA_SOURCE, &[self.sac, self.sic]);
+        record.add(FRN_MESSAGE_TYPE, &[MESSAGE_TYPE_TARGET_REPORT]);
+        record.add(FRN_DESCRIPTOR, &[DESCRIPTOR_PSR]);
+        record.add(FRN_TIME_OF_DAY, &time_of_day(time));
+
+        let position = value.get("position");
+        let field = |object: Option<&Value>, name: &str| object?.get(name)?.as_f64();
+        if let (Some(lat), Some(lon)) = (field(position, "latitude"), field(position, "longitude"))
+        {
+            let mut wgs84 = Vec::with_capacity(8);
+            wgs84.extend_from_slice(&wgs84_angle(lat).to_be_bytes());
+            wgs84.extend_from_slice(&wgs84_angle(lon).to_be_bytes());
+            record.add(FRN_POSITION_WGS84, &wgs84);
+        }
+        if let (Some(bearing), Some(distance)) =
+            (field(position, "bearing"), field(position, "distance"))
+        {
+            let mut polar = Vec::with_capacity(4);
+            polar.extend_from_slice(&(distance.clamp(0., u16::MAX as f64) as u16).to_be_bytes());
+            polar.extend_from_slice(&azimuth(bearing).to_be_bytes());
+            record.add(FRN_POSITION_POLAR, &polar);
+        }
+
+        let motion = value.get("motion");
+        if let (Some(speed), Some(course)) = (field(motion, "speed"), field(motion, "course")) {
+            // Ground speed in 2^-14 NM/s
+            let speed = speed / NAUTICAL_MILE_F64 * 16384.;
+            let mut velocity = Vec::with_capacity(4);
+            velocity.extend_from_slice(&(speed.clamp(0., u16::MAX as f64) as u16).to_be_bytes());
+            velocity.extend_from_slice(&azimuth(course).to_be_bytes());
+            record.add(FRN_VELOCITY_POLAR, &velocity);
+        }
+
+        record.add(
+            FRN_TRACK_NUMBER,
+            &((target_id & TRACK_NUMBER_MASK) as u16).to_be_bytes(),
+        );
+
+        let mut status = STATUS_SMOOTHED;
+        match value.get("status").and_then(Value::as_str) {
+            Some("tracking") => {}
+            Some("lost") => status |= STATUS_COASTING,
+            _ => status |= STATUS_IN_INITIATION,
+        }
+        if ended {
+            status |= STATUS_TRACK_END;
+        }
+        record.add(FRN_TRACK_STATUS, &[status]);
+
+        record.to_bytes()
+    }
+}
+
+/// Degrees to WGS-84 coordinate units of 180/2^31 degrees
+fn wgs84_angle(degrees: f64) -> i32 {
+    (degrees * (1u64 << 31) as f64 / 180.)
+        .round()
+        .clamp(i32::MIN as f64, i32::MAX as f64) as i32
+}
+
+/// Radians to azimuth units of 360/2^16 degrees
+fn azimuth(radians: f64) -> u16 {
+    (radians.rem_euclid(TAU) / TAU * 65536.) as u32 as u16
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn target(status: &str) -> Value {
+        json!({
+            "id": 7,
+            "status": status,
+            "position": {
+                "bearing": std::f64::consts::FRAC_PI_2,
+                "distance": 1852,
+                "latitude": 52.5,
+                "longitude": -4.25
+            },
+            "motion": { "course": std::f64::consts::PI, "speed": 5.144 },
+            "acquisition": "auto",
+            "firstSeen": "1970-01-01T00:59:00Z",
+            "lastSeen": "1970-01-01T01:00:00.5Z"
+        })
+    }
+
+    #[test]
+    fn test_target_record() {
+        let mut reports = TrackReports::new(25, 3);
+        reports.update(4103, &target("tracking"));
+        let blocks = reports.encode_revolution(0);
+        assert_eq!(blocks.len(), 1);
+        let block = &blocks[0];
+
+        assert_eq!(&block[0..3], &[CATEGORY, 0, block.len() as u8]);
+        // 010, 000, 020, 140, 041, 040; 200, 161, 170
+        assert_eq!(&block[3..5], &[0b1111_1101, 0b1011_0000]);
+        assert_eq!(
+            &block[5..9],
+            &[25, 3, MESSAGE_TYPE_TARGET_REPORT, DESCRIPTOR_PSR]
+        );
+        assert_eq!(&block[9..12], &time_of_day(3_600_500));
+        assert_eq!(&block[12..16], &626_349_397i32.to_be_bytes());
+        assert_eq!(&block[16..20], &(-50_704_475i32).to_be_bytes());
+        // 1852 m at 90 degrees
+        assert_eq!(&block[20..24], &[0x07, 0x3c, 0x40, 0x00]);
+        // 10 knots is 1/360 NM/s = 45.5 * 2^-14 NM/s, at 180 degrees
+        assert_eq!(&block[24..28], &[0, 45, 0x80, 0x00]);
+        // Track number 4103 wraps to 7
+        assert_eq!(&block[28..30], &[0, 7]);
+        assert_eq!(block[30], STATUS_SMOOTHED);
+        assert_eq!(block.len(), 31);
+    }
+
+    #[test]
+    fn test_track_lifecycle() {
+        let mut reports = TrackReports::new(0, 1);
+        reports.update(1, &target("acquiring"));
+        reports.update(2, &target("lost"));
+        let blocks = reports.encode_revolution(0);
+        assert_eq!(blocks.len(), 1);
+        assert_eq!(blocks[0].len(), 3 + 2 * 28);
+        assert_eq!(blocks[0][3 + 27], STATUS_SMOOTHED | STATUS_IN_INITIATION);
+        assert_eq!(blocks[0][3 + 28 + 27], STATUS_SMOOTHED | STATUS_COASTING);
+
+        // A deleted track is sent once more with the track end bit
+        reports.update(2, &Value::Null);
+        let blocks = reports.encode_revolution(0);
+        assert_eq!(
+            blocks[0][3 + 28 + 27],
+            STATUS_SMOOTHED | STATUS_COASTING | STATUS_TRACK_END
+        );
+        let blocks = reports.encode_revolution(0);
+        assert_eq!(blocks[0].len(), 3 + 28);
+
+        // Deleting an unknown track does nothing
+        reports.update(3, &Value::Null);
+        reports.update(1, &Value::Null);
+        assert_eq!(reports.encode_revolution(0).len(), 1);
+        assert!(reports.encode_revolution(0).is_empty());
+    }
+}
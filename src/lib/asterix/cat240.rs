@@ -0,0 +1,574 @@
+//! ASTERIX CAT240 radar video transmission.
+//!
+//! Each spoke becomes one video message with the azimuth sector it covers,
+//! the cell size and the cells themselves, packed at 1, 2, 4 or 8 bits per
+//! cell. At the start of every revolution a video summary message is sent
+//! that tells receivers what is on this channel.
+//!
+//! Video messages from other sources are decoded by `decode_block`.
+
+use super::{Record, data_block, parse_fspec, time_of_day};
+use crate::protos::RadarMessage::radar_message::Spoke;
+use crate::radar::Legend;
+
+pub(crate) const CATEGORY: u8 = 240;
+
+/// I240/000 message types
+const MESSAGE_TYPE_SUMMARY: u8 = 1;
+const MESSAGE_TYPE_VIDEO: u8 = 2;
+
+/// Field reference numbers in the CAT240 UAP
+const FRN_DATA_SOURCE: usize = 1; // I240/010
+const FRN_MESSAGE_TYPE: usize = 2; // I240/000
+const FRN_RECORD_HEADER: usize = 3; // I240/020
+const FRN_SUMMARY: usize = 4; // I240/030
+const FRN_HEADER_NANO: usize = 5; // I240/040
+const FRN_HEADER_FEMTO: usize = 6; // I240/041
+const FRN_RESOLUTION: usize = 7; // I240/048
+const FRN_COUNTERS: usize = 8; // I240/049
+const FRN_BLOCK_LOW: usize = 9; // I240/050
+const FRN_BLOCK_MEDIUM: usize = 10; // I240/051
+const FRN_BLOCK_HIGH: usize = 11; // I240/052
+const FRN_TIME_OF_DAY: usize = 12; // I240/140
+const FRN_RESERVED_EXPANSION: usize = 13; // RE
+const FRN_SPECIAL_PURPOSE: usize = 14; // SP
+
+/// Video block sizes of I240/050, I240/051 and I240/052, in octets
+const BLOCK_LOW: usize = 4;
+const BLOCK_MEDIUM: usize = 64;
+const BLOCK_HIGH: usize = 256;
+
+/// Speed of light in m/s, cell durations are two-way travel times
+const SPEED_OF_LIGHT: f64 = 299_792_458.;
+
+/// I240/048 compression indicator
+const RESOLUTION_COMPRESSED: u8 = 0x80;
+
+///
+/// Encodes the spokes of one radar as CAT240 data blocks.
+///
+pub(crate) struct VideoEncoder {
+    sac: u8,
+    sic: u8,
+    bits: u8,
+    spokes_per_revolution: u32,
+    /// Number of intensity values of a normal return, `Legend::pixel_colors`
+    pixel_colors: u8,
+    /// Pixel value ranges that are Doppler returns, sent at full intensity
+    doppler: Vec<(u8, u8)>,
+    summary: Vec<u8>,
+    msg_index: u32,
+    last_angle: Option<u32>,
+}
+
+impl VideoEncoder {
+    ///
+    /// Create an encoder. When `bits` is not given, the smallest resolution
+    /// that holds all intensities of the radar is used.
+    ///
+    pub(crate) fn new(
+        sac: u8,
+        sic: u8,
+        bits: Option<u8>,
+        spokes_per_revolution: u16,
+        legend: &Legend,
+        summary: &str,
+    ) -> Self {
+        let bits = bits.unwrap_or(match legend.pixel_colors {
+            0..=2 => 1,
+            3..=4 => 2,
+            5..=16 => 4,
+            _ => 8,
+        });
+        let mut summary: Vec<u8> = summary.bytes().filter(u8::is_ascii).collect();
+        summary.truncate(u8::MAX as usize);
+
+        VideoEncoder {
+            sac,
+            sic,
+            bits,
+            spokes_per_revolution: spokes_per_revolution.max(1) as u32,
+            pixel_colors: legend.pixel_colors,
+            doppler: [legend.doppler_approaching, legend.doppler_receding]
+                .into_iter()
+                .flatten()
+                .collect(),
+            summary,
+            msg_index: 0,
+            last_angle: None,
+        }
+    }
+
+    ///
+    /// Encode a spoke. Returns the data blocks to send, which includes a
+    /// video summary when the spoke starts a new revolution.
+    ///
+    pub(crate) fn encode_spoke(&mut self, spoke: &Spoke, now: u64) -> Vec<Vec<u8>> {
+        let mut blocks = Vec::with_capacity(2);
+        let time = spoke.time.unwrap_or(now);
+
+        if self.last_angle.is_none_or(|last| spoke.angle < last) {
+            blocks.push(data_block(CATEGORY, &[self.summary_record(time)]));
+        }
+        self.last_angle = Some(spoke.angle);
+
+        if !spoke.data.is_empty() {
+            blocks.push(data_block(CATEGORY, &[self.video_record(spoke, time)]));
+        }
+        blocks
+    }
+
+    fn summary_record(&self, time: u64) -> Vec<u8> {
+        let mut summary = Vec::with_capacity(self.summary.len() + 1);
+        summary.push(self.summary.len() as u8);
+        summary.extend_from_slice(&self.summary);
+
+        let mut record = Record::new();
+        record.add(FRN_DATA_SOURCE, &[self.sac, self.sic]);
+        record.add(FRN_MESSAGE_TYPE, &[MESSAGE_TYPE_SUMMARY]);
+        record.add(FRN_SUMMARY, &summary);
+        record.add(FRN_TIME_OF_DAY, &time_of_day(time));
+        record.to_bytes()
+    }
+
+    fn video_record(&mut self, spoke: &Spoke, time: u64) -> Vec<u8> {
+        self.msg_index = self.msg_index.wrapping_add(1);
+
+        // Prefer the north-up bearing, if we know our heading
+        let index = spoke.bearing.unwrap_or(spoke.angle) % self.spokes_per_revolution;
+        let start_az = self.azimuth(index);
+        let end_az = self.azimuth(index + 1);
+
+        let cell_size = spoke.range as f64 / spoke.data.len() as f64;
+        let cell_duration = 2. * cell_size / SPEED_OF_LIGHT;
+        let femto = cell_duration * 1e15;
+        let (frn_header, cell_duration) = if femto <= u32::MAX as f64 {
+            (FRN_HEADER_FEMTO, femto.round() as u32)
+        } else {
+            (FRN_HEADER_NANO, (cell_duration * 1e9).round() as u32)
+        };
+        let mut header = Vec::with_capacity(12);
+        header.extend_from_slice(&start_az.to_be_bytes());
+        header.extend_from_slice(&end_az.to_be_bytes());
+        header.extend_from_slice(&0u32.to_be_bytes()); // START_RG, in cells
+        header.extend_from_slice(&cell_duration.to_be_bytes());
+
+        let mut video = self.pack_cells(&spoke.data);
+        let octets = video.len();
+        let (frn_block, block_size) = [
+            (FRN_BLOCK_LOW, BLOCK_LOW),
+            (FRN_BLOCK_MEDIUM, BLOCK_MEDIUM),
+            (FRN_BLOCK_HIGH, BLOCK_HIGH),
+        ]
+        .into_iter()
+        .find(|(_, size)| octets.div_ceil(*size) <= u8::MAX as usize)
+        .unwrap_or((FRN_BLOCK_HIGH, BLOCK_HIGH));
+        let rep = octets.div_ceil(block_size).min(u8::MAX as usize);
+        video.resize(rep * block_size, 0);
+        let cells = (spoke.data.len() as u32).min(0x00ff_ffff);
+
+        let mut counters = Vec::with_capacity(5);
+        counters.extend_from_slice(&(octets.min(u16::MAX as usize) as u16).to_be_bytes());
+        counters.extend_from_slice(&cells.to_be_bytes()[1..]);
+
+        let mut block = Vec::with_capacity(video.len() + 1);
+        block.push(rep as u8);
+        block.extend_from_slice(&video);
+
+        let mut record = Record::new();
+        record.add(FRN_DATA_SOURCE, &[self.sac, self.sic]);
+        record.add(FRN_MESSAGE_TYPE, &[MESSAGE_TYPE_VIDEO]);
+        record.add(FRN_RECORD_HEADER, &self.msg_index.to_be_bytes());
+        record.add(frn_header, &header);
+        record.add(FRN_RESOLUTION, &[0, self.resolution()]);
+        record.add(FRN_COUNTERS, &counters);
+        record.add(frn_block, &block);
+        record.add(FRN_TIME_OF_DAY, &time_of_day(time));
+        record.to_bytes()
+    }
+
+    /// Spoke index to azimuth in units of 360/2^16 degrees
+    fn azimuth(&self, index: u32) -> u16 {
+        (index as u64 * 65536 / self.spokes_per_revolution as u64) as u16
+    }
+
+    /// I240/048 RES: 1 = 1 bit, 2 = 2 bits, 3 = 4 bits, 4 = 8 bits per cell
+    fn resolution(&self) -> u8 {
+        self.bits.trailing_zeros() as u8 + 1
+    }
+
+    ///
+    /// Scale each pixel value to the video resolution and pack the cells,
+    /// first cell in the most significant bits. Doppler returns are sent at
+    /// full intensity, history trails and other overlays are left out.
+    ///
+    fn pack_cells(&self, data: &[u8]) -> Vec<u8> {
+        let max = (1u16 << self.bits) - 1;
+        let top = self.pixel_colors.saturating_sub(1).max(1) as u16;
+        let per_octet = 8 / self.bits as usize;
+        let mut packed = vec![0u8; data.len().div_ceil(per_octet)];
+
+        for (i, &v) in data.iter().enumerate() {
+            let cell = if v < self.pixel_colors {
+                ((v as u16 * max + top / 2) / top).min(max)
+            } else if self
+                .doppler
+                .iter()
+                .any(|&(first, count)| v >= first && v - first < count)
+            {
+                max
+            } else {
+                0
+            };
+            let shift = 8 - self.bits as usize * (i % per_octet + 1);
+            packed[i / per_octet] |= (cell as u8) << shift;
+        }
+        packed
+    }
+}
+
+///
+/// A received CAT240 record. Video records that were cut off, as happens
+/// to packets seen by the locator, have all header fields but no `video`.
+///
+#[derive(Clone, Debug, Default, PartialEq)]
+pub(crate) struct VideoMessage {
+    pub(crate) sac: u8,
+    pub(crate) sic: u8,
+    pub(crate) message_type: u8,
+    /// Video summary text, from I240/030
+    pub(crate) summary: Option<String>,
+    /// Azimuth sector in units of 360/2^16 degrees
+    pub(crate) start_az: u16,
+    pub(crate) end_az: u16,
+    /// Index of the first cell
+    pub(crate) start_rg: u32,
+    /// Cell size in meters, zero when there is no video header
+    pub(crate) cell_size: f64,
+    /// Bits per cell, zero when there is no I240/048
+    pub(crate) bits: u8,
+    pub(crate) compressed: bool,
+    /// Number of valid cells, from I240/049
+    pub(crate) cells: u32,
+    /// The packed video octets
+    pub(crate) video: Option<Vec<u8>>,
+}
+
+impl VideoMessage {
+    /// Is this a video message with a usable header?
+    pub(crate) fn is_video(&self) -> bool {
+        self.message_type == MESSAGE_TYPE_VIDEO && self.cell_size > 0. && self.bits > 0
+    }
+
+    ///
+    /// Unpack the cells, first cell from the most significant bits. Cells
+    /// of more than 8 bits are cut to their 8 most significant bits.
+    /// Returns `None` when there is no video or it is compressed.
+    ///
+    pub(crate) fn unpack_cells(&self) -> Option<Vec<u8>> {
+        let video = self.video.as_ref()?;
+        if self.compressed || self.bits == 0 {
+            return None;
+        }
+        let bits = self.bits as usize;
+        let available = video.len() * 8 / bits;
+        let cells = (self.cells as usize).min(available);
+
+        let cells = if bits >= 8 {
+            let octets = bits / 8;
+            (0..cells).map(|i| video[i * octets]).collect()
+        } else {
+            let per_octet = 8 / bits;
+            let mask = (1u8 << bits) - 1;
+            (0..cells)
+                .map(|i| {
+                    let shift = 8 - bits * (i % per_octet + 1);
+                    (video[i / per_octet] >> shift) & mask
+                })
+                .collect()
+        };
+        Some(cells)
+    }
+}
+
+///
+/// Decode the records of a CAT240 data block. Decoding stops at the first
+/// record that cannot be decoded; a record that is cut off is still
+/// returned with the data items that were complete.
+///
+pub(crate) fn decode_block(data: &[u8]) -> Vec<VideoMessage> {
+    let mut messages = Vec::new();
+    if data.len() < 3 || data[0] != CATEGORY {
+        return messages;
+    }
+    let len = (u16::from_be_bytes([data[1], data[2]]) as usize).min(data.len());
+    let mut pos = 3;
+
+    while pos < len {
+        let data = &data[..len];
+        let Some((frns, fspec_len)) = parse_fspec(&data[pos..]) else {
+            break;
+        };
+        pos += fspec_len;
+
+        let mut message = VideoMessage::default();
+        let mut complete = true;
+        for frn in frns {
+            let item_len = match frn {
+                FRN_DATA_SOURCE => 2,
+                FRN_MESSAGE_TYPE => 1,
+                FRN_RECORD_HEADER => 4,
+                FRN_SUMMARY => 1 + *data.get(pos).unwrap_or(&0) as usize,
+                FRN_HEADER_NANO | FRN_HEADER_FEMTO => 12,
+                FRN_RESOLUTION => 2,
+                FRN_COUNTERS => 5,
+                FRN_BLOCK_LOW => 1 + *data.get(pos).unwrap_or(&0) as usize * BLOCK_LOW,
+                FRN_BLOCK_MEDIUM => 1 + *data.get(pos).unwrap_or(&0) as usize * BLOCK_MEDIUM,
+                FRN_BLOCK_HIGH => 1 + *data.get(pos).unwrap_or(&0) as usize * BLOCK_HIGH,
+                FRN_TIME_OF_DAY => 3,
+                FRN_RESERVED_EXPANSION | FRN_SPECIAL_PURPOSE => {
+                    (*data.get(pos).unwrap_or(&0) as usize).max(1)
+                }
+                _ => {
+                    // Not in the UAP, so we cannot know where the next item starts
+                    complete = false;
+                    break;
+                }
+            };
+            let Some(item) = data.get(pos..pos + item_len) else {
+                complete = false;
+                break;
+            };
+            pos += item_len;
+
+            match frn {
+                FRN_DATA_SOURCE => {
+                    message.sac = item[0];
+                    message.sic = item[1];
+                }
+                FRN_MESSAGE_TYPE => message.message_type = item[0],
+                FRN_SUMMARY => {
+                    message.summary = Some(String::from_utf8_lossy(&item[1..]).into_owned())
+                }
+                FRN_HEADER_NANO | FRN_HEADER_FEMTO => {
+                    message.start_az = u16::from_be_bytes([item[0], item[1]]);
+                    message.end_az = u16::from_be_bytes([item[2], item[3]]);
+                    message.start_rg = u32::from_be_bytes([item[4], item[5], item[6], item[7]]);
+                    let duration = u32::from_be_bytes([item[8], item[9], item[10], item[11]]);
+                    let unit = if frn == FRN_HEADER_NANO { 1e-9 } else { 1e-15 };
+                    message.cell_size = duration as f64 * unit * SPEED_OF_LIGHT / 2.;
+                }
+                FRN_RESOLUTION => {
+                    message.compressed = item[0] & RESOLUTION_COMPRESSED != 0;
+                    // RES 1 = 1 bit .. 6 = 32 bits per cell
+                    message.bits = match item[1] {
+                        res @ 1..=6 => 1 << (res - 1),
+                        _ => 0,
+                    };
+                }
+                FRN_COUNTERS => message.cells = u32::from_be_bytes([0, item[2], item[3], item[4]]),
+                FRN_BLOCK_LOW | FRN_BLOCK_MEDIUM | FRN_BLOCK_HIGH => {
+                    message.video = Some(item[1..].to_vec())
+                }
+                _ => {}
+            }
+        }
+        messages.push(message);
+        if !complete {
+            break;
+        }
+    }
+    messages
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn legend(pixel_colors: u8) -> Legend {
+        Legend {
+            pixels: Vec::new(),
+            pixel_colors,
+            history_start: pixel_colors + 3,
+            doppler_approaching: Some((pixel_colors + 1, 1)),
+            doppler_receding: Some((pixel_colors + 2, 1)),
+            strong_return: 0,
+            medium_return: 0,
+            low_return: 0,
+            static_background: Some(pixel_colors),
+        }
+    }
+
+    fn spoke(angle: u32, bearing: Option<u32>, range: u32, data: Vec<u8>) -> Spoke {
+        let mut spoke = Spoke::new();
+        spoke.angle = angle;
+        spoke.bearing = bearing;
+        spoke.range = range;
+        spoke.time = Some(3600 * 1000);
+        spoke.data = data;
+        spoke
+    }
+
+    #[test]
+    fn test_default_resolution() {
+        assert_eq!(VideoEncoder::new(0, 1, None, 2048, &legend(16), "").bits, 4);
+        assert_eq!(VideoEncoder::new(0, 1, None, 2048, &legend(64), "").bits, 8);
+        assert_eq!(VideoEncoder::new(0, 1, None, 2048, &legend(2), "").bits, 1);
+        assert_eq!(
+            VideoEncoder::new(0, 1, Some(2), 2048, &legend(64), "").bits,
+            2
+        );
+    }
+
+    #[test]
+    fn test_pack_cells() {
+        let encoder = VideoEncoder::new(0, 1, Some(2), 2048, &legend(16), "");
+        // 0 and 15 map to 0 and 3, 16 is static background, 17 and 18 Doppler
+        assert_eq!(
+            encoder.pack_cells(&[0, 15, 5, 10, 16, 17, 18]),
+            vec![0b0011_0110, 0b0011_1100]
+        );
+
+        let encoder = VideoEncoder::new(0, 1, Some(8), 2048, &legend(16), "");
+        assert_eq!(encoder.pack_cells(&[0, 1, 15]), vec![0, 17, 255]);
+
+        let encoder = VideoEncoder::new(0, 1, Some(1), 2048, &legend(16), "");
+        assert_eq!(
+            encoder.pack_cells(&[0, 15, 0, 15, 1, 14, 0, 0, 15]),
+            vec![0b0101_0100, 0b1000_0000]
+        );
+    }
+
+    #[test]
+    fn test_encode_spoke() {
+        let mut encoder = VideoEncoder::new(25, 3, Some(4), 2048, &legend(16), "mayara");
+
+        let blocks = encoder.encode_spoke(&spoke(512, None, 5996, vec![15; 4000]), 0);
+        assert_eq!(blocks.len(), 2);
+
+        let summary = &blocks[0];
+        assert_eq!(
+            summary,
+            &[
+                vec![
+                    CATEGORY,
+                    0,
+                    18,
+                    0b1101_0001,
+                    0b0000_1000,
+                    25,
+                    3,
+                    MESSAGE_TYPE_SUMMARY,
+                    6
+                ],
+                b"mayara".to_vec(),
+                time_of_day(3600 * 1000).to_vec()
+            ]
+            .concat()
+        );
+
+        let video = &blocks[1];
+        assert_eq!(video[0], CATEGORY);
+        assert_eq!(
+            u16::from_be_bytes([video[1], video[2]]) as usize,
+            video.len()
+        );
+        // 010, 000, 020, 041, 048, 049, 051 and 140
+        assert_eq!(&video[3..5], &[0b1110_0111, 0b1010_1000]);
+        assert_eq!(&video[5..8], &[25, 3, MESSAGE_TYPE_VIDEO]);
+        assert_eq!(&video[8..12], &1u32.to_be_bytes());
+        // Azimuth 90 degrees to 90 + 360/2048 degrees
+        assert_eq!(&video[12..14], &16384u16.to_be_bytes());
+        assert_eq!(&video[14..16], &16416u16.to_be_bytes());
+        assert_eq!(&video[16..20], &0u32.to_be_bytes());
+        // 1.499 m cells take 10 ns
+        let femto = u32::from_be_bytes(video[20..24].try_into().unwrap());
+        assert!((femto as i64 - 10_000_000).abs() < 1_000, "{femto}");
+        assert_eq!(&video[24..26], &[0, 3]);
+        // 2000 valid octets, 4000 cells in 32 blocks of 64 octets
+        assert_eq!(&video[26..31], &[0x07, 0xd0, 0x00, 0x0f, 0xa0]);
+        assert_eq!(video[31], 32);
+        assert_eq!(video[32], 0xff);
+        assert_eq!(video[32 + 1999], 0xff);
+        assert_eq!(video[32 + 2000], 0);
+        assert_eq!(video.len(), 32 + 32 * 64 + 3);
+
+        // No summary until the next revolution
+        let blocks = encoder.encode_spoke(&spoke(1000, Some(100), 1499, vec![15; 1000]), 0);
+        assert_eq!(blocks.len(), 1);
+        assert_eq!(&blocks[0][8..12], &2u32.to_be_bytes());
+        assert_eq!(&blocks[0][12..14], &3200u16.to_be_bytes());
+        let blocks = encoder.encode_spoke(&spoke(0, None, 1499, vec![15; 10]), 0);
+        assert_eq!(blocks.len(), 2);
+        assert_eq!(blocks[0][7], MESSAGE_TYPE_SUMMARY);
+        // Small spokes use the low data volume block
+        assert_eq!(blocks[1][4], 0b1100_1000);
+        assert_eq!(blocks[1][31], 2);
+    }
+
+    #[test]
+    fn test_decode_block() {
+        let mut encoder = VideoEncoder::new(25, 3, Some(4), 2048, &legend(16), "mayara");
+        let data: Vec<u8> = (0..1000).map(|i| (i % 16) as u8).collect();
+        let blocks = encoder.encode_spoke(&spoke(512, None, 1499, data.clone()), 0);
+
+        let summary = decode_block(&blocks[0]);
+        assert_eq!(summary.len(), 1);
+        assert_eq!(summary[0].sac, 25);
+        assert_eq!(summary[0].sic, 3);
+        assert_eq!(summary[0].summary.as_deref(), Some("mayara"));
+        assert!(!summary[0].is_video());
+
+        let video = decode_block(&blocks[1]);
+        assert_eq!(video.len(), 1);
+        let video = &video[0];
+        assert!(video.is_video());
+        assert_eq!((video.start_az, video.end_az), (16384, 16416));
+        assert_eq!(video.start_rg, 0);
+        assert!((video.cell_size - 1.499).abs() < 0.001);
+        assert_eq!(video.bits, 4);
+        assert_eq!(video.cells, 1000);
+        assert_eq!(video.unpack_cells(), Some(data));
+
+        // A record that is cut off still has its header
+        let cut = decode_block(&blocks[1][..40]);
+        assert_eq!(cut.len(), 1);
+        assert!(cut[0].is_video());
+        assert_eq!(cut[0].cells, 1000);
+        assert_eq!(cut[0].video, None);
+        assert_eq!(cut[0].unpack_cells(), None);
+
+        // Two records in one block
+        let mut two = blocks[0].clone();
+        two.extend_from_slice(&blocks[1][3..]);
+        let len = two.len() as u16;
+        two[1..3].copy_from_slice(&len.to_be_bytes());
+        assert_eq!(decode_block(&two).len(), 2);
+
+        assert!(decode_block(&[10, 0, 3]).is_empty());
+    }
+
+    #[test]
+    fn test_unpack_cells() {
+        let mut message = VideoMessage {
+            bits: 2,
+            cells: 7,
+            video: Some(vec![0b0011_0110, 0b0011_1100]),
+            ..Default::default()
+        };
+        assert_eq!(message.unpack_cells(), Some(vec![0, 3, 1, 2, 0, 3, 3]));
+
+        // 16 bit cells are cut to their most significant octet
+        message.bits = 16;
+        message.cells = 2;
+        message.video = Some(vec![0x12, 0x34, 0xab, 0xcd]);
+        assert_eq!(message.unpack_cells(), Some(vec![0x12, 0xab]));
+
+        // Never more cells than there is video for
+        message.cells = 3;
+        assert_eq!(message.unpack_cells(), Some(vec![0x12, 0xab]));
+
+        message.compressed = true;
+        assert_eq!(message.unpack_cells(), None);
+    }
+}
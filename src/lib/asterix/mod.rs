@@ -0,0 +1,254 @@
+//! EUROCONTROL ASTERIX surveillance data exchange.
+//!
+//! ASTERIX data is sent as data blocks: a category octet, a two octet block
+//! length, and one or more records. Each record starts with a field
+//! specification (FSPEC) that tells which of the data items of the category's
+//! user application profile (UAP) are present, followed by those items in
+//! UAP order.
+//!
+//! We send radar video as CAT240, see `cat240`, and tracked targets as
+//! CAT010, see `cat010`. CAT240 video from other sources can also be
+//! received, the `asterix` radar brand turns it into spokes.
+
+use std::net::{Ipv4Addr, SocketAddrV4};
+
+pub mod cat010;
+pub mod cat240;
+pub mod output;
+
+/// ASTERIX time of day has a resolution of 1/128 s
+const TIME_OF_DAY_RESOLUTION: u64 = 128;
+
+///
+/// A record being built. Data items must be added in increasing field
+/// reference number (FRN) order.
+///
+pub(crate) struct Record {
+    fspec: Vec<u8>,
+    items: Vec<u8>,
+}
+
+impl Record {
+    pub(crate) fn new() -> Self {
+        Record {
+            fspec: Vec::new(),
+            items: Vec::new(),
+        }
+    }
+
+    /// Add the data item with field reference number `frn` (starting at 1)
+    pub(crate) fn add(&mut self, frn: usize, data: &[u8]) {
+        let (octet, bit) = fspec_position(frn);
+        if self.fspec.len() <= octet {
+            self.fspec.resize(octet + 1, 0);
+        }
+        self.fspec[octet] |= bit;
+        self.items.extend_from_slice(data);
+    }
+
+    pub(crate) fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = self.fspec.clone();
+        // Set the field extension (FX) bit on all but the last FSPEC octet
+        let last = bytes.len().saturating_sub(1);
+        for octet in &mut bytes[..last] {
+            *octet |= 0x01;
+        }
+        bytes.extend_from_slice(&self.items);
+        bytes
+    }
+}
+
+/// The FSPEC octet and bit for a field reference number, seven per octet
+fn fspec_position(frn: usize) -> (usize, u8) {
+    assert!(frn > 0, "FRN starts at 1");
+    let octet = (frn - 1) / 7;
+    let bit = 0x80 >> ((frn - 1) % 7);
+    (octet, bit)
+}
+
+///
+/// Parse the FSPEC at the start of a record. Returns the field reference
+/// numbers of the data items that are present, and the length of the FSPEC.
+///
+pub(crate) fn parse_fspec(data: &[u8]) -> Option<(Vec<usize>, usize)> {
+    let mut frns = Vec::new();
+    for (octet, &bits) in data.iter().enumerate() {
+        for i in 0..7 {
+            if bits & (0x80 >> i) != 0 {
+                frns.push(octet * 7 + i + 1);
+            }
+        }
+        if bits & 0x01 == 0 {
+            return Some((frns, octet + 1));
+        }
+    }
+    None
+}
+
+/// Wrap records in a data block of `category`
+pub(crate) fn data_block(category: u8, records: &[Vec<u8>]) -> Vec<u8> {
+    let len = 3 + records.iter().map(|r| r.len()).sum::<usize>();
+    let mut block = Vec::with_capacity(len);
+    block.push(category);
+    block.extend_from_slice(&(len as u16).to_be_bytes());
+    for record in records {
+        block.extend_from_slice(record);
+    }
+    block
+}
+
+///
+/// Wrap records in as many data blocks of `category` as needed to keep each
+/// block within `max_len` octets. A record that is too big on its own gets
+/// a block of its own.
+///
+pub(crate) fn data_blocks(category: u8, records: Vec<Vec<u8>>, max_len: usize) -> Vec<Vec<u8>> {
+    let mut blocks = Vec::new();
+    let mut batch: Vec<Vec<u8>> = Vec::new();
+    let mut len = 3;
+    for record in records {
+        if !batch.is_empty() && len + record.len() > max_len {
+            blocks.push(data_block(category, &batch));
+            batch.clear();
+            len = 3;
+        }
+        len += record.len();
+        batch.push(record);
+    }
+    if !batch.is_empty() {
+        blocks.push(data_block(category, &batch));
+    }
+    blocks
+}
+
+/// The time of day of `time` (millis since epoch) in 1/128 s since midnight UTC
+pub(crate) fn time_of_day(time: u64) -> [u8; 3] {
+    let millis_of_day = time % (24 * 3600 * 1000);
+    let tod = (millis_of_day * TIME_OF_DAY_RESOLUTION / 1000) as u32;
+    let bytes = tod.to_be_bytes();
+    [bytes[1], bytes[2], bytes[3]]
+}
+
+///
+/// Where and how to send ASTERIX data for one radar, from a command line
+/// value `<radar>=<address>:<port>[,sac=<n>][,sic=<n>][,nic=<address>][,bits=<n>]`.
+///
+#[derive(Clone, Debug, PartialEq)]
+pub struct AsterixOutputSpec {
+    /// Key of the radar, as shown in the GUI and the REST API
+    pub radar: String,
+    /// Multicast group (or unicast address) and port to send to
+    pub addr: SocketAddrV4,
+    /// System Area Code
+    pub sac: u8,
+    /// System Identification Code
+    pub sic: u8,
+    /// Address of the network interface to send from, default the one the radar is on
+    pub nic: Option<Ipv4Addr>,
+    /// Bits per video cell (1, 2, 4 or 8), default from the radar's pixel values
+    pub bits: Option<u8>,
+}
+
+impl AsterixOutputSpec {
+    ///
+    /// Parse an output specification. `index` is the position of the value on
+    /// the command line, the default SIC is `index + 1` so that each radar
+    /// gets its own SIC.
+    ///
+    pub fn parse(s: &str, index: usize) -> Result<AsterixOutputSpec, String> {
+        let invalid = || {
+            format!(
+                "'{}' is not of the form <radar>=<address>:<port>[,sac=<n>][,sic=<n>][,nic=<address>][,bits=<n>]",
+                s
+            )
+        };
+        let (radar, rest) = s.split_once('=').ok_or_else(invalid)?;
+        let mut parts = rest.split(',');
+        let addr = parts
+            .next()
+            .and_then(|a| a.parse().ok())
+            .ok_or_else(invalid)?;
+        let mut spec = AsterixOutputSpec {
+            radar: radar.to_string(),
+            addr,
+            sac: 0,
+            sic: (index + 1).min(u8::MAX as usize) as u8,
+            nic: None,
+            bits: None,
+        };
+        for option in parts {
+            let (key, value) = option.split_once('=').ok_or_else(invalid)?;
+            let bad_value = || format!("Invalid {} '{}' in '{}'", key, value, s);
+            match key {
+                "sac" => spec.sac = value.parse().map_err(|_| bad_value())?,
+                "sic" => spec.sic = value.parse().map_err(|_| bad_value())?,
+                "nic" => spec.nic = Some(value.parse().map_err(|_| bad_value())?),
+                "bits" => match value.parse() {
+                    Ok(bits @ (1 | 2 | 4 | 8)) => spec.bits = Some(bits),
+                    _ => return Err(bad_value()),
+                },
+                _ => return Err(format!("Unknown option '{}' in '{}'", key, s)),
+            }
+        }
+        Ok(spec)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fspec() {
+        let mut record = Record::new();
+        record.add(1, &[0xaa]);
+        record.add(7, &[0xbb]);
+        record.add(9, &[0xcc, 0xdd]);
+        assert_eq!(
+            record.to_bytes(),
+            vec![0b1000_0011, 0b0100_0000, 0xaa, 0xbb, 0xcc, 0xdd]
+        );
+        assert_eq!(parse_fspec(&record.to_bytes()), Some((vec![1, 7, 9], 2)));
+        // FX set on the last octet
+        assert_eq!(parse_fspec(&[0b1000_0011]), None);
+    }
+
+    #[test]
+    fn test_data_block() {
+        let block = data_block(240, &[vec![1, 2], vec![3]]);
+        assert_eq!(block, vec![240, 0, 6, 1, 2, 3]);
+
+        let blocks = data_blocks(10, vec![vec![1, 2], vec![3], vec![4, 5, 6, 7]], 6);
+        assert_eq!(
+            blocks,
+            vec![vec![10, 0, 6, 1, 2, 3], vec![10, 0, 7, 4, 5, 6, 7]]
+        );
+        assert!(data_blocks(10, Vec::new(), 6).is_empty());
+    }
+
+    #[test]
+    fn test_time_of_day() {
+        // 1970-01-02 01:00:00.5
+        let time = (25 * 3600 * 1000) + 500;
+        // 3600.5 * 128 = 460864
+        assert_eq!(time_of_day(time), [0x07, 0x08, 0x40]);
+    }
+
+    #[test]
+    fn test_parse_spec() {
+        assert_eq!(
+            AsterixOutputSpec::parse("nav1034A=239.0.0.1:4000,sac=25,bits=4", 1),
+            Ok(AsterixOutputSpec {
+                radar: "nav1034A".to_string(),
+                addr: "239.0.0.1:4000".parse().unwrap(),
+                sac: 25,
+                sic: 2,
+                nic: None,
+                bits: Some(4),
+            })
+        );
+        assert!(AsterixOutputSpec::parse("nav1034A=239.0.0.1", 0).is_err());
+        assert!(AsterixOutputSpec::parse("nav1034A=239.0.0.1:4000,bits=3", 0).is_err());
+        assert!(AsterixOutputSpec::parse("nav1034A=239.0.0.1:4000,ttl=3", 0).is_err());
+    }
+}
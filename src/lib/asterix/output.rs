@@ -0,0 +1,338 @@
+//! Sending ASTERIX data to the network.
+//!
+//! Radars can show up at any time, so each output waits for the radar named
+//! in its specification and starts sending as soon as it is active. When the
+//! radar goes away or sending fails, the output waits a while and starts
+//! again. Video is sent as CAT240 and tracked targets as CAT010.
+
+use protobuf::Message;
+use tokio::sync::broadcast;
+use tokio_graceful_shutdown::{SubsystemBuilder, SubsystemHandle};
+
+use super::AsterixOutputSpec;
+use super::cat010::TrackReports;
+use super::cat240::VideoEncoder;
+use crate::network::create_multicast_send;
+use crate::protos::RadarMessage::RadarMessage;
+use crate::radar::{RadarError, RadarInfo, SharedRadars};
+use crate::stream::SignalKDelta;
+use crate::util::{Connection, RADAR_POLL_INTERVAL, now_millis, reconnect};
+
+enum Output {
+    /// Spokes as CAT240 video
+    Video(AsterixOutputSpec),
+    /// Tracked targets as CAT010 target reports
+    Tracks(AsterixOutputSpec),
+}
+
+///
+/// The subsystem that sends the spokes and targets of the selected radars.
+///
+pub struct AsterixOutput {
+    outputs: Vec<Output>,
+    radars: SharedRadars,
+}
+
+impl AsterixOutput {
+    pub fn new(
+        video: Vec<AsterixOutputSpec>,
+        tracks: Vec<AsterixOutputSpec>,
+        radars: SharedRadars,
+    ) -> Self {
+        let outputs = video
+            .into_iter()
+            .map(Output::Video)
+            .chain(tracks.into_iter().map(Output::Tracks))
+            .collect();
+        AsterixOutput { outputs, radars }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.outputs.is_empty()
+    }
+
+    pub async fn run(self, subsys: SubsystemHandle) -> Result<(), RadarError> {
+        for output in self.outputs {
+            let name = match &output {
+                Output::Video(spec) => format!("ASTERIX video {}", spec.addr),
+                Output::Tracks(spec) => format!("ASTERIX tracks {}", spec.addr),
+            };
+            let radars = self.radars.clone();
+            subsys.start(SubsystemBuilder::new(name, move |s| {
+                run_output(s, output, radars)
+            }));
+        }
+        subsys.on_shutdown_requested().await;
+        log::debug!("ASTERIX output shutdown requested");
+        Ok(())
+    }
+}
+
+///
+/// Keep one output running: wait for its radar, send until the radar goes
+/// away or sending fails, and start again after a while.
+///
+async fn run_output(
+    subsys: SubsystemHandle,
+    output: Output,
+    radars: SharedRadars,
+) -> Result<(), RadarError> {
+    reconnect(&subsys, OutputConnection { output, radars }).await;
+    Ok(())
+}
+
+struct OutputConnection {
+    output: Output,
+    radars: SharedRadars,
+}
+
+impl Connection for OutputConnection {
+    type Stream = RadarInfo;
+
+    /// Wait until the radar of the output is active
+    async fn connect(&self) -> Result<RadarInfo, RadarError> {
+        let (Output::Video(spec) | Output::Tracks(spec)) = &self.output;
+        let mut interval = tokio::time::interval(RADAR_POLL_INTERVAL);
+        loop {
+            interval.tick().await;
+            if let Some(info) = self
+                .radars
+                .get_active()
+                .into_iter()
+                .find(|i| i.key() == spec.radar)
+            {
+                return Ok(info);
+            }
+        }
+    }
+
+    async fn serve(&mut self, subsys: &SubsystemHandle, info: RadarInfo) -> Result<(), RadarError> {
+        let send = async {
+            match &self.output {
+                Output::Video(spec) => send_video(spec.clone(), info, self.radars.clone()).await,
+                Output::Tracks(spec) => {
+                    let sk_client_rx = self.radars.new_sk_client_subscription();
+                    send_tracks(spec.clone(), info, self.radars.clone(), sk_client_rx).await
+                }
+            }
+        };
+        let r = tokio::select! { biased;
+            _ = subsys.on_shutdown_requested() => {
+                return Err(RadarError::Shutdown);
+            },
+            r = send => r,
+        };
+        let (Output::Video(spec) | Output::Tracks(spec)) = &self.output;
+        match r {
+            Ok(()) => log::info!("{}: ASTERIX output to {} stopped", spec.radar, spec.addr),
+            Err(e) => log::warn!(
+                "{}: ASTERIX output to {} failed: {}",
+                spec.radar,
+                spec.addr,
+                e
+            ),
+        }
+        Ok(())
+    }
+}
+
+/// Whether radar `key` is still active, the outputs stop when it is not
+fn is_active(radars: &SharedRadars, key: &str) -> bool {
+    radars.get_active().iter().any(|i| i.key() == key)
+}
+
+async fn send_video(
+    spec: AsterixOutputSpec,
+    info: RadarInfo,
+    radars: SharedRadars,
+) -> Result<(), RadarError> {
+    let nic_addr = spec.nic.unwrap_or(info.nic_addr);
+    let socket = create_multicast_send(&spec.addr, &nic_addr)?;
+    log::info!(
+        "{}: sending ASTERIX CAT240 video to {} via {} (SAC {} SIC {})",
+        info.key(),
+        spec.addr,
+        nic_addr,
+        spec.sac,
+        spec.sic
+    );
+
+    let summary = format!("MAYARA {}", info.controls.user_name());
+    let mut encoder = VideoEncoder::new(
+        spec.sac,
+        spec.sic,
+        spec.bits,
+        info.spokes_per_revolution,
+        &info.get_legend(),
+        &summary,
+    );
+    let key = info.key();
+    let mut rx = info.message_tx.subscribe();
+    let mut interval = tokio::time::interval(RADAR_POLL_INTERVAL);
+
+    loop {
+        let r = tokio::select! { biased;
+            _ = interval.tick() => {
+                if !is_active(&radars, &key) {
+                    break;
+                }
+                continue;
+            },
+            r = rx.recv() => r,
+        };
+        match r {
+            Ok(bytes) => {
+                let message = match RadarMessage::parse_from_bytes(&bytes) {
+                    Ok(message) => message,
+                    Err(e) => {
+                        log::warn!("{}: cannot decode radar message: {}", key, e);
+                        continue;
+                    }
+                };
+                let now = now_millis();
+                for spoke in &message.spokes {
+                    for block in encoder.encode_spoke(spoke, now) {
+                        socket.send(&block).await?;
+                    }
+                }
+            }
+            Err(broadcast::error::RecvError::Lagged(n)) => {
+                log::warn!(
+                    "{}: ASTERIX video output lagged, skipped {} messages",
+                    key,
+                    n
+                );
+            }
+            Err(broadcast::error::RecvError::Closed) => {
+                break;
+            }
+        }
+    }
+    Ok(())
+}
+
+async fn send_tracks(
+    spec: AsterixOutputSpec,
+    info: RadarInfo,
+    radars: SharedRadars,
+    mut sk_client_rx: broadcast::Receiver<SignalKDelta>,
+) -> Result<(), RadarError> {
+    let nic_addr = spec.nic.unwrap_or(info.nic_addr);
+    let socket = create_multicast_send(&spec.addr, &nic_addr)?;
+    log::info!(
+        "{}: sending ASTERIX CAT010 targets to {} via {} (SAC {} SIC {})",
+        info.key(),
+        spec.addr,
+        nic_addr,
+        spec.sac,
+        spec.sic
+    );
+
+    let key = info.key();
+    let mut reports = TrackReports::new(spec.sac, spec.sic);
+    let mut rx = info.message_tx.subscribe();
+    let mut last_angle: Option<u32> = None;
+    let mut interval = tokio::time::interval(RADAR_POLL_INTERVAL);
+
+    loop {
+        tokio::select! { biased;
+            _ = interval.tick() => {
+                if !is_active(&radars, &key) {
+                    break;
+                }
+            },
+            r = sk_client_rx.recv() => {
+                match r {
+                    Ok(delta) => {
+                        for (radar_id, target_id, value) in delta.target_updates() {
+                            if radar_id == key {
+                                reports.update(target_id, value);
+                            }
+                        }
+                    }
+                    Err(broadcast::error::RecvError::Lagged(n)) => {
+                        log::warn!("{}: ASTERIX target output lagged, skipped {} target updates", key, n);
+                    }
+                    Err(broadcast::error::RecvError::Closed) => {
+                        break;
+                    }
+                }
+            },
+            r = rx.recv() => {
+                match r {
+                    Ok(bytes) => {
+                        // Only the spoke angles are needed, to find the start of a revolution
+                        let Ok(message) = RadarMessage::parse_from_bytes(&bytes) else {
+                            continue;
+                        };
+                        let mut revolution = false;
+                        for spoke in &message.spokes {
+                            revolution |= last_angle.is_some_and(|last| spoke.angle < last);
+                            last_angle = Some(spoke.angle);
+                        }
+                        if !revolution {
+                            continue;
+                        }
+                        for block in reports.encode_revolution(now_millis()) {
+                            socket.send(&block).await?;
+                        }
+                    }
+                    Err(broadcast::error::RecvError::Lagged(_)) => {}
+                    Err(broadcast::error::RecvError::Closed) => {
+                        break;
+                    }
+                }
+            },
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use clap::Parser;
+    use std::collections::HashMap;
+    use std::net::{Ipv4Addr, SocketAddrV4};
+
+    use super::*;
+    use crate::radar::range::Ranges;
+    use crate::radar::settings::SharedControls;
+    use crate::{Brand, Cli};
+
+    #[tokio::test]
+    async fn test_output_stops_when_radar_removed() {
+        let args = Cli::parse_from(["mayara-server"]);
+        let radars = SharedRadars::new();
+        let addr = SocketAddrV4::new(Ipv4Addr::LOCALHOST, 0);
+        let mut info = RadarInfo::new(
+            &radars,
+            &args,
+            Brand::Emulator,
+            Some("TEST9876"),
+            None,
+            16,
+            2048,
+            1024,
+            addr,
+            Ipv4Addr::LOCALHOST,
+            addr,
+            addr,
+            addr,
+            |id, tx| SharedControls::new(id, tx, &args, HashMap::new()),
+            false,
+            false,
+        );
+        info.set_ranges(Ranges::new_by_distance(&[500, 1000]));
+        let info = radars.add(info).unwrap();
+        let key = info.key();
+
+        let spec = AsterixOutputSpec::parse(&format!("{}=127.0.0.1:14240", key), 0).unwrap();
+        let output = tokio::spawn(send_video(spec, info, radars.clone()));
+        tokio::time::sleep(RADAR_POLL_INTERVAL * 2).await;
+        assert!(!output.is_finished());
+
+        radars.remove(&key);
+        let r = tokio::time::timeout(RADAR_POLL_INTERVAL * 3, output).await;
+        assert!(matches!(r, Ok(Ok(Ok(())))));
+    }
+}
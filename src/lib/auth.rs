@@ -0,0 +1,407 @@
+//! Optional authentication for the REST and WebSocket APIs.
+//!
+//! Authentication is enabled by creating `auth.json` in the config directory.
+//! Clients then identify themselves with a fixed API token from that file, or
+//! with a session token that they get by logging in with a user name and
+//! password. Each token has a role: a viewer can look at everything, an
+//! operator can also change the radar, the targets and the recordings.
+//!
+//! Passwords are not stored, only their Argon2 hash, which
+//! `mayara-server --hash-password` prints.
+//!
+//! ```json
+//! {
+//!   "tokens": [{ "name": "plotter", "token": "s3cret-token", "role": "viewer" }],
+//!   "users": [{ "username": "skipper", "passwordHash": "$argon2id$v=19$...", "role": "operator" }],
+//!   "anonymous": "viewer",
+//!   "sessionTimeout": 86400
+//! }
+//! ```
+
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use argon2::Argon2;
+use argon2::password_hash::{PasswordHash, PasswordHasher, PasswordVerifier, SaltString};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::config::get_project_dirs;
+
+const AUTH_FILE: &str = "auth.json";
+
+fn default_session_timeout() -> u64 {
+    24 * 60 * 60
+}
+
+/// What a client is allowed to do, operators can do everything viewers can
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    /// Read radars, controls, targets and recordings, receive spokes
+    Viewer,
+    /// Also set controls, acquire targets, manage recordings and quit
+    Operator,
+}
+
+impl fmt::Display for Role {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Role::Viewer => write!(f, "viewer"),
+            Role::Operator => write!(f, "operator"),
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct ApiToken {
+    /// Only used in log messages
+    pub name: String,
+    pub token: String,
+    pub role: Role,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct User {
+    pub username: String,
+    /// Argon2 hash of the password in PHC string format
+    pub password_hash: String,
+    pub role: Role,
+}
+
+/// The contents of `auth.json`
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct AuthConfig {
+    #[serde(default)]
+    pub tokens: Vec<ApiToken>,
+    #[serde(default)]
+    pub users: Vec<User>,
+    /// Role of clients without a token, none by default
+    #[serde(default)]
+    pub anonymous: Option<Role>,
+    /// Lifetime of a session token in seconds
+    #[serde(default = "default_session_timeout")]
+    pub session_timeout: u64,
+}
+
+struct Session {
+    username: String,
+    role: Role,
+    expires: Instant,
+}
+
+/// A client that is let in
+#[derive(Debug, Clone, PartialEq)]
+pub struct Principal {
+    /// Name of the API token or user, `None` for anonymous clients
+    pub name: Option<String>,
+    pub role: Role,
+}
+
+/// A session token handed out by `Authenticator::login`
+#[derive(Debug, Clone)]
+pub struct Login {
+    pub token: String,
+    pub role: Role,
+    pub time_to_live: Duration,
+}
+
+///
+/// Checks tokens and passwords against the configuration and keeps the
+/// sessions of users that logged in. Sessions only live in memory, so a
+/// restart logs everybody out.
+///
+pub struct Authenticator {
+    config: AuthConfig,
+    sessions: Mutex<HashMap<String, Session>>,
+}
+
+impl Authenticator {
+    pub fn new(config: AuthConfig) -> Self {
+        Authenticator {
+            config,
+            sessions: Mutex::new(HashMap::new()),
+        }
+    }
+
+    ///
+    /// Load `auth.json` from the config directory. Returns `None` when there
+    /// is no such file, so everybody is an operator as before. A file that
+    /// cannot be read locks out everybody, rather than letting everybody in.
+    ///
+    pub fn load() -> Option<Self> {
+        let mut path: PathBuf = get_project_dirs().config_dir().to_owned();
+        path.push(AUTH_FILE);
+        Self::load_from(&path)
+    }
+
+    fn load_from(path: &Path) -> Option<Self> {
+        let contents = match fs::read_to_string(path) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                log::debug!("No '{}', authentication disabled", path.display());
+                return None;
+            }
+            Err(e) => {
+                log::error!("Cannot read '{}': {}; nobody can log in", path.display(), e);
+                return Some(Self::new(AuthConfig::default()));
+            }
+        };
+        let config = match serde_json::from_str::<AuthConfig>(&contents) {
+            Ok(config) => config,
+            Err(e) => {
+                log::error!(
+                    "Cannot parse '{}': {}; nobody can log in",
+                    path.display(),
+                    e
+                );
+                AuthConfig::default()
+            }
+        };
+        for user in &config.users {
+            if let Err(e) = PasswordHash::new(&user.password_hash) {
+                log::error!(
+                    "Password hash of user '{}' in '{}' is invalid: {}; use --hash-password",
+                    user.username,
+                    path.display(),
+                    e
+                );
+            }
+        }
+        log::info!(
+            "Authentication enabled from '{}': {} tokens, {} users, anonymous {}",
+            path.display(),
+            config.tokens.len(),
+            config.users.len(),
+            config
+                .anonymous
+                .map_or("denied".to_string(), |r| r.to_string())
+        );
+        Some(Self::new(config))
+    }
+
+    ///
+    /// Who a client with `token` is: the name of the API token or the user
+    /// that logged in, and its role. Without a token the client is anonymous
+    /// and has no name. `None` when the client is not let in at all.
+    ///
+    pub fn identify(&self, token: Option<&str>) -> Option<Principal> {
+        let Some(token) = token else {
+            return self
+                .config
+                .anonymous
+                .map(|role| Principal { name: None, role });
+        };
+        if let Some(t) = self
+            .config
+            .tokens
+            .iter()
+            .find(|t| constant_time_eq(&t.token, token))
+        {
+            log::trace!("API token '{}'", t.name);
+            return Some(Principal {
+                name: Some(t.name.clone()),
+                role: t.role,
+            });
+        }
+
+        let mut sessions = self.sessions.lock().unwrap();
+        let now = Instant::now();
+        sessions.retain(|_, s| s.expires > now);
+        sessions.get(token).map(|s| {
+            log::trace!("Session of '{}'", s.username);
+            Principal {
+                name: Some(s.username.clone()),
+                role: s.role,
+            }
+        })
+    }
+
+    /// The role of a client with `token`, or of an anonymous client
+    pub fn role(&self, token: Option<&str>) -> Option<Role> {
+        self.identify(token).map(|p| p.role)
+    }
+
+    ///
+    /// Start a session for `username` if the password matches. Checking the
+    /// password takes tens of milliseconds on purpose, so call this from a
+    /// blocking task.
+    ///
+    pub fn login(&self, username: &str, password: &str) -> Option<Login> {
+        let user = self
+            .config
+            .users
+            .iter()
+            .find(|u| u.username == username && verify_password(&u.password_hash, password));
+        let Some(user) = user else {
+            log::warn!("Failed login for user '{}'", username);
+            return None;
+        };
+
+        let token = new_token();
+        let time_to_live = Duration::from_secs(self.config.session_timeout);
+        self.sessions.lock().unwrap().insert(
+            token.clone(),
+            Session {
+                username: user.username.clone(),
+                role: user.role,
+                expires: Instant::now() + time_to_live,
+            },
+        );
+        log::info!("User '{}' logged in as {}", user.username, user.role);
+        Some(Login {
+            token,
+            role: user.role,
+            time_to_live,
+        })
+    }
+
+    /// End the session of `token`, if it is one
+    pub fn logout(&self, token: &str) {
+        if let Some(session) = self.sessions.lock().unwrap().remove(token) {
+            log::info!("User '{}' logged out", session.username);
+        }
+    }
+}
+
+/// Compare secrets without giving away how much of them matched
+fn constant_time_eq(a: &str, b: &str) -> bool {
+    let (a, b) = (a.as_bytes(), b.as_bytes());
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+/// Random bytes from the operating system
+fn random_bytes<const N: usize>() -> [u8; N] {
+    let mut bytes = [0u8; N];
+    getrandom::fill(&mut bytes).expect("Operating system has no random source");
+    bytes
+}
+
+/// A random session token of 128 bits
+fn new_token() -> String {
+    random_bytes::<16>()
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+/// The hash of `password` with a random salt, to store in `auth.json`
+pub fn hash_password(password: &str) -> String {
+    let salt = SaltString::encode_b64(&random_bytes::<16>()).expect("Salt fits");
+    Argon2::default()
+        .hash_password(password.as_bytes(), &salt)
+        .expect("Password can be hashed with the default parameters")
+        .to_string()
+}
+
+fn verify_password(hash: &str, password: &str) -> bool {
+    PasswordHash::new(hash).is_ok_and(|hash| {
+        Argon2::default()
+            .verify_password(password.as_bytes(), &hash)
+            .is_ok()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn authenticator() -> Authenticator {
+        let config: AuthConfig = serde_json::from_str(&format!(
+            r#"{{
+                "tokens": [{{ "name": "plotter", "token": "abc", "role": "viewer" }}],
+                "users": [{{ "username": "skipper", "passwordHash": "{}", "role": "operator" }}]
+            }}"#,
+            hash_password("pw")
+        ))
+        .unwrap();
+        Authenticator::new(config)
+    }
+
+    #[test]
+    fn test_hash_password() {
+        let hash = hash_password("correct horse");
+        assert!(hash.starts_with("$argon2id$"));
+        assert!(!hash.contains("correct horse"));
+        assert!(verify_password(&hash, "correct horse"));
+        assert!(!verify_password(&hash, "correct horse!"));
+        // Same password, different salt
+        assert_ne!(hash, hash_password("correct horse"));
+        // Plain text is not a hash
+        assert!(!verify_password("correct horse", "correct horse"));
+    }
+
+    #[test]
+    fn test_roles() {
+        let auth = authenticator();
+        assert_eq!(auth.role(Some("abc")), Some(Role::Viewer));
+        assert_eq!(
+            auth.identify(Some("abc")).and_then(|p| p.name).as_deref(),
+            Some("plotter")
+        );
+        assert_eq!(auth.role(Some("abd")), None);
+        assert_eq!(auth.role(None), None);
+        assert!(Role::Operator > Role::Viewer);
+
+        let auth = Authenticator::new(AuthConfig {
+            anonymous: Some(Role::Viewer),
+            ..AuthConfig::default()
+        });
+        assert_eq!(auth.role(None), Some(Role::Viewer));
+        assert_eq!(auth.identify(None).unwrap().name, None);
+    }
+
+    #[test]
+    fn test_login() {
+        let auth = authenticator();
+        assert!(auth.login("skipper", "wrong").is_none());
+        assert!(auth.login("crew", "pw").is_none());
+
+        let login = auth.login("skipper", "pw").unwrap();
+        assert_eq!(login.role, Role::Operator);
+        assert_eq!(login.token.len(), 32);
+        assert_eq!(login.time_to_live, Duration::from_secs(86400));
+        assert_eq!(auth.role(Some(&login.token)), Some(Role::Operator));
+        assert_eq!(
+            auth.identify(Some(&login.token)),
+            Some(Principal {
+                name: Some("skipper".to_string()),
+                role: Role::Operator
+            })
+        );
+
+        let other = auth.login("skipper", "pw").unwrap();
+        assert_ne!(login.token, other.token);
+
+        auth.logout(&login.token);
+        assert_eq!(auth.role(Some(&login.token)), None);
+        assert_eq!(auth.role(Some(&other.token)), Some(Role::Operator));
+    }
+
+    #[test]
+    fn test_load_from() {
+        let dir = std::env::temp_dir().join(format!("mayara-auth-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join(AUTH_FILE);
+
+        let _ = fs::remove_file(&path);
+        assert!(Authenticator::load_from(&path).is_none());
+
+        // A broken file locks everybody out
+        fs::write(&path, "{ not json").unwrap();
+        let auth = Authenticator::load_from(&path).unwrap();
+        assert_eq!(auth.role(None), None);
+
+        fs::write(&path, r#"{ "anonymous": "operator" }"#).unwrap();
+        let auth = Authenticator::load_from(&path).unwrap();
+        assert_eq!(auth.role(None), Some(Role::Operator));
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
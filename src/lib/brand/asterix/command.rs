@@ -0,0 +1,45 @@
+use async_trait::async_trait;
+
+use crate::brand::CommandSender;
+use crate::radar::settings::{ControlId, ControlValue, SharedControls};
+use crate::radar::{RadarError, RadarInfo};
+
+/// Command sender for ASTERIX video sources.
+/// There is no way to send commands to the radar, so only client-side
+/// settings are accepted.
+pub(crate) struct Command {
+    key: String,
+}
+
+impl Command {
+    pub(crate) fn new(info: &RadarInfo) -> Self {
+        Command { key: info.key() }
+    }
+}
+
+#[async_trait]
+impl CommandSender for Command {
+    async fn set_control(
+        &mut self,
+        cv: &ControlValue,
+        controls: &SharedControls,
+    ) -> Result<(), RadarError> {
+        // RangeUnits is a client-side display preference; persist it in
+        // SharedControls as there is no radar that echoes it back.
+        if cv.id == ControlId::RangeUnits {
+            if let Some(v) = cv.value.as_ref().and_then(|v| v.as_f64()) {
+                controls
+                    .set_value(&ControlId::RangeUnits, v.into())
+                    .map_err(RadarError::ControlError)?;
+            }
+            return Ok(());
+        }
+        log::debug!(
+            "{}: ASTERIX video cannot set {:?} = {:?}",
+            self.key,
+            cv.id,
+            cv.value
+        );
+        Err(RadarError::CannotSetControlId(cv.id))
+    }
+}
@@ -0,0 +1,297 @@
+use std::collections::{HashMap, HashSet};
+use std::io;
+use std::net::{Ipv4Addr, SocketAddr, SocketAddrV4};
+
+use tokio_graceful_shutdown::{SubsystemBuilder, SubsystemHandle};
+
+use crate::asterix::cat240::{VideoMessage, decode_block};
+use crate::locator::LocatorAddress;
+use crate::radar::range::Ranges;
+use crate::radar::{RadarInfo, SharedRadars};
+use crate::{Brand, Cli};
+
+use super::{LocatorId, RadarLocator};
+
+mod command;
+mod report;
+mod settings;
+
+/// Spokes finer than this are drawn on top of each other
+const MAX_SPOKES: usize = 4096;
+/// Longer spokes are reduced to this length
+const MAX_SPOKE_LEN: usize = 2048;
+/// Pixel values for 8 bit and wider video; we drop the last bit so we have space for other data
+const HIGH_RES_PIXEL_VALUES: u8 = 128;
+
+/// CAT240 azimuths are in units of 360/2^16 degrees
+const AZIMUTH_UNITS: u32 = 65536;
+
+///
+/// Azimuth to spoke angle, rounded so that azimuths that were computed from
+/// a spoke angle by truncation map back to the same angle.
+///
+fn azimuth_to_angle(azimuth: u16, spokes_per_revolution: u16) -> u16 {
+    let spokes = spokes_per_revolution.max(1) as u32;
+    ((azimuth as u32 * spokes + AZIMUTH_UNITS / 2) / AZIMUTH_UNITS % spokes) as u16
+}
+
+/// Number of pixel values we use for video of `bits` bits per cell
+fn pixel_values(bits: u8) -> u8 {
+    match bits {
+        1 | 2 | 4 => 1 << bits,
+        _ => HIGH_RES_PIXEL_VALUES,
+    }
+}
+
+///
+/// What we have learned about a video source so far. Spokes per revolution
+/// and spoke length are not sent, so we watch the video for two azimuth
+/// wraps to see a full revolution.
+///
+#[derive(Clone, Default)]
+struct VideoSource {
+    last_az: Option<u16>,
+    wraps: u32,
+    /// Video messages in the last full revolution
+    messages: usize,
+    messages_this_revolution: usize,
+    /// How often each azimuth sector width was seen
+    widths: HashMap<u16, usize>,
+    max_cells: u32,
+    max_range: f64,
+    bits: u8,
+    summary: Option<String>,
+}
+
+impl VideoSource {
+    /// Returns true once a full revolution has been seen
+    fn add(&mut self, message: &VideoMessage) -> bool {
+        let width = message.end_az.wrapping_sub(message.start_az);
+        if width > 0 {
+            *self.widths.entry(width).or_default() += 1;
+        }
+        let cells = message.start_rg.saturating_add(message.cells);
+        self.max_cells = self.max_cells.max(cells);
+        self.max_range = self.max_range.max(cells as f64 * message.cell_size);
+        self.bits = self.bits.max(message.bits);
+
+        if self.last_az.is_some_and(|last| message.start_az < last) {
+            self.wraps += 1;
+            self.messages = self.messages_this_revolution;
+            self.messages_this_revolution = 0;
+        }
+        self.messages_this_revolution += 1;
+        self.last_az = Some(message.start_az);
+
+        self.wraps >= 2
+    }
+
+    ///
+    /// The most common azimuth sector width gives the spokes per revolution,
+    /// for sources that send a single azimuth per message we count them.
+    ///
+    fn spokes_per_revolution(&self) -> usize {
+        let spokes = match self.widths.iter().max_by_key(|(_, count)| **count) {
+            Some((width, _)) => (AZIMUTH_UNITS as f64 / *width as f64).round() as usize,
+            None => self.messages,
+        };
+        spokes.clamp(1, MAX_SPOKES)
+    }
+}
+
+#[derive(Clone)]
+struct AsterixLocator {
+    args: Cli,
+    addr: SocketAddrV4,
+    sources: HashMap<(u8, u8), VideoSource>,
+    known: HashSet<(u8, u8)>,
+}
+
+impl RadarLocator for AsterixLocator {
+    fn process(
+        &mut self,
+        message: &[u8],
+        from: &SocketAddrV4,
+        nic_addr: &Ipv4Addr,
+        radars: &SharedRadars,
+        subsys: &SubsystemHandle,
+    ) -> Result<(), io::Error> {
+        // The locator only gets the start of each packet, but the video
+        // header is in there.
+        for mut message in decode_block(message) {
+            let id = (message.sac, message.sic);
+            if self.known.contains(&id) {
+                continue;
+            }
+            if let Some(summary) = message.summary.take() {
+                self.sources.entry(id).or_default().summary = Some(summary);
+                continue;
+            }
+            if !message.is_video() {
+                continue;
+            }
+            let source = self.sources.entry(id).or_default();
+            if source.add(&message) {
+                let source = self.sources.remove(&id).unwrap_or_default();
+                self.known.insert(id);
+                self.found(id, source, from, nic_addr, radars, subsys);
+            }
+        }
+        Ok(())
+    }
+
+    fn clone(&self) -> Box<dyn RadarLocator> {
+        Box::new(Clone::clone(self))
+    }
+}
+
+impl AsterixLocator {
+    fn new(args: Cli, addr: SocketAddrV4) -> Self {
+        AsterixLocator {
+            args,
+            addr,
+            sources: HashMap::new(),
+            known: HashSet::new(),
+        }
+    }
+
+    fn found(
+        &self,
+        (sac, sic): (u8, u8),
+        source: VideoSource,
+        from: &SocketAddrV4,
+        nic_addr: &Ipv4Addr,
+        radars: &SharedRadars,
+        subsys: &SubsystemHandle,
+    ) {
+        let serial_no = format!("{:02X}{:02X}", sac, sic);
+        let spokes_per_revolution = source.spokes_per_revolution();
+        let spoke_len = (source.max_cells as usize).clamp(1, MAX_SPOKE_LEN);
+        let name = source
+            .summary
+            .filter(|s| !s.trim().is_empty())
+            .unwrap_or_else(|| format!("ASTERIX {}/{}", sac, sic));
+
+        log::debug!(
+            "{}: ASTERIX video from SAC {} SIC {}: {} spokes of {} cells at {} bits, range {} m",
+            from,
+            sac,
+            sic,
+            spokes_per_revolution,
+            source.max_cells,
+            source.bits,
+            source.max_range.round()
+        );
+
+        let info = RadarInfo::new(
+            radars,
+            &self.args,
+            Brand::Asterix,
+            Some(&serial_no),
+            None,
+            pixel_values(source.bits),
+            spokes_per_revolution,
+            spoke_len,
+            *from,
+            *nic_addr,
+            self.addr,
+            self.addr,
+            *from, // no commands
+            |id, tx| settings::new(id, tx, &self.args, &name, &serial_no),
+            false,
+            false,
+        );
+
+        if let Some(mut info) = radars.add(info) {
+            info.start_forwarding_radar_messages_to_stdout(subsys);
+            info.set_ranges(Ranges::new_by_distance(&[source.max_range.round() as i32]));
+            radars.update(&mut info);
+
+            let report_name = info.key() + " reports";
+            let report_receiver =
+                report::AsterixReportReceiver::new(&self.args, info, radars.clone(), sac, sic);
+            subsys.start(SubsystemBuilder::new(report_name, |s| {
+                report_receiver.run(s)
+            }));
+        }
+    }
+}
+
+pub(super) fn new(args: &Cli, addresses: &mut Vec<LocatorAddress>) {
+    for addr in &args.asterix_input {
+        let address = SocketAddr::V4(*addr);
+        if !addresses
+            .iter()
+            .any(|i| i.id == LocatorId::Asterix && i.address == address)
+        {
+            addresses.push(LocatorAddress::new(
+                LocatorId::Asterix,
+                &address,
+                Brand::Asterix,
+                vec![],
+                Box::new(AsterixLocator::new(args.clone(), *addr)),
+            ));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn video(start_az: u16, end_az: u16, cells: u32) -> VideoMessage {
+        VideoMessage {
+            message_type: 2,
+            start_az,
+            end_az,
+            cell_size: 1.5,
+            bits: 4,
+            cells,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_azimuth_to_angle() {
+        assert_eq!(azimuth_to_angle(0, 2048), 0);
+        assert_eq!(azimuth_to_angle(16384, 2048), 512);
+        assert_eq!(azimuth_to_angle(65535, 2048), 0);
+        // 3000 spokes do not divide 65536: spoke 7 starts at azimuth 152.9
+        assert_eq!(azimuth_to_angle(152, 3000), 7);
+    }
+
+    #[test]
+    fn test_video_source() {
+        let mut source = VideoSource::default();
+        let mut found = false;
+        // Start halfway, then two full revolutions of 1024 spokes
+        for i in 512..(3 * 1024) {
+            let az = ((i % 1024) * 64) as u16;
+            let cells = if i % 2 == 0 { 800 } else { 1000 };
+            found = source.add(&video(az, az.wrapping_add(64), cells));
+            if found {
+                break;
+            }
+        }
+        assert!(found);
+        assert_eq!(source.spokes_per_revolution(), 1024);
+        assert_eq!(source.max_cells, 1000);
+        assert_eq!(source.max_range, 1500.);
+
+        // Without sector widths the messages per revolution are counted
+        let mut source = VideoSource::default();
+        for i in 0..(2 * 360 + 1) {
+            let az = ((i % 360) * 65536 / 360) as u16;
+            source.add(&video(az, az, 100));
+        }
+        assert_eq!(source.spokes_per_revolution(), 360);
+    }
+
+    #[test]
+    fn test_pixel_values() {
+        assert_eq!(pixel_values(1), 2);
+        assert_eq!(pixel_values(4), 16);
+        assert_eq!(pixel_values(8), HIGH_RES_PIXEL_VALUES);
+        assert_eq!(pixel_values(16), HIGH_RES_PIXEL_VALUES);
+    }
+}
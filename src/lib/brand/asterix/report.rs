@@ -0,0 +1,309 @@
+use std::io;
+use std::time::Duration;
+
+use tokio::time::{Instant, sleep_until};
+use tokio_graceful_shutdown::SubsystemHandle;
+
+use super::command::Command;
+use super::{MAX_SPOKE_LEN, azimuth_to_angle};
+use crate::Cli;
+use crate::asterix::cat240::{VideoMessage, decode_block};
+use crate::network;
+use crate::radar::diagnostics::ConnectionState;
+use crate::radar::range::Ranges;
+use crate::radar::settings::ControlId;
+use crate::radar::spoke::GenericSpoke;
+use crate::radar::{CommonRadar, Power, RadarError, RadarInfo, SharedRadars};
+
+/// Spokes are sent to clients in batches, like Navico radars do
+const SPOKES_PER_MESSAGE: usize = 32;
+/// Without video for this long the radar is shown as in standby
+const VIDEO_TIMEOUT: Duration = Duration::from_secs(5);
+/// CAT240 data blocks can be up to 64 kB
+const MAX_BLOCK_LEN: usize = 65536;
+
+pub(crate) struct AsterixReportReceiver {
+    common: CommonRadar,
+    command_sender: Option<Command>,
+    socket: Option<crate::replay::RadarSocket>,
+    sac: u8,
+    sic: u8,
+    /// Ranges seen in the video, in meters
+    ranges: Vec<i32>,
+    range: i32,
+    /// Spokes can differ in length, so the range is the longest of a revolution
+    revolution_range: i32,
+    last_angle: Option<u16>,
+    spokes_in_message: usize,
+    transmitting: bool,
+    video_deadline: Instant,
+}
+
+impl AsterixReportReceiver {
+    pub(crate) fn new(
+        args: &Cli,
+        info: RadarInfo,
+        radars: SharedRadars,
+        sac: u8,
+        sic: u8,
+    ) -> AsterixReportReceiver {
+        let key = info.key();
+        let ranges: Vec<i32> = info.ranges.all.iter().map(|r| r.distance()).collect();
+        let range = ranges.last().copied().unwrap_or(0);
+        let command_sender = Some(Command::new(&info));
+        let control_update_rx = info.control_update_subscribe();
+        let replay = args.is_replay();
+        let blob_tx = radars.get_blob_tx();
+        let common = CommonRadar::new(args, key, info, radars, control_update_rx, replay, blob_tx);
+
+        AsterixReportReceiver {
+            common,
+            command_sender,
+            socket: None,
+            sac,
+            sic,
+            ranges,
+            range,
+            revolution_range: 0,
+            last_angle: None,
+            spokes_in_message: 0,
+            transmitting: false,
+            video_deadline: Instant::now() + VIDEO_TIMEOUT,
+        }
+    }
+
+    fn start_socket(&mut self) -> io::Result<()> {
+        let addr = self.common.info.spoke_data_addr;
+        let nic_addr = self.common.info.nic_addr;
+        match network::create_udp_listen(&addr, &nic_addr, network::SocketType::Any) {
+            Ok(socket) => {
+                self.socket = Some(socket);
+                log::debug!(
+                    "{}: {} via {}: listening for ASTERIX video from SAC {} SIC {}",
+                    self.common.key,
+                    addr,
+                    nic_addr,
+                    self.sac,
+                    self.sic
+                );
+                Ok(())
+            }
+            Err(e) => {
+                log::debug!(
+                    "{}: {} via {}: create multicast failed: {}",
+                    self.common.key,
+                    addr,
+                    nic_addr,
+                    e
+                );
+                Err(e)
+            }
+        }
+    }
+
+    async fn socket_loop(&mut self, subsys: &SubsystemHandle) -> Result<(), RadarError> {
+        let mut buf = Vec::with_capacity(MAX_BLOCK_LEN);
+
+        loop {
+            let video_deadline = self.video_deadline;
+            tokio::select! {
+                _ = subsys.on_shutdown_requested() => {
+                    log::debug!("{}: shutdown", self.common.key);
+                    return Err(RadarError::Shutdown);
+                },
+                _ = sleep_until(video_deadline), if self.transmitting => {
+                    log::info!("{}: no ASTERIX video for {:?}", self.common.key, VIDEO_TIMEOUT);
+                    self.transmitting = false;
+                    self.common.set_value(&ControlId::Power, Power::Standby as i32 as f64);
+                },
+                r = self.socket.as_mut().unwrap().recv_buf_from(&mut buf) => {
+                    match r {
+                        Ok(_) => {
+                            self.common.packet_received("data", "CAT240");
+                            self.process_block(&buf);
+                            buf.clear();
+                        }
+                        Err(e) => {
+                            log::error!("{}: receive error: {}", self.common.key, e);
+                            return Err(RadarError::Io(e));
+                        }
+                    }
+                },
+                r = self.common.control_update_rx.recv() => {
+                    match r {
+                        Err(_) => {},
+                        Ok(cu) => {
+                            let _ = self.common.process_control_update(cu, &mut self.command_sender).await;
+                        },
+                    }
+                },
+            }
+        }
+    }
+
+    pub async fn run(mut self, subsys: SubsystemHandle) -> Result<(), RadarError> {
+        self.common.set_value(&ControlId::Range, self.range as f64);
+        loop {
+            if self.start_socket().is_ok() {
+                match self.socket_loop(&subsys).await {
+                    Err(RadarError::Shutdown) => {
+                        return Ok(());
+                    }
+                    _ => {
+                        // Ignore, reopen socket
+                    }
+                }
+                self.socket = None;
+                self.common
+                    .set_connection_state(ConnectionState::Disconnected);
+            } else {
+                tokio::select! {
+                    _ = subsys.on_shutdown_requested() => {
+                        return Ok(());
+                    },
+                    _ = tokio::time::sleep(Duration::from_secs(1)) => {},
+                }
+            }
+        }
+    }
+
+    fn process_block(&mut self, data: &[u8]) {
+        for message in decode_block(data) {
+            if message.sac != self.sac || message.sic != self.sic || !message.is_video() {
+                continue;
+            }
+            match message.unpack_cells() {
+                Some(cells) => self.process_video(&message, cells),
+                None => {
+                    log::trace!(
+                        "{}: skipping {} video message",
+                        self.common.key,
+                        if message.compressed {
+                            "compressed"
+                        } else {
+                            "incomplete"
+                        }
+                    );
+                }
+            }
+        }
+    }
+
+    fn process_video(&mut self, message: &VideoMessage, cells: Vec<u8>) {
+        self.video_deadline = Instant::now() + VIDEO_TIMEOUT;
+        if !self.transmitting {
+            self.transmitting = true;
+            self.common
+                .set_value(&ControlId::Power, Power::Transmit as i32 as f64);
+        }
+
+        let total_cells = message.start_rg as usize + cells.len();
+        let range = (total_cells as f64 * message.cell_size).round() as i32;
+
+        let spokes_per_revolution = self.common.info.spokes_per_revolution;
+        let angle = azimuth_to_angle(message.start_az, spokes_per_revolution);
+        if self.last_angle.is_some_and(|last| angle < last) {
+            if self.revolution_range != self.range {
+                self.set_range(self.revolution_range);
+            }
+            self.revolution_range = 0;
+        }
+        self.last_angle = Some(angle);
+        self.revolution_range = self.revolution_range.max(range);
+        let spoke = to_generic_spoke(
+            message.start_rg as usize,
+            &cells,
+            message.bits,
+            self.common.info.pixel_values,
+            (self.common.info.max_spoke_len as usize).min(MAX_SPOKE_LEN),
+        );
+
+        if self.spokes_in_message == 0 {
+            self.common.new_spoke_message();
+        }
+        self.common.add_spoke(range as u32, angle, None, spoke);
+        self.spokes_in_message += 1;
+        if self.spokes_in_message >= SPOKES_PER_MESSAGE {
+            self.common.send_spoke_message();
+            self.spokes_in_message = 0;
+        }
+    }
+
+    /// The video shows a new range; add it to the ranges if we have not seen it before
+    fn set_range(&mut self, range: i32) {
+        log::debug!("{}: ASTERIX video range {} m", self.common.key, range);
+        self.range = range;
+        if !self.ranges.contains(&range) {
+            self.ranges.push(range);
+            self.ranges.sort();
+            self.common
+                .set_ranges(Ranges::new_by_distance(&self.ranges));
+        }
+        self.common.set_value(&ControlId::Range, range as f64);
+    }
+}
+
+///
+/// Turn unpacked cells into a spoke: cells before the first cell (START_RG)
+/// are empty, values are scaled to our pixel values and spokes that are too
+/// long are reduced, keeping the strongest return of the cells that are
+/// merged.
+///
+fn to_generic_spoke(
+    start_rg: usize,
+    cells: &[u8],
+    bits: u8,
+    pixel_values: u8,
+    max_len: usize,
+) -> GenericSpoke {
+    let max_in = (1u32 << bits.min(8)) - 1;
+    let max_out = pixel_values.saturating_sub(1) as u32;
+    let scale = |v: u8| ((v as u32 * max_out + max_in / 2) / max_in) as u8;
+
+    let len = start_rg + cells.len();
+    let cell = |i: usize| {
+        if i < start_rg {
+            0
+        } else {
+            scale(cells[i - start_rg])
+        }
+    };
+
+    if len <= max_len {
+        return (0..len).map(cell).collect();
+    }
+    (0..max_len)
+        .map(|i| {
+            let first = i * len / max_len;
+            let last = ((i + 1) * len / max_len).max(first + 1);
+            (first..last).map(cell).max().unwrap_or(0)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_to_generic_spoke() {
+        // 4 bit video into 16 pixel values is unchanged
+        assert_eq!(
+            to_generic_spoke(0, &[0, 5, 15], 4, 16, 1024),
+            vec![0, 5, 15]
+        );
+        // 8 bit video into 128 pixel values drops the last bit
+        assert_eq!(
+            to_generic_spoke(2, &[0, 128, 255], 8, 128, 1024),
+            vec![0, 0, 0, 64, 127]
+        );
+        // 1 bit video into 16 pixel values is all or nothing
+        assert_eq!(to_generic_spoke(0, &[0, 1], 1, 16, 1024), vec![0, 15]);
+        // Reduced spokes keep the strongest return
+        assert_eq!(
+            to_generic_spoke(0, &[1, 0, 0, 0, 0, 2, 3, 0], 4, 16, 4),
+            vec![1, 0, 2, 3]
+        );
+        assert_eq!(to_generic_spoke(0, &[1, 2, 3, 4, 5], 4, 16, 2), vec![2, 5]);
+    }
+}
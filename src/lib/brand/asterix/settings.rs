@@ -0,0 +1,61 @@
+use std::collections::HashMap;
+
+use crate::Cli;
+use crate::radar::NAUTICAL_MILE;
+use crate::radar::settings::{ControlId, SharedControls, new_list, new_numeric, new_string};
+use crate::radar::units::Units;
+use crate::stream::SignalKDelta;
+
+pub(crate) fn new(
+    radar_id: String,
+    sk_client_tx: tokio::sync::broadcast::Sender<SignalKDelta>,
+    args: &Cli,
+    name: &str,
+    serial_no: &str,
+) -> SharedControls {
+    let mut controls = HashMap::new();
+
+    new_string(ControlId::UserName).build(&mut controls);
+    controls
+        .get_mut(&ControlId::UserName)
+        .unwrap()
+        .set_string(name.to_string());
+
+    new_string(ControlId::ModelName)
+        .read_only(true)
+        .build(&mut controls);
+    controls
+        .get_mut(&ControlId::ModelName)
+        .unwrap()
+        .set_string("ASTERIX CAT240".to_string());
+
+    // SAC and SIC of the video source
+    new_string(ControlId::SerialNumber)
+        .read_only(true)
+        .build(&mut controls);
+    controls
+        .get_mut(&ControlId::SerialNumber)
+        .unwrap()
+        .set_string(serial_no.to_string());
+
+    // Range units
+    new_list(ControlId::RangeUnits, &["Nautical", "Metric", "Mixed"]).build(&mut controls);
+
+    let mut controls = SharedControls::new(radar_id, sk_client_tx, args, controls);
+
+    // We only receive video, so the radar itself cannot be controlled
+    controls.update_definition(
+        new_list(
+            ControlId::Power,
+            &["Off", "Standby", "Transmit", "Preparing"],
+        )
+        .read_only(true),
+    );
+    controls.update_definition(
+        new_numeric(ControlId::Range, 0., 120. * NAUTICAL_MILE as f64)
+            .wire_units(Units::Meters)
+            .read_only(true),
+    );
+
+    controls
+}
@@ -8,6 +8,7 @@ use super::command::Command;
 use super::world::{EmulatorWorld, TurnProgress, TURN_RADIUS};
 use super::{EMULATOR_SPOKE_LEN, EMULATOR_SPOKES, get_initial_position};
 use crate::Cli;
+use crate::radar::diagnostics::ConnectionState;
 use crate::radar::settings::{ControlId, ControlUpdate};
 use crate::radar::spoke::GenericSpoke;
 use crate::radar::{
@@ -87,6 +88,7 @@ impl EmulatorReportReceiver {
         self.common
             .set_value(&ControlId::Power, Power::Transmit as i32 as f64);
         self.transmitting = true;
+        self.common.set_connection_state(ConnectionState::Connected);
 
         // Set initial range value in controls
         self.common
@@ -301,10 +303,11 @@ impl EmulatorReportReceiver {
         crate::navdata::set_position(
             Some(self.boat_position.lat()),
             Some(self.boat_position.lon()),
+            "emulator",
         );
         crate::navdata::set_heading_true(Some(self.boat_heading * DEG_TO_RAD), "emulator");
-        crate::navdata::set_sog(Some(self.boat_speed * KNOTS_TO_MS));
-        crate::navdata::set_cog(Some(self.boat_heading * DEG_TO_RAD));
+        crate::navdata::set_sog(Some(self.boat_speed * KNOTS_TO_MS), "emulator");
+        crate::navdata::set_cog(Some(self.boat_heading * DEG_TO_RAD), "emulator");
     }
 
     fn generate_spoke_batch(&mut self) {
@@ -332,9 +335,7 @@ impl EmulatorReportReceiver {
 
             let new_spoke = (self.current_spoke + 1) % EMULATOR_SPOKES as u16;
             if new_spoke < self.current_spoke {
-                // Rotation wrapped - broadcast heading to ensure GUI receives it
                 self.rotation_count += 1;
-                crate::navdata::broadcast_heading("emulator");
             }
             self.current_spoke = new_spoke;
         }
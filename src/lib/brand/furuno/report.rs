@@ -27,6 +27,7 @@ use crate::replay::RadarSocket;
 use crate::radar::CommonRadar;
 use crate::radar::SharedRadars;
 use crate::radar::SpokeBearing;
+use crate::radar::diagnostics::ConnectionState;
 use crate::radar::settings::ControlId;
 use crate::radar::{Power, RadarError, RadarInfo};
 use crate::util::PrintableSpoke;
@@ -221,12 +222,15 @@ impl FurunoReportReceiver {
                     match r {
                         Ok(len) => {
                             if len > 2 {
+                                self.common.packet_received("report", report_type(&line));
                                 if let Err(e) = self.process_report(&line) {
                                     log::error!("{}: {}", self.common.key, e);
+                                    self.common.malformed_packet("report", &e);
                                 } else if !first_report_received {
                                     if let Some(ref mut cs) = self.command_sender {
                                         cs.init().await?;
                                     }
+                                    self.set_connection_state(ConnectionState::Connected);
                                     first_report_received = true;
                                 }
 
@@ -342,6 +346,7 @@ impl FurunoReportReceiver {
                     _ => {}
                 }
             }
+            self.set_connection_state(ConnectionState::Disconnected);
 
             tokio::select! {
                 _ = subsys.on_shutdown_requested() => return Ok(()),
@@ -350,6 +355,14 @@ impl FurunoReportReceiver {
         }
     }
 
+    /// The connection is shared by range A and B of a dual range radar
+    fn set_connection_state(&self, state: ConnectionState) {
+        self.common.set_connection_state(state);
+        if let Some(cb) = &self.common_b {
+            cb.set_connection_state(state);
+        }
+    }
+
     fn login_to_radar(&mut self) -> Result<(), RadarError> {
         if self.command_sender.is_none() {
             return Ok(());
@@ -1056,8 +1069,11 @@ impl FurunoReportReceiver {
     }
 
     fn process_frame(&mut self, data: &[u8]) {
+        self.common.packet_received("data", "spokes");
         if data.len() < 16 {
-            log::debug!("Dropping short frame ({} bytes)", data.len());
+            let error = format!("Dropping short frame ({} bytes)", data.len());
+            log::debug!("{}", error);
+            self.common.malformed_packet("data", &error);
             return;
         }
 
@@ -1071,7 +1087,9 @@ impl FurunoReportReceiver {
         }
 
         if data[0] != FRAME_MAGIC {
-            log::debug!("Dropping invalid frame (magic={:#04x})", data[0]);
+            let error = format!("Dropping invalid frame (magic={:#04x})", data[0]);
+            log::debug!("{}", error);
+            self.common.malformed_packet("data", &error);
             return;
         }
 
@@ -1196,7 +1214,9 @@ impl FurunoReportReceiver {
     /// Reference: `DecodeTileEchoFormat` @ 0x5eda0 in libNAVNETDLL.so.
     fn process_tile_frame(&mut self, data: &[u8]) {
         if data.len() < 24 {
-            log::debug!("Tile frame too short ({} bytes)", data.len());
+            let error = format!("Tile frame too short ({} bytes)", data.len());
+            log::debug!("{}", error);
+            self.common.malformed_packet("data", &error);
             return;
         }
 
@@ -1678,6 +1698,12 @@ impl FurunoReportReceiver {
     }
 }
 
+/// A TCP report is identified by its command, e.g. `$N69`
+fn report_type(line: &str) -> &str {
+    let line = &line[line.find('$').unwrap_or(0)..];
+    line.split(',').next().unwrap_or_default().trim_end()
+}
+
 async fn conditional_receive(
     socket: &mut Option<RadarSocket>,
     buf: &mut Vec<u8>,
@@ -13,6 +13,7 @@ use super::range_table;
 use crate::Cli;
 use crate::network;
 use crate::replay::RadarSocket;
+use crate::radar::diagnostics::ConnectionState;
 use crate::radar::settings::ControlId;
 use crate::radar::spoke::GenericSpoke;
 use crate::radar::{
@@ -329,8 +330,10 @@ impl GarminReportReceiver {
                 } => {
                     match r {
                         Ok((_len, _addr)) => {
+                            self.common.packet_received("report", &packet_type(&report_buf));
                             if let Err(e) = self.process_report(&report_buf) {
                                 log::error!("{}: {}", self.common.key, e);
+                                self.common.malformed_packet("report", &e);
                             }
                             report_buf.clear();
                         }
@@ -349,8 +352,10 @@ impl GarminReportReceiver {
                 } => {
                     match r {
                         Ok((_len, _addr)) => {
+                            self.common.packet_received("data", "spokes");
                             if let Err(e) = self.process_data(&data_buf) {
                                 log::error!("{}: {}", self.common.key, e);
+                                self.common.malformed_packet("data", &e);
                             }
                             data_buf.clear();
                         }
@@ -386,6 +391,7 @@ impl GarminReportReceiver {
         loop {
             if let Err(e) = self.start_sockets().await {
                 log::warn!("{}: Failed to start sockets: {}", self.common.key, e);
+                self.set_connection_state(ConnectionState::Disconnected);
                 sleep(Duration::from_millis(1000)).await;
                 continue;
             }
@@ -397,6 +403,7 @@ impl GarminReportReceiver {
                 _ => {
                     self.report_socket = None;
                     self.data_socket = None;
+                    self.set_connection_state(ConnectionState::Disconnected);
                 }
             }
 
@@ -404,6 +411,14 @@ impl GarminReportReceiver {
         }
     }
 
+    /// The sockets are shared by range A and B of a dual range radar
+    fn set_connection_state(&self, state: ConnectionState) {
+        self.common.set_connection_state(state);
+        if let Some(cb) = &self.common_b {
+            cb.set_connection_state(state);
+        }
+    }
+
     fn process_report(&mut self, data: &[u8]) -> Result<(), Error> {
         if data.len() < GMN_HEADER_LEN {
             bail!("Report too short: {} bytes", data.len());
@@ -1497,6 +1512,14 @@ async fn conditional_recv(
     }
 }
 
+/// The packet type in the first four bytes, e.g. `0919`
+fn packet_type(data: &[u8]) -> String {
+    match data.get(0..4) {
+        Some(t) => format!("{:04X}", u32::from_le_bytes(t.try_into().unwrap())),
+        None => "short".to_string(),
+    }
+}
+
 /// Unpack HD 1-bit packed spoke data to 8-bit values
 fn unpack_hd_spoke(packed: &[u8], wire_to_legend: &WireToLegendTable) -> GenericSpoke {
     let mut samples = Vec::with_capacity(packed.len() * 8);
@@ -6,6 +6,8 @@ use async_trait::async_trait;
 use serde::Serialize;
 use tokio_graceful_shutdown::SubsystemHandle;
 
+#[cfg(feature = "asterix")]
+pub(crate) mod asterix;
 #[cfg(feature = "emulator")]
 pub(crate) mod emulator;
 #[cfg(feature = "furuno")]
@@ -30,6 +32,7 @@ pub(crate) enum LocatorId {
     Garmin,
     GarminCdm,
     Raymarine,
+    Asterix,
 }
 
 pub(crate) fn create_brand_listeners(
@@ -65,6 +68,11 @@ pub(crate) fn create_brand_listeners(
         garmin::new(args, listen_addresses);
         brands.insert(Brand::Garmin);
     }
+    #[cfg(feature = "asterix")]
+    if !args.asterix_input.is_empty() && args.brand.unwrap_or(Brand::Asterix) == Brand::Asterix {
+        asterix::new(args, listen_addresses);
+        brands.insert(Brand::Asterix);
+    }
 }
 
 ///
@@ -25,6 +25,7 @@ use crate::Cli;
 use crate::brand::navico::info::{HaloHeadingPacket, HaloNavigationPacket, Information};
 use crate::brand::navico::{HALO_HEADING_INFO_ADDRESS, HaloMode};
 use crate::network;
+use crate::radar::diagnostics::ConnectionState;
 use crate::radar::settings::ControlId;
 use crate::radar::spoke::GenericSpoke;
 use crate::radar::target::MS_TO_KN;
@@ -60,6 +61,14 @@ fn extract_heading_value(x: u16) -> Option<u16> {
     }
 }
 
+/// The first two bytes of a report identify it, e.g. `02C4`
+fn report_type(data: &[u8]) -> String {
+    match data {
+        [a, b, ..] => format!("{:02X}{:02X}", a, b),
+        _ => "short".to_string(),
+    }
+}
+
 #[derive(Deserialize, Debug, Clone, Copy)]
 #[repr(packed)]
 struct GenBr24Header {
@@ -451,6 +460,8 @@ impl NavicoReportReceiver {
                 }
                 Err(e) => {
                     log::error!("{}: trying to recover from error {}", self.common.key, e);
+                    self.common
+                        .set_connection_state(ConnectionState::Disconnected);
                 }
             }
             sleep(Duration::from_millis(2000)).await;
@@ -584,8 +595,10 @@ impl NavicoReportReceiver {
                 r = self.report_socket.as_mut().unwrap().recv_buf_from(&mut self.report_buf)  => {
                     match r {
                         Ok((_len, _addr)) => {
+                            self.common.packet_received("report", &report_type(&self.report_buf));
                             if let Err(e) = self.process_report().await {
                                 log::error!("{}: {}", self.common.key, e);
+                                self.common.malformed_packet("report", &e);
                             }
                             self.report_buf.clear();
                         }
@@ -599,6 +612,7 @@ impl NavicoReportReceiver {
                 Some(r) = Self::conditional_receive(&mut self.info_socket, &mut self.info_buf) => {
                     match r {
                         Ok((_len, addr)) => {
+                            self.common.packet_received("info", "info");
                             self.process_info(&addr);
                             self.info_buf.clear();
                         }
@@ -644,11 +658,14 @@ impl NavicoReportReceiver {
     }
 
     fn process_frame(&mut self) {
+        self.common.packet_received("data", "spokes");
         if self.data_buf.len() < FRAME_HEADER_LENGTH + RADAR_LINE_LENGTH {
-            log::warn!(
+            let error = format!(
                 "UDP data frame with even less than one spoke, len {} dropped",
                 self.data_buf.len()
             );
+            log::warn!("{}", error);
+            self.common.malformed_packet("data", &error);
             return;
         }
 
@@ -675,7 +692,9 @@ impl NavicoReportReceiver {
                 self.common
                     .add_spoke(range, angle, heading, self.process_spoke(spoke_slice));
             } else {
-                log::warn!("Invalid spoke: header {:02X?}", &header_slice);
+                let error = format!("Invalid spoke: header {:02X?}", &header_slice);
+                log::warn!("{}", error);
+                self.common.malformed_packet("data", &error);
             }
 
             offset += RADAR_LINE_LENGTH;
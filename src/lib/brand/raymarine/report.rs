@@ -9,6 +9,7 @@ use crate::Cli;
 use crate::brand::raymarine::RaymarineModel;
 use crate::network;
 use crate::replay::RadarSocket;
+use crate::radar::diagnostics::ConnectionState;
 use crate::radar::range::Ranges;
 use crate::radar::{BYTE_LOOKUP_LENGTH, CommonRadar, Legend, RadarError, RadarInfo, SharedRadars};
 
@@ -113,6 +114,14 @@ pub(crate) struct RaymarineReportReceiver {
     wire_to_legend: WireToLegendTable,
 }
 
+/// The message id in the first four bytes, e.g. `280003`
+fn report_id(data: &[u8]) -> String {
+    match data.get(0..4) {
+        Some(id) => format!("{:06X}", u32::from_le_bytes(id.try_into().unwrap())),
+        None => "short".to_string(),
+    }
+}
+
 impl RaymarineReportReceiver {
     pub(crate) fn new(
         args: &Cli,
@@ -215,8 +224,12 @@ impl RaymarineReportReceiver {
                                 buf.reserve(1024);
                                 log::warn!("{}: UDP report buffer full, increasing size {} -> {}", self.common.key, old, buf.capacity()   );
                             }
-                            else if let Err(e) = self.process_report(&buf).await {
-                                log::error!("{}: {}", self.common.key, e);
+                            else {
+                                self.common.packet_received("report", &report_id(&buf));
+                                if let Err(e) = self.process_report(&buf).await {
+                                    log::error!("{}: {}", self.common.key, e);
+                                    self.common.malformed_packet("report", &e);
+                                }
                             }
                             buf.clear();
                         }
@@ -264,6 +277,8 @@ impl RaymarineReportReceiver {
                     }
                 }
                 self.report_socket = None;
+                self.common
+                    .set_connection_state(ConnectionState::Disconnected);
             } else {
                 sleep(Duration::from_millis(1000)).await;
                 self.start_report_socket().await?;
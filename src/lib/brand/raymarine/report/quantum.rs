@@ -37,10 +37,12 @@ pub(crate) fn process_frame(receiver: &mut RaymarineReportReceiver, data: &[u8])
     }
 
     if data.len() < FRAME_HEADER_LENGTH {
-        log::warn!(
+        let error = format!(
             "UDP data frame with even less than header, len {} dropped",
             data.len()
         );
+        log::warn!("{}", error);
+        receiver.common.malformed_packet("report", &error);
         return;
     }
     let header = &data[..FRAME_HEADER_LENGTH];
@@ -66,10 +66,12 @@ pub(crate) fn process_frame(receiver: &mut RaymarineReportReceiver, data: &[u8])
     }
 
     if data.len() < FRAME_HEADER_LENGTH + SPOKE_HEADER_1_LENGTH {
-        log::warn!(
+        let error = format!(
             "UDP data frame with even less than one spoke, len {} dropped",
             data.len()
         );
+        log::warn!("{}", error);
+        receiver.common.malformed_packet("report", &error);
         return;
     }
     log::trace!("{}: Scandata {:02X?}", receiver.common.key, data);
@@ -109,6 +111,9 @@ pub(crate) fn process_frame(receiver: &mut RaymarineReportReceiver, data: &[u8])
 
     if nspokes == 0 || nspokes > 360 {
         log::warn!("{}: Invalid spoke count {}", receiver.common.key, nspokes);
+        receiver
+            .common
+            .malformed_packet("report", &format!("Invalid spoke count {}", nspokes));
         return;
     }
 
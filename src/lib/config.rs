@@ -1,7 +1,7 @@
 use directories::ProjectDirs;
 use log::{debug, error, info, warn};
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::error::Error;
 use std::fs;
 use std::fs::File;
@@ -10,6 +10,7 @@ use std::path::PathBuf;
 use std::time::SystemTime;
 
 use crate::radar::RadarInfo;
+use crate::radar::preset::Preset;
 use crate::radar::range::Ranges;
 use crate::radar::settings::ControlId;
 
@@ -92,6 +93,10 @@ pub(crate) struct Radar {
     pub arpa_max_speed: i32, // 0 = Normal (25kn), 1 = Medium (40kn), 2 = Fast (50kn)
     #[serde(default)]
     pub doppler_auto_track: bool,
+
+    // Named control presets
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub presets: BTreeMap<String, Preset>,
 }
 
 #[derive(Serialize, Deserialize, Debug, Default, Clone)]
@@ -358,6 +363,48 @@ impl Persistence {
         }
     }
 
+    pub(crate) fn presets(&self, key: &str) -> BTreeMap<String, Preset> {
+        self.config
+            .radars
+            .get(key)
+            .map(|r| r.presets.clone())
+            .unwrap_or_default()
+    }
+
+    /// Store or, when `preset` is `None`, remove a preset. Returns the old one.
+    pub(crate) fn set_preset(
+        &mut self,
+        key: &str,
+        name: &str,
+        preset: Option<Preset>,
+    ) -> Option<Preset> {
+        let old = match preset {
+            Some(preset) => self
+                .config
+                .radars
+                .entry(key.to_string())
+                .or_insert(Radar::default())
+                .presets
+                .insert(name.to_string(), preset),
+            None => {
+                let old = self
+                    .config
+                    .radars
+                    .get_mut(key)
+                    .and_then(|radar| radar.presets.remove(name));
+                if old.is_none() {
+                    // Nothing to remove, so nothing to save either
+                    return None;
+                }
+                old
+            }
+        };
+        if !self.path.as_os_str().is_empty() {
+            self.save();
+        }
+        old
+    }
+
     pub(crate) fn update_info_from_persistence(&self, info: &mut RadarInfo) {
         if let Some(p) = self.config.radars.get(&info.key()) {
             if p.model_name.is_some() {
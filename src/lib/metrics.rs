@@ -0,0 +1,364 @@
+//! Health metrics in the Prometheus text format, served at `/metrics`.
+//!
+//! The radar receivers, WebSocket handlers and the recorder bump the counters
+//! here as they go; `render()` adds the values that are already kept
+//! elsewhere, such as the spokes and revolutions in the radar diagnostics,
+//! the AIS vessels and the age of the navigation data.
+
+use std::collections::{BTreeMap, HashSet};
+use std::fmt::Write;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use tokio::sync::broadcast;
+use tokio_graceful_shutdown::SubsystemHandle;
+
+use crate::Brand;
+use crate::navdata;
+use crate::radar::diagnostics::DiagnosticsApi;
+use crate::radar::{RadarError, SharedRadars};
+use crate::stream::SignalKDelta;
+
+/// Targets that exist in the tracker, i.e. not deleted yet, by radar
+static TARGETS: Mutex<BTreeMap<String, HashSet<u64>>> = Mutex::new(BTreeMap::new());
+/// Messages skipped by slow receivers of a broadcast channel, by (channel, radar)
+static LAGGED: Mutex<BTreeMap<(&'static str, String), u64>> = Mutex::new(BTreeMap::new());
+/// Packets that a brand's parser could not make sense of, by brand
+static MALFORMED: Mutex<BTreeMap<String, u64>> = Mutex::new(BTreeMap::new());
+
+static SPOKE_CLIENTS: AtomicUsize = AtomicUsize::new(0);
+static STREAM_CLIENTS: AtomicUsize = AtomicUsize::new(0);
+
+/// Count `n` messages of radar `key` that a receiver of `channel` skipped
+pub fn lagged(channel: &'static str, key: &str, n: u64) {
+    let mut lagged = LAGGED.lock().unwrap();
+    *lagged.entry((channel, key.to_string())).or_default() += n;
+}
+
+/// Forget the metrics of radar `key`, which went away
+pub(crate) fn radar_removed(key: &str) {
+    TARGETS.lock().unwrap().remove(key);
+    LAGGED.lock().unwrap().retain(|(_, radar), _| radar != key);
+}
+
+/// Count a packet that the parser for `brand` dropped
+pub(crate) fn malformed_packet(brand: &Brand) {
+    let mut malformed = MALFORMED.lock().unwrap();
+    *malformed.entry(brand.to_string()).or_default() += 1;
+}
+
+/// The kind of WebSocket a client connected to
+#[derive(Clone, Copy, Debug)]
+pub enum WebSocketKind {
+    Spokes,
+    Stream,
+}
+
+///
+/// Counts a connected WebSocket client for as long as it lives; create one
+/// when the connection is upgraded and keep it until the handler returns.
+///
+pub struct WebSocketClient(WebSocketKind);
+
+impl WebSocketClient {
+    pub fn new(kind: WebSocketKind) -> Self {
+        clients(kind).fetch_add(1, Ordering::Relaxed);
+        WebSocketClient(kind)
+    }
+}
+
+impl Drop for WebSocketClient {
+    fn drop(&mut self) {
+        clients(self.0).fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+fn clients(kind: WebSocketKind) -> &'static AtomicUsize {
+    match kind {
+        WebSocketKind::Spokes => &SPOKE_CLIENTS,
+        WebSocketKind::Stream => &STREAM_CLIENTS,
+    }
+}
+
+///
+/// Keep track of the targets of each radar, from the target updates that
+/// the `TrackerManager` sends to Signal K clients.
+///
+pub async fn count_targets(
+    subsys: SubsystemHandle,
+    mut sk_client_rx: broadcast::Receiver<SignalKDelta>,
+) -> Result<(), RadarError> {
+    loop {
+        tokio::select! { biased;
+            _ = subsys.on_shutdown_requested() => {
+                break;
+            },
+            r = sk_client_rx.recv() => {
+                match r {
+                    Ok(delta) => update_targets(&delta),
+                    Err(broadcast::error::RecvError::Lagged(n)) => {
+                        log::debug!("Target count lagged, skipped {} updates", n);
+                    }
+                    Err(broadcast::error::RecvError::Closed) => break,
+                }
+            },
+        }
+    }
+    Ok(())
+}
+
+fn update_targets(delta: &SignalKDelta) {
+    let mut updates = delta.target_updates().peekable();
+    if updates.peek().is_none() {
+        return;
+    }
+    let mut radars = TARGETS.lock().unwrap();
+    for (radar_id, target_id, value) in updates {
+        let targets = radars.entry(radar_id.to_string()).or_default();
+        if value.is_null() {
+            targets.remove(&target_id);
+        } else {
+            targets.insert(target_id);
+        }
+    }
+}
+
+/// Escape a label value
+fn label(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+///
+/// One metric with its help and type lines, followed by a sample for each of
+/// `samples`, which are the labels (without braces) and the value.
+///
+fn family(out: &mut String, name: &str, kind: &str, help: &str, samples: Vec<(String, f64)>) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} {}", name, kind);
+    for (labels, value) in samples {
+        if labels.is_empty() {
+            let _ = writeln!(out, "{} {}", name, value);
+        } else {
+            let _ = writeln!(out, "{}{{{}}} {}", name, labels, value);
+        }
+    }
+}
+
+/// All metrics, in the Prometheus text exposition format
+pub fn render(radars: &SharedRadars) -> String {
+    let mut out = String::new();
+
+    let active = radars.get_active();
+    family(
+        &mut out,
+        "mayara_radars",
+        "gauge",
+        "Number of active radars",
+        vec![(String::new(), active.len() as f64)],
+    );
+
+    let diagnostics: Vec<(String, DiagnosticsApi)> = active
+        .iter()
+        .map(|info| {
+            let api = info
+                .diagnostics
+                .to_api(info.spokes_per_revolution, info.sparse_spokes);
+            (format!("radar=\"{}\"", label(&info.key())), api)
+        })
+        .collect();
+    let per_radar = |f: &dyn Fn(&DiagnosticsApi) -> Option<f64>| {
+        diagnostics
+            .iter()
+            .filter_map(|(labels, api)| Some((labels.clone(), f(api)?)))
+            .collect::<Vec<_>>()
+    };
+    family(
+        &mut out,
+        "mayara_radar_spokes_total",
+        "counter",
+        "Spokes sent to clients",
+        per_radar(&|api| Some(api.spokes.sent as f64)),
+    );
+    family(
+        &mut out,
+        "mayara_radar_spokes_per_second",
+        "gauge",
+        "Spokes per second, from the spokes of the last revolution and the rotation period",
+        per_radar(&|api| Some(api.spokes.last_revolution? as f64 * 1000. / api.rotation.period?)),
+    );
+    family(
+        &mut out,
+        "mayara_radar_revolutions_total",
+        "counter",
+        "Antenna revolutions",
+        per_radar(&|api| Some(api.rotation.revolutions as f64)),
+    );
+    family(
+        &mut out,
+        "mayara_radar_revolutions_per_second",
+        "gauge",
+        "Revolutions per second, from the rotation period",
+        per_radar(&|api| Some(1000. / api.rotation.period?)),
+    );
+
+    {
+        let targets = TARGETS.lock().unwrap();
+        let samples = active
+            .iter()
+            .map(|info| {
+                let key = info.key();
+                let count = targets.get(&key).map(HashSet::len).unwrap_or_default();
+                (format!("radar=\"{}\"", label(&key)), count as f64)
+            })
+            .collect();
+        family(
+            &mut out,
+            "mayara_radar_targets",
+            "gauge",
+            "ARPA targets being tracked",
+            samples,
+        );
+    }
+
+    let samples = LAGGED
+        .lock()
+        .unwrap()
+        .iter()
+        .map(|((channel, key), n)| {
+            (
+                format!("channel=\"{}\",radar=\"{}\"", channel, label(key)),
+                *n as f64,
+            )
+        })
+        .collect();
+    family(
+        &mut out,
+        "mayara_broadcast_lagged_total",
+        "counter",
+        "Radar messages skipped because a receiver could not keep up",
+        samples,
+    );
+
+    let samples = MALFORMED
+        .lock()
+        .unwrap()
+        .iter()
+        .map(|(brand, n)| (format!("brand=\"{}\"", label(brand)), *n as f64))
+        .collect();
+    family(
+        &mut out,
+        "mayara_malformed_packets_total",
+        "counter",
+        "Packets dropped by the radar protocol parsers",
+        samples,
+    );
+
+    family(
+        &mut out,
+        "mayara_websocket_clients",
+        "gauge",
+        "Connected WebSocket clients",
+        vec![
+            (
+                "kind=\"spokes\"".to_string(),
+                SPOKE_CLIENTS.load(Ordering::Relaxed) as f64,
+            ),
+            (
+                "kind=\"stream\"".to_string(),
+                STREAM_CLIENTS.load(Ordering::Relaxed) as f64,
+            ),
+        ],
+    );
+
+    if let Some(store) = navdata::get_ais_store() {
+        family(
+            &mut out,
+            "mayara_ais_vessels",
+            "gauge",
+            "AIS vessels being passed to clients",
+            vec![(String::new(), store.get_all_active().len() as f64)],
+        );
+    }
+
+    let samples = navdata::get_navigation_state()
+        .into_iter()
+        .filter_map(|(path, value)| Some((format!("path=\"{}\"", path), value.age?)))
+        .collect();
+    family(
+        &mut out,
+        "mayara_navigation_age_seconds",
+        "gauge",
+        "Time since the navigation value was last updated",
+        samples,
+    );
+
+    let samples = navdata::get_signalk_ignored_paths()
+        .into_iter()
+        .map(|(path, count)| (format!("path=\"{}\"", label(&path)), count as f64))
+        .collect();
+    family(
+        &mut out,
+        "mayara_signalk_ignored_values_total",
+        "counter",
+        "Own-ship Signal K values received for paths that are not used",
+        samples,
+    );
+
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_family() {
+        let mut out = String::new();
+        family(
+            &mut out,
+            "mayara_test_total",
+            "counter",
+            "A test",
+            vec![
+                (String::new(), 1.),
+                (format!("radar=\"{}\"", label("a\"b")), 2.5),
+            ],
+        );
+        assert_eq!(
+            out,
+            "# HELP mayara_test_total A test\n\
+             # TYPE mayara_test_total counter\n\
+             mayara_test_total 1\n\
+             mayara_test_total{radar=\"a\\\"b\"} 2.5\n"
+        );
+    }
+
+    #[test]
+    fn test_radar_removed() {
+        lagged("spokes", "test1", 3);
+        lagged("spokes", "test2", 4);
+        TARGETS
+            .lock()
+            .unwrap()
+            .entry("test1".to_string())
+            .or_default()
+            .insert(1);
+        radar_removed("test1");
+        assert!(!TARGETS.lock().unwrap().contains_key("test1"));
+        let lagged = LAGGED.lock().unwrap();
+        assert!(!lagged.contains_key(&("spokes", "test1".to_string())));
+        assert_eq!(lagged[&("spokes", "test2".to_string())], 4);
+    }
+
+    #[test]
+    fn test_websocket_clients() {
+        let before = STREAM_CLIENTS.load(Ordering::Relaxed);
+        let client = WebSocketClient::new(WebSocketKind::Stream);
+        assert_eq!(STREAM_CLIENTS.load(Ordering::Relaxed), before + 1);
+        drop(client);
+        assert_eq!(STREAM_CLIENTS.load(Ordering::Relaxed), before);
+    }
+}
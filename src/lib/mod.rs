@@ -8,23 +8,30 @@ use radar::target::{BlobMessage, TrackerManager};
 use serde::{Serialize, Serializer};
 use std::{
     collections::{HashMap, HashSet},
-    net::Ipv4Addr,
+    net::{Ipv4Addr, SocketAddrV4},
 };
 use tokio::sync::{broadcast, mpsc};
 use tokio_graceful_shutdown::{SubsystemBuilder, SubsystemHandle};
 use utoipa::ToSchema;
 
 pub mod ais;
+pub mod alarm;
+pub mod asterix;
+pub mod auth;
 pub mod brand;
 pub mod config;
 pub mod locator;
+pub mod metrics;
+pub mod mqtt;
 pub mod navdata;
+pub mod nmea_output;
 pub mod pcap;
 pub mod replay;
 pub mod network;
 pub mod protos;
 pub mod radar;
 pub mod recording;
+pub mod signalk;
 pub mod stream;
 pub mod util;
 
@@ -32,9 +39,9 @@ pub const VERSION: &str = env!("CARGO_PKG_VERSION");
 pub const PACKAGE: &str = env!("CARGO_PKG_NAME");
 pub const SIGNALK_RADAR_API_VERSION: &str = env!("SIGNALK_RADAR_API_VERSION");
 
-/// How often the static-position task re-broadcasts the current heading
-/// so late-joining GUI clients can receive it.
-const STATIC_NAV_REBROADCAST_INTERVAL_SECS: u64 = 2;
+/// How often the static-position task sets the navigation data again,
+/// so it does not time out.
+const STATIC_NAV_REFRESH_INTERVAL_SECS: u64 = 2;
 
 #[derive(clap::ValueEnum, Clone, Default, Debug, PartialEq, Serialize)]
 #[serde(rename_all = "kebab-case")]
@@ -78,13 +85,39 @@ pub struct Cli {
     /// - Nothing: all interfaces will search via MDNS
     /// - An interface name: only that interface will seach for via MDNS
     /// - `udp-listen:ipv4-address:port` = listen on (broadcast) address at given port
+    /// - `serial:device[:baud]` = read NMEA 0183 from a serial port (default 4800
+    ///   baud), or NMEA 2000 in Actisense ASCII format with `--nmea2000`
+    /// - `gpsd:host[:port]` = connect to gpsd (default port 2947)
+    ///
+    /// Can be given more than once to use several navigation inputs. An address
+    /// can be preceded by `<name>=`, which then prefixes the source names of the
+    /// values from that input, and by `signalk:`, `nmea0183:` or `nmea2000:`
+    /// to use that protocol for this input instead of the default.
     #[arg(short, long)]
-    pub navigation_address: Option<String>,
+    pub navigation_address: Vec<String>,
 
-    /// Use NMEA 0183 for navigation service instead of Signal K
+    /// Priority of navigation sources for one quantity, as
+    /// `<quantity>=<source>,<source>,...` with quantity `position`, `heading`
+    /// or `cog-sog`. Sources may contain `*` and `?` wildcards. When the active
+    /// source goes stale the next available source takes over.
+    #[arg(long, value_name = "PRIORITY")]
+    pub navigation_priority: Vec<String>,
+
+    /// Use NMEA 0183 for navigation service instead of Signal K, unless the
+    /// navigation address selects another protocol
     #[arg(long)]
     pub nmea0183: bool,
 
+    /// Use NMEA 2000 via a gateway (Yacht Devices RAW or Actisense N2K ASCII
+    /// format) for navigation service instead of Signal K
+    #[arg(long, conflicts_with = "nmea0183")]
+    pub nmea2000: bool,
+
+    /// Seconds after which heading, position, COG and SOG that are no longer
+    /// updated are considered lost
+    #[arg(long, value_name = "SECS", default_value_t = navdata::DEFAULT_NAVIGATION_TIMEOUT_SECS)]
+    pub navigation_timeout: u64,
+
     /// Write RadarMessage data to stdout
     #[arg(long, default_value_t = false)]
     pub output: bool,
@@ -126,11 +159,15 @@ pub struct Cli {
     #[arg(long, default_value_t = false)]
     pub openapi: bool,
 
+    /// Read a password from stdin, print its hash for `auth.json` and exit
+    #[arg(long, default_value_t = false)]
+    pub hash_password: bool,
+
     /// Automatically put detected radars into transmit mode
     #[arg(long, default_value_t = false)]
     pub transmit: bool,
 
-    /// Pass AIS targets from Signal K server to GUI clients
+    /// Pass AIS targets from Signal K server (or NMEA 0183 AIVDM) to GUI clients
     #[arg(long, default_value_t = false)]
     pub pass_ais: bool,
 
@@ -141,6 +178,81 @@ pub struct Cli {
     /// Merge targets from multiple radars into a single shared target list
     #[arg(long, default_value_t = false)]
     pub merge_targets: bool,
+
+    /// Send ARPA targets as NMEA 0183 TTM/TLL sentences, either
+    /// - `udp:ipv4-address:port` = send to (broadcast) address at given port
+    /// - `tcp:ipv4-address:port` = listen for TCP clients on given address and port
+    #[arg(long, value_name = "ADDR")]
+    pub nmea_output: Option<String>,
+
+    /// Send the radar image of one radar as ASTERIX CAT240 video, as
+    /// `<radar>=<ipv4-address>:<port>[,sac=<n>][,sic=<n>][,nic=<ipv4-address>][,bits=<n>]`.
+    /// The SIC defaults to the position of the option, starting at 1, and the
+    /// bits per cell (1, 2, 4 or 8) to what the radar needs.
+    /// Can be given more than once.
+    #[arg(long, value_name = "SPEC")]
+    pub asterix_video: Vec<String>,
+
+    /// Send the ARPA targets of one radar as ASTERIX CAT010 target reports,
+    /// once per revolution, as
+    /// `<radar>=<ipv4-address>:<port>[,sac=<n>][,sic=<n>][,nic=<ipv4-address>]`.
+    /// The SIC defaults to the position of the option, starting at 1.
+    /// Can be given more than once.
+    #[arg(long, value_name = "SPEC")]
+    pub asterix_tracks: Vec<String>,
+
+    /// Receive ASTERIX CAT240 radar video on this multicast group and port,
+    /// each video source (SAC/SIC) shows up as a radar.
+    /// Can be given more than once.
+    #[arg(long, value_name = "ADDR")]
+    pub asterix_input: Vec<SocketAddrV4>,
+
+    /// Signal K server to connect to as a client, as `[ws://]<host>:<port>`.
+    /// Secure (`wss://` or `https://`) servers are not supported.
+    #[arg(long, value_name = "URL")]
+    pub signalk_server: Option<String>,
+
+    /// Access token for the --signalk-server, sent as bearer token.
+    /// Publishing needs a token with write access.
+    #[arg(long, value_name = "TOKEN", requires = "signalk_server")]
+    pub signalk_token: Option<String>,
+
+    /// Publish ARPA targets as vessels, and CPA and guard zone alarms as
+    /// notifications, to the --signalk-server
+    #[arg(long, default_value_t = false, requires = "signalk_server")]
+    pub signalk_publish: bool,
+
+    /// Offer our radars to the --signalk-server, so clients can use the radars
+    /// via that server. Needs the mayara plugin in `signalk-plugin/` there.
+    #[arg(long, default_value_t = false, requires = "signalk_server")]
+    pub signalk_provider: bool,
+
+    /// MQTT broker to publish radar status, ARPA targets and alarms to, as
+    /// `[mqtt://]<host>[:<port>]`
+    #[arg(long, value_name = "HOST")]
+    pub mqtt_broker: Option<String>,
+
+    /// User name for the --mqtt-broker
+    #[arg(long, value_name = "USER", requires = "mqtt_broker")]
+    pub mqtt_username: Option<String>,
+
+    /// Password for the --mqtt-broker
+    #[arg(long, value_name = "PASSWORD", requires = "mqtt_username")]
+    pub mqtt_password: Option<String>,
+
+    /// Prefix of the MQTT topics we publish and subscribe to
+    #[arg(long, value_name = "PREFIX", default_value = "mayara")]
+    pub mqtt_topic: String,
+
+    /// Prefix of the MQTT topics for alarms, default `<mqtt-topic>/alarms`
+    #[arg(long, value_name = "PREFIX", requires = "mqtt_broker")]
+    pub mqtt_alarm_topic: Option<String>,
+
+    /// Take control commands from the `.../controls/<control>/set` topics of
+    /// the --mqtt-broker. Anyone who can publish there can then control the
+    /// radars.
+    #[arg(long, default_value_t = false, requires = "mqtt_broker")]
+    pub mqtt_control: bool,
 }
 
 /// Static position data (latitude, longitude, heading)
@@ -186,6 +298,7 @@ pub enum Brand {
     Raymarine,
     Emulator,
     Playback,
+    Asterix,
 }
 
 impl Brand {
@@ -197,6 +310,7 @@ impl Brand {
             Self::Raymarine => "ray",
             Self::Emulator => "emu",
             Self::Playback => "play",
+            Self::Asterix => "ast",
         }
     }
 }
@@ -210,6 +324,7 @@ impl Into<Brand> for &str {
             "raymarine" => Brand::Raymarine,
             "emulator" => Brand::Emulator,
             "playback" => Brand::Playback,
+            "asterix" => Brand::Asterix,
             _ => panic!("Invalid brand"),
         }
     }
@@ -227,6 +342,7 @@ impl Serialize for Brand {
             Self::Raymarine => serializer.serialize_str("Raymarine"),
             Self::Emulator => serializer.serialize_str("Emulator"),
             Self::Playback => serializer.serialize_str("Playback"),
+            Self::Asterix => serializer.serialize_str("ASTERIX"),
         }
     }
 }
@@ -240,6 +356,7 @@ impl std::fmt::Display for Brand {
             Self::Raymarine => write!(f, "Raymarine"),
             Self::Emulator => write!(f, "Emulator"),
             Self::Playback => write!(f, "Playback"),
+            Self::Asterix => write!(f, "ASTERIX"),
         }
     }
 }
@@ -373,6 +490,12 @@ pub async fn start_session(
     let radars = SharedRadars::new();
     let (tx_interface_request, _) = broadcast::channel(10);
 
+    let mut signalk_server = args.signalk_server.as_ref();
+    if let Some(Err(e)) = signalk_server.map(|server| signalk::server_url(server, "")) {
+        log::error!("--signalk-server ignored: {}", e);
+        signalk_server = None;
+    }
+
     // Initialize target tracker manager if ARPA mode is enabled
     if args.targets == TargetMode::Arpa {
         let (blob_tx, blob_rx) = mpsc::channel::<BlobMessage>(512);
@@ -394,15 +517,115 @@ pub async fn start_session(
                 Ok::<(), miette::Report>(())
             },
         ));
+
+        let sk_client_rx = radars.new_sk_client_subscription();
+        subsystem.start(SubsystemBuilder::new("Target Metrics", |subsys| {
+            metrics::count_targets(subsys, sk_client_rx)
+        }));
+
+        if let Some(nmea_output) = &args.nmea_output {
+            match nmea_output::NmeaOutputAddress::parse(nmea_output) {
+                Ok(address) => {
+                    let output = nmea_output::NmeaOutput::new(address);
+                    let sk_client_rx = radars.new_sk_client_subscription();
+                    subsystem.start(SubsystemBuilder::new("NMEA Output", |subsys| {
+                        output.run(subsys, sk_client_rx)
+                    }));
+                }
+                Err(e) => {
+                    log::error!("--nmea-output ignored: {}", e);
+                }
+            }
+        }
+
+        if let (true, Some(server)) = (args.signalk_publish, signalk_server) {
+            let publisher = signalk::publish::SignalKPublisher::new(
+                server.clone(),
+                args.signalk_token.clone(),
+                radars.new_sk_client_subscription(),
+            );
+            subsystem.start(SubsystemBuilder::new("Signal K Publisher", |subsys| {
+                publisher.run(subsys)
+            }));
+        }
+    } else if args.signalk_publish {
+        log::error!("--signalk-publish ignored: requires --targets arpa");
+    }
+
+    if let (true, Some(server)) = (args.signalk_provider, signalk_server) {
+        let provider = signalk::provider::SignalKProvider::new(
+            server.clone(),
+            args.signalk_token.clone(),
+            radars.clone(),
+        );
+        subsystem.start(SubsystemBuilder::new("Signal K Provider", |subsys| {
+            provider.run(subsys)
+        }));
+    }
+
+    if let Some(broker) = &args.mqtt_broker {
+        let config = mqtt::client::MqttConfig {
+            broker: broker.clone(),
+            username: args.mqtt_username.clone(),
+            password: args.mqtt_password.clone(),
+            topic: args.mqtt_topic.trim_end_matches('/').to_string(),
+            alarm_topic: args
+                .mqtt_alarm_topic
+                .as_ref()
+                .map(|t| t.trim_end_matches('/').to_string()),
+            control: args.mqtt_control,
+        };
+        let client = mqtt::client::MqttClient::new(config, radars.clone());
+        subsystem.start(SubsystemBuilder::new("MQTT Client", |subsys| {
+            client.run(subsys)
+        }));
+    }
+
+    let asterix_video = parse_asterix_specs(&args.asterix_video, "--asterix-video");
+    let mut asterix_tracks = parse_asterix_specs(&args.asterix_tracks, "--asterix-tracks");
+    if !asterix_tracks.is_empty() && args.targets != TargetMode::Arpa {
+        log::error!("--asterix-tracks ignored: requires --targets arpa");
+        asterix_tracks.clear();
+    }
+    let output = asterix::output::AsterixOutput::new(asterix_video, asterix_tracks, radars.clone());
+    if !output.is_empty() {
+        subsystem.start(SubsystemBuilder::new("ASTERIX Output", |subsys| {
+            output.run(subsys)
+        }));
     }
 
     // Initialize navigation broadcast sender so navdata can push updates to GUI clients
     navdata::init_nav_broadcast(radars.get_sk_client_tx());
+    navdata::set_navigation_timeout(std::time::Duration::from_secs(args.navigation_timeout));
+    for priority in &args.navigation_priority {
+        if let Err(e) = navdata::set_navigation_priority(priority) {
+            log::error!("--navigation-priority ignored: {}", e);
+        }
+    }
+
+    // Start background task to report navigation data that is no longer updated
+    subsystem.start(SubsystemBuilder::new(
+        "Navigation Timeout",
+        |subsys| async move {
+            let mut interval = tokio::time::interval(std::time::Duration::from_secs(1));
+            loop {
+                tokio::select! { biased;
+                    _ = subsys.on_shutdown_requested() => {
+                        log::debug!("Navigation timeout task shutdown");
+                        break;
+                    },
+                    _ = interval.tick() => {
+                        navdata::check_timeouts();
+                    }
+                }
+            }
+            Ok::<(), miette::Report>(())
+        },
+    ));
 
     // Seed navigation data from --static-position (for shore-based installations
     // without a connected Signal K/NMEA navigation source). Mirrors the emulator
-    // pattern: set atomics once, then periodically re-broadcast so late-joining
-    // GUI clients receive the current heading/position.
+    // pattern: periodically set the atomics again so they do not time out.
     if let Some(static_pos) = args.get_static_position() {
         if static_pos.lat.is_finite()
             && static_pos.lon.is_finite()
@@ -411,22 +634,25 @@ pub async fn start_session(
             && (-180.0..=180.0).contains(&static_pos.lon)
         {
             let heading_rad = static_pos.heading.to_radians();
-            navdata::set_position(Some(static_pos.lat), Some(static_pos.lon));
-            navdata::set_heading_true(Some(heading_rad), "static");
-            navdata::set_sog(Some(0.0));
-            navdata::set_cog(Some(heading_rad));
+            let set_static_navdata = move || {
+                navdata::set_position(Some(static_pos.lat), Some(static_pos.lon), "static");
+                navdata::set_heading_true(Some(heading_rad), "static");
+                navdata::set_sog(Some(0.0), "static");
+                navdata::set_cog(Some(heading_rad), "static");
+            };
+            set_static_navdata();
 
             subsystem.start(SubsystemBuilder::new(
                 "Static Navigation",
                 |subsys| async move {
                     let mut interval = tokio::time::interval(std::time::Duration::from_secs(
-                        STATIC_NAV_REBROADCAST_INTERVAL_SECS,
+                        STATIC_NAV_REFRESH_INTERVAL_SECS,
                     ));
                     loop {
                         tokio::select! { biased;
                             _ = subsys.on_shutdown_requested() => break,
                             _ = interval.tick() => {
-                                navdata::broadcast_heading("static");
+                                set_static_navdata();
                             }
                         }
                     }
@@ -498,12 +724,26 @@ pub async fn start_session(
     let locator = Locator::new(args.clone(), radars.clone());
 
     let (tx_ip_change, _rx_ip_change) = broadcast::channel(1);
-    let mut navdata = navdata::NavigationData::new(args.clone());
-
-    let rx_ip_change_clone = tx_ip_change.subscribe();
-    subsystem.start(SubsystemBuilder::new("NavData", |subsys| async move {
-        navdata.run(subsys, rx_ip_change_clone).await
-    }));
+    let navigation_inputs: Vec<Option<&str>> = if args.navigation_address.is_empty() {
+        vec![None]
+    } else {
+        args.navigation_address
+            .iter()
+            .map(|a| Some(a.as_str()))
+            .collect()
+    };
+    for (i, input) in navigation_inputs.into_iter().enumerate() {
+        let mut navdata = navdata::NavigationData::new(args.clone(), input);
+        let name = match i {
+            0 => "NavData".to_string(),
+            _ => format!("NavData{}", i + 1),
+        };
+
+        let rx_ip_change_clone = tx_ip_change.subscribe();
+        subsystem.start(SubsystemBuilder::new(name, |subsys| async move {
+            navdata.run(subsys, rx_ip_change_clone).await
+        }));
+    }
     let tx_interface_request_clone = tx_interface_request.clone();
     subsystem.start(SubsystemBuilder::new("Locator", |subsys| {
         locator.run(subsys, tx_ip_change, tx_interface_request_clone)
@@ -525,3 +765,19 @@ pub async fn start_session(
 
     (radars, tx_interface_request)
 }
+
+/// Parse the values of a repeatable ASTERIX output option, logging and
+/// skipping the invalid ones
+fn parse_asterix_specs(values: &[String], option: &str) -> Vec<asterix::AsterixOutputSpec> {
+    values
+        .iter()
+        .enumerate()
+        .filter_map(|(i, s)| match asterix::AsterixOutputSpec::parse(s, i) {
+            Ok(spec) => Some(spec),
+            Err(e) => {
+                log::error!("{} ignored: {}", option, e);
+                None
+            }
+        })
+        .collect()
+}
@@ -0,0 +1,420 @@
+//! The MQTT client subsystem.
+//!
+//! Keeps a connection to the `--mqtt-broker` and, below the `--mqtt-topic`
+//! prefix:
+//! - `status`: `online`, or `offline` via the last will when we go away;
+//! - `radars/<radar>/status`: power, range, model and operating time of each
+//!   radar as JSON, retained, published when any of it changes;
+//! - `radars/<radar>/targets/<target>`: each ARPA target update as JSON, the
+//!   same as the REST API returns, or `null` when the target is deleted;
+//! - `radars/<radar>/controls/<control>/set`: subscribed with
+//!   `--mqtt-control` only, sets the control as client `mqtt`.
+//!
+//! CPA and guard zone alarms go below the `--mqtt-alarm-topic` prefix, with
+//! the dots in their path turned into topic levels.
+
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use serde::Serialize;
+use serde_json::Value;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tokio::sync::broadcast;
+use tokio_graceful_shutdown::SubsystemHandle;
+
+use super::{Packet, Will};
+use crate::alarm::{Alarm, TargetAlarms};
+use crate::radar::settings::{BareControlValue, ControlId};
+use crate::radar::{Power, RadarError, RadarInfo, SharedRadars};
+use crate::signalk::provider::put_control;
+use crate::stream::SignalKDelta;
+use crate::util::{Connection, RADAR_POLL_INTERVAL, reconnect};
+
+/// The port MQTT brokers listen on when the `--mqtt-broker` does not say
+const DEFAULT_PORT: u16 = 1883;
+
+/// The broker disconnects us when it hears nothing for 1.5 times this long
+const KEEP_ALIVE: Duration = Duration::from_secs(30);
+
+/// How long the broker gets to accept a connection
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// The only packet identifier we use, for our single SUBSCRIBE
+const SUBSCRIBE_PACKET_ID: u16 = 1;
+
+/// The client name of control changes from MQTT, for control locks
+const MQTT_CLIENT: &str = "mqtt";
+
+/// Connection and topic settings from the command line
+#[derive(Clone, Debug)]
+pub struct MqttConfig {
+    pub broker: String,
+    pub username: Option<String>,
+    pub password: Option<String>,
+    pub topic: String,
+    pub alarm_topic: Option<String>,
+    /// Take control commands from the `.../set` topics
+    pub control: bool,
+}
+
+///
+/// The `host:port` to connect to for a `--mqtt-broker` given as
+/// `[mqtt://]<host>[:<port>]`.
+///
+fn broker_address(broker: &str) -> String {
+    let host = broker.strip_prefix("mqtt://").unwrap_or(broker);
+    let host = host.trim_end_matches('/');
+    match host.rsplit_once(':') {
+        Some((_, port)) if port.parse::<u16>().is_ok() => host.to_string(),
+        _ => format!("{}:{}", host, DEFAULT_PORT),
+    }
+}
+
+/// The status of a radar, as published in `radars/<radar>/status`
+#[derive(Serialize, Clone, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+struct RadarStatus {
+    name: String,
+    brand: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    model: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    power: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    range: Option<Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    operating_time: Option<Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    transmit_time: Option<Value>,
+}
+
+impl From<&RadarInfo> for RadarStatus {
+    fn from(info: &RadarInfo) -> Self {
+        let value = |id: &ControlId| info.controls.get(id).and_then(|c| c.value());
+        RadarStatus {
+            name: info.controls.user_name(),
+            brand: info.brand.to_string(),
+            model: info.controls.model_name(),
+            power: value(&ControlId::Power)
+                .and_then(|v| Power::from_value(&v).ok())
+                .map(|p| p.to_string()),
+            range: value(&ControlId::Range),
+            operating_time: value(&ControlId::OperatingTime),
+            transmit_time: value(&ControlId::TransmitTime),
+        }
+    }
+}
+
+///
+/// The body of a `.../set` message. A JSON object is taken as the body of a
+/// REST control PUT; anything else as just its value, so that
+/// `mosquitto_pub -m 50` or `-m Transmit` work.
+///
+fn parse_set_payload(payload: &[u8]) -> Result<BareControlValue, String> {
+    let value = match serde_json::from_slice::<Value>(payload) {
+        Ok(value @ Value::Object(_)) => value,
+        Ok(value) => serde_json::json!({ "value": value }),
+        Err(_) => {
+            let s = std::str::from_utf8(payload).map_err(|e| e.to_string())?;
+            serde_json::json!({ "value": s.trim() })
+        }
+    };
+    serde_json::from_value(value).map_err(|e| e.to_string())
+}
+
+///
+/// The subsystem that keeps a connection to the MQTT broker, publishes the
+/// radar status, targets and alarms and passes control commands on.
+///
+pub struct MqttClient {
+    config: MqttConfig,
+    radars: SharedRadars,
+    address: String,
+    client_id: String,
+}
+
+impl MqttClient {
+    pub fn new(config: MqttConfig, radars: SharedRadars) -> Self {
+        MqttClient {
+            address: broker_address(&config.broker),
+            config,
+            radars,
+            client_id: format!("{}-{}", crate::PACKAGE, std::process::id()),
+        }
+    }
+
+    fn topic(&self, suffix: &str) -> String {
+        format!("{}/{}", self.config.topic, suffix)
+    }
+
+    fn alarm_topic(&self, alarm: &Alarm) -> String {
+        let path = alarm.path.replace('.', "/");
+        match &self.config.alarm_topic {
+            Some(prefix) => format!("{}/{}", prefix, path),
+            None => self.topic(&format!("alarms/{}", path)),
+        }
+    }
+
+    fn set_filter(&self) -> String {
+        self.topic("radars/+/controls/+/set")
+    }
+
+    pub async fn run(self, subsys: SubsystemHandle) -> Result<(), RadarError> {
+        reconnect(&subsys, self).await;
+        log::debug!("MQTT client shutdown requested");
+        Ok(())
+    }
+
+    /// Publish the status of radars that changed, and clear that of radars that went away
+    fn publish_status(&self, published: &mut HashMap<String, RadarStatus>, out: &mut Vec<u8>) {
+        let active: HashMap<String, RadarStatus> = self
+            .radars
+            .get_active()
+            .iter()
+            .map(|info| (info.key(), RadarStatus::from(info)))
+            .collect();
+
+        for (key, status) in &active {
+            if published.get(key) != Some(status)
+                && let Ok(json) = serde_json::to_vec(status)
+            {
+                let topic = self.topic(&format!("radars/{}/status", key));
+                out.extend(super::encode_publish(&topic, &json, true));
+            }
+        }
+        for key in published.keys().filter(|k| !active.contains_key(*k)) {
+            // An empty retained message removes the retained status
+            let topic = self.topic(&format!("radars/{}/status", key));
+            out.extend(super::encode_publish(&topic, &[], true));
+        }
+        *published = active;
+    }
+
+    fn publish_targets(&self, delta: &SignalKDelta, alarms: &mut TargetAlarms, out: &mut Vec<u8>) {
+        for (radar_id, target_id, value) in delta.target_updates() {
+            let topic = self.topic(&format!("radars/{}/targets/{}", radar_id, target_id));
+            if let Ok(json) = serde_json::to_vec(value) {
+                out.extend(super::encode_publish(&topic, &json, false));
+            }
+            for alarm in alarms.process_target(radar_id, target_id, value) {
+                if let Ok(json) = serde_json::to_vec(&alarm) {
+                    out.extend(super::encode_publish(
+                        &self.alarm_topic(&alarm),
+                        &json,
+                        false,
+                    ));
+                }
+            }
+        }
+    }
+}
+
+impl Connection for MqttClient {
+    type Stream = (TcpStream, Vec<u8>);
+
+    ///
+    /// Connect to the broker and wait until it accepts us. Returns the stream
+    /// and whatever the broker sent after the CONNACK.
+    ///
+    async fn connect(&self) -> Result<(TcpStream, Vec<u8>), RadarError> {
+        let address = &self.address;
+        let error = |e: &dyn std::fmt::Display| RadarError::Mqtt(format!("{}: {}", address, e));
+        let will = Will {
+            topic: self.topic("status"),
+            payload: b"offline".to_vec(),
+            retain: true,
+        };
+        let connect = super::encode_connect(
+            &self.client_id,
+            self.config.username.as_deref(),
+            self.config.password.as_deref(),
+            KEEP_ALIVE.as_secs() as u16,
+            Some(&will),
+        );
+
+        let handshake = async {
+            let mut stream = TcpStream::connect(address).await.map_err(|e| error(&e))?;
+            stream.write_all(&connect).await.map_err(|e| error(&e))?;
+            let mut buf = Vec::new();
+            loop {
+                if let Some((packet, len)) = super::decode(&buf) {
+                    buf.drain(..len);
+                    return match packet {
+                        Packet::ConnAck { return_code: 0 } => Ok((stream, buf)),
+                        Packet::ConnAck { return_code } => Err(error(&format!(
+                            "connection refused ({})",
+                            connack_reason(return_code)
+                        ))),
+                        _ => Err(error(&"no CONNACK")),
+                    };
+                }
+                if read_some(&mut stream, &mut buf)
+                    .await
+                    .map_err(|e| error(&e))?
+                    == 0
+                {
+                    return Err(error(&"closed the connection"));
+                }
+            }
+        };
+        tokio::time::timeout(CONNECT_TIMEOUT, handshake)
+            .await
+            .map_err(|_| error(&"timeout"))?
+    }
+
+    async fn serve(
+        &mut self,
+        subsys: &SubsystemHandle,
+        (stream, mut buf): (TcpStream, Vec<u8>),
+    ) -> Result<(), RadarError> {
+        log::info!("Connected to MQTT broker {}", self.address);
+        let error =
+            |e: &dyn std::fmt::Display| RadarError::Mqtt(format!("{}: {}", self.config.broker, e));
+        let (mut read, mut write) = stream.into_split();
+
+        let mut sk_client_rx = self.radars.new_sk_client_subscription();
+        let mut alarms = TargetAlarms::new();
+        let mut published: HashMap<String, RadarStatus> = HashMap::new();
+        let mut poll = tokio::time::interval(RADAR_POLL_INTERVAL);
+        let mut ping = tokio::time::interval(KEEP_ALIVE / 2);
+        let mut last_received = Instant::now();
+
+        let set_filter = self.set_filter();
+        let mut outgoing = super::encode_publish(&self.topic("status"), b"online", true);
+        if self.config.control {
+            outgoing.extend(super::encode_subscribe(
+                SUBSCRIBE_PACKET_ID,
+                &[set_filter.as_str()],
+            ));
+        }
+        write.write_all(&outgoing).await.map_err(|e| error(&e))?;
+
+        loop {
+            let mut outgoing: Vec<u8> = Vec::new();
+            tokio::select! { biased;
+                _ = subsys.on_shutdown_requested() => {
+                    // The will is only sent when we disappear without a DISCONNECT
+                    let mut bye = super::encode_publish(&self.topic("status"), b"offline", true);
+                    bye.extend(super::encode_disconnect());
+                    let _ = write.write_all(&bye).await;
+                    return Err(RadarError::Shutdown);
+                },
+                r = read_some(&mut read, &mut buf) => {
+                    match r {
+                        Ok(0) => return Err(error(&"closed the connection")),
+                        Ok(_) => {}
+                        Err(e) => return Err(error(&e)),
+                    }
+                    last_received = Instant::now();
+                    while let Some((packet, len)) = super::decode(&buf) {
+                        buf.drain(..len);
+                        if let Packet::Publish { topic, payload } = packet
+                            && self.config.control
+                            && super::topic_matches(&set_filter, &topic)
+                        {
+                            // Setting a control waits for the radar, which
+                            // must not hold up reading from the broker
+                            tokio::spawn(set_control(self.radars.clone(), topic, payload));
+                        }
+                    }
+                },
+                _ = ping.tick() => {
+                    if last_received.elapsed() > KEEP_ALIVE {
+                        return Err(error(&"no reply to ping"));
+                    }
+                    outgoing.extend(super::encode_pingreq());
+                },
+                _ = poll.tick() => {
+                    self.publish_status(&mut published, &mut outgoing);
+                },
+                r = sk_client_rx.recv() => {
+                    match r {
+                        Ok(delta) => {
+                            self.publish_targets(&delta, &mut alarms, &mut outgoing);
+                        }
+                        Err(broadcast::error::RecvError::Lagged(n)) => {
+                            log::warn!("MQTT client lagged, skipped {} updates", n);
+                        }
+                        Err(broadcast::error::RecvError::Closed) => {
+                            return Err(RadarError::Shutdown);
+                        }
+                    }
+                },
+            }
+            if !outgoing.is_empty() {
+                write.write_all(&outgoing).await.map_err(|e| error(&e))?;
+            }
+        }
+    }
+}
+
+/// Handle a message on `<prefix>/radars/<radar>/controls/<control>/set`
+async fn set_control(radars: SharedRadars, topic: String, payload: Vec<u8>) {
+    let levels: Vec<&str> = topic.rsplitn(5, '/').collect();
+    let (control_id, radar_id) = (levels[1], levels[3]);
+    let value = match parse_set_payload(&payload) {
+        Ok(value) => value,
+        Err(e) => {
+            log::warn!("MQTT {}: {}", topic, e);
+            return;
+        }
+    };
+    log::debug!("MQTT {}: {:?}", topic, value);
+    if let Err((status_code, message)) =
+        put_control(&radars, radar_id, control_id, value, Some(MQTT_CLIENT)).await
+    {
+        log::warn!(
+            "MQTT {}: {}",
+            topic,
+            message.unwrap_or_else(|| format!("status {}", status_code))
+        );
+    }
+}
+
+fn connack_reason(return_code: u8) -> &'static str {
+    match return_code {
+        1 => "unacceptable protocol version",
+        2 => "identifier rejected",
+        3 => "server unavailable",
+        4 => "bad user name or password",
+        5 => "not authorized",
+        _ => "unknown reason",
+    }
+}
+
+async fn read_some<R: AsyncReadExt + Unpin>(
+    read: &mut R,
+    buf: &mut Vec<u8>,
+) -> std::io::Result<usize> {
+    let mut chunk = [0u8; 4096];
+    let n = read.read(&mut chunk).await?;
+    buf.extend_from_slice(&chunk[..n]);
+    Ok(n)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_broker_address() {
+        assert_eq!(broker_address("localhost"), "localhost:1883");
+        assert_eq!(broker_address("192.168.1.5:1884"), "192.168.1.5:1884");
+        assert_eq!(broker_address("mqtt://broker.local/"), "broker.local:1883");
+    }
+
+    #[test]
+    fn test_parse_set_payload() {
+        let v = parse_set_payload(b"50").unwrap();
+        assert_eq!(v.value, Some(serde_json::json!(50)));
+        assert_eq!(v.auto, None);
+
+        let v = parse_set_payload(b"Transmit\n").unwrap();
+        assert_eq!(v.value, Some(serde_json::json!("Transmit")));
+
+        let v = parse_set_payload(br#"{"value": 30, "auto": true}"#).unwrap();
+        assert_eq!(v.value, Some(serde_json::json!(30)));
+        assert_eq!(v.auto, Some(true));
+    }
+}
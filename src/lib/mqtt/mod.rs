@@ -0,0 +1,333 @@
+//! MQTT client for fleet monitoring.
+//!
+//! A small MQTT 3.1.1 client, enough to talk to a broker such as Mosquitto:
+//! we only publish and subscribe at QoS 0, so there are no acknowledgements
+//! to keep track of. The packet encoding and decoding is here, the subsystem
+//! that uses it is in `client`.
+
+pub mod client;
+
+/// MQTT control packet types, in the upper nibble of the first byte
+const CONNECT: u8 = 0x10;
+const CONNACK: u8 = 0x20;
+const PUBLISH: u8 = 0x30;
+const SUBSCRIBE: u8 = 0x82; // with the required flags 0b0010
+const SUBACK: u8 = 0x90;
+const PINGREQ: u8 = 0xc0;
+const PINGRESP: u8 = 0xd0;
+const DISCONNECT: u8 = 0xe0;
+
+/// Protocol level for MQTT 3.1.1
+const PROTOCOL_LEVEL: u8 = 4;
+
+const CONNECT_FLAG_USERNAME: u8 = 0x80;
+const CONNECT_FLAG_PASSWORD: u8 = 0x40;
+const CONNECT_FLAG_WILL_RETAIN: u8 = 0x20;
+const CONNECT_FLAG_WILL: u8 = 0x04;
+const CONNECT_FLAG_CLEAN_SESSION: u8 = 0x02;
+
+const PUBLISH_FLAG_RETAIN: u8 = 0x01;
+
+/// A message that the broker publishes when we disappear
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct Will {
+    pub topic: String,
+    pub payload: Vec<u8>,
+    pub retain: bool,
+}
+
+/// Packets we can receive from the broker
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) enum Packet {
+    ConnAck {
+        return_code: u8,
+    },
+    Publish {
+        topic: String,
+        payload: Vec<u8>,
+    },
+    SubAck {
+        packet_id: u16,
+    },
+    PingResp,
+    /// Anything else, by packet type
+    Other(u8),
+}
+
+fn put_remaining_length(buf: &mut Vec<u8>, mut len: usize) {
+    loop {
+        let mut byte = (len % 128) as u8;
+        len /= 128;
+        if len > 0 {
+            byte |= 0x80;
+        }
+        buf.push(byte);
+        if len == 0 {
+            break;
+        }
+    }
+}
+
+fn put_string(buf: &mut Vec<u8>, s: &[u8]) {
+    buf.extend_from_slice(&(s.len() as u16).to_be_bytes());
+    buf.extend_from_slice(s);
+}
+
+/// Fixed header followed by the variable header and payload
+fn packet(first_byte: u8, body: Vec<u8>) -> Vec<u8> {
+    let mut buf = Vec::with_capacity(body.len() + 5);
+    buf.push(first_byte);
+    put_remaining_length(&mut buf, body.len());
+    buf.extend_from_slice(&body);
+    buf
+}
+
+pub(crate) fn encode_connect(
+    client_id: &str,
+    username: Option<&str>,
+    password: Option<&str>,
+    keep_alive_secs: u16,
+    will: Option<&Will>,
+) -> Vec<u8> {
+    let mut flags = CONNECT_FLAG_CLEAN_SESSION;
+    if let Some(will) = will {
+        flags |= CONNECT_FLAG_WILL;
+        if will.retain {
+            flags |= CONNECT_FLAG_WILL_RETAIN;
+        }
+    }
+    if username.is_some() {
+        flags |= CONNECT_FLAG_USERNAME;
+        if password.is_some() {
+            flags |= CONNECT_FLAG_PASSWORD;
+        }
+    }
+
+    let mut body = Vec::new();
+    put_string(&mut body, b"MQTT");
+    body.push(PROTOCOL_LEVEL);
+    body.push(flags);
+    body.extend_from_slice(&keep_alive_secs.to_be_bytes());
+    put_string(&mut body, client_id.as_bytes());
+    if let Some(will) = will {
+        put_string(&mut body, will.topic.as_bytes());
+        put_string(&mut body, &will.payload);
+    }
+    if let Some(username) = username {
+        put_string(&mut body, username.as_bytes());
+        if let Some(password) = password {
+            put_string(&mut body, password.as_bytes());
+        }
+    }
+    packet(CONNECT, body)
+}
+
+/// PUBLISH at QoS 0
+pub(crate) fn encode_publish(topic: &str, payload: &[u8], retain: bool) -> Vec<u8> {
+    let mut body = Vec::with_capacity(topic.len() + payload.len() + 2);
+    put_string(&mut body, topic.as_bytes());
+    body.extend_from_slice(payload);
+    let flags = if retain { PUBLISH_FLAG_RETAIN } else { 0 };
+    packet(PUBLISH | flags, body)
+}
+
+/// SUBSCRIBE to each of the topic filters at QoS 0
+pub(crate) fn encode_subscribe(packet_id: u16, filters: &[&str]) -> Vec<u8> {
+    let mut body = Vec::new();
+    body.extend_from_slice(&packet_id.to_be_bytes());
+    for filter in filters {
+        put_string(&mut body, filter.as_bytes());
+        body.push(0); // QoS 0
+    }
+    packet(SUBSCRIBE, body)
+}
+
+pub(crate) fn encode_pingreq() -> Vec<u8> {
+    vec![PINGREQ, 0]
+}
+
+pub(crate) fn encode_disconnect() -> Vec<u8> {
+    vec![DISCONNECT, 0]
+}
+
+///
+/// Decode the first packet in `buf`. Returns the packet and its length, or
+/// `None` when `buf` does not hold a complete packet yet.
+///
+pub(crate) fn decode(buf: &[u8]) -> Option<(Packet, usize)> {
+    let first_byte = *buf.first()?;
+
+    let mut len = 0usize;
+    let mut header_len = 1;
+    for i in 0..4 {
+        let byte = *buf.get(1 + i)?;
+        len |= ((byte & 0x7f) as usize) << (7 * i);
+        header_len += 1;
+        if byte & 0x80 == 0 {
+            break;
+        }
+    }
+    let total = header_len + len;
+    let body = buf.get(header_len..total)?;
+
+    let packet = match first_byte & 0xf0 {
+        CONNACK if body.len() >= 2 => Packet::ConnAck {
+            return_code: body[1],
+        },
+        PUBLISH => decode_publish(first_byte, body).unwrap_or(Packet::Other(PUBLISH)),
+        SUBACK if body.len() >= 2 => Packet::SubAck {
+            packet_id: u16::from_be_bytes([body[0], body[1]]),
+        },
+        PINGRESP => Packet::PingResp,
+        t => Packet::Other(t),
+    };
+    Some((packet, total))
+}
+
+fn decode_publish(first_byte: u8, body: &[u8]) -> Option<Packet> {
+    let topic_len = u16::from_be_bytes([*body.first()?, *body.get(1)?]) as usize;
+    let topic = std::str::from_utf8(body.get(2..2 + topic_len)?).ok()?;
+    let mut offset = 2 + topic_len;
+    // QoS 1 and 2 messages have a packet identifier
+    if (first_byte >> 1) & 0x03 > 0 {
+        offset += 2;
+    }
+    Some(Packet::Publish {
+        topic: topic.to_string(),
+        payload: body.get(offset..)?.to_vec(),
+    })
+}
+
+///
+/// Does `topic` match the subscription `filter`, with its `+` (one level)
+/// and `#` (all remaining levels) wildcards?
+///
+pub(crate) fn topic_matches(filter: &str, topic: &str) -> bool {
+    let mut topic_levels = topic.split('/');
+    for level in filter.split('/') {
+        match level {
+            "#" => return true,
+            "+" => {
+                if topic_levels.next().is_none() {
+                    return false;
+                }
+            }
+            level => {
+                if topic_levels.next() != Some(level) {
+                    return false;
+                }
+            }
+        }
+    }
+    topic_levels.next().is_none()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_remaining_length() {
+        for (len, expected) in [
+            (0, vec![0x00]),
+            (127, vec![0x7f]),
+            (128, vec![0x80, 0x01]),
+            (16383, vec![0xff, 0x7f]),
+            (2097152, vec![0x80, 0x80, 0x80, 0x01]),
+        ] {
+            let mut buf = Vec::new();
+            put_remaining_length(&mut buf, len);
+            assert_eq!(buf, expected, "length {}", len);
+        }
+    }
+
+    #[test]
+    fn test_connect() {
+        let will = Will {
+            topic: "m/s".to_string(),
+            payload: b"off".to_vec(),
+            retain: true,
+        };
+        let p = encode_connect("id", Some("u"), Some("p"), 30, Some(&will));
+        assert_eq!(
+            p,
+            vec![
+                0x10, 30, // fixed header
+                0, 4, b'M', b'Q', b'T', b'T', 4, 0xe6, 0, 30, // variable header
+                0, 2, b'i', b'd', // client id
+                0, 3, b'm', b'/', b's', 0, 3, b'o', b'f', b'f', // will
+                0, 1, b'u', 0, 1, b'p', // username and password
+            ]
+        );
+        let p = encode_connect("id", None, None, 30, None);
+        assert_eq!(p[9], CONNECT_FLAG_CLEAN_SESSION);
+    }
+
+    #[test]
+    fn test_publish_round_trip() {
+        let p = encode_publish("mayara/status", b"online", true);
+        assert_eq!(p[0], 0x31);
+        let (packet, len) = decode(&p).unwrap();
+        assert_eq!(len, p.len());
+        assert_eq!(
+            packet,
+            Packet::Publish {
+                topic: "mayara/status".to_string(),
+                payload: b"online".to_vec()
+            }
+        );
+
+        // Incomplete packets are not decoded
+        assert_eq!(decode(&p[..p.len() - 1]), None);
+        assert_eq!(decode(&[]), None);
+    }
+
+    #[test]
+    fn test_decode() {
+        let buf = [0x20, 2, 0, 5, 0x90, 3, 0, 7, 0, 0xd0, 0];
+        let (packet, len) = decode(&buf).unwrap();
+        assert_eq!(packet, Packet::ConnAck { return_code: 5 });
+        let (packet, len2) = decode(&buf[len..]).unwrap();
+        assert_eq!(packet, Packet::SubAck { packet_id: 7 });
+        let (packet, _) = decode(&buf[len + len2..]).unwrap();
+        assert_eq!(packet, Packet::PingResp);
+
+        // QoS 1 publish has a packet identifier after the topic
+        let buf = [0x32, 7, 0, 1, b't', 0, 9, b'h', b'i'];
+        let (packet, _) = decode(&buf).unwrap();
+        assert_eq!(
+            packet,
+            Packet::Publish {
+                topic: "t".to_string(),
+                payload: b"hi".to_vec()
+            }
+        );
+    }
+
+    #[test]
+    fn test_subscribe() {
+        let p = encode_subscribe(1, &["a/+/set"]);
+        assert_eq!(
+            p,
+            vec![
+                0x82, 12, 0, 1, 0, 7, b'a', b'/', b'+', b'/', b's', b'e', b't', 0
+            ]
+        );
+    }
+
+    #[test]
+    fn test_topic_matches() {
+        let filter = "mayara/radars/+/controls/+/set";
+        assert!(topic_matches(
+            filter,
+            "mayara/radars/nav1/controls/gain/set"
+        ));
+        assert!(!topic_matches(filter, "mayara/radars/nav1/controls/gain"));
+        assert!(!topic_matches(
+            filter,
+            "mayara/radars/nav1/controls/gain/set/x"
+        ));
+        assert!(topic_matches("mayara/#", "mayara/radars/nav1"));
+        assert!(!topic_matches("other/#", "mayara/radars/nav1"));
+    }
+}
@@ -1,17 +1,19 @@
 use atomic_float::AtomicF64;
+use chrono::{DateTime, Utc};
 use futures_util::future::select_ok;
 use mdns_sd::{Error, IfKind, ServiceDaemon, ServiceEvent};
 use nmea_parser::*;
+use serde::Serialize;
 use serde_json::Value;
 use std::{
-    collections::HashSet,
+    collections::{BTreeMap, HashMap, HashSet},
     future::Future,
     io::ErrorKind,
     net::SocketAddr,
     pin::Pin,
     sync::{
-        OnceLock, RwLock,
-        atomic::{AtomicBool, Ordering},
+        Mutex, OnceLock, RwLock,
+        atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering},
     },
     time::Duration,
 };
@@ -19,21 +21,367 @@ use tokio::{io::AsyncBufReadExt, net::UdpSocket, time::sleep};
 use tokio::{io::AsyncWriteExt, net::TcpStream};
 use tokio::{io::BufReader, sync::broadcast::Receiver};
 use tokio_graceful_shutdown::SubsystemHandle;
+use utoipa::ToSchema;
+use wildmatch::WildMatch;
 
 use crate::{
     Cli,
     ais::AisVesselStore,
-    radar::{GeoPosition, RadarError},
+    radar::{GeoPosition, RadarError, target::KN_TO_MS},
     stream::SignalKDelta,
+    util::now_millis,
 };
 
+mod gpsd;
+mod history;
+mod nmea2000;
+mod serial;
+mod wmm;
+
+use history::History;
+
 static HEADING_TRUE: AtomicF64 = AtomicF64::new(f64::NAN);
+/// When the true heading was last set from a source that reports it directly (millis since epoch)
+static HEADING_TRUE_DIRECT_TIME: AtomicU64 = AtomicU64::new(0);
+static HEADING_MAGNETIC: AtomicF64 = AtomicF64::new(f64::NAN);
+/// Magnetic variation as reported by the navigation source, radians positive east
+static MAGNETIC_VARIATION: AtomicF64 = AtomicF64::new(f64::NAN);
 static POSITION_VALID: AtomicBool = AtomicBool::new(false);
 static POSITION_LAT: AtomicF64 = AtomicF64::new(f64::NAN);
 static POSITION_LON: AtomicF64 = AtomicF64::new(f64::NAN);
 static COG: AtomicF64 = AtomicF64::new(f64::NAN);
 static SOG: AtomicF64 = AtomicF64::new(f64::NAN);
 
+/// Recent true headings in radians, to look up the heading at the time of a spoke
+static HEADING_TRUE_HISTORY: Mutex<History<f64>> = Mutex::new(History::new());
+/// Recent positions (lat, lon) in degrees, to look up the position at the time of a spoke
+static POSITION_HISTORY: Mutex<History<(f64, f64)>> = Mutex::new(History::new());
+/// Recent rate of turn reports in radians per second, positive to starboard
+static RATE_OF_TURN_HISTORY: Mutex<History<f64>> = Mutex::new(History::new());
+
+/// Default maximum age of navigation data before it is considered lost
+pub const DEFAULT_NAVIGATION_TIMEOUT_SECS: u64 = 10;
+
+/// Maximum age of navigation data (millis) before it is considered lost
+static NAVIGATION_TIMEOUT: AtomicU64 = AtomicU64::new(DEFAULT_NAVIGATION_TIMEOUT_SECS * 1000);
+
+///
+/// When a navigation quantity was last set and by which source, so that
+/// values from a source that stopped sending are not used forever.
+///
+/// Only one source is active per quantity. Values from other sources are
+/// ignored, unless they have a higher priority or the active source went
+/// stale, in which case they take over.
+///
+struct Freshness {
+    /// Signal K path used to broadcast the loss of the quantity
+    path: &'static str,
+    /// When the quantity was last set (millis since epoch), 0 = never or cleared
+    time: AtomicU64,
+    /// The active source
+    source: RwLock<String>,
+    /// Priority of the active source, lower is better
+    rank: AtomicUsize,
+    /// Source name patterns in order of priority
+    priority: RwLock<Vec<String>>,
+    /// Whether the loss has been broadcast since the quantity went stale
+    lost: AtomicBool,
+}
+
+impl Freshness {
+    const fn new(path: &'static str) -> Self {
+        Freshness {
+            path,
+            time: AtomicU64::new(0),
+            source: RwLock::new(String::new()),
+            rank: AtomicUsize::new(0),
+            priority: RwLock::new(Vec::new()),
+            lost: AtomicBool::new(false),
+        }
+    }
+
+    fn set_priority(&self, patterns: Vec<String>) {
+        *self.priority.write().unwrap() = patterns;
+    }
+
+    /// The priority of `source`, sources that match no pattern come last
+    fn rank(&self, source: &str) -> usize {
+        let priority = self.priority.read().unwrap();
+        priority
+            .iter()
+            .position(|pattern| WildMatch::new(pattern).matches(source))
+            .unwrap_or(priority.len())
+    }
+
+    ///
+    /// Whether a value from `source` is to be used: when it comes from the
+    /// active source, a source with a higher priority, or when the active
+    /// source went stale.
+    ///
+    fn accept(&self, source: &str) -> bool {
+        if !self.is_fresh() {
+            return true;
+        }
+        let rank = self.rank(source);
+        let active_rank = self.rank.load(Ordering::Acquire);
+        if rank != active_rank {
+            return rank < active_rank;
+        }
+        let Ok(active) = self.source.read() else {
+            return true;
+        };
+        // A value derived from other quantities, such as a true heading from
+        // a magnetic heading plus variation, is named `<source>+<how>`.
+        // It is still the same source, but a value that is reported directly
+        // replaces it.
+        let base = |s: &str| s.split('+').next().unwrap_or_default().to_string();
+        base(&active) == base(source) || (active.contains('+') && !source.contains('+'))
+    }
+
+    ///
+    /// Record that the quantity was set by `source`. Returns whether `source`
+    /// just became the active source.
+    ///
+    fn update(&self, source: &str) -> bool {
+        let was_fresh = self.is_fresh();
+        let mut changed = !was_fresh;
+        self.time.store(now_millis(), Ordering::Release);
+        if let Ok(mut guard) = self.source.write() {
+            if *guard != source {
+                if was_fresh {
+                    log::info!("{}: source '{}' replaces '{}'", self.path, source, guard);
+                }
+                guard.clear();
+                guard.push_str(source);
+                changed = true;
+            }
+        }
+        if changed {
+            self.rank.store(self.rank(source), Ordering::Release);
+        }
+        self.lost.store(false, Ordering::Release);
+        changed
+    }
+
+    ///
+    /// Forget the value because its source reports that it is not available,
+    /// and let clients know right away instead of after the timeout.
+    ///
+    fn clear(&self) {
+        let was_fresh = self.is_fresh();
+        self.time.store(0, Ordering::Release);
+        if was_fresh && !self.lost.swap(true, Ordering::AcqRel) {
+            if let Ok(source) = self.source.read() {
+                log::warn!("Lost {}: not available from '{}'", self.path, source);
+                broadcast_nav_loss(self.path, &source);
+            }
+        }
+    }
+
+    fn is_fresh(&self) -> bool {
+        let time = self.time.load(Ordering::Acquire);
+        time != 0 && now_millis().saturating_sub(time) <= NAVIGATION_TIMEOUT.load(Ordering::Relaxed)
+    }
+
+    fn source(&self) -> Option<String> {
+        if !self.is_fresh() {
+            return None;
+        }
+        self.source.read().ok().map(|guard| guard.clone())
+    }
+
+    /// The source that last set the quantity, even when it went stale
+    fn last_source(&self) -> Option<String> {
+        self.source
+            .read()
+            .ok()
+            .filter(|guard| !guard.is_empty())
+            .map(|guard| guard.clone())
+    }
+
+    /// Time since the quantity was last set
+    fn age(&self) -> Option<Duration> {
+        match self.time.load(Ordering::Acquire) {
+            0 => None,
+            time => Some(Duration::from_millis(now_millis().saturating_sub(time))),
+        }
+    }
+
+    ///
+    /// Returns the source of the quantity if it went stale since it was last
+    /// set, and this has not been reported yet.
+    ///
+    fn check_lost(&self) -> Option<String> {
+        if self.time.load(Ordering::Acquire) == 0 || self.is_fresh() {
+            return None;
+        }
+        if self.lost.swap(true, Ordering::AcqRel) {
+            return None;
+        }
+        self.source.read().ok().map(|guard| guard.clone())
+    }
+}
+
+static HEADING_TRUE_FRESHNESS: Freshness = Freshness::new("navigation.headingTrue");
+static POSITION_FRESHNESS: Freshness = Freshness::new("navigation.position");
+static COG_FRESHNESS: Freshness = Freshness::new("navigation.courseOverGroundTrue");
+static SOG_FRESHNESS: Freshness = Freshness::new("navigation.speedOverGround");
+static MAGNETIC_VARIATION_FRESHNESS: Freshness = Freshness::new("navigation.magneticVariation");
+
+/// The active source and the configured source priority of a navigation quantity
+#[derive(Serialize, Clone, Debug, ToSchema)]
+pub struct NavigationSourceApi {
+    /// Source that currently provides the value, absent when there is none
+    #[schema(example = "nmea0183")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub source: Option<String>,
+    /// Source name patterns in order of priority
+    #[schema(example = json!(["gps1.*", "gps2.*"]))]
+    pub priority: Vec<String>,
+}
+
+///
+/// Set the priority of the sources for one quantity, from a specification
+/// like `heading=compass1.*,compass2.*`. The quantity is `position`,
+/// `heading` or `cog-sog`, the sources are Signal K `$source` names or
+/// NMEA 0183 source names, optionally with `*` and `?` wildcards.
+///
+pub fn set_navigation_priority(spec: &str) -> Result<(), String> {
+    let (quantity, sources) = spec
+        .split_once('=')
+        .ok_or_else(|| format!("'{}' is not of the form <quantity>=<source>,...", spec))?;
+    let patterns: Vec<String> = sources
+        .split(',')
+        .map(|s| s.trim().to_string())
+        .filter(|s| !s.is_empty())
+        .collect();
+    match quantity {
+        "position" => POSITION_FRESHNESS.set_priority(patterns),
+        "heading" => HEADING_TRUE_FRESHNESS.set_priority(patterns),
+        "cog-sog" => {
+            COG_FRESHNESS.set_priority(patterns.clone());
+            SOG_FRESHNESS.set_priority(patterns);
+        }
+        _ => {
+            return Err(format!(
+                "Unknown quantity '{}', expected position, heading or cog-sog",
+                quantity
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Get the active source and source priority for each navigation quantity, by Signal K path
+pub fn get_navigation_sources() -> BTreeMap<&'static str, NavigationSourceApi> {
+    [
+        &HEADING_TRUE_FRESHNESS,
+        &POSITION_FRESHNESS,
+        &COG_FRESHNESS,
+        &SOG_FRESHNESS,
+    ]
+    .into_iter()
+    .map(|freshness| {
+        (
+            freshness.path,
+            NavigationSourceApi {
+                source: freshness.source(),
+                priority: freshness.priority.read().unwrap().clone(),
+            },
+        )
+    })
+    .collect()
+}
+
+/// The current value of a navigation quantity, with its source, age and validity
+#[derive(Serialize, Clone, Debug, ToSchema)]
+pub struct NavigationValueApi {
+    /// Last known value: radians for angles, m/s for speed, an object with
+    /// latitude and longitude in degrees for position. Null when no value
+    /// was received, or when the source reported it as not available.
+    #[schema(example = 1.5708)]
+    pub value: Value,
+    /// Source of the last value
+    #[schema(example = "nmea0183")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub source: Option<String>,
+    /// Seconds since the value was last updated
+    #[schema(example = 0.4)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub age: Option<f64>,
+    /// Whether the value is in use, i.e. it was updated within the navigation timeout
+    pub valid: bool,
+}
+
+fn navigation_value(freshness: &Freshness, value: Value) -> NavigationValueApi {
+    NavigationValueApi {
+        valid: freshness.is_fresh() && !value.is_null(),
+        value,
+        source: freshness.last_source(),
+        age: freshness.age().map(|age| age.as_secs_f64()),
+    }
+}
+
+/// Get the current own-ship navigation values, by Signal K path
+pub fn get_navigation_state() -> BTreeMap<&'static str, NavigationValueApi> {
+    let position = match POSITION_VALID.load(Ordering::Acquire) {
+        true => serde_json::json!({
+            "latitude": POSITION_LAT.load(Ordering::Acquire),
+            "longitude": POSITION_LON.load(Ordering::Acquire),
+        }),
+        false => Value::Null,
+    };
+    // Value::from() turns NaN, the value for "not available", into null
+    [
+        (
+            &HEADING_TRUE_FRESHNESS,
+            Value::from(HEADING_TRUE.load(Ordering::Acquire)),
+        ),
+        (&POSITION_FRESHNESS, position),
+        (&COG_FRESHNESS, Value::from(COG.load(Ordering::Acquire))),
+        (&SOG_FRESHNESS, Value::from(SOG.load(Ordering::Acquire))),
+    ]
+    .into_iter()
+    .map(|(freshness, value)| (freshness.path, navigation_value(freshness, value)))
+    .collect()
+}
+
+///
+/// Set the maximum age of navigation data. Heading, position, COG and SOG that
+/// have not been updated for longer than this are treated as not available.
+///
+pub fn set_navigation_timeout(timeout: Duration) {
+    NAVIGATION_TIMEOUT.store(timeout.as_millis() as u64, Ordering::Relaxed);
+}
+
+///
+/// Check whether any navigation quantity went stale, and if so broadcast
+/// its loss so clients can show that it is no longer available.
+/// Called periodically.
+///
+pub fn check_timeouts() -> usize {
+    let mut lost_count = 0;
+    for freshness in [
+        &HEADING_TRUE_FRESHNESS,
+        &POSITION_FRESHNESS,
+        &COG_FRESHNESS,
+        &SOG_FRESHNESS,
+        &MAGNETIC_VARIATION_FRESHNESS,
+    ] {
+        if let Some(source) = freshness.check_lost() {
+            log::warn!(
+                "Lost {}: no update from '{}' for more than {} ms",
+                freshness.path,
+                source,
+                NAVIGATION_TIMEOUT.load(Ordering::Relaxed)
+            );
+            broadcast_nav_loss(freshness.path, &source);
+            lost_count += 1;
+        }
+    }
+    lost_count
+}
+
 /// Broadcast sender for navigation updates to GUI clients
 static NAV_BROADCAST_TX: OnceLock<tokio::sync::broadcast::Sender<SignalKDelta>> = OnceLock::new();
 
@@ -52,6 +400,24 @@ fn broadcast_nav_update(path: &str, value: f64, source: &str) {
     }
 }
 
+/// Broadcast the position to subscribed clients
+fn broadcast_nav_position(lat: f64, lon: f64, source: &str) {
+    if let Some(tx) = NAV_BROADCAST_TX.get() {
+        let mut delta = SignalKDelta::new();
+        delta.add_navigation_position(lat, lon, source);
+        let _ = tx.send(delta);
+    }
+}
+
+/// Broadcast that a navigation value is no longer available
+fn broadcast_nav_loss(path: &str, source: &str) {
+    if let Some(tx) = NAV_BROADCAST_TX.get() {
+        let mut delta = SignalKDelta::new();
+        delta.add_navigation_loss(path, source);
+        let _ = tx.send(delta);
+    }
+}
+
 /// Own-ship context detected from Signal K server (when pass_ais is enabled)
 static OWN_SHIP_CONTEXT: OnceLock<RwLock<Option<String>>> = OnceLock::new();
 
@@ -100,18 +466,33 @@ fn update_ais_vessel(context: &str, updates: &Value) {
 ///
 pub(crate) fn get_heading_true() -> Option<f64> {
     let heading = HEADING_TRUE.load(Ordering::Acquire);
-    if !heading.is_nan() {
+    if !heading.is_nan() && HEADING_TRUE_FRESHNESS.is_fresh() {
         return Some(heading);
     }
     return None;
 }
 
+/// How long a directly reported true heading takes precedence over a true
+/// heading derived from a magnetic heading
+const HEADING_TRUE_PREFERENCE: Duration = Duration::from_secs(3);
+
 ///
 /// Set the heading in radians [0..2*PI>
 ///
 pub(crate) fn set_heading_true(heading: Option<f64>, source: &str) {
+    if heading.is_some() && HEADING_TRUE_FRESHNESS.accept(source) {
+        HEADING_TRUE_DIRECT_TIME.store(now_millis(), Ordering::Release);
+    }
+    store_heading_true(heading, source);
+}
+
+fn store_heading_true(heading: Option<f64>, source: &str) {
     use std::f64::consts::TAU;
 
+    if !HEADING_TRUE_FRESHNESS.accept(source) {
+        log::trace!("Heading from '{}' ignored, other source active", source);
+        return;
+    }
     if let Some(h) = heading {
         assert!(
             h > -TAU && h < 2.0 * TAU,
@@ -121,25 +502,140 @@ pub(crate) fn set_heading_true(heading: Option<f64>, source: &str) {
         let h = h.rem_euclid(TAU);
 
         let old = HEADING_TRUE.swap(h, Ordering::AcqRel);
+        let new_source = HEADING_TRUE_FRESHNESS.update(source);
+        HEADING_TRUE_HISTORY.lock().unwrap().push(now_millis(), h);
         // Only broadcast if value changed significantly (> 0.001 rad ~ 0.06 deg)
-        if (old - h).abs() > 0.001 || old.is_nan() {
+        if (old - h).abs() > 0.001 || old.is_nan() || new_source {
             broadcast_nav_update("navigation.headingTrue", h, source);
         }
     } else {
         HEADING_TRUE.store(f64::NAN, Ordering::Release);
+        HEADING_TRUE_FRESHNESS.clear();
+        HEADING_TRUE_HISTORY.lock().unwrap().clear();
     }
 }
 
-/// Force broadcast the current heading value (for emulator to ensure GUI receives heading)
-pub(crate) fn broadcast_heading(source: &str) {
-    let h = HEADING_TRUE.load(Ordering::Acquire);
-    if !h.is_nan() {
-        broadcast_nav_update("navigation.headingTrue", h, source);
+///
+/// Get the true heading in radians [0..2*PI> at `time` (millis since epoch),
+/// interpolated from the recent headings, or extrapolated with the rate of
+/// turn when `time` is more recent than the last heading.
+///
+pub(crate) fn get_heading_true_at(time: u64) -> Option<f64> {
+    if !HEADING_TRUE_FRESHNESS.is_fresh() {
+        return None;
+    }
+    let headings = HEADING_TRUE_HISTORY.lock().unwrap();
+    let rates_of_turn = RATE_OF_TURN_HISTORY.lock().unwrap();
+    history::heading_at(&headings, &rates_of_turn, time)
+}
+
+///
+/// Set the rate of turn in radians per second, positive to starboard
+///
+pub(crate) fn set_rate_of_turn(rate_of_turn: Option<f64>, source: &str) {
+    match rate_of_turn.filter(|r| r.is_finite()) {
+        Some(r) => {
+            RATE_OF_TURN_HISTORY.lock().unwrap().push(now_millis(), r);
+            log::trace!("navdata::set_rate_of_turn({}) from '{}'", r, source);
+        }
+        None => {
+            RATE_OF_TURN_HISTORY.lock().unwrap().clear();
+        }
+    }
+}
+
+///
+/// Set the true heading as seen in the spokes of a radar. Spoke bearings are
+/// usually derived from our own heading, so this only takes over when there
+/// is no other heading source, or the radar itself is that source. Otherwise
+/// a lost compass would never time out.
+///
+pub(crate) fn set_heading_true_from_radar(heading: f64, radar_key: &str) {
+    match HEADING_TRUE_FRESHNESS.source() {
+        Some(source) if source != radar_key => {}
+        _ => set_heading_true(Some(heading), radar_key),
+    }
+}
+
+///
+/// Set the magnetic heading in radians [0..2*PI>, and derive the true heading
+/// from it using the magnetic variation. A true heading that is reported
+/// directly (HDT, THS, Signal K headingTrue, radar) takes precedence.
+///
+pub(crate) fn set_heading_magnetic(heading: Option<f64>, source: &str) {
+    use std::f64::consts::TAU;
+
+    let Some(h) = heading.filter(|h| h.is_finite()) else {
+        HEADING_MAGNETIC.store(f64::NAN, Ordering::Release);
+        return;
+    };
+    let h = h.rem_euclid(TAU);
+    let old = HEADING_MAGNETIC.swap(h, Ordering::AcqRel);
+    if (old - h).abs() > 0.001 || old.is_nan() {
+        broadcast_nav_update("navigation.headingMagnetic", h, source);
+    }
+
+    let last_direct = HEADING_TRUE_DIRECT_TIME.load(Ordering::Acquire);
+    if now_millis().saturating_sub(last_direct) < HEADING_TRUE_PREFERENCE.as_millis() as u64 {
+        return;
+    }
+
+    match get_magnetic_variation_with_source() {
+        Some((variation, variation_source)) => {
+            let source = format!("{}+{}", source, variation_source);
+            store_heading_true(Some((h + variation).rem_euclid(TAU)), &source);
+        }
+        None => {
+            log::debug!(
+                "Magnetic heading from '{}' ignored: no variation or position known",
+                source
+            );
+        }
+    }
+}
+
+///
+/// Get the magnetic variation in radians, positive east, and where it came
+/// from: the variation reported by the navigation source, or when that is
+/// not available or stale the World Magnetic Model value at the current
+/// position.
+///
+fn get_magnetic_variation_with_source() -> Option<(f64, &'static str)> {
+    let variation = MAGNETIC_VARIATION.load(Ordering::Acquire);
+    if !variation.is_nan() && MAGNETIC_VARIATION_FRESHNESS.is_fresh() {
+        return Some((variation, "variation"));
+    }
+    let position = get_radar_position()?;
+    let year = wmm::decimal_year(&Utc::now());
+    let declination = wmm::declination(position.lat(), position.lon(), year);
+    Some((declination, "wmm"))
+}
+
+///
+/// Set the magnetic variation in radians, positive east
+///
+pub(crate) fn set_magnetic_variation(variation: Option<f64>, source: &str) {
+    if !MAGNETIC_VARIATION_FRESHNESS.accept(source) {
+        log::trace!("Variation from '{}' ignored, other source active", source);
+        return;
+    }
+    match variation.filter(|v| v.is_finite() && v.abs() <= std::f64::consts::PI) {
+        Some(v) => {
+            let old = MAGNETIC_VARIATION.swap(v, Ordering::AcqRel);
+            let new_source = MAGNETIC_VARIATION_FRESHNESS.update(source);
+            if (old - v).abs() > 0.001 || old.is_nan() || new_source {
+                broadcast_nav_update("navigation.magneticVariation", v, source);
+            }
+        }
+        None => {
+            MAGNETIC_VARIATION.store(f64::NAN, Ordering::Release);
+            MAGNETIC_VARIATION_FRESHNESS.clear();
+        }
     }
 }
 
 pub fn get_radar_position() -> Option<GeoPosition> {
-    if POSITION_VALID.load(Ordering::Acquire) {
+    if POSITION_VALID.load(Ordering::Acquire) && POSITION_FRESHNESS.is_fresh() {
         let lat = POSITION_LAT.load(Ordering::Acquire);
         let lon = POSITION_LON.load(Ordering::Acquire);
         return Some(GeoPosition::new(lat, lon));
@@ -148,7 +644,7 @@ pub fn get_radar_position() -> Option<GeoPosition> {
 }
 
 pub(crate) fn get_position() -> (Option<f64>, Option<f64>) {
-    if POSITION_VALID.load(Ordering::Acquire) {
+    if POSITION_VALID.load(Ordering::Acquire) && POSITION_FRESHNESS.is_fresh() {
         let lat = POSITION_LAT.load(Ordering::Acquire);
         let lon = POSITION_LON.load(Ordering::Acquire);
         log::trace!("navdata::get_position() -> lat={}, lon={}", lat, lon);
@@ -157,63 +653,108 @@ pub(crate) fn get_position() -> (Option<f64>, Option<f64>) {
     return (None, None);
 }
 
-pub(crate) fn set_position(lat: Option<f64>, lon: Option<f64>) {
+pub(crate) fn set_position(lat: Option<f64>, lon: Option<f64>, source: &str) {
+    if !POSITION_FRESHNESS.accept(source) {
+        log::trace!("Position from '{}' ignored, other source active", source);
+        return;
+    }
     if let (Some(lat), Some(lon)) = (lat, lon) {
         log::trace!("navdata::set_position(lat={}, lon={})", lat, lon);
         POSITION_LAT.store(lat, Ordering::Release);
         POSITION_LON.store(lon, Ordering::Release);
         POSITION_VALID.store(true, Ordering::Release);
+        if POSITION_FRESHNESS.update(source) {
+            broadcast_nav_position(lat, lon, source);
+        }
+        POSITION_HISTORY
+            .lock()
+            .unwrap()
+            .push(now_millis(), (lat, lon));
     } else {
         POSITION_VALID.store(false, Ordering::Release);
+        POSITION_FRESHNESS.clear();
+        POSITION_HISTORY.lock().unwrap().clear();
         return;
     }
 }
 
+///
+/// Get the position at `time` (millis since epoch), interpolated from the
+/// recent positions.
+///
+pub(crate) fn get_position_at(time: u64) -> (Option<f64>, Option<f64>) {
+    if !POSITION_VALID.load(Ordering::Acquire) || !POSITION_FRESHNESS.is_fresh() {
+        return (None, None);
+    }
+    match history::position_at(&POSITION_HISTORY.lock().unwrap(), time) {
+        Some((lat, lon)) => (Some(lat), Some(lon)),
+        None => (None, None),
+    }
+}
+
 pub(crate) fn get_cog() -> Option<f64> {
     let cog = COG.load(Ordering::Acquire);
-    if !cog.is_nan() {
+    if !cog.is_nan() && COG_FRESHNESS.is_fresh() {
         return Some(cog);
     }
     return None;
 }
 
-pub(crate) fn set_cog(cog: Option<f64>) {
+pub(crate) fn set_cog(cog: Option<f64>, source: &str) {
     use std::f64::consts::TAU;
 
+    if !COG_FRESHNESS.accept(source) {
+        log::trace!("COG from '{}' ignored, other source active", source);
+        return;
+    }
     if let Some(c) = cog {
         assert!(
             c > -TAU && c < 2.0 * TAU,
             "set_cog: COG {c} rad ({} deg) is out of range",
             c.to_degrees()
         );
-        COG.store(c.rem_euclid(TAU), Ordering::Release);
+        let c = c.rem_euclid(TAU);
+        COG.store(c, Ordering::Release);
+        if COG_FRESHNESS.update(source) {
+            broadcast_nav_update("navigation.courseOverGroundTrue", c, source);
+        }
     } else {
         COG.store(f64::NAN, Ordering::Release);
+        COG_FRESHNESS.clear();
     }
 }
 
 pub(crate) fn get_sog() -> Option<f64> {
     let sog = SOG.load(Ordering::Acquire);
-    if !sog.is_nan() {
+    if !sog.is_nan() && SOG_FRESHNESS.is_fresh() {
         return Some(sog);
     }
     return None;
 }
 
-pub(crate) fn set_sog(sog: Option<f64>) {
+pub(crate) fn set_sog(sog: Option<f64>, source: &str) {
+    if !SOG_FRESHNESS.accept(source) {
+        log::trace!("SOG from '{}' ignored, other source active", source);
+        return;
+    }
     if let Some(s) = sog {
         SOG.store(s, Ordering::Release);
+        if SOG_FRESHNESS.update(source) {
+            broadcast_nav_update("navigation.speedOverGround", s, source);
+        }
     } else {
         SOG.store(f64::NAN, Ordering::Release);
+        SOG_FRESHNESS.clear();
     }
 }
 
 /// The hostname of the devices we are searching for.
 const SIGNAL_K_SERVICE_NAME: &'static str = "_signalk-tcp._tcp.local.";
 const NMEA0183_SERVICE_NAME: &'static str = "_nmea-0183._tcp.local.";
+const NMEA2000_SERVICE_NAME: &'static str = "_nmea-2000._tcp.local.";
 
 /// Subscription for own-ship navigation data only
-const SUBSCRIBE_SELF: &'static str = "{\"context\":\"vessels.self\",\"subscribe\":[{\"path\":\"navigation.headingTrue\"},{\"path\":\"navigation.position\"},{\"path\":\"navigation.speedOverGround\"},{\"path\":\"navigation.courseOverGroundTrue\"}]}\r\n";
+const SUBSCRIBE_SELF: &'static str = "{\"context\":\"vessels.self\",\"subscribe\":[{\"path\":\"navigation.headingTrue\"},{\"path\":\"navigation.headingMagnetic\"},{\"path\":\"navigation.rateOfTurn\"},{\"path\":\"navigation.magneticVariation\"},{\"path\":\"navigation.position\"},{\"path\":\"navigation.speedOverGround\"},{\"path\":\"navigation.courseOverGroundTrue\"}]}\r\n";
 
 /// Additional subscription for all vessels (sent after own-ship context is known)
 const SUBSCRIBE_ALL: &'static str =
@@ -223,33 +764,69 @@ enum ConnectionType {
     Mdns,
     Udp(SocketAddr),
     Tcp(SocketAddr),
+    /// Device and baud rate
+    Serial(String, u32),
+    /// `host:port`, resolved when connecting
+    Gpsd(String),
 }
 
 impl ConnectionType {
-    fn parse(interface: &Option<String>) -> ConnectionType {
-        match interface {
-            None => {
-                return ConnectionType::Mdns;
-            }
-            Some(interface) => {
-                let parts: Vec<&str> = interface.splitn(2, ':').collect();
-                if parts.len() == 1 {
-                    return ConnectionType::Mdns;
-                } else if parts.len() == 2 {
-                    if let Ok(addr) = parts[1].parse() {
-                        // Dump if illegal address
-                        match parts[0].to_ascii_lowercase().as_str() {
-                            "udp" => return ConnectionType::Udp(addr),
-                            "tcp" => return ConnectionType::Tcp(addr),
-                            _ => {} // fallthrough to panic below
-                        }
-                    }
-                }
+    fn parse(interface: &Option<String>) -> Result<ConnectionType, RadarError> {
+        let Some(interface) = interface else {
+            return Ok(ConnectionType::Mdns);
+        };
+        let Some((connection, address)) = interface.split_once(':') else {
+            return Ok(ConnectionType::Mdns);
+        };
+        let invalid = || RadarError::InvalidNavigationAddress(interface.clone());
+        match connection.to_ascii_lowercase().as_str() {
+            "serial" => {
+                let (device, baud_rate) = serial::parse_address(address);
+                Ok(ConnectionType::Serial(device, baud_rate))
             }
+            // The port is optional for gpsd, and the host can be a name
+            "gpsd" => gpsd::address(address)
+                .map(ConnectionType::Gpsd)
+                .ok_or_else(invalid),
+            "udp" => address
+                .parse()
+                .map(ConnectionType::Udp)
+                .map_err(|_| invalid()),
+            "tcp" => address
+                .parse()
+                .map(ConnectionType::Tcp)
+                .map_err(|_| invalid()),
+            _ => Err(invalid()),
+        }
+    }
+}
+
+/// The protocol spoken by a navigation input
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Protocol {
+    SignalK,
+    Nmea0183,
+    Nmea2000,
+    Gpsd,
+}
+
+impl Protocol {
+    ///
+    /// Split the protocol off a navigation address like `nmea0183:udp:0.0.0.0:10110`,
+    /// so that inputs can use another protocol than the one selected with
+    /// `--nmea0183` or `--nmea2000`. The protocol is None when the address does
+    /// not start with one.
+    ///
+    fn split(address: &str) -> (Option<Protocol>, &str) {
+        let Some((protocol, rest)) = address.split_once(':') else {
+            return (None, address);
+        };
+        match protocol.to_ascii_lowercase().as_str() {
+            "signalk" => (Some(Protocol::SignalK), rest),
+            "nmea0183" => (Some(Protocol::Nmea0183), rest),
+            "nmea2000" => (Some(Protocol::Nmea2000), rest),
+            _ => (None, address),
         }
-        panic!(
-            "Interface must be either interface name (no :) or <connection>:<address>:<port> with <connection> one of `udp` or `tcp`."
-        );
     }
 }
 
@@ -257,37 +834,92 @@ impl ConnectionType {
 enum Stream {
     Tcp(TcpStream),
     Udp(UdpSocket),
+    Serial(tokio_serial::SerialStream),
 }
 
 pub(crate) struct NavigationData {
-    args: Cli,
-    nmea0183_mode: bool,
+    /// Name of this input, used as prefix for its source names
+    name: Option<String>,
+    /// Navigation address of this input, see `--navigation-address`
+    address: Option<String>,
+    protocol: Protocol,
     pass_ais: bool,
     service_name: &'static str,
     what: &'static str,
     nmea_parser: Option<NmeaParser>,
+    nmea2000_parser: Option<nmea2000::Nmea2000Parser>,
 }
 
 impl NavigationData {
-    pub(crate) fn new(args: Cli) -> Self {
-        let nmea0183 = args.nmea0183;
+    ///
+    /// Create a navigation input for `input`, which is a `--navigation-address`
+    /// value: an address optionally preceded by `<name>=`. When `input` is None
+    /// the navigation service is found via mDNS.
+    ///
+    pub(crate) fn new(args: Cli, input: Option<&str>) -> Self {
         let pass_ais = args.pass_ais;
-        match nmea0183 {
-            true => NavigationData {
-                args,
-                nmea0183_mode: true,
+        let (name, address) = match input.map(|i| i.split_once('=')) {
+            Some(Some((name, address))) => (Some(name.to_string()), Some(address.to_string())),
+            Some(None) => (None, input.map(|i| i.to_string())),
+            None => (None, None),
+        };
+        let (input_protocol, address) = match address.as_deref().map(Protocol::split) {
+            Some((protocol, address)) => (protocol, Some(address.to_string())),
+            None => (None, None),
+        };
+        let connection = address
+            .as_ref()
+            .and_then(|a| a.split_once(':'))
+            .map(|(connection, _)| connection.to_ascii_lowercase());
+        let protocol = match (connection.as_deref(), input_protocol) {
+            (Some("gpsd"), _) => Protocol::Gpsd,
+            (_, Some(protocol)) => protocol,
+            // Signal K is not sent over serial ports, NMEA 0183 usually is
+            (Some("serial"), _) if !args.nmea2000 => Protocol::Nmea0183,
+            _ if args.nmea0183 => Protocol::Nmea0183,
+            _ if args.nmea2000 => Protocol::Nmea2000,
+            _ => Protocol::SignalK,
+        };
+        match protocol {
+            Protocol::Nmea0183 => NavigationData {
+                name,
+                address,
+                protocol,
                 pass_ais,
                 service_name: NMEA0183_SERVICE_NAME,
                 what: "NMEA0183",
                 nmea_parser: Some(NmeaParser::new()),
+                nmea2000_parser: None,
+            },
+            Protocol::Nmea2000 => NavigationData {
+                name,
+                address,
+                protocol,
+                pass_ais,
+                service_name: NMEA2000_SERVICE_NAME,
+                what: "NMEA 2000",
+                nmea_parser: None,
+                nmea2000_parser: Some(nmea2000::Nmea2000Parser::new()),
             },
-            false => NavigationData {
-                args,
-                nmea0183_mode: false,
+            Protocol::Gpsd => NavigationData {
+                name,
+                address,
+                protocol,
+                pass_ais,
+                service_name: "",
+                what: "gpsd",
+                nmea_parser: None,
+                nmea2000_parser: None,
+            },
+            Protocol::SignalK => NavigationData {
+                name,
+                address,
+                protocol,
                 pass_ais,
                 service_name: SIGNAL_K_SERVICE_NAME,
                 what: "Signal K",
                 nmea_parser: None,
+                nmea2000_parser: None,
             },
         }
     }
@@ -299,7 +931,14 @@ impl NavigationData {
     ) -> Result<(), Error> {
         log::debug!("{} run_loop (re)start", self.what);
         let mut rx_ip_change = rx_ip_change;
-        let navigation_address = self.args.navigation_address.clone();
+        let navigation_address = self.address.clone();
+        if let Err(e) = ConnectionType::parse(&navigation_address) {
+            log::error!(
+                "{}: must be either interface name (no :), <connection>:<address>:<port> with <connection> one of `udp` or `tcp`, gpsd:<host>[:<port>] or serial:<device>[:<baud rate>]",
+                e
+            );
+            return Ok(());
+        }
 
         loop {
             match self
@@ -334,6 +973,15 @@ impl NavigationData {
                         }
                     }
                 }
+                Ok(Stream::Serial(port)) => match self.receive_serial_loop(port, &subsys).await {
+                    Ok(()) | Err(RadarError::Shutdown) => {
+                        log::debug!("{} receive_loop shutdown", self.what);
+                        return Ok(());
+                    }
+                    Err(e) => {
+                        log::warn!("{} serial port lost, reopening: {}", self.what, e);
+                    }
+                },
                 Err(e) => match e {
                     RadarError::Shutdown => {
                         log::debug!("{} run_loop shutdown", self.what);
@@ -353,14 +1001,18 @@ impl NavigationData {
         rx_ip_change: &mut Receiver<()>,
         interface: &Option<String>,
     ) -> Result<Stream, RadarError> {
-        let connection_type = ConnectionType::parse(interface);
+        let connection_type = ConnectionType::parse(interface)?;
         match connection_type {
             ConnectionType::Mdns => {
                 self.find_mdns_service(subsys, rx_ip_change, interface)
                     .await
             }
-            ConnectionType::Tcp(addr) => self.find_tcp_service(subsys, addr).await,
+            ConnectionType::Tcp(addr) => self.find_tcp_service(subsys, &addr.to_string()).await,
             ConnectionType::Udp(addr) => self.find_udp_service(subsys, addr).await,
+            ConnectionType::Serial(device, baud_rate) => {
+                self.find_serial_service(subsys, &device, baud_rate).await
+            }
+            ConnectionType::Gpsd(address) => self.find_tcp_service(subsys, &address).await,
         }
     }
 
@@ -376,13 +1028,7 @@ impl NavigationData {
 
         if interface.is_some() {
             let _ = mdns.disable_interface(IfKind::All);
-            let navigation_address = self
-                .args
-                .navigation_address
-                .as_ref()
-                .unwrap()
-                .to_string()
-                .clone();
+            let navigation_address = interface.as_ref().unwrap().to_string();
             let _ = mdns.enable_interface(IfKind::Name(navigation_address));
         }
         let tcp_locator = mdns.browse(self.service_name).expect(&format!(
@@ -452,7 +1098,7 @@ impl NavigationData {
     async fn find_tcp_service(
         &self,
         subsys: &SubsystemHandle,
-        addr: SocketAddr,
+        address: &str,
     ) -> Result<Stream, RadarError> {
         log::debug!("TCP find_service {} (re)start", self.what);
 
@@ -463,7 +1109,7 @@ impl NavigationData {
                 _ = s.on_shutdown_requested() => {
                     return Err(RadarError::Shutdown);
                 },
-                stream = connect_to_socket(addr) => {
+                stream = connect_to_host(address) => {
                     match stream {
                         Ok(stream) => {
                             log::info!(
@@ -474,7 +1120,7 @@ impl NavigationData {
                             return Ok(Stream::Tcp(stream));
                         }
                         Err(e) => {
-                            log::trace!("Failed to connect {} to {addr}: {e}", self.what);
+                            log::trace!("Failed to connect {} to {address}: {e}", self.what);
                             sleep(Duration::from_millis(1000)).await;
                         }
                     }
@@ -517,6 +1163,38 @@ impl NavigationData {
         }
     }
 
+    async fn find_serial_service(
+        &self,
+        subsys: &SubsystemHandle,
+        device: &str,
+        baud_rate: u32,
+    ) -> Result<Stream, RadarError> {
+        log::debug!("Serial find_service {} (re)start", self.what);
+
+        loop {
+            match serial::open(device, baud_rate) {
+                Ok(port) => {
+                    log::info!(
+                        "Receiving {} data from {} at {} baud",
+                        self.what,
+                        device,
+                        baud_rate
+                    );
+                    return Ok(Stream::Serial(port));
+                }
+                Err(e) => {
+                    log::trace!("Failed to open {} port {device}: {e}", self.what);
+                }
+            }
+            tokio::select! { biased;
+                _ = subsys.on_shutdown_requested() => {
+                    return Err(RadarError::Shutdown);
+                },
+                _ = sleep(Duration::from_millis(1000)) => {}
+            }
+        }
+    }
+
     // Loop until we get an error, then just return the error
     // or Ok if we are to shutdown.
     async fn receive_loop(
@@ -542,10 +1220,17 @@ impl NavigationData {
                     match r {
                         Ok(Some(line)) => {
                             log::trace!("{} <- {}", self.what, line);
-                            if self.nmea0183_mode {
-                                // We are in NMEA0183 mode, so we need to parse
+                            if self.protocol == Protocol::Gpsd {
+                                // gpsd greets us with its version, then we ask
+                                // for reports
+                                if self.parse_gpsd(&line) {
+                                    log::debug!("{} sending WATCH", self.what);
+                                    write_half.write_all(gpsd::WATCH.as_bytes()).await?;
+                                }
+                            } else if self.protocol != Protocol::SignalK {
+                                // We are in NMEA mode, so we need to parse
                                 // the data we get.
-                                match self.parse_nmea0183(&line) {
+                                match self.parse_nmea(&line) {
                                     Err(e) => { log::warn!("{}", e)}
                                     Ok(_) => { }
                                 }
@@ -561,7 +1246,7 @@ impl NavigationData {
                                     // (when pass_ais is enabled and we just learned the own-ship context)
                                     let had_own_ship = get_own_ship_context().is_some();
 
-                                    match parse_signalk(&line, self.pass_ais) {
+                                    match parse_signalk(&line, self.pass_ais, self.name.as_deref()) {
                                         Err(e) => { log::trace!("{} parse error: {}", self.what, e)}
                                         Ok(_) => { }
                                     }
@@ -658,46 +1343,242 @@ impl NavigationData {
         }
     }
 
+    // Loop until the device is gone, then return the error
+    // or Ok if we are to shutdown.
+    async fn receive_serial_loop(
+        &mut self,
+        port: tokio_serial::SerialStream,
+        subsys: &SubsystemHandle,
+    ) -> Result<(), RadarError> {
+        let mut reader = BufReader::new(port);
+        let mut buf = Vec::new();
+        loop {
+            tokio::select! { biased;
+                _ = subsys.on_shutdown_requested() => {
+                    log::debug!("{} receive_loop shutdown", self.what);
+                    return Ok(());
+                },
+                r = serial::next_line(&mut reader, &mut buf) => {
+                    match r {
+                        Ok(Some(line)) => {
+                            log::trace!("{} <- {}", self.what, line);
+                            self.process_line(&line);
+                        }
+                        Ok(None) => {
+                            return Err(std::io::Error::from(ErrorKind::UnexpectedEof).into());
+                        }
+                        Err(e) => {
+                            return Err(e.into());
+                        }
+                    }
+                }
+            }
+        }
+    }
+
     fn process_udp_buf(&mut self, buf: &[u8]) {
         if let Ok(data) = String::from_utf8(buf.to_vec()) {
             for line in data.lines() {
-                match if self.nmea0183_mode {
-                    self.parse_nmea0183(line)
+                self.process_line(line);
+            }
+        }
+    }
+
+    fn process_line(&mut self, line: &str) {
+        match if self.protocol != Protocol::SignalK {
+            self.parse_nmea(line)
+        } else {
+            parse_signalk(&line, self.pass_ais, self.name.as_deref())
+        } {
+            Err(e) => {
+                log::warn!("{}", e)
+            }
+            Ok(_) => {}
+        }
+    }
+
+    ///
+    /// Handle a gpsd report. Returns true if it is the VERSION banner, which
+    /// is to be answered with a WATCH request.
+    ///
+    fn parse_gpsd(&self, s: &str) -> bool {
+        let report = match serde_json::from_str::<gpsd::Report>(s) {
+            Ok(report) => report,
+            Err(e) => {
+                log::warn!("Unable to parse gpsd report '{}': {}", s, e);
+                return false;
+            }
+        };
+        let name = self.name.as_deref();
+        match report {
+            gpsd::Report::Version(version) => {
+                log::info!(
+                    "Connected to gpsd {}",
+                    version.release.as_deref().unwrap_or("(unknown release)")
+                );
+                return true;
+            }
+            gpsd::Report::Tpv(tpv) => {
+                let source = input_source(name, &gpsd::source_name(tpv.device.as_deref()));
+                if tpv.has_fix() {
+                    if tpv.lat.is_some() && tpv.lon.is_some() {
+                        set_position(tpv.lat, tpv.lon, &source);
+                    }
+                    // gpsd may send several TPV reports per cycle, with only
+                    // some of the fields; a missing field is not a loss
+                    if let Some(track) = tpv.track {
+                        set_cog(Some(track.to_radians()), &source);
+                    }
+                    if let Some(speed) = tpv.speed {
+                        set_sog(Some(speed), &source);
+                    }
                 } else {
-                    parse_signalk(&line, self.pass_ais)
-                } {
-                    Err(e) => {
-                        log::warn!("{}", e)
+                    // No fix, so the position and velocity from this receiver
+                    // are no longer valid
+                    set_position(None, None, &source);
+                    set_cog(None, &source);
+                    set_sog(None, &source);
+                }
+            }
+            gpsd::Report::Att(att) => {
+                let source = input_source(name, &gpsd::source_name(att.device.as_deref()));
+                if let Some(heading) = att.heading.filter(|h| (0. ..=360.).contains(h)) {
+                    set_heading_true(Some(heading.to_radians()), &source);
+                }
+                if let Some(rot) = att.rot {
+                    set_rate_of_turn(Some(rot.to_radians() / 60.), &source);
+                }
+            }
+            gpsd::Report::Other => {}
+        }
+        false
+    }
+
+    fn parse_nmea(&mut self, s: &str) -> Result<(), RadarError> {
+        match self.protocol {
+            Protocol::Nmea2000 => self.parse_nmea2000(s),
+            _ => self.parse_nmea0183(s),
+        }
+    }
+
+    fn parse_nmea2000(&mut self, s: &str) -> Result<(), RadarError> {
+        let parser = self.nmea2000_parser.as_mut().unwrap();
+        let Some(message) = parser.parse_line(s)? else {
+            return Ok(());
+        };
+        let Some(decoded) = nmea2000::decode(&message) else {
+            return Ok(());
+        };
+        log::trace!("{} PGN {} -> {:?}", self.what, message.pgn, decoded);
+
+        let source = input_source(
+            self.name.as_deref(),
+            &format!("nmea2000.{}", message.source),
+        );
+        match decoded {
+            nmea2000::Decoded::Heading {
+                heading,
+                deviation,
+                variation,
+                magnetic,
+            } => {
+                if let Some(variation) = variation {
+                    set_magnetic_variation(Some(variation), &source);
+                }
+                if magnetic {
+                    set_heading_magnetic(Some(heading + deviation.unwrap_or(0.)), &source);
+                } else {
+                    set_heading_true(Some(heading), &source);
+                }
+            }
+            nmea2000::Decoded::Variation(variation) => {
+                set_magnetic_variation(Some(variation), &source);
+            }
+            nmea2000::Decoded::Position { lat, lon } => {
+                set_position(Some(lat), Some(lon), &source);
+            }
+            nmea2000::Decoded::CogSog { cog, sog, magnetic } => {
+                let cog = match magnetic {
+                    true => cog.and_then(|cog| {
+                        get_magnetic_variation_with_source().map(|(variation, _)| {
+                            (cog + variation).rem_euclid(std::f64::consts::TAU)
+                        })
+                    }),
+                    false => cog,
+                };
+                set_cog(cog, &source);
+                set_sog(sog, &source);
+            }
+            decoded => {
+                if self.pass_ais {
+                    if let Some((mmsi, updates)) = nmea2000::ais_updates(&decoded) {
+                        update_ais_vessel(&crate::ais::mmsi_context(mmsi), &updates);
                     }
-                    Ok(_) => {}
                 }
             }
         }
+        Ok(())
     }
 
     fn parse_nmea0183(&mut self, s: &str) -> Result<(), RadarError> {
+        let name = self.name.as_deref();
+        if let Some(r) = parse_nmea0183_heading(s, name) {
+            return r;
+        }
+
+        let source = input_source(name, "nmea0183");
         let parser = self.nmea_parser.as_mut().unwrap();
 
         match parser.parse_sentence(s) {
             Ok(ParsedMessage::Rmc(rmc)) => {
-                set_position(rmc.latitude, rmc.longitude);
+                set_position(rmc.latitude, rmc.longitude, &source);
             }
             Ok(ParsedMessage::Gll(gll)) => {
-                set_position(gll.latitude, gll.longitude);
+                set_position(gll.latitude, gll.longitude, &source);
             }
             Ok(ParsedMessage::Hdt(hdt)) => {
-                set_heading_true(
-                    hdt.heading_true.map(|h| h.to_radians()),
-                    "nmea0183",
-                );
+                set_heading_true(hdt.heading_true.map(|h| h.to_radians()), &source);
             }
             Ok(ParsedMessage::Vtg(vtg)) => {
-                set_cog(vtg.cog_true.map(|c| c.to_radians()));
+                set_cog(vtg.cog_true.map(|c| c.to_radians()), &source);
                 let sog = vtg
                     .sog_kph
                     .or_else(|| vtg.sog_knots.map(|k| k * 1.852))
                     .map(|s| s * 3.6); // convert to m/s
-                set_sog(sog);
+                set_sog(sog, &source);
+            }
+            Ok(ParsedMessage::VesselDynamicData(vdd)) => {
+                if vdd.own_vessel {
+                    // !AIVDO: our own transponder reporting our position
+                    let source = input_source(name, "nmea0183-vdo");
+                    set_position(vdd.latitude, vdd.longitude, &source);
+                    set_cog(vdd.cog.map(|c| c.to_radians()), &source);
+                    set_sog(vdd.sog_knots.map(|k| k * KN_TO_MS), &source);
+                    if let Some(heading) = vdd.heading_true {
+                        set_heading_true(Some(heading.to_radians()), &source);
+                    }
+                } else if self.pass_ais {
+                    update_ais_vessel(
+                        &crate::ais::mmsi_context(vdd.mmsi),
+                        &ais_dynamic_updates(&vdd),
+                    );
+                }
+            }
+            Ok(ParsedMessage::VesselStaticData(vsd)) => {
+                if !vsd.own_vessel && self.pass_ais {
+                    update_ais_vessel(
+                        &crate::ais::mmsi_context(vsd.mmsi),
+                        &ais_static_updates(&vsd),
+                    );
+                }
+            }
+            Ok(ParsedMessage::AidToNavigationReport(aton)) => {
+                if self.pass_ais {
+                    update_ais_vessel(
+                        &crate::ais::mmsi_context(aton.mmsi),
+                        &ais_aton_updates(&aton),
+                    );
+                }
             }
 
             Err(e) => match e {
@@ -715,97 +1596,359 @@ impl NavigationData {
     }
 }
 
+///
+/// The source name of a value from navigation input `name`
+///
+fn input_source(name: Option<&str>, source: &str) -> String {
+    match name {
+        Some(name) => format!("{}.{}", name, source),
+        None => source.to_string(),
+    }
+}
+
+///
+/// nmea-parser does not know the HDG, HDM, ROT and THS sentences, so we parse
+/// them here. Returns None if `s` is not one of these sentences.
+///
+fn parse_nmea0183_heading(s: &str, name: Option<&str>) -> Option<Result<(), RadarError>> {
+    let s = s.trim();
+    let body = s.strip_prefix('$')?;
+    let sentence_type = body.get(2..5)?;
+    if !matches!(sentence_type, "HDG" | "HDM" | "ROT" | "THS") {
+        return None;
+    }
+    let body = match body.split_once('*') {
+        Some((body, checksum)) => {
+            let expected = body.bytes().fold(0u8, |acc, b| acc ^ b);
+            if u8::from_str_radix(checksum, 16).ok() != Some(expected) {
+                return Some(Err(RadarError::ParseNmea0183(format!(
+                    "{s}: checksum mismatch"
+                ))));
+            }
+            body
+        }
+        None => body,
+    };
+
+    let source = input_source(name, &format!("nmea0183.{}", sentence_type));
+    let fields: Vec<&str> = body.split(',').collect();
+    let field = |i: usize| fields.get(i).and_then(|f| f.parse::<f64>().ok());
+    // Deviation and variation come with an E/W indicator, west is negative
+    let east_west = |i: usize| {
+        field(i).map(|v| match fields.get(i + 1) {
+            Some(&"W") => -v,
+            _ => v,
+        })
+    };
+
+    match sentence_type {
+        "HDG" => {
+            // $--HDG,heading,deviation,E/W,variation,E/W
+            if let Some(variation) = east_west(4) {
+                set_magnetic_variation(Some(variation.to_radians()), &source);
+            }
+            let deviation = east_west(2).unwrap_or(0.);
+            set_heading_magnetic(field(1).map(|h| (h + deviation).to_radians()), &source);
+        }
+        "HDM" => {
+            // $--HDM,heading,M
+            set_heading_magnetic(field(1).map(|h| h.to_radians()), &source);
+        }
+        "ROT" => {
+            // $--ROT,rate,status with rate in degrees per minute, negative to port,
+            // and status V when the data is not valid
+            if fields.get(2) != Some(&"V") {
+                set_rate_of_turn(field(1).map(|r| r.to_radians() / 60.), &source);
+            }
+        }
+        _ => {
+            // $--THS,heading,mode where mode V means the data is not valid
+            if fields.get(2) != Some(&"V") {
+                if let Some(h) = field(1).filter(|h| (0. ..=360.).contains(h)) {
+                    set_heading_true(Some(h.to_radians()), &source);
+                }
+            }
+        }
+    }
+    Some(Ok(()))
+}
+
+/// Signal K values for an AIS position report (messages 1, 2, 3, 18 and 19)
+fn ais_dynamic_updates(vdd: &nmea_parser::ais::VesselDynamicData) -> Value {
+    let mut values = Vec::new();
+    if let (Some(latitude), Some(longitude)) = (vdd.latitude, vdd.longitude) {
+        values.push((
+            "navigation.position",
+            serde_json::json!({ "latitude": latitude, "longitude": longitude }),
+        ));
+    }
+    if let Some(heading) = vdd.heading_true {
+        values.push(("navigation.headingTrue", heading.to_radians().into()));
+    }
+    if let Some(cog) = vdd.cog {
+        values.push(("navigation.courseOverGroundTrue", cog.to_radians().into()));
+    }
+    if let Some(sog) = vdd.sog_knots {
+        values.push(("navigation.speedOverGround", (sog * KN_TO_MS).into()));
+    }
+    crate::ais::signalk_updates(values)
+}
+
+/// Signal K values for AIS static and voyage data (messages 5 and 24)
+fn ais_static_updates(vsd: &nmea_parser::ais::VesselStaticData) -> Value {
+    let mut values = Vec::new();
+    if let Some(name) = &vsd.name {
+        values.push(("", serde_json::json!({ "name": name })));
+    }
+    values.extend(ais_dimension_values(
+        vsd.dimension_to_bow,
+        vsd.dimension_to_stern,
+        vsd.dimension_to_port,
+        vsd.dimension_to_starboard,
+    ));
+    crate::ais::signalk_updates(values)
+}
+
+/// Signal K values for an AIS aid to navigation report (message 21)
+fn ais_aton_updates(aton: &nmea_parser::ais::AidToNavigationReport) -> Value {
+    let mut values = Vec::new();
+    if !aton.name.is_empty() {
+        values.push(("", serde_json::json!({ "name": aton.name })));
+    }
+    if let (Some(latitude), Some(longitude)) = (aton.latitude, aton.longitude) {
+        values.push((
+            "navigation.position",
+            serde_json::json!({ "latitude": latitude, "longitude": longitude }),
+        ));
+    }
+    crate::ais::signalk_updates(values)
+}
+
+/// AIS reports the position of the GPS antenna as distances to bow, stern,
+/// port and starboard. Signal K wants length, beam and the antenna offset
+/// from the bow and from the centerline (positive to starboard).
+fn ais_dimension_values(
+    to_bow: Option<u16>,
+    to_stern: Option<u16>,
+    to_port: Option<u16>,
+    to_starboard: Option<u16>,
+) -> Vec<(&'static str, Value)> {
+    let mut values = Vec::new();
+    if let (Some(bow), Some(stern)) = (to_bow, to_stern) {
+        if bow + stern > 0 {
+            values.push((
+                "design.length",
+                serde_json::json!({ "overall": (bow + stern) as f64 }),
+            ));
+            values.push(("sensors.ais.fromBow", (bow as f64).into()));
+        }
+    }
+    if let (Some(port), Some(starboard)) = (to_port, to_starboard) {
+        if port + starboard > 0 {
+            values.push(("design.beam", ((port + starboard) as f64).into()));
+            values.push((
+                "sensors.ais.fromCenter",
+                ((port as f64 - starboard as f64) / 2.).into(),
+            ));
+        }
+    }
+    values
+}
+
 //  {"context":"vessels.urn:mrn:imo:mmsi:244060807","updates":
 //   [{"source":{"sentence":"GLL","talker":"BM","type":"NMEA0183","label":"canboat-merrimac"},
 //     "$source":"canboat-merrimac.BM","timestamp":"2024-10-01T09:11:36.000Z",
 //     "values":[{"path":"navigation.position","value":{"longitude":5.428445,"latitude":53.180205}}]}]}
 
-fn parse_signalk(s: &str, pass_ais: bool) -> Result<(), RadarError> {
+fn parse_signalk(s: &str, pass_ais: bool, name: Option<&str>) -> Result<(), RadarError> {
     log::trace!("parse_signalk: parsing '{}'", s);
-    match serde_json::from_str::<Value>(s) {
-        Ok(v) => {
-            log::trace!("parse_signalk: parsed JSON successfully");
-            let context = v["context"].as_str();
-            let updates = &v["updates"];
-
-            // When pass_ais is enabled, handle own-ship detection and AIS forwarding
-            if pass_ais {
-                if let Some(ctx) = context {
-                    let own_ship = get_own_ship_context();
-
-                    if own_ship.is_none() {
-                        // First message after subscribing to vessels.self establishes own-ship
-                        // The context will be the actual vessel URN (e.g., vessels.urn:mrn:imo:mmsi:244060807)
-                        set_own_ship_context(ctx);
-                        log::info!("Own-ship context detected: {}", ctx);
-                        // Continue to process this as navigation data
-                    } else if let Some(own_ship_ctx) = own_ship {
-                        // We know which vessel is own-ship, check if this is a different vessel
-                        let is_own_ship = own_ship_ctx == ctx || ctx == "vessels.self";
-                        if !is_own_ship {
-                            // This is an AIS target - update the vessel store
-                            update_ais_vessel(ctx, updates);
-                            return Ok(());
-                        }
-                    }
+    let v = match serde_json::from_str::<Value>(s) {
+        Ok(v) => v,
+        Err(e) => {
+            log::warn!("Unable to parse SK message '{}'", s);
+            return Err(RadarError::ParseJson(e.to_string()));
+        }
+    };
+    let context = v["context"].as_str();
+    let updates = &v["updates"];
+
+    // When pass_ais is enabled, handle own-ship detection and AIS forwarding
+    if pass_ais {
+        if let Some(ctx) = context {
+            let own_ship = get_own_ship_context();
+
+            if own_ship.is_none() {
+                // First message after subscribing to vessels.self establishes own-ship
+                // The context will be the actual vessel URN (e.g., vessels.urn:mrn:imo:mmsi:244060807)
+                set_own_ship_context(ctx);
+                log::info!("Own-ship context detected: {}", ctx);
+                // Continue to process this as navigation data
+            } else if let Some(own_ship_ctx) = own_ship {
+                // We know which vessel is own-ship, check if this is a different vessel
+                let is_own_ship = own_ship_ctx == ctx || ctx == "vessels.self";
+                if !is_own_ship {
+                    // This is an AIS target - update the vessel store
+                    update_ais_vessel(ctx, updates);
+                    return Ok(());
                 }
             }
+        }
+    }
 
-            // Process own-ship navigation data
-            let update = &updates[0];
-            // Extract source from upstream SignalK message
-            // Try $source first (more specific), then source, fall back to "signalk"
-            let source = update["$source"]
-                .as_str()
-                .or_else(|| update["source"]["label"].as_str())
-                .or_else(|| update["source"]["type"].as_str())
-                .unwrap_or("signalk");
-            let values = &update["values"][0];
-            {
-                log::trace!("parse_signalk: values = {:?}", values);
-
-                if let (Some(path), value) = (values["path"].as_str(), &values["value"]) {
-                    log::trace!("parse_signalk: path = '{}', value = {:?}", path, value);
-                    match path {
-                        "navigation.position" => {
-                            log::trace!(
-                                "parse_signalk: position lat={:?} lon={:?}",
-                                value["latitude"].as_f64(),
-                                value["longitude"].as_f64()
-                            );
-                            set_position(value["latitude"].as_f64(), value["longitude"].as_f64());
-                            return Ok(());
-                        }
-                        "navigation.headingTrue" => {
-                            set_heading_true(value.as_f64(), source);
-                            return Ok(());
-                        }
-                        "navigation.speedOverGround" => {
-                            set_sog(value.as_f64());
-                            return Ok(());
-                        }
-                        "navigation.courseOverGroundTrue" => {
-                            set_cog(value.as_f64());
-                            return Ok(());
-                        }
-                        _ => {
-                            return Err(RadarError::ParseJson(format!("Ignored path '{}'", path)));
-                        }
-                    }
-                } else {
-                    log::trace!("parse_signalk: no path or value found in values");
-                }
+    // Process own-ship navigation data. A single delta may contain several
+    // updates from different sources, each with several values.
+    let Some(updates) = updates.as_array() else {
+        return Err(RadarError::ParseJson(format!(
+            "Insufficient fields in '{}'",
+            s
+        )));
+    };
+    for update in updates {
+        // Extract source from upstream SignalK message
+        // Try $source first (more specific), then source, fall back to "signalk"
+        let source = update["$source"]
+            .as_str()
+            .or_else(|| update["source"]["label"].as_str())
+            .or_else(|| update["source"]["type"].as_str())
+            .unwrap_or("signalk");
+        let source = input_source(name, source);
+        let timestamp = update["timestamp"]
+            .as_str()
+            .and_then(|t| DateTime::parse_from_rfc3339(t).ok())
+            .map(|t| t.with_timezone(&Utc));
+
+        let Some(values) = update["values"].as_array() else {
+            continue;
+        };
+        for value in values {
+            log::trace!("parse_signalk: value = {:?}", value);
+            if let Some(path) = value["path"].as_str() {
+                process_signalk_value(path, &value["value"], &source, timestamp);
+            } else {
+                log::trace!("parse_signalk: no path found in value");
             }
         }
-        Err(e) => {
-            log::warn!("Unable to parse SK message '{}'", s);
-            return Err(RadarError::ParseJson(e.to_string()));
+    }
+    Ok(())
+}
+
+/// Latest Signal K timestamp applied per source and navigation path
+static SIGNALK_TIMESTAMPS: OnceLock<Mutex<HashMap<(String, String), DateTime<Utc>>>> =
+    OnceLock::new();
+
+/// Number of Signal K values received per path that we do not use
+static SIGNALK_IGNORED_PATHS: OnceLock<Mutex<BTreeMap<String, u64>>> = OnceLock::new();
+
+///
+/// Return the Signal K paths that were received but ignored, with the number
+/// of values received for each.
+///
+pub fn get_signalk_ignored_paths() -> BTreeMap<String, u64> {
+    SIGNALK_IGNORED_PATHS
+        .get()
+        .and_then(|m| m.lock().ok().map(|m| m.clone()))
+        .unwrap_or_default()
+}
+
+fn count_ignored_path(path: &str) {
+    let mut ignored = SIGNALK_IGNORED_PATHS
+        .get_or_init(|| Mutex::new(BTreeMap::new()))
+        .lock()
+        .unwrap();
+    let count = ignored.entry(path.to_string()).or_insert(0);
+    if *count == 0 {
+        log::debug!("Ignoring Signal K path '{}'", path);
+    }
+    *count += 1;
+}
+
+///
+/// Servers may relay values out of order. Only apply a value if it is not
+/// older than the last one applied for the same path from the same source.
+/// Sources do not share clocks, so which of several sources is used for a
+/// path is left to the source priority.
+///
+fn is_newest_signalk_value(source: &str, path: &str, timestamp: Option<DateTime<Utc>>) -> bool {
+    let Some(timestamp) = timestamp else {
+        return true;
+    };
+    let mut timestamps = SIGNALK_TIMESTAMPS
+        .get_or_init(|| Mutex::new(HashMap::new()))
+        .lock()
+        .unwrap();
+    let key = (source.to_string(), path.to_string());
+    match timestamps.get(&key) {
+        Some(last) if *last > timestamp => false,
+        _ => {
+            timestamps.insert(key, timestamp);
+            true
+        }
+    }
+}
+
+fn process_signalk_value(
+    path: &str,
+    value: &Value,
+    source: &str,
+    timestamp: Option<DateTime<Utc>>,
+) {
+    log::trace!("parse_signalk: path = '{}', value = {:?}", path, value);
+    if !matches!(
+        path,
+        "navigation.position"
+            | "navigation.headingTrue"
+            | "navigation.headingMagnetic"
+            | "navigation.rateOfTurn"
+            | "navigation.magneticVariation"
+            | "navigation.speedOverGround"
+            | "navigation.courseOverGroundTrue"
+    ) {
+        count_ignored_path(path);
+        return;
+    }
+    if !is_newest_signalk_value(source, path, timestamp) {
+        log::trace!(
+            "parse_signalk: skipping out of order value for '{}' from '{}' at {:?}",
+            path,
+            source,
+            timestamp
+        );
+        return;
+    }
+
+    match path {
+        "navigation.position" => {
+            log::trace!(
+                "parse_signalk: position lat={:?} lon={:?}",
+                value["latitude"].as_f64(),
+                value["longitude"].as_f64()
+            );
+            set_position(
+                value["latitude"].as_f64(),
+                value["longitude"].as_f64(),
+                source,
+            );
+        }
+        "navigation.headingTrue" => {
+            set_heading_true(value.as_f64(), source);
+        }
+        "navigation.headingMagnetic" => {
+            set_heading_magnetic(value.as_f64(), source);
+        }
+        "navigation.rateOfTurn" => {
+            set_rate_of_turn(value.as_f64(), source);
+        }
+        "navigation.magneticVariation" => {
+            set_magnetic_variation(value.as_f64(), source);
+        }
+        "navigation.speedOverGround" => {
+            set_sog(value.as_f64(), source);
+        }
+        _ => {
+            // navigation.courseOverGroundTrue
+            set_cog(value.as_f64(), source);
         }
     }
-    return Err(RadarError::ParseJson(format!(
-        "Insufficient fields in '{}'",
-        s
-    )));
 }
 
 async fn connect_to_socket(address: SocketAddr) -> Result<TcpStream, RadarError> {
@@ -816,6 +1959,18 @@ async fn connect_to_socket(address: SocketAddr) -> Result<TcpStream, RadarError>
     Ok(stream)
 }
 
+///
+/// Resolve `address`, given as `host:port`, and return a TCP stream to the
+/// first of its addresses that connects.
+///
+async fn connect_to_host(address: &str) -> Result<TcpStream, RadarError> {
+    let addresses: Vec<SocketAddr> = tokio::net::lookup_host(address).await?.collect();
+    if addresses.is_empty() {
+        return Err(RadarError::Io(std::io::Error::from(ErrorKind::NotFound)));
+    }
+    connect_first(addresses).await
+}
+
 ///
 /// Take an interable of SocketAddr and return a TCP stream to the first socket that connects.
 ///
@@ -853,3 +2008,406 @@ where
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::MutexGuard;
+
+    /// Tests that use the global navigation state take turns
+    static NAVIGATION_STATE: Mutex<()> = Mutex::new(());
+
+    ///
+    /// Take the global navigation state and forget all values and sources,
+    /// so that the sources of the previous test are not active.
+    ///
+    fn reset() -> MutexGuard<'static, ()> {
+        let guard = NAVIGATION_STATE.lock().unwrap_or_else(|e| e.into_inner());
+        for freshness in [
+            &HEADING_TRUE_FRESHNESS,
+            &POSITION_FRESHNESS,
+            &COG_FRESHNESS,
+            &SOG_FRESHNESS,
+            &MAGNETIC_VARIATION_FRESHNESS,
+        ] {
+            freshness.time.store(0, Ordering::Release);
+            freshness.source.write().unwrap().clear();
+            freshness.set_priority(Vec::new());
+        }
+        for value in [
+            &HEADING_TRUE,
+            &HEADING_MAGNETIC,
+            &MAGNETIC_VARIATION,
+            &COG,
+            &SOG,
+        ] {
+            value.store(f64::NAN, Ordering::Release);
+        }
+        POSITION_VALID.store(false, Ordering::Release);
+        HEADING_TRUE_DIRECT_TIME.store(0, Ordering::Release);
+        if let Some(timestamps) = SIGNALK_TIMESTAMPS.get() {
+            timestamps.lock().unwrap().clear();
+        }
+        guard
+    }
+
+    fn value(path: &str) -> NavigationValueApi {
+        get_navigation_state().remove(path).unwrap()
+    }
+
+    #[test]
+    fn test_signalk_delta() {
+        let _state = reset();
+        let delta = r#"{"context":"vessels.self","updates":[
+            {"$source":"gps.GP","timestamp":"2024-10-01T09:11:36.000Z","values":[
+                {"path":"navigation.position","value":{"latitude":53.18,"longitude":5.43}},
+                {"path":"navigation.speedOverGround","value":3.1},
+                {"path":"environment.wind.speedApparent","value":5.0},
+                {"path":"navigation.courseOverGroundTrue","value":1.2}]},
+            {"source":{"label":"compass","type":"NMEA2000"},"timestamp":"2024-10-01T09:11:36.100Z","values":[
+                {"path":"navigation.headingTrue","value":0.5},
+                {"path":"navigation.rateOfTurn","value":0.01}]}]}"#;
+        parse_signalk(delta, false, Some("sk")).unwrap();
+
+        let position = value("navigation.position");
+        assert_eq!(position.value["latitude"], 53.18);
+        assert_eq!(position.value["longitude"], 5.43);
+        assert_eq!(position.source.as_deref(), Some("sk.gps.GP"));
+        assert_eq!(value("navigation.speedOverGround").value, 3.1);
+        assert_eq!(value("navigation.courseOverGroundTrue").value, 1.2);
+        let heading = value("navigation.headingTrue");
+        assert_eq!(heading.value, 0.5);
+        assert_eq!(heading.source.as_deref(), Some("sk.compass"));
+        assert!(get_signalk_ignored_paths()["environment.wind.speedApparent"] >= 1);
+
+        // A delta without updates is an error, an update without values is not
+        assert!(parse_signalk(r#"{"context":"vessels.self"}"#, false, None).is_err());
+        parse_signalk(r#"{"updates":[{"$source":"gps.GP"}]}"#, false, None).unwrap();
+    }
+
+    #[test]
+    fn test_signalk_out_of_order() {
+        let _state = reset();
+        let update = |source: &str, time: &str, sog: f64| {
+            format!(
+                r#"{{"updates":[{{"$source":"{source}","timestamp":"{time}","values":[
+                    {{"path":"navigation.speedOverGround","value":{sog}}}]}}]}}"#
+            )
+        };
+        parse_signalk(
+            &update("gps1", "2024-10-01T09:11:37.000Z", 3.0),
+            false,
+            None,
+        )
+        .unwrap();
+        // An older value from the same source is skipped
+        parse_signalk(
+            &update("gps1", "2024-10-01T09:11:36.000Z", 2.0),
+            false,
+            None,
+        )
+        .unwrap();
+        assert_eq!(value("navigation.speedOverGround").value, 3.0);
+        parse_signalk(
+            &update("gps1", "2024-10-01T09:11:38.000Z", 4.0),
+            false,
+            None,
+        )
+        .unwrap();
+        assert_eq!(value("navigation.speedOverGround").value, 4.0);
+
+        // Another source has its own clock
+        assert!(is_newest_signalk_value(
+            "gps2",
+            "navigation.speedOverGround",
+            DateTime::parse_from_rfc3339("2024-10-01T09:00:00.000Z")
+                .ok()
+                .map(|t| t.with_timezone(&Utc))
+        ));
+        // Values without a timestamp are always applied
+        assert!(is_newest_signalk_value(
+            "gps1",
+            "navigation.speedOverGround",
+            None
+        ));
+    }
+
+    /// Make the active source of `freshness` older than the navigation timeout
+    fn expire(freshness: &Freshness) {
+        let timeout = NAVIGATION_TIMEOUT.load(Ordering::Relaxed);
+        freshness
+            .time
+            .store(now_millis() - timeout - 1, Ordering::Release);
+    }
+
+    #[test]
+    fn test_priority_override() {
+        let freshness = Freshness::new("navigation.position");
+        freshness.set_priority(vec!["gps.*".to_string(), "ais.*".to_string()]);
+        assert!(freshness.update("ais.own"));
+        assert_eq!(freshness.source().as_deref(), Some("ais.own"));
+
+        // A source with a higher priority takes over right away
+        assert!(freshness.accept("gps.GP"));
+        assert!(freshness.update("gps.GP"));
+        assert_eq!(freshness.source().as_deref(), Some("gps.GP"));
+
+        // Lower priorities, and sources that match no pattern, are ignored
+        assert!(!freshness.accept("ais.own"));
+        assert!(!freshness.accept("radar"));
+        assert!(freshness.accept("gps.GP"));
+        assert!(!freshness.update("gps.GP"));
+    }
+
+    #[test]
+    fn test_staleness_failover() {
+        let freshness = Freshness::new("navigation.headingTrue");
+        freshness.set_priority(vec!["compass".to_string()]);
+        assert!(freshness.accept("radar"));
+        freshness.update("compass");
+        assert!(!freshness.accept("radar"));
+
+        // When the compass goes quiet, any source takes over
+        expire(&freshness);
+        assert_eq!(freshness.source(), None);
+        assert_eq!(freshness.last_source().as_deref(), Some("compass"));
+        assert!(freshness.accept("radar"));
+        assert!(freshness.update("radar"));
+        assert_eq!(freshness.source().as_deref(), Some("radar"));
+
+        // and gives way again when the compass is back
+        assert!(freshness.accept("compass"));
+        assert!(freshness.update("compass"));
+
+        // The same source that was stale becomes active again
+        expire(&freshness);
+        assert!(freshness.update("compass"));
+    }
+
+    #[test]
+    fn test_equal_rank() {
+        let freshness = Freshness::new("navigation.headingTrue");
+        freshness.update("gps1");
+        // The first source keeps the quantity while it is fresh
+        assert!(freshness.accept("gps1"));
+        assert!(!freshness.accept("gps2"));
+
+        // A derived value is replaced by one that is reported directly, but
+        // not by a value derived from another source
+        freshness.update("compass+wmm");
+        assert!(freshness.accept("compass+variation"));
+        assert!(freshness.accept("compass"));
+        assert!(freshness.accept("gps2"));
+        assert!(!freshness.accept("fluxgate+wmm"));
+        freshness.update("compass");
+        assert!(freshness.accept("compass+wmm"));
+        assert!(!freshness.accept("gps2"));
+    }
+
+    /// The true heading in degrees and its source
+    fn heading_true() -> (f64, String) {
+        let heading = value("navigation.headingTrue");
+        (
+            heading.value.as_f64().unwrap().to_degrees(),
+            heading.source.unwrap(),
+        )
+    }
+
+    #[test]
+    fn test_nmea0183_heading() {
+        let _state = reset();
+        assert!(parse_nmea0183_heading("$GPGLL,5222.20,N,00454.00,E,123519,A*2F", None).is_none());
+        assert!(
+            parse_nmea0183_heading("$HCHDG,98.3,0.0,E,12.6,W*56", None)
+                .unwrap()
+                .is_err()
+        );
+
+        // HDG: a magnetic heading with the variation from the same sentence
+        parse_nmea0183_heading("$HCHDG,98.3,0.0,E,12.6,W*57", None)
+            .unwrap()
+            .unwrap();
+        let (heading, source) = heading_true();
+        assert!((heading - 85.7).abs() < 1e-9);
+        assert_eq!(source, "nmea0183.HDG+variation");
+
+        // THS: a true heading, which replaces the derived one
+        parse_nmea0183_heading("$HETHS,123.4,A*29", Some("gyro"))
+            .unwrap()
+            .unwrap();
+        let (heading, source) = heading_true();
+        assert!((heading - 123.4).abs() < 1e-9);
+        assert_eq!(source, "gyro.nmea0183.THS");
+
+        // HDM does not override it while the true heading keeps coming
+        parse_nmea0183_heading("$HCHDM,10.0,M*18", None)
+            .unwrap()
+            .unwrap();
+        assert!((heading_true().0 - 123.4).abs() < 1e-9);
+        // and a THS that is not valid is ignored
+        parse_nmea0183_heading("$HETHS,200.0,V*38", Some("gyro"))
+            .unwrap()
+            .unwrap();
+        assert!((heading_true().0 - 123.4).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_magnetic_to_true() {
+        let _state = reset();
+        // Without variation or position there is no true heading
+        set_heading_magnetic(Some(100f64.to_radians()), "compass");
+        assert!(value("navigation.headingTrue").value.is_null());
+
+        // With a position, the World Magnetic Model gives the variation
+        set_position(Some(52.37), Some(4.90), "gps");
+        let year = wmm::decimal_year(&Utc::now());
+        let declination = wmm::declination(52.37, 4.90, year).to_degrees();
+        set_heading_magnetic(Some(100f64.to_radians()), "compass");
+        let (heading, source) = heading_true();
+        assert!((heading - (100. + declination)).abs() < 1e-6);
+        assert_eq!(source, "compass+wmm");
+
+        // A reported variation takes precedence, also across north
+        set_magnetic_variation(Some(2f64.to_radians()), "compass");
+        set_heading_magnetic(Some(359f64.to_radians()), "compass");
+        let (heading, source) = heading_true();
+        assert!((heading - 1.).abs() < 1e-9);
+        assert_eq!(source, "compass+variation");
+
+        // until it goes stale
+        expire(&MAGNETIC_VARIATION_FRESHNESS);
+        set_heading_magnetic(Some(100f64.to_radians()), "compass");
+        assert_eq!(heading_true().1, "compass+wmm");
+    }
+
+    fn ais_store() -> std::sync::Arc<AisVesselStore> {
+        AisVesselStore::new(tokio::sync::broadcast::channel(16).0)
+    }
+
+    #[test]
+    fn test_aivdm_position_report() {
+        let mut parser = NmeaParser::new();
+        let Ok(ParsedMessage::VesselDynamicData(vdd)) =
+            parser.parse_sentence("!AIVDM,1,1,,A,13u?etPv2;0n:dDPwUM1U1Cb069D,0*24")
+        else {
+            panic!("not a position report");
+        };
+        assert!(!vdd.own_vessel);
+
+        let store = ais_store();
+        assert!(store.update(
+            &crate::ais::mmsi_context(vdd.mmsi),
+            &ais_dynamic_updates(&vdd)
+        ));
+        let vessels = store.get_all_active();
+        assert_eq!(vessels.len(), 1);
+        let vessel = &vessels[0];
+        assert_eq!(vessel.mmsi, "265547250");
+        let position = vessel.position.as_ref().unwrap();
+        assert!((position.latitude - 57.660353).abs() < 1e-6);
+        assert!((position.longitude - 11.832977).abs() < 1e-6);
+        assert!((vessel.sog.unwrap() - 13.9 * KN_TO_MS).abs() < 1e-9);
+        assert!((vessel.cog.unwrap() - 40.4f64.to_radians()).abs() < 1e-9);
+        assert!((vessel.heading.unwrap() - 41f64.to_radians()).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_aivdm_static_two_fragments() {
+        let mut parser = NmeaParser::new();
+        assert!(matches!(
+            parser.parse_sentence(
+                "!AIVDM,2,1,1,A,55?MbV02;H;s<HtKR20EHE:0@T4@Dn2222222216L961O5Gf0NSQEp6ClRp8,0*1C"
+            ),
+            Ok(ParsedMessage::Incomplete)
+        ));
+        let Ok(ParsedMessage::VesselStaticData(vsd)) =
+            parser.parse_sentence("!AIVDM,2,2,1,A,88888888880,2*25")
+        else {
+            panic!("not static data");
+        };
+
+        let store = ais_store();
+        assert!(store.update(
+            &crate::ais::mmsi_context(vsd.mmsi),
+            &ais_static_updates(&vsd)
+        ));
+        let vessels = store.get_all_active();
+        let vessel = &vessels[0];
+        assert_eq!(vessel.mmsi, "351759000");
+        assert_eq!(vessel.name.as_deref(), Some("EVER DIADEM"));
+        // 225 m to the bow, 70 m to the stern, 1 m to port, 31 m to starboard
+        assert_eq!(
+            vessel.dimensions,
+            Some(crate::ais::Dimensions {
+                length: Some(295.),
+                beam: Some(32.),
+                from_bow: Some(225.),
+                from_center: Some(-15.),
+            })
+        );
+    }
+
+    #[test]
+    fn test_aivdo_own_ship() {
+        let _state = reset();
+        let args = <crate::Cli as clap::Parser>::parse_from(["mayara", "--nmea0183"]);
+        let mut navigation = NavigationData::new(args, None);
+        navigation
+            .parse_nmea0183("!AIVDO,1,1,,A,13u?etPv2;0n:dDPwUM1U1Cb069D,0*26")
+            .unwrap();
+
+        let position = value("navigation.position");
+        assert!((position.value["latitude"].as_f64().unwrap() - 57.660353).abs() < 1e-6);
+        assert_eq!(position.source.as_deref(), Some("nmea0183-vdo"));
+        let (heading, source) = heading_true();
+        assert!((heading - 41.).abs() < 1e-9);
+        assert_eq!(source, "nmea0183-vdo");
+    }
+
+    fn protocol(args: &[&str], input: &str) -> Protocol {
+        let args = <crate::Cli as clap::Parser>::parse_from(args);
+        NavigationData::new(args, Some(input)).protocol
+    }
+
+    #[test]
+    fn test_serial_protocol() {
+        // Serial ports carry NMEA, never Signal K
+        assert_eq!(
+            protocol(&["mayara"], "serial:/dev/ttyUSB0"),
+            Protocol::Nmea0183
+        );
+        assert_eq!(
+            protocol(&["mayara", "--nmea2000"], "gps=serial:COM3:38400"),
+            Protocol::Nmea2000
+        );
+        assert_eq!(protocol(&["mayara"], "gpsd:localhost"), Protocol::Gpsd);
+        assert_eq!(
+            protocol(&["mayara"], "udp:0.0.0.0:10110"),
+            Protocol::SignalK
+        );
+    }
+
+    #[test]
+    fn test_input_protocol() {
+        // A Signal K GPS with an NMEA 0183 compass
+        let args = ["mayara"];
+        assert_eq!(protocol(&args, "tcp:10.0.0.1:3000"), Protocol::SignalK);
+        let compass = NavigationData::new(
+            <crate::Cli as clap::Parser>::parse_from(args),
+            Some("compass=nmea0183:udp:0.0.0.0:10111"),
+        );
+        assert_eq!(compass.protocol, Protocol::Nmea0183);
+        assert_eq!(compass.name.as_deref(), Some("compass"));
+        assert_eq!(compass.address.as_deref(), Some("udp:0.0.0.0:10111"));
+
+        // The global flag is only the default
+        let args = ["mayara", "--nmea0183"];
+        assert_eq!(protocol(&args, "udp:0.0.0.0:10110"), Protocol::Nmea0183);
+        assert_eq!(
+            protocol(&args, "signalk:tcp:10.0.0.1:3000"),
+            Protocol::SignalK
+        );
+        assert_eq!(protocol(&args, "NMEA2000:eth0"), Protocol::Nmea2000);
+        assert_eq!(protocol(&args, "eth0"), Protocol::Nmea0183);
+    }
+}
@@ -0,0 +1,175 @@
+//! gpsd client navigation input.
+//!
+//! gpsd owns the GPS on many Linux systems, and shares it with clients over
+//! TCP (port 2947) using a JSON protocol. After gpsd sends its VERSION
+//! banner we ask for reports with `?WATCH`, and then receive one JSON object
+//! per line. We use TPV (time, position, velocity) and ATT (attitude) reports.
+
+use serde::Deserialize;
+use std::net::{IpAddr, SocketAddr};
+
+/// The default gpsd port
+pub(super) const DEFAULT_PORT: u16 = 2947;
+
+/// Request to stream reports in JSON format
+pub(super) const WATCH: &str = "?WATCH={\"enable\":true,\"json\":true};\n";
+
+/// TPV mode values below this mean that there is no fix
+pub(super) const MODE_2D: u8 = 2;
+
+#[derive(Deserialize, Debug, PartialEq)]
+#[serde(tag = "class", rename_all = "UPPERCASE")]
+pub(super) enum Report {
+    Version(Version),
+    Tpv(Tpv),
+    Att(Att),
+    #[serde(other)]
+    Other,
+}
+
+#[derive(Deserialize, Debug, PartialEq)]
+pub(super) struct Version {
+    pub(super) release: Option<String>,
+}
+
+/// Time-position-velocity report
+#[derive(Deserialize, Debug, PartialEq)]
+pub(super) struct Tpv {
+    /// Device that sent the data
+    pub(super) device: Option<String>,
+    /// Fix mode: 0 unknown, 1 no fix, 2 2D fix, 3 3D fix
+    #[serde(default)]
+    pub(super) mode: u8,
+    /// Latitude in degrees, positive north
+    pub(super) lat: Option<f64>,
+    /// Longitude in degrees, positive east
+    pub(super) lon: Option<f64>,
+    /// Course over ground in degrees from true north
+    pub(super) track: Option<f64>,
+    /// Speed over ground in meters per second
+    pub(super) speed: Option<f64>,
+}
+
+/// Attitude report, from a compass or GNSS heading sensor
+#[derive(Deserialize, Debug, PartialEq)]
+pub(super) struct Att {
+    /// Device that sent the data
+    pub(super) device: Option<String>,
+    /// Heading in degrees from true north
+    pub(super) heading: Option<f64>,
+    /// Rate of turn in degrees per minute
+    pub(super) rot: Option<f64>,
+}
+
+impl Tpv {
+    /// Whether the receiver has a position fix
+    pub(super) fn has_fix(&self) -> bool {
+        self.mode >= MODE_2D
+    }
+}
+
+///
+/// The source name for a report from `device`: `gpsd.<device name>`, so
+/// that several receivers behind one gpsd can be told apart.
+///
+pub(super) fn source_name(device: Option<&str>) -> String {
+    match device
+        .and_then(|d| d.rsplit('/').next())
+        .filter(|d| !d.is_empty())
+    {
+        Some(device) => format!("gpsd.{}", device),
+        None => "gpsd".to_string(),
+    }
+}
+
+///
+/// The `host:port` to connect to for `gpsd:<host>[:<port>]`. The host can be
+/// a name, which is resolved when connecting, or an IP address.
+///
+pub(super) fn address(address: &str) -> Option<String> {
+    if let Ok(ip) = address.parse::<IpAddr>() {
+        return Some(SocketAddr::new(ip, DEFAULT_PORT).to_string());
+    }
+    if let Ok(addr) = address.parse::<SocketAddr>() {
+        return Some(addr.to_string());
+    }
+    match address.rsplit_once(':') {
+        Some((host, port)) if !host.is_empty() && port.parse::<u16>().is_ok() => {
+            Some(address.to_string())
+        }
+        Some(_) => None,
+        None if !address.is_empty() => Some(format!("{}:{}", address, DEFAULT_PORT)),
+        None => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_reports() {
+        let version: Report = serde_json::from_str(
+            r#"{"class":"VERSION","release":"3.25","rev":"3.25","proto_major":3,"proto_minor":15}"#,
+        )
+        .unwrap();
+        assert_eq!(
+            version,
+            Report::Version(Version {
+                release: Some("3.25".to_string())
+            })
+        );
+
+        let tpv: Report = serde_json::from_str(
+            r#"{"class":"TPV","device":"/dev/ttyACM0","mode":3,"time":"2024-10-01T09:11:36.000Z",
+                "lat":53.180205,"lon":5.428445,"alt":1.2,"track":274.1,"speed":2.57}"#,
+        )
+        .unwrap();
+        let Report::Tpv(tpv) = tpv else {
+            panic!("not a TPV report");
+        };
+        assert!(tpv.has_fix());
+        assert_eq!(tpv.lat, Some(53.180205));
+        assert_eq!(tpv.track, Some(274.1));
+        assert_eq!(source_name(tpv.device.as_deref()), "gpsd.ttyACM0");
+
+        let no_fix: Report =
+            serde_json::from_str(r#"{"class":"TPV","device":"/dev/ttyACM0","mode":1}"#).unwrap();
+        assert!(matches!(no_fix, Report::Tpv(tpv) if !tpv.has_fix() && tpv.lat.is_none()));
+
+        let att: Report = serde_json::from_str(
+            r#"{"class":"ATT","device":"/dev/ttyUSB1","heading":12.5,"mag_st":"N","rot":-30.0}"#,
+        )
+        .unwrap();
+        assert_eq!(
+            att,
+            Report::Att(Att {
+                device: Some("/dev/ttyUSB1".to_string()),
+                heading: Some(12.5),
+                rot: Some(-30.0),
+            })
+        );
+
+        let sky: Report =
+            serde_json::from_str(r#"{"class":"SKY","device":"/dev/ttyACM0","satellites":[]}"#)
+                .unwrap();
+        assert_eq!(sky, Report::Other);
+        assert_eq!(source_name(None), "gpsd");
+    }
+
+    #[test]
+    fn test_address() {
+        assert_eq!(address("localhost").as_deref(), Some("localhost:2947"));
+        assert_eq!(
+            address("gpsd.local:2948").as_deref(),
+            Some("gpsd.local:2948")
+        );
+        assert_eq!(address("127.0.0.1").as_deref(), Some("127.0.0.1:2947"));
+        assert_eq!(address("127.0.0.1:2947").as_deref(), Some("127.0.0.1:2947"));
+        assert_eq!(address("::1").as_deref(), Some("[::1]:2947"));
+        assert_eq!(address("[::1]:2948").as_deref(), Some("[::1]:2948"));
+        assert_eq!(address(""), None);
+        assert_eq!(address("localhost:port"), None);
+        assert_eq!(address(":2947"), None);
+    }
+}
@@ -0,0 +1,205 @@
+//! Short history of timestamped navigation samples.
+//!
+//! Spokes are stabilised and blobs are placed with the heading and position
+//! at the time the spoke was received, instead of whatever value was last
+//! set. On a yawing boat the heading can change several degrees between two
+//! compass updates, so the heading is interpolated between the samples around
+//! the spoke time, or extrapolated with the rate of turn past the last one.
+
+use std::{collections::VecDeque, f64::consts::TAU};
+
+/// How long samples are kept (millis)
+const HISTORY_MILLIS: u64 = 10_000;
+/// How far beyond the last heading it is extrapolated with the rate of turn (millis)
+const MAX_EXTRAPOLATION_MILLIS: u64 = 1_000;
+/// How old a rate of turn sample may be and still be used (millis)
+const MAX_RATE_OF_TURN_AGE_MILLIS: u64 = 2_000;
+/// Period over which the rate of turn is derived from headings when it is not reported (millis)
+const RATE_OF_TURN_WINDOW_MILLIS: u64 = 1_000;
+
+pub(crate) struct History<T> {
+    /// Samples (millis since epoch, value) in increasing time order
+    samples: VecDeque<(u64, T)>,
+}
+
+impl<T: Copy> History<T> {
+    pub(crate) const fn new() -> Self {
+        History {
+            samples: VecDeque::new(),
+        }
+    }
+
+    pub(crate) fn push(&mut self, time: u64, value: T) {
+        if let Some((last, _)) = self.samples.back() {
+            if time < *last {
+                // The clock went backwards, samples can no longer be compared
+                self.samples.clear();
+            }
+        }
+        self.samples.push_back((time, value));
+        let oldest = time.saturating_sub(HISTORY_MILLIS);
+        while self.samples.front().is_some_and(|(t, _)| *t < oldest) {
+            self.samples.pop_front();
+        }
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.samples.clear();
+    }
+
+    /// The samples directly at or before, and after `time`
+    fn around(&self, time: u64) -> (Option<(u64, T)>, Option<(u64, T)>) {
+        let i = self.samples.partition_point(|(t, _)| *t <= time);
+        let before = i.checked_sub(1).and_then(|i| self.samples.get(i)).copied();
+        (before, self.samples.get(i).copied())
+    }
+
+    /// The oldest sample that is at most `window` millis older than `time`
+    fn oldest_within(&self, time: u64, window: u64) -> Option<(u64, T)> {
+        let start = time.saturating_sub(window);
+        let i = self.samples.partition_point(|(t, _)| *t < start);
+        self.samples.get(i).copied().filter(|(t, _)| *t <= time)
+    }
+}
+
+/// Signed shortest difference from angle `a` to angle `b`, in <-PI..PI]
+fn angle_difference(a: f64, b: f64) -> f64 {
+    let d = (b - a).rem_euclid(TAU);
+    if d > std::f64::consts::PI { d - TAU } else { d }
+}
+
+///
+/// The heading in radians at `time` (millis since epoch): interpolated
+/// between the samples around it, or extrapolated with the rate of turn
+/// when `time` is after the last sample.
+///
+pub(crate) fn heading_at(
+    headings: &History<f64>,
+    rates_of_turn: &History<f64>,
+    time: u64,
+) -> Option<f64> {
+    match headings.around(time) {
+        (Some((t0, h0)), Some((t1, h1))) => {
+            let fraction = (time - t0) as f64 / (t1 - t0) as f64;
+            Some((h0 + angle_difference(h0, h1) * fraction).rem_euclid(TAU))
+        }
+        (Some((t0, h0)), None) => {
+            let rate_of_turn = rate_of_turn_at(headings, rates_of_turn, t0).unwrap_or(0.);
+            let dt = (time - t0).min(MAX_EXTRAPOLATION_MILLIS) as f64 / 1000.;
+            Some((h0 + rate_of_turn * dt).rem_euclid(TAU))
+        }
+        (None, Some((_, h1))) => Some(h1),
+        (None, None) => None,
+    }
+}
+
+///
+/// The rate of turn in radians per second at `time`: the reported rate of
+/// turn if there is a recent one, otherwise derived from the headings.
+///
+fn rate_of_turn_at(
+    headings: &History<f64>,
+    rates_of_turn: &History<f64>,
+    time: u64,
+) -> Option<f64> {
+    if let (Some((t, rate_of_turn)), _) = rates_of_turn.around(time) {
+        if time - t <= MAX_RATE_OF_TURN_AGE_MILLIS {
+            return Some(rate_of_turn);
+        }
+    }
+    let (Some((t1, h1)), _) = headings.around(time) else {
+        return None;
+    };
+    let (t0, h0) = headings.oldest_within(t1, RATE_OF_TURN_WINDOW_MILLIS)?;
+    if t0 == t1 {
+        return None;
+    }
+    Some(angle_difference(h0, h1) / ((t1 - t0) as f64 / 1000.))
+}
+
+///
+/// The position (lat, lon) in degrees at `time` (millis since epoch):
+/// interpolated between the samples around it, or the nearest sample.
+///
+pub(crate) fn position_at(positions: &History<(f64, f64)>, time: u64) -> Option<(f64, f64)> {
+    match positions.around(time) {
+        (Some((t0, (lat0, lon0))), Some((t1, (lat1, lon1)))) => {
+            let fraction = (time - t0) as f64 / (t1 - t0) as f64;
+            let lon_difference =
+                angle_difference(lon0.to_radians(), lon1.to_radians()).to_degrees();
+            let lon = (lon0 + lon_difference * fraction + 180.).rem_euclid(360.) - 180.;
+            Some((lat0 + (lat1 - lat0) * fraction, lon))
+        }
+        (Some((_, p)), None) | (None, Some((_, p))) => Some(p),
+        (None, None) => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_degrees(actual: Option<f64>, expected: f64) {
+        let actual = actual.expect("no heading").to_degrees();
+        assert!(
+            angle_difference(actual.to_radians(), expected.to_radians()).abs() < 1e-6,
+            "heading {actual}, expected {expected}"
+        );
+    }
+
+    #[test]
+    fn test_heading_interpolation() {
+        let mut headings = History::new();
+        let rates_of_turn = History::new();
+        headings.push(1000, 350f64.to_radians());
+        headings.push(1100, 10f64.to_radians());
+
+        assert_degrees(heading_at(&headings, &rates_of_turn, 1000), 350.);
+        assert_degrees(heading_at(&headings, &rates_of_turn, 1050), 0.);
+        assert_degrees(heading_at(&headings, &rates_of_turn, 1075), 5.);
+        // Before the first sample
+        assert_degrees(heading_at(&headings, &rates_of_turn, 500), 350.);
+    }
+
+    #[test]
+    fn test_heading_extrapolation() {
+        let mut headings = History::new();
+        let mut rates_of_turn = History::new();
+        headings.push(1000, 90f64.to_radians());
+        headings.push(1500, 95f64.to_radians());
+
+        // Derived rate of turn is 10 deg/s
+        assert_degrees(heading_at(&headings, &rates_of_turn, 1600), 96.);
+        // Extrapolation is limited
+        assert_degrees(heading_at(&headings, &rates_of_turn, 5000), 105.);
+
+        // Reported rate of turn takes precedence
+        rates_of_turn.push(1400, (-20f64).to_radians());
+        assert_degrees(heading_at(&headings, &rates_of_turn, 1600), 93.);
+    }
+
+    #[test]
+    fn test_history_expiry() {
+        let mut headings = History::new();
+        headings.push(1000, 1.);
+        headings.push(1000 + HISTORY_MILLIS + 1, 2.);
+        assert_eq!(headings.samples.len(), 1);
+
+        // Clock going backwards starts over
+        headings.push(500, 3.);
+        assert_eq!(headings.samples.len(), 1);
+        assert_eq!(headings.around(600), (Some((500, 3.)), None));
+    }
+
+    #[test]
+    fn test_position_interpolation() {
+        let mut positions = History::new();
+        positions.push(1000, (52.0, 179.9));
+        positions.push(2000, (52.2, -179.9));
+
+        let (lat, lon) = position_at(&positions, 1500).unwrap();
+        assert!((lat - 52.1).abs() < 1e-9);
+        assert!((lon.abs() - 180.).abs() < 1e-9);
+        assert_eq!(position_at(&positions, 3000), Some((52.2, -179.9)));
+    }
+}
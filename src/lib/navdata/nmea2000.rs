@@ -0,0 +1,547 @@
+//! NMEA 2000 navigation input through a gateway.
+//!
+//! Reads the text formats that NMEA 2000 gateways send over TCP or UDP:
+//!
+//! - Yacht Devices RAW (YDWG-02, YDEN-02): one CAN frame per line,
+//!   `17:33:21.107 R 09F80115 A0 7D E6 18 00 00 00 00`
+//! - Actisense N2K ASCII (W2K-1): one complete message per line,
+//!   `A173321.107 23FF7 1F513 012F3070002F30709F`
+//!
+//! Messages longer than 8 bytes are sent by YD gateways as fast-packets,
+//! which are reassembled here. Only the PGNs that we use are decoded.
+
+use std::collections::HashMap;
+
+use serde_json::Value;
+
+use crate::radar::RadarError;
+
+const PGN_VESSEL_HEADING: u32 = 127250;
+const PGN_MAGNETIC_VARIATION: u32 = 127258;
+const PGN_POSITION_RAPID: u32 = 129025;
+const PGN_COG_SOG_RAPID: u32 = 129026;
+const PGN_AIS_CLASS_A_POSITION: u32 = 129038;
+const PGN_AIS_CLASS_B_POSITION: u32 = 129039;
+const PGN_AIS_CLASS_A_STATIC: u32 = 129794;
+const PGN_AIS_CLASS_B_STATIC_A: u32 = 129809;
+const PGN_AIS_CLASS_B_STATIC_B: u32 = 129810;
+
+/// PGNs that we decode and that are sent as fast-packets
+const FAST_PACKET_PGNS: [u32; 5] = [
+    PGN_AIS_CLASS_A_POSITION,
+    PGN_AIS_CLASS_B_POSITION,
+    PGN_AIS_CLASS_A_STATIC,
+    PGN_AIS_CLASS_B_STATIC_A,
+    PGN_AIS_CLASS_B_STATIC_B,
+];
+
+/// PGNs that we decode and that fit in a single frame
+const SINGLE_FRAME_PGNS: [u32; 4] = [
+    PGN_VESSEL_HEADING,
+    PGN_MAGNETIC_VARIATION,
+    PGN_POSITION_RAPID,
+    PGN_COG_SOG_RAPID,
+];
+
+/// AIS transceiver information values for our own vessel
+const AIS_OWN_VESSEL: [u8; 3] = [2, 3, 4];
+
+/// A complete NMEA 2000 message
+#[derive(Debug, PartialEq)]
+pub(super) struct Message {
+    pub(super) pgn: u32,
+    /// Source address on the NMEA 2000 bus
+    pub(super) source: u8,
+    pub(super) data: Vec<u8>,
+}
+
+struct FastPacket {
+    sequence: u8,
+    next_frame: u8,
+    length: usize,
+    data: Vec<u8>,
+}
+
+pub(super) struct Nmea2000Parser {
+    /// Fast-packets being reassembled, by source address and PGN
+    fast_packets: HashMap<(u8, u32), FastPacket>,
+}
+
+impl Nmea2000Parser {
+    pub(super) fn new() -> Self {
+        Nmea2000Parser {
+            fast_packets: HashMap::new(),
+        }
+    }
+
+    ///
+    /// Parse a line in YD RAW or Actisense N2K ASCII format. Returns the
+    /// message when it is complete and one that we decode.
+    ///
+    pub(super) fn parse_line(&mut self, line: &str) -> Result<Option<Message>, RadarError> {
+        let line = line.trim();
+        if line.is_empty() {
+            return Ok(None);
+        }
+        let invalid = || RadarError::ParseNmea2000(line.to_string());
+        let mut tokens = line.split_whitespace();
+        let first = tokens.next().ok_or_else(invalid)?;
+
+        if first.starts_with('A') {
+            // A<time> <SRC><DST><PRIO> <PGN> <DATA>
+            let address = tokens.next().filter(|t| t.len() == 5).ok_or_else(invalid)?;
+            let source = address.get(0..2).ok_or_else(invalid)?;
+            let source = u8::from_str_radix(source, 16).map_err(|_| invalid())?;
+            let pgn = tokens
+                .next()
+                .and_then(|t| u32::from_str_radix(t, 16).ok())
+                .ok_or_else(invalid)?;
+            if !FAST_PACKET_PGNS.contains(&pgn) && !SINGLE_FRAME_PGNS.contains(&pgn) {
+                return Ok(None);
+            }
+            let data = parse_hex(tokens.next().unwrap_or_default()).ok_or_else(invalid)?;
+            return Ok(Some(Message { pgn, source, data }));
+        }
+
+        // <time> <R|T> <CAN id> <byte> ...
+        let _direction = tokens.next().ok_or_else(invalid)?;
+        let id = tokens
+            .next()
+            .and_then(|t| u32::from_str_radix(t, 16).ok())
+            .ok_or_else(invalid)?;
+        let (pgn, source) = decode_can_id(id);
+        let frame = tokens
+            .map(|t| u8::from_str_radix(t, 16))
+            .collect::<Result<Vec<u8>, _>>()
+            .map_err(|_| invalid())?;
+        if frame.is_empty() || frame.len() > 8 {
+            return Err(invalid());
+        }
+
+        if SINGLE_FRAME_PGNS.contains(&pgn) {
+            return Ok(Some(Message {
+                pgn,
+                source,
+                data: frame,
+            }));
+        }
+        if FAST_PACKET_PGNS.contains(&pgn) {
+            return Ok(self.add_fast_packet_frame(pgn, source, &frame));
+        }
+        Ok(None)
+    }
+
+    fn add_fast_packet_frame(&mut self, pgn: u32, source: u8, frame: &[u8]) -> Option<Message> {
+        let sequence = frame[0] >> 5;
+        let frame_counter = frame[0] & 0x1f;
+        let key = (source, pgn);
+
+        if frame_counter == 0 {
+            // First frame: sequence/counter, total length, 6 data bytes
+            let length = *frame.get(1)? as usize;
+            self.fast_packets.insert(
+                key,
+                FastPacket {
+                    sequence,
+                    next_frame: 1,
+                    length,
+                    data: frame[2..].to_vec(),
+                },
+            );
+        } else {
+            // Next frames: sequence/counter, 7 data bytes
+            let packet = self.fast_packets.get_mut(&key)?;
+            if packet.sequence != sequence || packet.next_frame != frame_counter {
+                log::trace!(
+                    "NMEA 2000 PGN {} from {}: fast-packet frame lost",
+                    pgn,
+                    source
+                );
+                self.fast_packets.remove(&key);
+                return None;
+            }
+            packet.next_frame += 1;
+            packet.data.extend_from_slice(&frame[1..]);
+        }
+
+        let packet = self.fast_packets.get(&key)?;
+        if packet.data.len() < packet.length {
+            return None;
+        }
+        let mut packet = self.fast_packets.remove(&key)?;
+        packet.data.truncate(packet.length);
+        Some(Message {
+            pgn,
+            source,
+            data: packet.data,
+        })
+    }
+}
+
+/// The PGN and source address in a 29 bit CAN identifier
+fn decode_can_id(id: u32) -> (u32, u8) {
+    let source = (id & 0xff) as u8;
+    let pdu_format = (id >> 16) & 0xff;
+    let mut pgn = (id >> 8) & 0x3ffff;
+    if pdu_format < 240 {
+        // PDU1: the PDU specific byte is the destination address
+        pgn &= 0x3ff00;
+    }
+    (pgn, source)
+}
+
+fn parse_hex(s: &str) -> Option<Vec<u8>> {
+    if s.len() % 2 != 0 {
+        return None;
+    }
+    (0..s.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(s.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+// Field accessors; NMEA 2000 uses the highest values of a field to indicate
+// that data is not available, out of range or reserved.
+
+fn u16_field(data: &[u8], i: usize) -> Option<u16> {
+    let v = u16::from_le_bytes(data.get(i..i + 2)?.try_into().ok()?);
+    (v < 0xfffd).then_some(v)
+}
+
+fn i16_field(data: &[u8], i: usize) -> Option<i16> {
+    let v = i16::from_le_bytes(data.get(i..i + 2)?.try_into().ok()?);
+    (v < 0x7ffd).then_some(v)
+}
+
+fn u32_field(data: &[u8], i: usize) -> Option<u32> {
+    let v = u32::from_le_bytes(data.get(i..i + 4)?.try_into().ok()?);
+    (v < 0xfffffffd).then_some(v)
+}
+
+fn i32_field(data: &[u8], i: usize) -> Option<i32> {
+    let v = i32::from_le_bytes(data.get(i..i + 4)?.try_into().ok()?);
+    (v < 0x7ffffffd).then_some(v)
+}
+
+/// Angle in radians, resolution 0.0001 rad
+fn angle_field(data: &[u8], i: usize) -> Option<f64> {
+    u16_field(data, i).map(|v| v as f64 * 1e-4)
+}
+
+/// Signed angle in radians, resolution 0.0001 rad
+fn signed_angle_field(data: &[u8], i: usize) -> Option<f64> {
+    i16_field(data, i).map(|v| v as f64 * 1e-4)
+}
+
+/// Latitude or longitude in degrees, resolution 1e-7 degree
+fn coordinate_field(data: &[u8], i: usize) -> Option<f64> {
+    i32_field(data, i).map(|v| v as f64 * 1e-7)
+}
+
+/// Length in meters, resolution 0.1 m
+fn length_field(data: &[u8], i: usize) -> Option<f64> {
+    u16_field(data, i).map(|v| v as f64 * 0.1)
+}
+
+/// AIS text, 6-bit ASCII padded with '@', spaces or 0xff
+fn text_field(data: &[u8], i: usize, len: usize) -> Option<String> {
+    let text: String = data
+        .get(i..i + len)?
+        .iter()
+        .take_while(|b| **b != 0 && **b != 0xff && **b != b'@')
+        .map(|b| *b as char)
+        .collect();
+    let text = text.trim();
+    (!text.is_empty()).then(|| text.to_string())
+}
+
+/// The navigation and AIS data in a message
+#[derive(Debug, PartialEq)]
+pub(super) enum Decoded {
+    Heading {
+        heading: f64,
+        deviation: Option<f64>,
+        variation: Option<f64>,
+        magnetic: bool,
+    },
+    Variation(f64),
+    Position {
+        lat: f64,
+        lon: f64,
+    },
+    CogSog {
+        cog: Option<f64>,
+        sog: Option<f64>,
+        magnetic: bool,
+    },
+    AisPosition {
+        mmsi: u32,
+        lat: Option<f64>,
+        lon: Option<f64>,
+        cog: Option<f64>,
+        sog: Option<f64>,
+        heading: Option<f64>,
+    },
+    AisStatic {
+        mmsi: u32,
+        name: Option<String>,
+        length: Option<f64>,
+        beam: Option<f64>,
+        from_starboard: Option<f64>,
+        from_bow: Option<f64>,
+    },
+}
+
+pub(super) fn decode(message: &Message) -> Option<Decoded> {
+    let data = &message.data;
+    match message.pgn {
+        PGN_VESSEL_HEADING => Some(Decoded::Heading {
+            heading: angle_field(data, 1)?,
+            deviation: signed_angle_field(data, 3),
+            variation: signed_angle_field(data, 5),
+            magnetic: data.get(7)? & 0x03 == 1,
+        }),
+        PGN_MAGNETIC_VARIATION => Some(Decoded::Variation(signed_angle_field(data, 4)?)),
+        PGN_POSITION_RAPID => Some(Decoded::Position {
+            lat: coordinate_field(data, 0)?,
+            lon: coordinate_field(data, 4)?,
+        }),
+        PGN_COG_SOG_RAPID => Some(Decoded::CogSog {
+            cog: angle_field(data, 2),
+            sog: u16_field(data, 4).map(|v| v as f64 * 0.01),
+            magnetic: data.get(1)? & 0x03 == 1,
+        }),
+        PGN_AIS_CLASS_A_POSITION | PGN_AIS_CLASS_B_POSITION => {
+            if AIS_OWN_VESSEL.contains(&(data.get(20)? >> 3)) {
+                return None;
+            }
+            Some(Decoded::AisPosition {
+                mmsi: u32_field(data, 1)?,
+                lon: coordinate_field(data, 5),
+                lat: coordinate_field(data, 9),
+                cog: angle_field(data, 14),
+                sog: u16_field(data, 16).map(|v| v as f64 * 0.01),
+                heading: angle_field(data, 21),
+            })
+        }
+        PGN_AIS_CLASS_A_STATIC => {
+            if AIS_OWN_VESSEL.contains(&(data.get(74)? & 0x1f)) {
+                return None;
+            }
+            Some(Decoded::AisStatic {
+                mmsi: u32_field(data, 1)?,
+                name: text_field(data, 16, 20),
+                length: length_field(data, 37),
+                beam: length_field(data, 39),
+                from_starboard: length_field(data, 41),
+                from_bow: length_field(data, 43),
+            })
+        }
+        PGN_AIS_CLASS_B_STATIC_A => {
+            if AIS_OWN_VESSEL.contains(&(data.get(25)? & 0x1f)) {
+                return None;
+            }
+            Some(Decoded::AisStatic {
+                mmsi: u32_field(data, 1)?,
+                name: text_field(data, 5, 20),
+                length: None,
+                beam: None,
+                from_starboard: None,
+                from_bow: None,
+            })
+        }
+        PGN_AIS_CLASS_B_STATIC_B => Some(Decoded::AisStatic {
+            mmsi: u32_field(data, 1)?,
+            name: None,
+            length: length_field(data, 20),
+            beam: length_field(data, 22),
+            from_starboard: length_field(data, 24),
+            from_bow: length_field(data, 26),
+        }),
+        _ => None,
+    }
+}
+
+/// Signal K values for a decoded AIS message
+pub(super) fn ais_updates(decoded: &Decoded) -> Option<(u32, Value)> {
+    let mut values = Vec::new();
+    let mmsi = match decoded {
+        Decoded::AisPosition {
+            mmsi,
+            lat,
+            lon,
+            cog,
+            sog,
+            heading,
+        } => {
+            if let (Some(latitude), Some(longitude)) = (lat, lon) {
+                values.push((
+                    "navigation.position",
+                    serde_json::json!({ "latitude": latitude, "longitude": longitude }),
+                ));
+            }
+            if let Some(heading) = heading {
+                values.push(("navigation.headingTrue", (*heading).into()));
+            }
+            if let Some(cog) = cog {
+                values.push(("navigation.courseOverGroundTrue", (*cog).into()));
+            }
+            if let Some(sog) = sog {
+                values.push(("navigation.speedOverGround", (*sog).into()));
+            }
+            *mmsi
+        }
+        Decoded::AisStatic {
+            mmsi,
+            name,
+            length,
+            beam,
+            from_starboard,
+            from_bow,
+        } => {
+            if let Some(name) = name {
+                values.push(("", serde_json::json!({ "name": name })));
+            }
+            let meters = |v: Option<f64>| v.map(|v| v.round() as u16);
+            let to_stern = match (length, from_bow) {
+                (Some(length), Some(from_bow)) => Some(length - from_bow),
+                _ => None,
+            };
+            let to_port = match (beam, from_starboard) {
+                (Some(beam), Some(from_starboard)) => Some(beam - from_starboard),
+                _ => None,
+            };
+            values.extend(super::ais_dimension_values(
+                meters(*from_bow),
+                meters(to_stern),
+                meters(to_port),
+                meters(*from_starboard),
+            ));
+            *mmsi
+        }
+        _ => return None,
+    };
+    Some((mmsi, crate::ais::signalk_updates(values)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decode_can_id() {
+        // PDU2: PGN 129025 from source 0x15
+        assert_eq!(decode_can_id(0x09F80115), (129025, 0x15));
+        // PDU1: destination address is not part of the PGN
+        assert_eq!(decode_can_id(0x18EAFF00), (59904, 0x00));
+    }
+
+    #[test]
+    fn test_yd_raw_position() {
+        let mut parser = Nmea2000Parser::new();
+        // 52.3676 N, 4.9041 E
+        let lat = (52.3676e7 as i32).to_le_bytes();
+        let lon = (4.9041e7 as i32).to_le_bytes();
+        let line = format!(
+            "17:33:21.107 R 09F80115 {}",
+            lat.iter()
+                .chain(lon.iter())
+                .map(|b| format!("{:02X}", b))
+                .collect::<Vec<_>>()
+                .join(" ")
+        );
+        let message = parser.parse_line(&line).unwrap().unwrap();
+        assert_eq!(message.pgn, PGN_POSITION_RAPID);
+        assert_eq!(message.source, 0x15);
+        match decode(&message) {
+            Some(Decoded::Position { lat, lon }) => {
+                assert!((lat - 52.3676).abs() < 1e-6);
+                assert!((lon - 4.9041).abs() < 1e-6);
+            }
+            d => panic!("unexpected {:?}", d),
+        }
+
+        // Not a PGN that we decode
+        assert_eq!(
+            parser
+                .parse_line("17:33:21.108 R 09F50B23 FF FF FF FF FF FF FF FF")
+                .unwrap(),
+            None
+        );
+        assert!(parser.parse_line("17:33:21.109 R 09F80115 XX").is_err());
+    }
+
+    #[test]
+    fn test_actisense_ascii_heading() {
+        let mut parser = Nmea2000Parser::new();
+        // Heading 1.578 rad magnetic, deviation n/a, variation -0.0036 rad
+        let line = "A173321.107 23FF2 1F112 00A43DFF7FDCFFFD";
+        let message = parser.parse_line(line).unwrap().unwrap();
+        assert_eq!(message.source, 0x23);
+        assert_eq!(
+            decode(&message),
+            Some(Decoded::Heading {
+                heading: 15780. * 1e-4,
+                deviation: None,
+                variation: Some(-36. * 1e-4),
+                magnetic: true,
+            })
+        );
+
+        // Five bytes, but the source address would split a character
+        assert!(parser.parse_line("A173321.107 2\u{e9}FF 1F112 00").is_err());
+    }
+
+    #[test]
+    fn test_fast_packet_ais_static() {
+        let mut data = vec![0u8; 27];
+        data[0] = 24;
+        data[1..5].copy_from_slice(&244060807u32.to_le_bytes());
+        data[5..25].copy_from_slice(b"MERRIMAC@@@@@@@@@@@@");
+        data[25] = 0; // received on channel A
+
+        // Split in fast-packet frames: 6 bytes in the first, 7 in the others
+        let mut frames = vec![[0x40, data.len() as u8, 0, 0, 0, 0, 0, 0]];
+        frames[0][2..].copy_from_slice(&data[..6]);
+        for (i, chunk) in data[6..].chunks(7).enumerate() {
+            let mut frame = [0xffu8; 8];
+            frame[0] = 0x40 | (i as u8 + 1);
+            frame[1..1 + chunk.len()].copy_from_slice(chunk);
+            frames.push(frame);
+        }
+
+        let mut parser = Nmea2000Parser::new();
+        let mut messages = Vec::new();
+        for frame in &frames {
+            let bytes: Vec<String> = frame.iter().map(|b| format!("{:02X}", b)).collect();
+            let line = format!("10:00:00.000 R 19FB1123 {}", bytes.join(" "));
+            messages.extend(parser.parse_line(&line).unwrap());
+        }
+        assert_eq!(messages.len(), 1);
+        assert_eq!(messages[0].data, data);
+
+        let decoded = decode(&messages[0]).unwrap();
+        assert_eq!(
+            decoded,
+            Decoded::AisStatic {
+                mmsi: 244060807,
+                name: Some("MERRIMAC".to_string()),
+                length: None,
+                beam: None,
+                from_starboard: None,
+                from_bow: None,
+            }
+        );
+        let (mmsi, updates) = ais_updates(&decoded).unwrap();
+        assert_eq!(mmsi, 244060807);
+        assert_eq!(updates[0]["values"][0]["value"]["name"], "MERRIMAC");
+
+        // A lost frame drops the message
+        let mut parser = Nmea2000Parser::new();
+        for frame in frames.iter().filter(|f| f[0] != 0x42) {
+            let bytes: Vec<String> = frame.iter().map(|b| format!("{:02X}", b)).collect();
+            let line = format!("10:00:00.000 R 19FB1123 {}", bytes.join(" "));
+            assert_eq!(parser.parse_line(&line).unwrap(), None);
+        }
+    }
+}
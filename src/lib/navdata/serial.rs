@@ -0,0 +1,101 @@
+//! Serial port navigation input.
+//!
+//! GPS receivers and compasses are often connected through an RS-422 or USB
+//! serial adapter. The address is `serial:<device>[:<baud rate>]`, for example
+//! `serial:/dev/ttyUSB0:4800` or `serial:COM3:38400`.
+
+use tokio::io::{AsyncBufRead, AsyncBufReadExt};
+use tokio_serial::{SerialPortBuilderExt, SerialStream};
+
+use crate::radar::RadarError;
+
+/// NMEA 0183 standard baud rate; high speed (AIS) devices use 38400
+const DEFAULT_BAUD_RATE: u32 = 4800;
+
+///
+/// Parse `<device>[:<baud rate>]` into device and baud rate. Device names
+/// may contain ':' themselves (`/dev/serial/by-path/...`), so the last part
+/// is only taken as baud rate when it is a number.
+///
+pub(super) fn parse_address(address: &str) -> (String, u32) {
+    match address.rsplit_once(':') {
+        Some((device, baud_rate)) if !device.is_empty() => match baud_rate.parse() {
+            Ok(baud_rate) => (device.to_string(), baud_rate),
+            Err(_) => (address.to_string(), DEFAULT_BAUD_RATE),
+        },
+        _ => (address.to_string(), DEFAULT_BAUD_RATE),
+    }
+}
+
+pub(super) fn open(device: &str, baud_rate: u32) -> Result<SerialStream, RadarError> {
+    tokio_serial::new(device, baud_rate)
+        .open_native_async()
+        .map_err(|e| RadarError::Io(e.into()))
+}
+
+///
+/// Read the next non-empty line. Serial lines often carry some garbage, for
+/// instance right after connecting or with a wrong baud rate, so invalid
+/// UTF-8 is replaced instead of failing the connection. Returns None at end
+/// of file.
+///
+pub(super) async fn next_line<R: AsyncBufRead + Unpin>(
+    reader: &mut R,
+    buf: &mut Vec<u8>,
+) -> std::io::Result<Option<String>> {
+    loop {
+        // Partially read data stays in `buf` when this future is cancelled
+        if reader.read_until(b'\n', buf).await? == 0 {
+            return Ok(None);
+        }
+        let line = String::from_utf8_lossy(buf).trim().to_string();
+        buf.clear();
+        if !line.is_empty() {
+            return Ok(Some(line));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_address() {
+        assert_eq!(
+            parse_address("/dev/ttyUSB0:38400"),
+            ("/dev/ttyUSB0".to_string(), 38400)
+        );
+        assert_eq!(
+            parse_address("/dev/ttyUSB0"),
+            ("/dev/ttyUSB0".to_string(), DEFAULT_BAUD_RATE)
+        );
+        assert_eq!(parse_address("COM3:4800"), ("COM3".to_string(), 4800));
+        let by_path = "/dev/serial/by-path/pci-0000:00:14.0-usb-0:2:1.0-port0";
+        assert_eq!(
+            parse_address(by_path),
+            (by_path.to_string(), DEFAULT_BAUD_RATE)
+        );
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_read_lines_from_pty() {
+        use tokio::io::{AsyncWriteExt, BufReader};
+
+        let (mut master, slave) = SerialStream::pair().expect("cannot create pty pair");
+        let mut reader = BufReader::new(slave);
+        let mut buf = Vec::new();
+
+        master
+            .write_all(b"$GPHDT,274.07,T*03\r\n\xff\xfe$GPROT,3.5,A*30\r\n")
+            .await
+            .unwrap();
+        assert_eq!(
+            next_line(&mut reader, &mut buf).await.unwrap().as_deref(),
+            Some("$GPHDT,274.07,T*03")
+        );
+        let line = next_line(&mut reader, &mut buf).await.unwrap().unwrap();
+        assert!(line.ends_with("$GPROT,3.5,A*30"), "{line}");
+    }
+}
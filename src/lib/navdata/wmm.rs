@@ -0,0 +1,236 @@
+//! Magnetic declination from the World Magnetic Model (WMM).
+//!
+//! Used to convert a magnetic heading into a true heading when neither the
+//! compass nor the navigation server reports the magnetic variation.
+//!
+//! The coefficients are those of WMM2025 (epoch 2025.0). Outside its five
+//! year validity window the model is extrapolated with its own secular
+//! variation, which degrades slowly (typically a few tenths of a degree
+//! per year), still far better than not correcting at all.
+
+use std::f64::consts::FRAC_PI_2;
+
+use chrono::{DateTime, Datelike, NaiveDate, Timelike, Utc};
+
+const EPOCH: f64 = 2025.0;
+const MAX_DEGREE: usize = 12;
+
+/// Geomagnetic reference radius in km
+const REFERENCE_RADIUS: f64 = 6371.2;
+/// WGS-84 semi-major axis in km
+const WGS84_A: f64 = 6378.137;
+/// WGS-84 flattening
+const WGS84_F: f64 = 1. / 298.257223563;
+
+/// Gauss coefficients (n, m, g, h, g_dot, h_dot) in nT and nT/year
+#[rustfmt::skip]
+const COEFFICIENTS: [(usize, usize, f64, f64, f64, f64); 90] = [
+    (1, 0, -29351.8, 0.0, 12.0, 0.0),
+    (1, 1, -1410.8, 4545.4, 9.7, -21.5),
+    (2, 0, -2556.6, 0.0, -11.6, 0.0),
+    (2, 1, 2951.1, -3133.6, -5.2, -27.7),
+    (2, 2, 1649.3, -815.1, -8.0, -12.1),
+    (3, 0, 1361.0, 0.0, -1.3, 0.0),
+    (3, 1, -2404.1, -56.6, -4.2, 4.0),
+    (3, 2, 1243.8, 237.5, 0.4, -0.3),
+    (3, 3, 453.6, -549.5, -15.6, -4.1),
+    (4, 0, 895.0, 0.0, -1.6, 0.0),
+    (4, 1, 799.5, 278.6, -2.4, -1.1),
+    (4, 2, 55.7, -133.9, -6.0, 4.1),
+    (4, 3, -281.1, 212.0, 5.6, 1.6),
+    (4, 4, 12.1, -375.6, -7.0, -4.4),
+    (5, 0, -233.2, 0.0, 0.6, 0.0),
+    (5, 1, 368.9, 45.4, 1.4, -0.5),
+    (5, 2, 187.2, 220.2, 0.0, 2.2),
+    (5, 3, -138.7, -122.9, 0.6, 0.4),
+    (5, 4, -142.0, 43.0, 2.2, 1.7),
+    (5, 5, 20.9, 106.1, 0.9, 1.9),
+    (6, 0, 64.4, 0.0, -0.2, 0.0),
+    (6, 1, 63.8, -18.4, -0.4, 0.3),
+    (6, 2, 76.9, 16.8, 0.9, -1.6),
+    (6, 3, -115.7, 48.8, 1.2, -0.4),
+    (6, 4, -40.9, -59.8, -0.9, 0.9),
+    (6, 5, 14.9, 10.9, 0.3, 0.7),
+    (6, 6, -60.7, 72.7, 0.9, 0.9),
+    (7, 0, 79.5, 0.0, -0.0, 0.0),
+    (7, 1, -77.0, -48.9, -0.1, 0.6),
+    (7, 2, -8.8, -14.4, -0.1, 0.5),
+    (7, 3, 59.3, -1.0, 0.5, -0.8),
+    (7, 4, 15.8, 23.4, -0.1, 0.0),
+    (7, 5, 2.5, -7.4, -0.8, -1.0),
+    (7, 6, -11.1, -25.1, -0.8, 0.6),
+    (7, 7, 14.2, -2.3, 0.8, -0.2),
+    (8, 0, 23.2, 0.0, -0.1, 0.0),
+    (8, 1, 10.8, 7.1, 0.2, -0.2),
+    (8, 2, -17.5, -12.6, 0.0, 0.5),
+    (8, 3, 2.0, 11.4, 0.5, -0.4),
+    (8, 4, -21.7, -9.7, -0.1, 0.4),
+    (8, 5, 16.9, 12.7, 0.3, -0.5),
+    (8, 6, 15.0, 0.7, 0.2, -0.6),
+    (8, 7, -16.8, -5.2, -0.0, 0.3),
+    (8, 8, 0.9, 3.9, 0.2, 0.2),
+    (9, 0, 4.6, 0.0, -0.0, 0.0),
+    (9, 1, 7.8, -24.8, -0.1, -0.3),
+    (9, 2, 3.0, 12.2, 0.1, 0.3),
+    (9, 3, -0.2, 8.3, 0.3, -0.3),
+    (9, 4, -2.5, -3.3, -0.3, 0.3),
+    (9, 5, -13.1, -5.2, 0.0, 0.2),
+    (9, 6, 2.4, 7.2, 0.3, -0.1),
+    (9, 7, 8.6, -0.6, -0.1, -0.2),
+    (9, 8, -8.7, 0.8, 0.1, 0.4),
+    (9, 9, -12.9, 10.0, -0.1, 0.1),
+    (10, 0, -1.3, 0.0, 0.1, 0.0),
+    (10, 1, -6.4, 3.3, 0.0, 0.0),
+    (10, 2, 0.2, 0.0, 0.1, -0.0),
+    (10, 3, 2.0, 2.4, 0.1, -0.2),
+    (10, 4, -1.0, 5.3, -0.0, 0.1),
+    (10, 5, -0.6, -9.1, -0.3, -0.1),
+    (10, 6, -0.9, 0.4, 0.0, 0.1),
+    (10, 7, 1.5, -4.2, -0.1, 0.0),
+    (10, 8, 0.9, -3.8, -0.1, -0.1),
+    (10, 9, -2.7, 0.9, -0.0, 0.2),
+    (10, 10, -3.9, -9.1, -0.0, -0.0),
+    (11, 0, 2.9, 0.0, 0.0, 0.0),
+    (11, 1, -1.5, 0.0, -0.0, -0.0),
+    (11, 2, -2.5, 2.9, 0.0, 0.1),
+    (11, 3, 2.4, -0.6, 0.0, -0.0),
+    (11, 4, -0.6, 0.2, 0.0, 0.1),
+    (11, 5, -0.1, 0.5, -0.1, -0.0),
+    (11, 6, -0.6, -0.3, 0.0, -0.0),
+    (11, 7, -0.1, -1.2, -0.0, 0.1),
+    (11, 8, 1.1, -1.7, -0.1, -0.0),
+    (11, 9, -1.0, -2.9, -0.1, 0.0),
+    (11, 10, -0.2, -1.8, -0.1, 0.0),
+    (11, 11, 2.6, -2.3, -0.1, 0.0),
+    (12, 0, -2.0, 0.0, 0.0, 0.0),
+    (12, 1, -0.2, -1.3, 0.0, -0.0),
+    (12, 2, 0.3, 0.7, -0.0, 0.0),
+    (12, 3, 1.2, 1.0, -0.0, -0.1),
+    (12, 4, -1.3, -1.4, -0.0, 0.1),
+    (12, 5, 0.6, -0.0, -0.0, -0.0),
+    (12, 6, 0.6, 0.6, 0.1, -0.0),
+    (12, 7, 0.5, -0.1, -0.0, -0.0),
+    (12, 8, -0.1, 0.8, 0.0, 0.0),
+    (12, 9, -0.4, 0.1, 0.0, -0.0),
+    (12, 10, -0.2, -1.0, -0.1, -0.0),
+    (12, 11, -1.3, 0.1, -0.0, 0.0),
+    (12, 12, -0.7, 0.2, -0.1, -0.1),
+];
+
+/// Decimal year, e.g. 2025.5 for the start of July 2025
+pub(crate) fn decimal_year(t: &DateTime<Utc>) -> f64 {
+    let days_in_year = NaiveDate::from_ymd_opt(t.year(), 12, 31)
+        .map(|d| d.ordinal())
+        .unwrap_or(365) as f64;
+    let day = t.ordinal0() as f64 + t.num_seconds_from_midnight() as f64 / 86400.;
+    t.year() as f64 + day / days_in_year
+}
+
+///
+/// Magnetic declination (variation) in radians at sea level, positive east.
+///
+/// `lat` and `lon` are WGS-84 degrees, `year` is a decimal year.
+///
+pub(crate) fn declination(lat: f64, lon: f64, year: f64) -> f64 {
+    let dt = year - EPOCH;
+
+    // Geodetic to geocentric spherical coordinates
+    let phi = lat.to_radians();
+    let lambda = lon.to_radians();
+    let e2 = WGS84_F * (2. - WGS84_F);
+    let rc = WGS84_A / (1. - e2 * phi.sin().powi(2)).sqrt();
+    let p = rc * phi.cos();
+    let z = rc * (1. - e2) * phi.sin();
+    let r = p.hypot(z);
+    let phi_geocentric = (z / r).asin();
+
+    // Associated Legendre functions P(n, m) of cos(colatitude) and their
+    // derivatives to colatitude, Gauss normalized, and the factors to turn
+    // them into the Schmidt semi-normalized functions the WMM uses.
+    let theta = FRAC_PI_2 - phi_geocentric;
+    let (st, ct) = theta.sin_cos();
+    let mut p_nm = [[0f64; MAX_DEGREE + 1]; MAX_DEGREE + 1];
+    let mut dp_nm = [[0f64; MAX_DEGREE + 1]; MAX_DEGREE + 1];
+    let mut schmidt = [[0f64; MAX_DEGREE + 1]; MAX_DEGREE + 1];
+    p_nm[0][0] = 1.;
+    schmidt[0][0] = 1.;
+    for n in 1..=MAX_DEGREE {
+        for m in 0..=n {
+            if n == m {
+                p_nm[n][m] = st * p_nm[n - 1][m - 1];
+                dp_nm[n][m] = st * dp_nm[n - 1][m - 1] + ct * p_nm[n - 1][m - 1];
+            } else if n == 1 || m == n - 1 {
+                p_nm[n][m] = ct * p_nm[n - 1][m];
+                dp_nm[n][m] = ct * dp_nm[n - 1][m] - st * p_nm[n - 1][m];
+            } else {
+                let k = ((n - 1) * (n - 1) - m * m) as f64 / ((2 * n - 1) * (2 * n - 3)) as f64;
+                p_nm[n][m] = ct * p_nm[n - 1][m] - k * p_nm[n - 2][m];
+                dp_nm[n][m] = ct * dp_nm[n - 1][m] - st * p_nm[n - 1][m] - k * dp_nm[n - 2][m];
+            }
+        }
+        schmidt[n][0] = schmidt[n - 1][0] * (2 * n - 1) as f64 / n as f64;
+        for m in 1..=n {
+            let j = if m == 1 { 2. } else { 1. };
+            schmidt[n][m] = schmidt[n][m - 1] * ((n - m + 1) as f64 * j / (n + m) as f64).sqrt();
+        }
+    }
+
+    // Field components in the geocentric frame: north (x) and east (y),
+    // plus down (z) which is needed to rotate x back to geodetic north.
+    let mut b_theta = 0.;
+    let mut b_phi = 0.;
+    let mut b_r = 0.;
+    for &(n, m, g, h, g_dot, h_dot) in COEFFICIENTS.iter() {
+        let ar = (REFERENCE_RADIUS / r).powi(n as i32 + 2);
+        let g = (g + dt * g_dot) * schmidt[n][m];
+        let h = (h + dt * h_dot) * schmidt[n][m];
+        let (sm, cm) = (m as f64 * lambda).sin_cos();
+
+        b_r += ar * (n + 1) as f64 * (g * cm + h * sm) * p_nm[n][m];
+        b_theta -= ar * (g * cm + h * sm) * dp_nm[n][m];
+        if st != 0. {
+            b_phi += ar * m as f64 * (g * sm - h * cm) * p_nm[n][m] / st;
+        }
+    }
+    let x = -b_theta;
+    let y = b_phi;
+    let z = -b_r;
+
+    let psi = phi_geocentric - phi;
+    let x_geodetic = x * psi.cos() - z * psi.sin();
+
+    y.atan2(x_geodetic)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Sea level declinations at the WMM2025 epoch and 2.5 years later
+    #[test]
+    fn test_declination() {
+        let cases = [
+            (80., 0., 2025.0, 1.28),
+            (0., 120., 2025.0, -0.16),
+            (-80., 240., 2025.0, 68.78),
+            (80., 0., 2027.5, 2.59),
+            (-80., 240., 2027.5, 68.49),
+        ];
+        for (lat, lon, year, expected) in cases {
+            let d = declination(lat, lon, year).to_degrees();
+            assert!(
+                (d - expected).abs() < 0.01,
+                "declination at {lat},{lon} in {year} = {d}, expected {expected}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_decimal_year() {
+        let t = DateTime::parse_from_rfc3339("2024-07-02T00:00:00Z")
+            .unwrap()
+            .with_timezone(&Utc);
+        // 2024 is a leap year, July 2nd is day 183 (0 based)
+        assert!((decimal_year(&t) - (2024. + 183. / 366.)).abs() < 1e-9);
+    }
+}
@@ -0,0 +1,499 @@
+//! NMEA 0183 output of ARPA targets.
+//!
+//! Listens to the target updates that the `TrackerManager` sends to Signal K
+//! clients and re-encodes them as `$RATTM` (tracked target message) and
+//! `$RATLL` (target latitude and longitude) sentences, so that chart plotters
+//! and autopilots that only understand NMEA 0183 can show our targets.
+//!
+//! The sentences are sent to a UDP (broadcast) address, or to every client
+//! connected to a TCP listen port.
+
+use std::collections::{HashMap, HashSet};
+use std::f64::consts::TAU;
+use std::fmt::Write;
+use std::net::SocketAddr;
+
+use chrono::{DateTime, Timelike, Utc};
+use serde_json::Value;
+use tokio::io::AsyncWriteExt;
+use tokio::net::{TcpListener, TcpStream, UdpSocket};
+use tokio::sync::broadcast;
+use tokio_graceful_shutdown::SubsystemHandle;
+
+use crate::radar::{NAUTICAL_MILE_F64, RadarError};
+use crate::stream::{SignalKDelta, opt_f64};
+
+/// Talker ID used for all sentences: "RA" = RADAR and/or ARPA
+const TALKER_ID: &str = "RA";
+
+/// TTM target numbers run from 01 to 99
+const MAX_TARGET_NUMBER: u8 = 99;
+
+const MS_TO_KN: f64 = 3600. / NAUTICAL_MILE_F64;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum NmeaOutputAddress {
+    /// Send each sentence as a datagram to this (broadcast) address
+    Udp(SocketAddr),
+    /// Listen on this address and send each sentence to all connected clients
+    Tcp(SocketAddr),
+}
+
+impl NmeaOutputAddress {
+    pub fn parse(s: &str) -> Result<NmeaOutputAddress, String> {
+        let parts: Vec<&str> = s.splitn(2, ':').collect();
+        if parts.len() == 2 {
+            if let Ok(addr) = parts[1].parse() {
+                match parts[0].to_ascii_lowercase().as_str() {
+                    "udp" => return Ok(NmeaOutputAddress::Udp(addr)),
+                    "tcp" => return Ok(NmeaOutputAddress::Tcp(addr)),
+                    _ => {}
+                }
+            }
+        }
+        Err(format!(
+            "NMEA output address '{}' must be <connection>:<address>:<port> with <connection> one of `udp` or `tcp`",
+            s
+        ))
+    }
+}
+
+///
+/// Keeps the mapping from (radar, target id) to the two digit target number
+/// that goes into the TTM and TLL sentences. Our target ids are unique per
+/// radar and grow without bound, so we hand out the lowest free number when
+/// a target first appears and release it when the target is deleted.
+///
+#[derive(Default)]
+struct TargetNumbers {
+    numbers: HashMap<(String, u64), u8>,
+}
+
+impl TargetNumbers {
+    fn get(&self, radar_id: &str, target_id: u64) -> Option<u8> {
+        self.numbers
+            .get(&(radar_id.to_string(), target_id))
+            .copied()
+    }
+
+    fn get_or_assign(&mut self, radar_id: &str, target_id: u64) -> Option<u8> {
+        if let Some(n) = self.get(radar_id, target_id) {
+            return Some(n);
+        }
+        let n = (1..=MAX_TARGET_NUMBER).find(|n| !self.numbers.values().any(|v| v == n))?;
+        self.numbers.insert((radar_id.to_string(), target_id), n);
+        Some(n)
+    }
+
+    fn release(&mut self, radar_id: &str, target_id: u64) -> Option<u8> {
+        self.numbers.remove(&(radar_id.to_string(), target_id))
+    }
+}
+
+///
+/// Converts target updates into NMEA 0183 sentences, following the same
+/// acquiring -> tracking -> lost -> deleted lifecycle as the Signal K stream.
+///
+#[derive(Default)]
+pub(crate) struct TargetSentences {
+    numbers: TargetNumbers,
+    /// Target numbers whose last sentence had status 'L'
+    lost: HashSet<u8>,
+}
+
+impl TargetSentences {
+    pub(crate) fn new() -> Self {
+        Self::default()
+    }
+
+    /// Return the sentences for all target updates in `delta`
+    pub(crate) fn process_delta(&mut self, delta: &SignalKDelta) -> Vec<String> {
+        let mut sentences = Vec::new();
+        for (radar_id, target_id, value) in delta.target_updates() {
+            self.process_target(radar_id, target_id, value, &mut sentences);
+        }
+        sentences
+    }
+
+    fn process_target(
+        &mut self,
+        radar_id: &str,
+        target_id: u64,
+        value: &Value,
+        sentences: &mut Vec<String>,
+    ) {
+        if value.is_null() {
+            // Target deleted. If the plotter has not seen it as lost yet, tell it now
+            // so it does not keep a stale symbol on the screen.
+            if let Some(number) = self.numbers.release(radar_id, target_id) {
+                if !self.lost.remove(&number) {
+                    sentences.push(lost_ttm(number));
+                }
+            }
+            return;
+        }
+
+        let Some(number) = self.numbers.get_or_assign(radar_id, target_id) else {
+            log::debug!(
+                "No free NMEA target number for target {} of radar {}",
+                target_id,
+                radar_id
+            );
+            return;
+        };
+
+        let status = target_status(value);
+        if status == 'L' {
+            self.lost.insert(number);
+        } else {
+            self.lost.remove(&number);
+        }
+
+        sentences.push(ttm(number, status, value));
+        if let Some(tll) = tll(number, status, value) {
+            sentences.push(tll);
+        }
+    }
+}
+
+fn target_status(value: &Value) -> char {
+    match value.get("status").and_then(Value::as_str) {
+        Some("tracking") => 'T',
+        Some("lost") => 'L',
+        _ => 'Q', // acquiring
+    }
+}
+
+fn acquisition_type(value: &Value) -> char {
+    match value.get("acquisition").and_then(Value::as_str) {
+        Some("manual") => 'M',
+        _ => 'A',
+    }
+}
+
+/// Format the UTC time of the last update as hhmmss.ss
+fn utc_time(value: &Value) -> String {
+    value
+        .get("lastSeen")
+        .and_then(Value::as_str)
+        .and_then(|s| DateTime::parse_from_rfc3339(s).ok())
+        .map(|t| format_time(&t.with_timezone(&Utc)))
+        .unwrap_or_default()
+}
+
+fn format_time(t: &DateTime<Utc>) -> String {
+    format!(
+        "{:02}{:02}{:02}.{:02}",
+        t.hour(),
+        t.minute(),
+        t.second(),
+        t.nanosecond() / 10_000_000 % 100
+    )
+}
+
+fn format_opt(v: Option<f64>, precision: usize) -> String {
+    v.map(|v| format!("{:.*}", precision, v))
+        .unwrap_or_default()
+}
+
+/// $RATTM,xx,x.x,x.x,T,x.x,x.x,T,x.x,x.x,N,c--c,a,,hhmmss.ss,a*hh
+fn ttm(number: u8, status: char, value: &Value) -> String {
+    let distance = opt_f64(value, "position", "distance").map(|d| d / NAUTICAL_MILE_F64);
+    let bearing = opt_f64(value, "position", "bearing").map(|b| b.rem_euclid(TAU).to_degrees());
+    let speed = opt_f64(value, "motion", "speed").map(|s| s * MS_TO_KN);
+    let course = opt_f64(value, "motion", "course").map(|c| c.rem_euclid(TAU).to_degrees());
+    // Danger is omitted entirely when the vessels are diverging
+    let cpa = opt_f64(value, "danger", "cpa").map(|d| d / NAUTICAL_MILE_F64);
+    let tcpa = opt_f64(value, "danger", "tcpa").map(|t| t / 60.);
+
+    let body = format!(
+        "{}TTM,{:02},{},{},T,{},{},T,{},{},N,,{},,{},{}",
+        TALKER_ID,
+        number,
+        format_opt(distance, 2),
+        format_opt(bearing, 1),
+        format_opt(speed, 1),
+        format_opt(course, 1),
+        format_opt(cpa, 2),
+        format_opt(tcpa, 1),
+        status,
+        utc_time(value),
+        acquisition_type(value),
+    );
+    with_checksum('$', &body)
+}
+
+/// TTM announcing that a target is gone, with all other fields empty
+fn lost_ttm(number: u8) -> String {
+    let body = format!(
+        "{}TTM,{:02},,,T,,,T,,,N,,L,,{},",
+        TALKER_ID,
+        number,
+        format_time(&Utc::now())
+    );
+    with_checksum('$', &body)
+}
+
+/// $RATLL,xx,llll.ll,a,yyyyy.yy,a,c--c,hhmmss.ss,a,*hh
+///
+/// Only sent when the target position is known in lat/lon.
+fn tll(number: u8, status: char, value: &Value) -> Option<String> {
+    let lat = opt_f64(value, "position", "latitude")?;
+    let lon = opt_f64(value, "position", "longitude")?;
+
+    let body = format!(
+        "{}TLL,{:02},{},{},,{},{},",
+        TALKER_ID,
+        number,
+        format_latitude(lat),
+        format_longitude(lon),
+        utc_time(value),
+        status,
+    );
+    Some(with_checksum('$', &body))
+}
+
+fn format_latitude(lat: f64) -> String {
+    let hemisphere = if lat < 0. { 'S' } else { 'N' };
+    let lat = lat.abs();
+    let degrees = lat.trunc();
+    let minutes = (lat - degrees) * 60.;
+    format!("{:02}{:07.4},{}", degrees as u32, minutes, hemisphere)
+}
+
+fn format_longitude(lon: f64) -> String {
+    let hemisphere = if lon < 0. { 'W' } else { 'E' };
+    let lon = lon.abs();
+    let degrees = lon.trunc();
+    let minutes = (lon - degrees) * 60.;
+    format!("{:03}{:07.4},{}", degrees as u32, minutes, hemisphere)
+}
+
+/// Wrap a sentence body (without start delimiter) with its start
+/// delimiter, checksum and CR/LF terminator.
+pub(crate) fn with_checksum(start: char, body: &str) -> String {
+    let checksum = body.bytes().fold(0u8, |acc, b| acc ^ b);
+    let mut s = String::with_capacity(body.len() + 6);
+    s.push(start);
+    s.push_str(body);
+    let _ = write!(s, "*{:02X}\r\n", checksum);
+    s
+}
+
+///
+/// The subsystem that sends the sentences out.
+///
+pub struct NmeaOutput {
+    address: NmeaOutputAddress,
+    sentences: TargetSentences,
+}
+
+impl NmeaOutput {
+    pub fn new(address: NmeaOutputAddress) -> Self {
+        NmeaOutput {
+            address,
+            sentences: TargetSentences::new(),
+        }
+    }
+
+    pub async fn run(
+        mut self,
+        subsys: SubsystemHandle,
+        mut sk_client_rx: broadcast::Receiver<SignalKDelta>,
+    ) -> Result<(), RadarError> {
+        let mut udp: Option<(UdpSocket, SocketAddr)> = None;
+        let mut listener: Option<TcpListener> = None;
+        let mut clients: Vec<(SocketAddr, TcpStream)> = Vec::new();
+
+        match self.address {
+            NmeaOutputAddress::Udp(addr) => {
+                let bind_addr: SocketAddr = if addr.is_ipv4() {
+                    "0.0.0.0:0".parse().unwrap()
+                } else {
+                    "[::]:0".parse().unwrap()
+                };
+                let socket = UdpSocket::bind(bind_addr).await?;
+                socket.set_broadcast(true)?;
+                log::info!("Sending NMEA 0183 targets to UDP {}", addr);
+                udp = Some((socket, addr));
+            }
+            NmeaOutputAddress::Tcp(addr) => {
+                listener = Some(TcpListener::bind(addr).await?);
+                log::info!("Serving NMEA 0183 targets on TCP {}", addr);
+            }
+        }
+
+        loop {
+            tokio::select! { biased;
+                _ = subsys.on_shutdown_requested() => {
+                    log::debug!("NMEA output shutdown requested");
+                    break;
+                },
+                r = accept(&listener) => {
+                    match r {
+                        Ok((stream, addr)) => {
+                            log::info!("NMEA output client {} connected", addr);
+                            clients.push((addr, stream));
+                        }
+                        Err(e) => {
+                            log::warn!("NMEA output accept failed: {}", e);
+                        }
+                    }
+                },
+                r = sk_client_rx.recv() => {
+                    match r {
+                        Ok(delta) => {
+                            let sentences = self.sentences.process_delta(&delta);
+                            if sentences.is_empty() {
+                                continue;
+                            }
+                            if let Some((socket, addr)) = &udp {
+                                for s in &sentences {
+                                    if let Err(e) = socket.send_to(s.as_bytes(), addr).await {
+                                        log::debug!("NMEA output to {} failed: {}", addr, e);
+                                    }
+                                }
+                            }
+                            if !clients.is_empty() {
+                                let data = sentences.concat();
+                                let mut connected = Vec::with_capacity(clients.len());
+                                for (addr, mut stream) in clients.drain(..) {
+                                    match stream.write_all(data.as_bytes()).await {
+                                        Ok(()) => connected.push((addr, stream)),
+                                        Err(e) => {
+                                            log::info!("NMEA output client {} disconnected: {}", addr, e);
+                                        }
+                                    }
+                                }
+                                clients = connected;
+                            }
+                        }
+                        Err(broadcast::error::RecvError::Lagged(n)) => {
+                            log::warn!("NMEA output lagged, skipped {} target updates", n);
+                        }
+                        Err(broadcast::error::RecvError::Closed) => {
+                            break;
+                        }
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+async fn accept(listener: &Option<TcpListener>) -> std::io::Result<(TcpStream, SocketAddr)> {
+    match listener {
+        Some(listener) => listener.accept().await,
+        None => std::future::pending().await,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn checksum_ok(s: &str) -> bool {
+        let s = s.trim_end();
+        let (body, checksum) = s[1..].split_once('*').unwrap();
+        let expected = body.bytes().fold(0u8, |acc, b| acc ^ b);
+        u8::from_str_radix(checksum, 16).unwrap() == expected
+    }
+
+    fn tracking_target() -> Value {
+        json!({
+            "id": 42,
+            "status": "tracking",
+            "position": {
+                "bearing": std::f64::consts::FRAC_PI_2,
+                "distance": 1852,
+                "latitude": 52.5,
+                "longitude": -4.25
+            },
+            "motion": { "course": std::f64::consts::PI, "speed": NAUTICAL_MILE_F64 / 360. },
+            "danger": { "cpa": 926.0, "tcpa": 300.0 },
+            "acquisition": "auto",
+            "firstSeen": "2024-01-15T10:29:00.000Z",
+            "lastSeen": "2024-01-15T10:30:05.250Z"
+        })
+    }
+
+    #[test]
+    fn test_parse_address() {
+        assert_eq!(
+            NmeaOutputAddress::parse("udp:255.255.255.255:10110"),
+            Ok(NmeaOutputAddress::Udp(
+                "255.255.255.255:10110".parse().unwrap()
+            ))
+        );
+        assert_eq!(
+            NmeaOutputAddress::parse("TCP:0.0.0.0:10110"),
+            Ok(NmeaOutputAddress::Tcp("0.0.0.0:10110".parse().unwrap()))
+        );
+        assert!(NmeaOutputAddress::parse("10110").is_err());
+        assert!(NmeaOutputAddress::parse("serial:10110").is_err());
+    }
+
+    #[test]
+    fn test_ttm() {
+        let s = ttm(7, 'T', &tracking_target());
+        assert!(s.starts_with("$RATTM,07,1.00,90.0,T,10.0,180.0,T,0.50,5.0,N,,T,,103005.25,A*"));
+        assert!(s.ends_with("\r\n"));
+        assert!(checksum_ok(&s));
+    }
+
+    #[test]
+    fn test_ttm_without_motion() {
+        let mut target = tracking_target();
+        target["status"] = json!("acquiring");
+        target.as_object_mut().unwrap().remove("motion");
+        target.as_object_mut().unwrap().remove("danger");
+        let s = ttm(1, target_status(&target), &target);
+        assert!(s.starts_with("$RATTM,01,1.00,90.0,T,,,T,,,N,,Q,,103005.25,A*"));
+        assert!(checksum_ok(&s));
+    }
+
+    #[test]
+    fn test_tll() {
+        let s = tll(7, 'T', &tracking_target()).unwrap();
+        assert!(s.starts_with("$RATLL,07,5230.0000,N,00415.0000,W,,103005.25,T,*"));
+        assert!(checksum_ok(&s));
+    }
+
+    #[test]
+    fn test_lifecycle() {
+        let mut sentences = TargetSentences::new();
+        let mut out = Vec::new();
+
+        let mut target = tracking_target();
+        target["status"] = json!("acquiring");
+        sentences.process_target("nav1", 1000, &target, &mut out);
+        assert_eq!(out.len(), 2);
+        assert!(out[0].starts_with("$RATTM,01,"));
+
+        // A second target gets the next free number
+        sentences.process_target("nav1", 1001, &tracking_target(), &mut out);
+        assert!(out[2].starts_with("$RATTM,02,"));
+
+        // Lost target keeps its number, deletion frees it without a second 'L'
+        target["status"] = json!("lost");
+        out.clear();
+        sentences.process_target("nav1", 1000, &target, &mut out);
+        assert!(out[0].contains(",L,,"));
+        out.clear();
+        sentences.process_target("nav1", 1000, &Value::Null, &mut out);
+        assert!(out.is_empty());
+
+        // Deleting a target that was never lost sends a final 'L'
+        sentences.process_target("nav1", 1001, &Value::Null, &mut out);
+        assert_eq!(out.len(), 1);
+        assert!(out[0].starts_with("$RATTM,02,,,T,,,T,,,N,,L,"));
+        assert!(checksum_ok(&out[0]));
+
+        // Number 1 is free again
+        out.clear();
+        sentences.process_target("nav2", 5, &tracking_target(), &mut out);
+        assert!(out[0].starts_with("$RATTM,01,"));
+    }
+}
@@ -0,0 +1,376 @@
+//! Setting several controls of a radar as one operation.
+//!
+//! All values of a batch are checked against their control definitions before
+//! the first one is sent, so a typo in the last control does not leave the
+//! radar half configured. Each control can only be given once. The values are
+//! then sent in the order given. The radar can still reject a value, e.g.
+//! because it is not transmitting; when the client asks for it, the controls
+//! that were changed are then set back to the values they had before the batch.
+
+use std::borrow::Cow;
+use std::collections::HashSet;
+use std::time::Duration;
+
+use axum::http::StatusCode;
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use super::RadarError;
+use super::settings::{BareControlValue, ControlId, ControlValue, SharedControls};
+
+/// How long to wait for the radar to report errors after the last control is sent
+const REPLY_TIMEOUT: Duration = Duration::from_millis(250);
+
+/// A control value in a batch
+#[derive(Deserialize, Clone, Debug, ToSchema)]
+pub struct BatchControlValue {
+    /// Control identifier
+    #[schema(example = "gain")]
+    pub id: String,
+    #[serde(flatten)]
+    pub value: BareControlValue,
+}
+
+/// A list of control values to set as one operation
+#[derive(Deserialize, Clone, Debug, ToSchema)]
+#[schema(example = json!({
+    "controls": [
+        {"id": "range", "value": 1852},
+        {"id": "gain", "value": 40, "auto": false},
+        {"id": "guardZone1", "value": -0.5, "endValue": 0.5, "startDistance": 200, "endDistance": 800, "enabled": true}
+    ],
+    "rollback": true
+}))]
+pub struct BatchRequest {
+    /// Control values, sent in this order
+    pub controls: Vec<BatchControlValue>,
+    /// Set the controls back to their previous values when the radar rejects one
+    #[serde(default)]
+    pub rollback: bool,
+}
+
+/// What happened to one control of a batch. Radars only reply when a
+/// control fails, so `applied` means that no error came back within 250 ms
+/// of sending the last control, not that the radar confirmed the value.
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub enum BatchStatus {
+    /// The value was sent and no error arrived within `REPLY_TIMEOUT`
+    Applied,
+    /// The value is invalid or was rejected by the radar
+    Failed,
+    /// The value was applied, then set back because another control failed
+    RolledBack,
+    /// The value was not sent, because another control failed
+    NotApplied,
+}
+
+/// The result for one control of a batch
+#[derive(Serialize, Clone, Debug, PartialEq, ToSchema)]
+pub struct BatchResult {
+    #[schema(example = "gain")]
+    pub id: String,
+    pub status: BatchStatus,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// The results of a batch, in the order of the request
+#[derive(Serialize, Clone, Debug, PartialEq, ToSchema)]
+pub struct BatchReport {
+    /// Whether all controls were applied
+    pub success: bool,
+    pub results: Vec<BatchResult>,
+}
+
+/// The outcome of `send_control_values`
+#[derive(Debug, Default)]
+pub(crate) struct Sent {
+    pub applied: Vec<ControlId>,
+    pub failed: Vec<(ControlId, String)>,
+}
+
+///
+/// Send control values to the radar in the given order, and wait a little for
+/// the radar to reject any of them. When `stop_on_error` is set, the values
+/// after the first one that cannot be sent are neither applied nor failed.
+///
+pub(crate) async fn send_control_values(
+    controls: &SharedControls,
+    values: Vec<ControlValue>,
+    client: Option<&str>,
+    stop_on_error: bool,
+) -> Sent {
+    let mut sent = Sent::default();
+    let (reply_tx, mut reply_rx) = tokio::sync::mpsc::channel(values.len().max(1));
+    for cv in values {
+        let id = cv.id;
+        match controls.process_client_request(cv, client, reply_tx.clone()) {
+            Ok(()) => sent.applied.push(id),
+            Err(e) => {
+                sent.failed.push((id, e.to_string()));
+                if stop_on_error {
+                    break;
+                }
+            }
+        }
+    }
+    drop(reply_tx);
+    if sent.applied.is_empty() {
+        return sent;
+    }
+
+    // The radar only replies when a control fails
+    let timeout = tokio::time::sleep(REPLY_TIMEOUT);
+    tokio::pin!(timeout);
+    loop {
+        tokio::select! {
+            reply = reply_rx.recv() => match reply {
+                Some(cv) => {
+                    if let Some(error) = cv.error {
+                        sent.applied.retain(|id| *id != cv.id);
+                        sent.failed.push((cv.id, error));
+                    }
+                }
+                None => break,
+            },
+            _ = &mut timeout => break,
+        }
+    }
+    sent
+}
+
+impl BatchRequest {
+    ///
+    /// Check all values, and only when they are all valid send them to the
+    /// radar. Returns the report as the error when nothing was sent because
+    /// a value is invalid or locked, with 409 Conflict when the only problem
+    /// is that controls are locked and 400 Bad Request otherwise.
+    ///
+    pub async fn apply(
+        self,
+        controls: &SharedControls,
+        client: Option<&str>,
+    ) -> Result<BatchReport, (StatusCode, BatchReport)> {
+        let mut values = Vec::with_capacity(self.controls.len());
+        let mut errors = Vec::with_capacity(self.controls.len());
+        let mut seen = HashSet::new();
+        for c in &self.controls {
+            let checked = ControlId::parse_str(Cow::Borrowed(c.id.as_str()))
+                .map_err(|_| RadarError::InvalidControlId(c.id.clone()))
+                .and_then(|id| {
+                    if seen.insert(id) {
+                        Ok(id)
+                    } else {
+                        Err(RadarError::DuplicateControl(id))
+                    }
+                })
+                .and_then(|id| {
+                    let cv = ControlValue::from_request(id, c.value.clone());
+                    controls.validate_client_request(&cv, client).map(|_| cv)
+                });
+            match checked {
+                Ok(cv) => {
+                    values.push(cv);
+                    errors.push(None);
+                }
+                Err(e) => errors.push(Some(e)),
+            }
+        }
+        if errors.iter().any(|e| e.is_some()) {
+            let locked = errors
+                .iter()
+                .flatten()
+                .all(|e| e.status_code() == StatusCode::CONFLICT);
+            let status = if locked {
+                StatusCode::CONFLICT
+            } else {
+                StatusCode::BAD_REQUEST
+            };
+            let results = self
+                .controls
+                .iter()
+                .zip(errors)
+                .map(|(c, error)| BatchResult {
+                    id: c.id.clone(),
+                    status: match error {
+                        Some(_) => BatchStatus::Failed,
+                        None => BatchStatus::NotApplied,
+                    },
+                    error: error.map(|e| e.to_string()),
+                })
+                .collect();
+            return Err((
+                status,
+                BatchReport {
+                    success: false,
+                    results,
+                },
+            ));
+        }
+
+        // What to go back to, in SI units like the requests
+        let previous: Vec<ControlValue> = values
+            .iter()
+            .filter_map(|cv| controls.get(&cv.id))
+            .map(|c| ControlValue {
+                units: None,
+                ..ControlValue::from(&c, None)
+            })
+            .collect();
+
+        let sent = send_control_values(controls, values, client, self.rollback).await;
+        let mut rolled_back = Vec::new();
+        if self.rollback && !sent.failed.is_empty() && !sent.applied.is_empty() {
+            let restore = previous
+                .into_iter()
+                .rev()
+                .filter(|cv| sent.applied.contains(&cv.id))
+                .collect();
+            let restored = send_control_values(controls, restore, client, false).await;
+            for (id, e) in &restored.failed {
+                log::warn!("Cannot roll back control {}: {}", id, e);
+            }
+            rolled_back = restored.applied;
+        }
+
+        let results: Vec<BatchResult> = self
+            .controls
+            .iter()
+            .map(|c| {
+                let id = ControlId::parse_str(Cow::Borrowed(c.id.as_str())).ok();
+                let failed = sent.failed.iter().find(|(f, _)| Some(*f) == id);
+                let (status, error) = match failed {
+                    Some((_, e)) => (BatchStatus::Failed, Some(e.clone())),
+                    None if id.is_some_and(|id| rolled_back.contains(&id)) => {
+                        (BatchStatus::RolledBack, None)
+                    }
+                    None if id.is_some_and(|id| sent.applied.contains(&id)) => {
+                        (BatchStatus::Applied, None)
+                    }
+                    None => (BatchStatus::NotApplied, None),
+                };
+                BatchResult {
+                    id: c.id.clone(),
+                    status,
+                    error,
+                }
+            })
+            .collect();
+        Ok(BatchReport {
+            success: results.iter().all(|r| r.status == BatchStatus::Applied),
+            results,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::radar::settings::test_controls as controls;
+
+    fn batch(json: &str) -> BatchRequest {
+        serde_json::from_str(json).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_invalid_batch_sends_nothing() {
+        let controls = controls();
+        let (status, report) = batch(
+            r#"{"controls": [
+                {"id": "spokeProcessing", "value": 2},
+                {"id": "targetTrails", "value": 9},
+                {"id": "spokes", "value": 1024},
+                {"id": "nonexistent", "value": 1}
+            ]}"#,
+        )
+        .apply(&controls, None)
+        .await
+        .unwrap_err();
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+
+        let status: Vec<_> = report.results.iter().map(|r| r.status).collect();
+        assert_eq!(
+            status,
+            vec![
+                BatchStatus::NotApplied,
+                BatchStatus::Failed,
+                BatchStatus::Failed,
+                BatchStatus::Failed
+            ]
+        );
+        assert_eq!(
+            report.results[1].error.as_deref(),
+            Some("Control targetTrails value 9 is higher than maximum value 6")
+        );
+        assert_eq!(controls.spoke_processing(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_duplicate_control() {
+        let controls = controls();
+        let (status, report) = batch(
+            r#"{"controls": [
+                {"id": "spokeProcessing", "value": 2},
+                {"id": "spokeProcessing", "value": 3}
+            ]}"#,
+        )
+        .apply(&controls, None)
+        .await
+        .unwrap_err();
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(report.results[0].status, BatchStatus::NotApplied);
+        assert_eq!(report.results[1].status, BatchStatus::Failed);
+        assert_eq!(
+            report.results[1].error.as_deref(),
+            Some("Control 'spokeProcessing' is given more than once")
+        );
+        assert_eq!(controls.spoke_processing(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_locked_batch() {
+        let controls = controls();
+        controls
+            .claim_lock("helm", None, Duration::from_secs(60))
+            .unwrap();
+        let b = batch(r#"{"controls": [{"id": "spokeProcessing", "value": 2}]}"#);
+        let (status, report) = b.clone().apply(&controls, None).await.unwrap_err();
+        assert_eq!(status, StatusCode::CONFLICT);
+        assert_eq!(report.results[0].status, BatchStatus::Failed);
+
+        let report = b.apply(&controls, Some("helm")).await.unwrap();
+        assert!(report.success);
+        assert_eq!(controls.spoke_processing(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_rollback() {
+        let controls = controls();
+        let b = r#"{"controls": [
+                {"id": "spokeProcessing", "value": 2},
+                {"id": "range", "value": 1852}
+            ], "rollback": ROLLBACK}"#;
+
+        // Without a radar the range cannot be sent
+        let report = batch(&b.replace("ROLLBACK", "false"))
+            .apply(&controls, None)
+            .await
+            .unwrap();
+        assert!(!report.success);
+        assert_eq!(report.results[0].status, BatchStatus::Applied);
+        assert_eq!(report.results[1].status, BatchStatus::Failed);
+        assert_eq!(controls.spoke_processing(), 2);
+
+        controls
+            .set_value(&ControlId::SpokeProcessing, serde_json::json!(1))
+            .unwrap();
+        let report = batch(&b.replace("ROLLBACK", "true"))
+            .apply(&controls, None)
+            .await
+            .unwrap();
+        assert_eq!(report.results[0].status, BatchStatus::RolledBack);
+        assert_eq!(report.results[1].status, BatchStatus::Failed);
+        assert_eq!(controls.spoke_processing(), 1);
+    }
+}
@@ -0,0 +1,480 @@
+//! Per-radar diagnostics, for when a radar misbehaves.
+//!
+//! The brand report receivers record what they receive and what goes wrong
+//! via `CommonRadar`; the REST API returns the result as `DiagnosticsApi`.
+
+use std::collections::{BTreeMap, VecDeque};
+use std::fmt::Display;
+use std::sync::{Arc, Mutex};
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use utoipa::ToSchema;
+
+use crate::util::now_millis;
+
+/// How many revolutions the rotation period and jitter are measured over
+const ROTATION_HISTORY: usize = 16;
+
+/// A connected radar that sends nothing for this long (ms) is disconnected
+const SILENCE_TIMEOUT: u64 = 5000;
+
+/// Whether the report receiver is talking to the radar
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub enum ConnectionState {
+    /// The sockets are open, but nothing has been received yet
+    Connecting,
+    /// Packets are coming in
+    Connected,
+    /// The receiver lost its sockets and is trying to recover, or nothing
+    /// came in for a few seconds
+    Disconnected,
+}
+
+fn timestamp(millis: u64) -> Option<DateTime<Utc>> {
+    match millis {
+        0 => None,
+        millis => DateTime::from_timestamp_millis(millis as i64),
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+struct Event {
+    time: u64,
+    message: String,
+}
+
+#[derive(Clone, Debug, Default)]
+struct SocketDiagnostics {
+    packets: u64,
+    message_types: BTreeMap<String, u64>,
+    last_packet: u64,
+    parse_failures: u64,
+    last_parse_failure: Option<Event>,
+}
+
+#[derive(Debug)]
+struct Diagnostics {
+    state: ConnectionState,
+    state_since: u64,
+    sockets: BTreeMap<&'static str, SocketDiagnostics>,
+    last_spoke: u64,
+    spokes_sent: u64,
+    /// Duration of the most recent revolutions in milliseconds
+    rotation_periods: VecDeque<u32>,
+    revolutions: u64,
+    last_revolution_spokes: Option<u32>,
+    missing_spokes: u64,
+    command_errors: u64,
+    last_command_error: Option<Event>,
+}
+
+///
+/// The diagnostics of one radar, shared by all clones of its `RadarInfo`.
+///
+#[derive(Clone, Debug)]
+pub struct SharedDiagnostics {
+    inner: Arc<Mutex<Diagnostics>>,
+}
+
+impl SharedDiagnostics {
+    pub(crate) fn new() -> Self {
+        SharedDiagnostics {
+            inner: Arc::new(Mutex::new(Diagnostics {
+                state: ConnectionState::Connecting,
+                state_since: now_millis(),
+                sockets: BTreeMap::new(),
+                last_spoke: 0,
+                spokes_sent: 0,
+                rotation_periods: VecDeque::with_capacity(ROTATION_HISTORY),
+                revolutions: 0,
+                last_revolution_spokes: None,
+                missing_spokes: 0,
+                command_errors: 0,
+                last_command_error: None,
+            })),
+        }
+    }
+
+    fn set_state(d: &mut Diagnostics, state: ConnectionState) {
+        if d.state != state {
+            d.state = state;
+            d.state_since = now_millis();
+        }
+    }
+
+    pub(crate) fn set_connection_state(&self, state: ConnectionState) {
+        Self::set_state(&mut self.inner.lock().unwrap(), state);
+    }
+
+    /// A packet of `message_type` came in on `socket`
+    pub(crate) fn packet_received(&self, socket: &'static str, message_type: &str) {
+        let mut d = self.inner.lock().unwrap();
+        Self::set_state(&mut d, ConnectionState::Connected);
+        let s = d.sockets.entry(socket).or_default();
+        s.packets += 1;
+        s.last_packet = now_millis();
+        match s.message_types.get_mut(message_type) {
+            Some(count) => *count += 1,
+            None => {
+                s.message_types.insert(message_type.to_string(), 1);
+            }
+        }
+    }
+
+    /// A packet that came in on `socket` could not be parsed
+    pub(crate) fn parse_failure(&self, socket: &'static str, error: &dyn Display) {
+        let mut d = self.inner.lock().unwrap();
+        let s = d.sockets.entry(socket).or_default();
+        s.parse_failures += 1;
+        s.last_parse_failure = Some(Event {
+            time: now_millis(),
+            message: error.to_string(),
+        });
+    }
+
+    /// `count` spokes were sent to the clients
+    pub(crate) fn spokes_sent(&self, count: usize) {
+        let mut d = self.inner.lock().unwrap();
+        d.last_spoke = now_millis();
+        d.spokes_sent += count as u64;
+    }
+
+    pub(crate) fn missing_spokes(&self, missing: u32) {
+        self.inner.lock().unwrap().missing_spokes += missing as u64;
+    }
+
+    /// A revolution that took `millis` (0 when not measurable) with `spokes` spokes
+    pub(crate) fn revolution(&self, millis: u32, spokes: u32) {
+        let mut d = self.inner.lock().unwrap();
+        d.revolutions += 1;
+        d.last_revolution_spokes = Some(spokes);
+        if millis > 0 {
+            if d.rotation_periods.len() == ROTATION_HISTORY {
+                d.rotation_periods.pop_front();
+            }
+            d.rotation_periods.push_back(millis);
+        }
+    }
+
+    pub(crate) fn command_error(&self, error: &dyn Display) {
+        let mut d = self.inner.lock().unwrap();
+        d.command_errors += 1;
+        d.last_command_error = Some(Event {
+            time: now_millis(),
+            message: error.to_string(),
+        });
+    }
+
+    ///
+    /// The connection state and since when. Packets only tell us that the
+    /// radar is connected, so it is disconnected once they stop coming in.
+    ///
+    fn connection_state(d: &Diagnostics, now: u64) -> (ConnectionState, u64) {
+        let last_packet = d.sockets.values().map(|s| s.last_packet).max();
+        match (d.state, last_packet) {
+            (ConnectionState::Connected, Some(last_packet))
+                if now.saturating_sub(last_packet) > SILENCE_TIMEOUT =>
+            {
+                (ConnectionState::Disconnected, last_packet + SILENCE_TIMEOUT)
+            }
+            (state, _) => (state, d.state_since),
+        }
+    }
+
+    pub fn to_api(&self, spokes_per_revolution: u16, sparse_spokes: bool) -> DiagnosticsApi {
+        let d = self.inner.lock().unwrap();
+        let (connection_state, connection_state_since) = Self::connection_state(&d, now_millis());
+
+        let periods = &d.rotation_periods;
+        let (period, jitter) = if periods.is_empty() {
+            (None, None)
+        } else {
+            let n = periods.len() as f64;
+            let mean = periods.iter().map(|p| *p as f64).sum::<f64>() / n;
+            let variance = periods
+                .iter()
+                .map(|p| (*p as f64 - mean).powi(2))
+                .sum::<f64>()
+                / n;
+            (Some(mean), Some(variance.sqrt()))
+        };
+
+        DiagnosticsApi {
+            connection_state,
+            connection_state_since: timestamp(connection_state_since),
+            sockets: d
+                .sockets
+                .iter()
+                .map(|(name, s)| {
+                    (
+                        name.to_string(),
+                        SocketDiagnosticsApi {
+                            packets: s.packets,
+                            message_types: s.message_types.clone(),
+                            last_packet: timestamp(s.last_packet),
+                            parse_failures: s.parse_failures,
+                            last_parse_failure: s.last_parse_failure.as_ref().map(EventApi::from),
+                        },
+                    )
+                })
+                .collect(),
+            last_report: d
+                .sockets
+                .get("report")
+                .and_then(|s| timestamp(s.last_packet)),
+            last_spoke: timestamp(d.last_spoke),
+            rotation: RotationApi {
+                revolutions: d.revolutions,
+                period,
+                jitter,
+            },
+            spokes: SpokesApi {
+                spokes_per_revolution,
+                sparse: sparse_spokes,
+                sent: d.spokes_sent,
+                last_revolution: d.last_revolution_spokes,
+                missing_last_revolution: d
+                    .last_revolution_spokes
+                    .filter(|_| !sparse_spokes)
+                    .map(|n| (spokes_per_revolution as u32).saturating_sub(n)),
+                missing: d.missing_spokes,
+            },
+            commands: CommandsApi {
+                errors: d.command_errors,
+                last_error: d.last_command_error.as_ref().map(EventApi::from),
+            },
+        }
+    }
+}
+
+/// Something that went wrong, and when
+#[derive(Serialize, Clone, Debug, ToSchema)]
+pub struct EventApi {
+    #[schema(example = "2024-01-15T10:30:05.250Z")]
+    pub time: DateTime<Utc>,
+    #[schema(example = "UDP report len 1 dropped")]
+    pub message: String,
+}
+
+impl From<&Event> for EventApi {
+    fn from(event: &Event) -> Self {
+        EventApi {
+            time: timestamp(event.time).unwrap_or_default(),
+            message: event.message.clone(),
+        }
+    }
+}
+
+/// What came in on one of the sockets of the radar
+#[derive(Serialize, Clone, Debug, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct SocketDiagnosticsApi {
+    /// Packets received
+    #[schema(example = 12345)]
+    pub packets: u64,
+    /// Packets received by message type, as the brand names or numbers them
+    #[schema(example = json!({"01C4": 1200, "02C4": 1200}))]
+    pub message_types: BTreeMap<String, u64>,
+    /// When the last packet came in
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[schema(example = "2024-01-15T10:30:05.250Z")]
+    pub last_packet: Option<DateTime<Utc>>,
+    /// Packets that could not be parsed
+    #[schema(example = 0)]
+    pub parse_failures: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub last_parse_failure: Option<EventApi>,
+}
+
+/// Measured antenna rotation
+#[derive(Serialize, Clone, Debug, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct RotationApi {
+    /// Revolutions seen since the radar was found
+    #[schema(example = 1500)]
+    pub revolutions: u64,
+    /// Average duration of the last revolutions, in milliseconds
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[schema(example = 2500.0)]
+    pub period: Option<f64>,
+    /// Standard deviation of the duration of the last revolutions, in milliseconds
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[schema(example = 12.5)]
+    pub jitter: Option<f64>,
+}
+
+/// Spokes received compared to what the radar should send
+#[derive(Serialize, Clone, Debug, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct SpokesApi {
+    #[schema(example = 2048)]
+    pub spokes_per_revolution: u16,
+    /// Whether the radar sends fewer spokes than `spokesPerRevolution` by design
+    #[schema(example = false)]
+    pub sparse: bool,
+    /// Spokes sent to clients since the radar was found
+    #[schema(example = 1474560)]
+    pub sent: u64,
+    /// Spokes received in the last revolution
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[schema(example = 2046)]
+    pub last_revolution: Option<u32>,
+    /// Spokes missing from the last revolution
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[schema(example = 2)]
+    pub missing_last_revolution: Option<u32>,
+    /// Spokes skipped between consecutive spokes since the radar was found
+    #[schema(example = 37)]
+    pub missing: u64,
+}
+
+/// Commands sent to the radar by the `CommandSender`
+#[derive(Serialize, Clone, Debug, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct CommandsApi {
+    /// Commands that could not be sent
+    #[schema(example = 0)]
+    pub errors: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub last_error: Option<EventApi>,
+}
+
+/// Diagnostics of a radar, as gathered by its report receiver
+#[derive(Serialize, Clone, Debug, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct DiagnosticsApi {
+    pub connection_state: ConnectionState,
+    /// Since when the connection is in this state
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[schema(example = "2024-01-15T10:00:00.000Z")]
+    pub connection_state_since: Option<DateTime<Utc>>,
+    /// What came in, by socket (`report`, `data`, ...)
+    pub sockets: BTreeMap<String, SocketDiagnosticsApi>,
+    /// When the last report came in
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[schema(example = "2024-01-15T10:30:05.000Z")]
+    pub last_report: Option<DateTime<Utc>>,
+    /// When the last spokes were sent to clients
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[schema(example = "2024-01-15T10:30:05.250Z")]
+    pub last_spoke: Option<DateTime<Utc>>,
+    pub rotation: RotationApi,
+    pub spokes: SpokesApi,
+    pub commands: CommandsApi,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_packets() {
+        let diagnostics = SharedDiagnostics::new();
+        assert_eq!(
+            diagnostics.to_api(2048, false).connection_state,
+            ConnectionState::Connecting
+        );
+
+        diagnostics.packet_received("report", "01C4");
+        diagnostics.packet_received("report", "01C4");
+        diagnostics.packet_received("report", "02C4");
+        diagnostics.parse_failure("report", &"UDP report len 1 dropped");
+        diagnostics.packet_received("data", "spokes");
+
+        let api = diagnostics.to_api(2048, false);
+        assert_eq!(api.connection_state, ConnectionState::Connected);
+        let report = &api.sockets["report"];
+        assert_eq!(report.packets, 3);
+        assert_eq!(report.message_types["01C4"], 2);
+        assert_eq!(report.parse_failures, 1);
+        assert_eq!(
+            report.last_parse_failure.as_ref().unwrap().message,
+            "UDP report len 1 dropped"
+        );
+        assert!(api.last_report.is_some());
+        assert_eq!(api.sockets["data"].packets, 1);
+
+        diagnostics.set_connection_state(ConnectionState::Disconnected);
+        assert_eq!(
+            diagnostics.to_api(2048, false).connection_state,
+            ConnectionState::Disconnected
+        );
+    }
+
+    #[test]
+    fn test_silence() {
+        let diagnostics = SharedDiagnostics::new();
+        diagnostics.packet_received("report", "01C4");
+        let last_packet = diagnostics.inner.lock().unwrap().sockets["report"].last_packet;
+        {
+            let d = diagnostics.inner.lock().unwrap();
+            let (state, _) = SharedDiagnostics::connection_state(&d, last_packet + SILENCE_TIMEOUT);
+            assert_eq!(state, ConnectionState::Connected);
+        }
+
+        // Pretend that the last packet came in a while ago
+        diagnostics
+            .inner
+            .lock()
+            .unwrap()
+            .sockets
+            .get_mut("report")
+            .unwrap()
+            .last_packet -= SILENCE_TIMEOUT + 1000;
+        let api = diagnostics.to_api(2048, false);
+        assert_eq!(api.connection_state, ConnectionState::Disconnected);
+        assert_eq!(api.connection_state_since, timestamp(last_packet - 1000));
+
+        // The next packet connects again
+        diagnostics.packet_received("report", "01C4");
+        assert_eq!(
+            diagnostics.to_api(2048, false).connection_state,
+            ConnectionState::Connected
+        );
+    }
+
+    #[test]
+    fn test_rotation() {
+        let diagnostics = SharedDiagnostics::new();
+        diagnostics.revolution(0, 100); // first, not measurable
+        diagnostics.revolution(2400, 2040);
+        diagnostics.revolution(2600, 2048);
+
+        let api = diagnostics.to_api(2048, false);
+        assert_eq!(api.rotation.revolutions, 3);
+        assert_eq!(api.rotation.period, Some(2500.));
+        assert_eq!(api.rotation.jitter, Some(100.));
+        assert_eq!(api.spokes.last_revolution, Some(2048));
+        assert_eq!(api.spokes.missing_last_revolution, Some(0));
+
+        diagnostics.spokes_sent(32);
+        diagnostics.spokes_sent(32);
+        assert_eq!(diagnostics.to_api(2048, false).spokes.sent, 64);
+
+        for _ in 0..ROTATION_HISTORY {
+            diagnostics.revolution(2000, 1000);
+        }
+        let api = diagnostics.to_api(2048, false);
+        assert_eq!(api.rotation.period, Some(2000.));
+        assert_eq!(api.rotation.jitter, Some(0.));
+        assert_eq!(api.spokes.missing_last_revolution, Some(1048));
+
+        // Radars with sparse spokes are not expected to send them all
+        let api = diagnostics.to_api(2048, true);
+        assert_eq!(api.spokes.missing_last_revolution, None);
+    }
+
+    #[test]
+    fn test_command_errors() {
+        let diagnostics = SharedDiagnostics::new();
+        diagnostics.command_error(&"Cannot set control");
+        let api = diagnostics.to_api(2048, false);
+        assert_eq!(api.commands.errors, 1);
+        assert_eq!(
+            api.commands.last_error.unwrap().message,
+            "Cannot set control"
+        );
+    }
+}
@@ -0,0 +1,246 @@
+//! Control locks, so that clients do not fight over the controls of a radar.
+//!
+//! A client names itself, e.g. "helm", and claims all controls of a radar or
+//! one category of them for a lease period. Until the lease runs out or the
+//! client releases the lock, other clients cannot change those controls.
+//! Without authentication locks are cooperative, as the client name is not a
+//! secret; with it, the web server uses the name of the user or API token.
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::time::Duration;
+use utoipa::ToSchema;
+
+use super::RadarError;
+use super::settings::{Category, ControlId};
+
+/// Lease when the client does not ask for one
+pub const DEFAULT_LEASE: Duration = Duration::from_secs(300);
+/// Longest lease a client can get, so a forgotten lock does not stay forever
+pub const MAX_LEASE: Duration = Duration::from_secs(3600);
+
+/// A lock on the controls of a radar
+#[derive(Serialize, Clone, Debug, PartialEq, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct ControlLock {
+    /// Name of the client that holds the lock
+    #[schema(example = "helm")]
+    pub holder: String,
+    /// Category of controls that is locked, all controls when absent
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub category: Option<Category>,
+    /// When the lock expires unless it is claimed again
+    pub expires: DateTime<Utc>,
+}
+
+impl ControlLock {
+    fn covers(&self, category: Option<Category>) -> bool {
+        self.category.is_none() || category.is_none() || self.category == category
+    }
+}
+
+/// The locks held on one radar
+#[derive(Clone, Debug, Default)]
+pub(crate) struct ControlLocks {
+    locks: Vec<ControlLock>,
+    /// When the first of the locks expires, so that checking is cheap
+    next_expiry: Option<DateTime<Utc>>,
+}
+
+impl ControlLocks {
+    fn update_next_expiry(&mut self) {
+        self.next_expiry = self.locks.iter().map(|l| l.expires).min();
+    }
+
+    /// Whether any lock expired before `now`
+    pub(crate) fn has_expired(&self, now: DateTime<Utc>) -> bool {
+        self.next_expiry.is_some_and(|expiry| expiry <= now)
+    }
+
+    /// Drop the locks that expired before `now`, returns whether there were any
+    pub(crate) fn expire(&mut self, now: DateTime<Utc>) -> bool {
+        if !self.has_expired(now) {
+            return false;
+        }
+        self.locks.retain(|l| l.expires > now);
+        self.update_next_expiry();
+        true
+    }
+
+    pub(crate) fn get(&self) -> Vec<ControlLock> {
+        self.locks.clone()
+    }
+
+    ///
+    /// Can `client` change control `control_id`? A client without a name can
+    /// only change controls that nobody has locked.
+    ///
+    pub(crate) fn check(
+        &self,
+        client: Option<&str>,
+        control_id: ControlId,
+    ) -> Result<(), RadarError> {
+        let category = Some(control_id.get_category());
+        match self
+            .locks
+            .iter()
+            .find(|l| Some(l.holder.as_str()) != client && l.covers(category))
+        {
+            Some(lock) => Err(RadarError::ControlLocked(control_id, lock.holder.clone())),
+            None => Ok(()),
+        }
+    }
+
+    ///
+    /// Claim or renew a lock for `client` on `category`, or all controls when
+    /// `None`. Fails when another client holds a lock that overlaps.
+    ///
+    pub(crate) fn claim(
+        &mut self,
+        client: &str,
+        category: Option<Category>,
+        lease: Duration,
+        now: DateTime<Utc>,
+    ) -> Result<ControlLock, RadarError> {
+        if let Some(lock) = self
+            .locks
+            .iter()
+            .find(|l| l.holder != client && l.covers(category))
+        {
+            return Err(RadarError::LockHeld(lock.holder.clone()));
+        }
+
+        let lock = ControlLock {
+            holder: client.to_string(),
+            category,
+            expires: now + lease.min(MAX_LEASE),
+        };
+        match self
+            .locks
+            .iter_mut()
+            .find(|l| l.holder == client && l.category == category)
+        {
+            Some(existing) => *existing = lock.clone(),
+            None => self.locks.push(lock.clone()),
+        }
+        self.update_next_expiry();
+        Ok(lock)
+    }
+
+    /// Release the lock of `client` on `category`, returns whether it held one
+    pub(crate) fn release(
+        &mut self,
+        client: &str,
+        category: Option<Category>,
+    ) -> Result<bool, RadarError> {
+        if let Some(lock) = self
+            .locks
+            .iter()
+            .find(|l| l.holder != client && l.category == category)
+        {
+            return Err(RadarError::LockHeld(lock.holder.clone()));
+        }
+        let len = self.locks.len();
+        self.locks
+            .retain(|l| l.holder != client || l.category != category);
+        self.update_next_expiry();
+        Ok(self.locks.len() != len)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_category_lock() {
+        let now = Utc::now();
+        let mut locks = ControlLocks::default();
+        let lock = locks
+            .claim("helm", Some(Category::Base), Duration::from_secs(60), now)
+            .unwrap();
+        assert_eq!(lock.expires, now + Duration::from_secs(60));
+
+        assert!(locks.check(Some("helm"), ControlId::Gain).is_ok());
+        assert!(locks.check(Some("phone"), ControlId::GuardZone1).is_ok());
+        let e = locks.check(Some("phone"), ControlId::Gain).unwrap_err();
+        assert_eq!(e.to_string(), "Control 'gain' is locked by 'helm'");
+        assert!(locks.check(None, ControlId::Range).is_err());
+
+        // Other categories can be locked by others, but not everything
+        assert!(
+            locks
+                .claim("phone", Some(Category::Targets), DEFAULT_LEASE, now)
+                .is_ok()
+        );
+        assert!(locks.claim("phone", None, DEFAULT_LEASE, now).is_err());
+        assert!(
+            locks
+                .claim("phone", Some(Category::Base), DEFAULT_LEASE, now)
+                .is_err()
+        );
+        assert_eq!(locks.get().len(), 2);
+    }
+
+    #[test]
+    fn test_radar_lock() {
+        let now = Utc::now();
+        let mut locks = ControlLocks::default();
+        locks
+            .claim("helm", None, Duration::from_secs(60), now)
+            .unwrap();
+        assert!(locks.check(Some("phone"), ControlId::GuardZone1).is_err());
+        assert!(
+            locks
+                .claim("phone", Some(Category::Trails), DEFAULT_LEASE, now)
+                .is_err()
+        );
+
+        // Renewing keeps a single lock, with the lease capped
+        let lock = locks
+            .claim("helm", None, Duration::from_secs(86400), now)
+            .unwrap();
+        assert_eq!(lock.expires, now + MAX_LEASE);
+        assert_eq!(locks.get().len(), 1);
+
+        assert!(locks.release("phone", None).is_err());
+        assert!(locks.release("helm", None).unwrap());
+        assert!(!locks.release("helm", None).unwrap());
+        assert!(locks.check(Some("phone"), ControlId::Gain).is_ok());
+    }
+
+    #[test]
+    fn test_expire() {
+        let now = Utc::now();
+        let mut locks = ControlLocks::default();
+        locks
+            .claim("helm", None, Duration::from_secs(60), now)
+            .unwrap();
+        assert!(!locks.expire(now + Duration::from_secs(59)));
+        assert!(locks.expire(now + Duration::from_secs(60)));
+        assert!(locks.check(Some("phone"), ControlId::Gain).is_ok());
+        assert!(!locks.has_expired(now + Duration::from_secs(3600)));
+    }
+
+    #[test]
+    fn test_renewal_moves_expiry() {
+        let now = Utc::now();
+        let mut locks = ControlLocks::default();
+        locks
+            .claim("helm", None, Duration::from_secs(60), now)
+            .unwrap();
+        locks
+            .claim(
+                "helm",
+                None,
+                Duration::from_secs(60),
+                now + Duration::from_secs(30),
+            )
+            .unwrap();
+        assert!(!locks.has_expired(now + Duration::from_secs(60)));
+        assert!(locks.has_expired(now + Duration::from_secs(90)));
+
+        locks.release("helm", None).unwrap();
+        assert!(!locks.has_expired(now + Duration::from_secs(90)));
+    }
+}
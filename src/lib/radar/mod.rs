@@ -8,7 +8,7 @@ use serde_json::Value;
 use std::cmp::{max, min};
 use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 use std::{
-    collections::HashMap,
+    collections::{BTreeMap, HashMap},
     fmt::{self, Display, Write},
     net::{Ipv4Addr, SocketAddrV4},
     sync::{Arc, RwLock},
@@ -18,21 +18,30 @@ use tokio::sync::{broadcast, mpsc};
 use tokio_graceful_shutdown::{SubsystemBuilder, SubsystemHandle};
 use utoipa::ToSchema;
 
+pub mod batch;
 pub mod cpa;
+pub mod diagnostics;
 pub mod exclusion;
+pub mod lock;
+pub mod preset;
 pub mod range;
 pub mod settings;
+pub mod snapshot;
 pub mod spoke;
 pub mod target;
 pub mod trail;
 pub(crate) mod units;
 
+use crate::auth::Role;
 use crate::brand::CommandSender;
 use crate::config::Persistence;
 use crate::protos::RadarMessage::RadarMessage;
+use crate::radar::diagnostics::{ConnectionState, SharedDiagnostics};
+use crate::radar::preset::{Preset, PresetReport};
 use crate::radar::settings::{
     ControlDestination, ControlError, ControlId, ControlUpdate, ControlValue, SharedControls,
 };
+use crate::radar::snapshot::SharedSnapshot;
 use crate::radar::spoke::{GenericSpoke, to_protobuf_spoke};
 use crate::radar::target::{BlobDetector, BlobMessage, SpokeContext, TrackerCommand};
 use crate::radar::trail::TrailBuffer;
@@ -84,34 +93,69 @@ pub enum RadarError {
     ParseJson(String),
     #[error("Cannot parse NMEA0183 '{0}'")]
     ParseNmea0183(String),
+    #[error("Cannot parse NMEA 2000 '{0}'")]
+    ParseNmea2000(String),
     #[error("IP address changed")]
     IPAddressChanged,
     #[error("Cannot login to radar")]
     LoginFailed,
     #[error("Invalid port number")]
     InvalidPort,
+    #[error("Invalid navigation address '{0}'")]
+    InvalidNavigationAddress(String),
     #[error("Not connected")]
     NotConnected,
+    #[error("Signal K server: {0}")]
+    SignalK(String),
+    #[error("MQTT broker: {0}")]
+    Mqtt(String),
+    #[error("Role '{0}' is not allowed to change the radar")]
+    Forbidden(Role),
+    #[error("Control '{0}' is locked by '{1}'")]
+    ControlLocked(ControlId, String),
+    #[error("Controls are locked by '{0}'")]
+    LockHeld(String),
+    #[error("Cannot act as client '{0}' when authenticated as '{1}'")]
+    ClientMismatch(String, String),
+    #[error("Log in to act as client '{0}'")]
+    ClientNotAuthenticated(String),
+    #[error("No such preset '{0}'")]
+    NoSuchPreset(String),
+    #[error("Control '{0}' is given more than once")]
+    DuplicateControl(ControlId),
     #[cfg(windows)]
     #[error("OS error: {0}")]
     OSError(String),
 }
 
-// Tell axum how to convert `RadarError` into a response.
-impl IntoResponse for RadarError {
-    fn into_response(self) -> Response {
-        let status = match &self {
+impl RadarError {
+    /// The HTTP status code for this error, also for requests that do not
+    /// come in over HTTP
+    pub fn status_code(&self) -> StatusCode {
+        match self {
             RadarError::NoSuchRadar(_) => StatusCode::NOT_FOUND,
             RadarError::InvalidControlId(_) => StatusCode::NOT_FOUND,
+            RadarError::NoSuchPreset(_) => StatusCode::NOT_FOUND,
             RadarError::CannotSetControlId(_)
             | RadarError::CannotSetControlIdValue(_, _)
             | RadarError::MissingValue(_)
             | RadarError::NotNumeric(_, _)
             | RadarError::ControlError(_)
-            | RadarError::CannotParseControlId(_) => StatusCode::BAD_REQUEST,
+            | RadarError::CannotParseControlId(_)
+            | RadarError::DuplicateControl(_) => StatusCode::BAD_REQUEST,
+            RadarError::Forbidden(_)
+            | RadarError::ClientMismatch(_, _)
+            | RadarError::ClientNotAuthenticated(_) => StatusCode::FORBIDDEN,
+            RadarError::ControlLocked(_, _) | RadarError::LockHeld(_) => StatusCode::CONFLICT,
             _ => StatusCode::INTERNAL_SERVER_ERROR,
-        };
-        (status, self.to_string()).into_response()
+        }
+    }
+}
+
+// Tell axum how to convert `RadarError` into a response.
+impl IntoResponse for RadarError {
+    fn into_response(self) -> Response {
+        (self.status_code(), self.to_string()).into_response()
     }
 }
 
@@ -231,6 +275,16 @@ pub struct Legend {
     pub static_background: Option<u8>,
 }
 
+impl Legend {
+    /// The RGBA color of each pixel value
+    pub fn palette(&self) -> Vec<[u8; 4]> {
+        self.pixels
+            .iter()
+            .map(|p| [p.color.r, p.color.g, p.color.b, p.color.a])
+            .collect()
+    }
+}
+
 /// A geographic position expressed in degrees latitude and longitude.
 /// Latitude is positive in the northern hemisphere, negative in the southern.
 /// Longitude is positive in the eastern hemisphere, negative in the western.
@@ -312,6 +366,8 @@ pub struct RadarInfo {
     pub sparse_spokes: bool, // Does it produce fewer spokes than spokes_per_revolution?
     pub stationary: bool,    // Is radar stationary (shore-based)?
     rotation_timestamp: Instant,
+    pub diagnostics: SharedDiagnostics, // Shared by all clones, for the diagnostics API
+    pub snapshot: SharedSnapshot,       // Latest revolution, for the image API
 
     // Channels
     pub message_tx: tokio::sync::broadcast::Sender<Vec<u8>>, // Serialized RadarMessage
@@ -390,6 +446,8 @@ impl RadarInfo {
             sparse_spokes,
             stationary: args.stationary,
             rotation_timestamp: Instant::now() - Duration::from_secs(2),
+            diagnostics: SharedDiagnostics::new(),
+            snapshot: SharedSnapshot::new(spokes_per_revolution as u16),
         };
 
         log::trace!("Created RadarInfo {:?}", info);
@@ -678,10 +736,68 @@ impl SharedRadars {
         }
     }
 
+    /// The presets of a radar by name
+    pub fn get_presets(&self, key: &str) -> BTreeMap<String, Preset> {
+        let radars = self.radars.read().unwrap();
+        radars.persistent_data.presets(key)
+    }
+
+    ///
+    /// Store preset `name` for radar `key`, after checking it against the
+    /// controls of the radar. Returns the preset as it is stored.
+    ///
+    pub fn set_preset(&self, key: &str, name: &str, preset: Preset) -> Result<Preset, RadarError> {
+        let mut radars = self.radars.write().unwrap();
+        let info = radars
+            .info
+            .get(key)
+            .ok_or_else(|| RadarError::NoSuchRadar(key.to_string()))?;
+        let preset = preset.normalize(&info.controls)?;
+        log::info!("{}: store preset '{}' {:?}", key, name, preset);
+        radars
+            .persistent_data
+            .set_preset(key, name, Some(preset.clone()));
+        Ok(preset)
+    }
+
+    pub fn delete_preset(&self, key: &str, name: &str) -> Result<(), RadarError> {
+        let mut radars = self.radars.write().unwrap();
+        match radars.persistent_data.set_preset(key, name, None) {
+            Some(_) => Ok(()),
+            None => Err(RadarError::NoSuchPreset(name.to_string())),
+        }
+    }
+
+    ///
+    /// Apply preset `name` to radar `key` on behalf of `client`, see
+    /// `Preset::apply`.
+    ///
+    pub async fn apply_preset(
+        &self,
+        key: &str,
+        name: &str,
+        client: Option<&str>,
+    ) -> Result<PresetReport, RadarError> {
+        let info = self
+            .get_by_key(key)
+            .ok_or_else(|| RadarError::NoSuchRadar(key.to_string()))?;
+        let preset = self
+            .get_presets(key)
+            .remove(name)
+            .ok_or_else(|| RadarError::NoSuchPreset(name.to_string()))?;
+
+        let report = preset.apply(&info.controls, client).await;
+        log::info!("{}: applied preset '{}': {:?}", key, name, report);
+        // Guard zones and the like are also persisted
+        self.save_persistence(key);
+        Ok(report)
+    }
+
     pub fn remove(&self, key: &str) {
         let mut radars = self.radars.write().unwrap();
 
         radars.info.remove(key);
+        crate::metrics::radar_removed(key);
     }
 
     ///
@@ -1280,6 +1396,7 @@ impl CommonRadar {
                                 Ok(()) => {}
                                 Err(RadarError::CannotSetControlId(_)) => {}
                                 Err(e) => {
+                                    self.info.diagnostics.command_error(&e);
                                     log::warn!(
                                         "{}: guard zone hardware sync failed: {}",
                                         self.key,
@@ -1342,6 +1459,7 @@ impl CommonRadar {
             ControlDestination::Command => {
                 if let Some(command_sender) = command_sender {
                     if let Err(e) = command_sender.set_control(&cv, &self.info.controls).await {
+                        self.info.diagnostics.command_error(&e);
                         return self
                             .info
                             .controls
@@ -1498,6 +1616,8 @@ impl CommonRadar {
                         let max_target_speed_ms = SpokeContext::max_speed_from_mode(max_speed_mode);
 
                         for blob in &completed_blobs {
+                            // Bearing and position are those at the spoke time,
+                            // see to_protobuf_spoke()
                             let ctx = SpokeContext {
                                 time: spoke.time.unwrap_or(self.spoke_time),
                                 range: spoke.range,
@@ -1528,18 +1648,22 @@ impl CommonRadar {
                     as f64;
                 let heading_rad =
                     heading_spokes / self.info.spokes_per_revolution as f64 * std::f64::consts::TAU;
-                crate::navdata::set_heading_true(Some(heading_rad), &self.key);
+                crate::navdata::set_heading_true_from_radar(heading_rad, &self.key);
             }
 
             // Always broadcast spoke to clients
             let mut spoke = spoke;
             self.trails
                 .update_trails(&mut spoke, &self.info.legend, &self.info.controls);
+            self.info
+                .snapshot
+                .record(spoke.angle, spoke.bearing, spoke.range, &spoke.data);
             message.spokes.push(spoke);
 
             if angle < self.prev_angle {
                 let ms = self.info.full_rotation();
                 self.trails.set_rotation_speed(ms);
+                self.info.diagnostics.revolution(ms, self.spoke_count);
 
                 log::debug!("spoke_count = {}", self.spoke_count);
                 self.info
@@ -1561,6 +1685,9 @@ impl CommonRadar {
                     - self.prev_angle as u32
                     - 1)
                     % self.info.spokes_per_revolution as u32;
+                if !self.info.sparse_spokes {
+                    self.info.diagnostics.missing_spokes(missing_spokes);
+                }
                 log::trace!(
                     "{}: Spoke angle {} is not consecutive to previous angle {}, missing spokes {}",
                     self.key,
@@ -1576,11 +1703,28 @@ impl CommonRadar {
     pub(crate) fn send_spoke_message(&mut self) {
         if let Some(message) = self.spoke_message.take() {
             if !message.spokes.is_empty() {
+                self.info.diagnostics.spokes_sent(message.spokes.len());
                 self.info.broadcast_radar_message(message);
             }
         }
     }
 
+    /// Count a packet of `message_type` received on `socket`, for the diagnostics
+    pub(crate) fn packet_received(&self, socket: &'static str, message_type: &str) {
+        self.info.diagnostics.packet_received(socket, message_type);
+    }
+
+    /// Count a packet from the radar that could not be parsed, for `/metrics`
+    /// and the diagnostics
+    pub(crate) fn malformed_packet(&self, socket: &'static str, error: &dyn fmt::Display) {
+        crate::metrics::malformed_packet(&self.info.brand);
+        self.info.diagnostics.parse_failure(socket, error);
+    }
+
+    pub(crate) fn set_connection_state(&self, state: ConnectionState) {
+        self.info.diagnostics.set_connection_state(state);
+    }
+
     pub(crate) fn set<T>(
         &mut self,
         control_id: &ControlId,
@@ -1756,11 +1900,15 @@ fn apply_antenna_offset(
     };
     // Prefer the spoke's own heading over the global navdata heading to avoid
     // a one-spoke lag on startup or when heading only comes from the radar feed.
+    // Otherwise use the navdata heading at the time of the spoke.
     let heading = if let Some(bearing) = spoke.bearing {
         let heading_spokes = (bearing as i32 - spoke.angle as i32)
             .rem_euclid(spokes_per_revolution as i32) as f64;
         heading_spokes / spokes_per_revolution as f64 * std::f64::consts::TAU
-    } else if let Some(h) = crate::navdata::get_heading_true() {
+    } else if let Some(h) = spoke.time.map_or_else(
+        crate::navdata::get_heading_true,
+        crate::navdata::get_heading_true_at,
+    ) {
         h
     } else {
         return;
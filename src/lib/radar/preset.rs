@@ -0,0 +1,222 @@
+//! Named control presets, e.g. "harbour", "open sea" or "rain".
+//!
+//! A preset holds values for a chosen subset of the controls of one radar and
+//! is stored with the other settings of that radar. Applying a preset sends its
+//! values as ordinary client requests, so they end up in the brand's
+//! `CommandSender::set_control` in the order in which they are sent: first the
+//! range, as some radars adjust gain, sea and rain when the range changes, then
+//! gain, sea and rain, then the rest.
+
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+use serde_json::{Number, Value};
+use utoipa::ToSchema;
+
+use super::RadarError;
+use super::batch::send_control_values;
+use super::settings::{
+    BareControlValue, ControlDestination, ControlId, ControlValue, SharedControls,
+};
+use super::units::Units;
+
+/// A named set of control values
+#[derive(Serialize, Deserialize, Clone, Debug, Default, ToSchema)]
+pub struct Preset {
+    /// Control values by control id, in SI units
+    #[schema(example = json!({
+        "range": {"value": 1852},
+        "gain": {"value": 40, "auto": false},
+        "sea": {"auto": true},
+        "rain": {"value": 0}
+    }))]
+    pub controls: BTreeMap<String, BareControlValue>,
+}
+
+/// What happened when a preset was applied
+#[derive(Serialize, Clone, Debug, Default, PartialEq, ToSchema)]
+pub struct PresetReport {
+    /// Controls that were sent to the radar, in the order they were sent
+    #[schema(example = json!(["range", "gain", "sea"]))]
+    pub applied: Vec<String>,
+    /// Controls that could not be set, with the reason
+    #[schema(example = json!({"rain": "Cannot set value for control 'rain'"}))]
+    pub failed: BTreeMap<String, String>,
+}
+
+/// Range first, then gain, sea and rain, then the rest in the usual control order
+fn apply_order(id: ControlId) -> (u8, u8) {
+    let first = match id {
+        ControlId::Range => 0,
+        ControlId::Gain => 1,
+        ControlId::Sea => 2,
+        ControlId::Rain => 3,
+        _ => 4,
+    };
+    (first, id as u8)
+}
+
+fn parse_control_id(name: &str) -> Result<ControlId, RadarError> {
+    ControlId::parse_str(Cow::Borrowed(name))
+        .map_err(|_| RadarError::InvalidControlId(name.to_string()))
+}
+
+fn to_si(units: Units, value: f64) -> f64 {
+    units.to_si(value).1
+}
+
+impl Preset {
+    ///
+    /// Check that the radar has the controls of the preset and that they can be
+    /// set, and return the preset keyed by control id with values in SI units,
+    /// which is how it is stored.
+    ///
+    pub fn normalize(self, controls: &SharedControls) -> Result<Preset, RadarError> {
+        let mut normalized = BTreeMap::new();
+        for (name, mut value) in self.controls {
+            let id = parse_control_id(&name)?;
+            if !controls.contains_key(&id) {
+                return Err(RadarError::InvalidControlId(name));
+            }
+            if id.get_destination() == ControlDestination::ReadOnly {
+                return Err(RadarError::CannotSetControlId(id));
+            }
+            if let Some(units) = value.units.take()
+                && units != Units::None
+            {
+                value.value = match value.value {
+                    Some(Value::Number(n)) => n
+                        .as_f64()
+                        .and_then(|v| Number::from_f64(to_si(units, v)))
+                        .map(Value::Number),
+                    v => v,
+                };
+                value.auto_value = value.auto_value.map(|v| to_si(units, v));
+                value.end_value = value.end_value.map(|v| to_si(units, v));
+            }
+            value.allowed = None;
+            value.error = None;
+            value.timestamp = None;
+            normalized.insert(id.to_string(), value);
+        }
+        Ok(Preset {
+            controls: normalized,
+        })
+    }
+
+    /// A preset with the current values of the controls in `names`
+    pub fn capture(controls: &SharedControls, names: &[String]) -> Result<Preset, RadarError> {
+        let mut captured = BTreeMap::new();
+        for name in names {
+            let id = parse_control_id(name)?;
+            let control = controls
+                .get(&id)
+                .ok_or_else(|| RadarError::InvalidControlId(name.clone()))?;
+            let mut value = BareControlValue::from(ControlValue::from(&control, None));
+            // Values of controls are kept in SI units already
+            value.units = None;
+            captured.insert(id.to_string(), value);
+        }
+        Preset { controls: captured }.normalize(controls)
+    }
+
+    ///
+    /// Send the values of the preset to the radar. Controls that are locked,
+    /// out of range or rejected by the radar are reported as failed, the
+    /// others are still applied.
+    ///
+    pub async fn apply(&self, controls: &SharedControls, client: Option<&str>) -> PresetReport {
+        let mut report = PresetReport::default();
+        let mut values = Vec::new();
+        for (name, value) in &self.controls {
+            match parse_control_id(name) {
+                Ok(id) => values.push(ControlValue::from_request(id, value.clone())),
+                Err(e) => {
+                    report.failed.insert(name.clone(), e.to_string());
+                }
+            }
+        }
+        values.sort_by_key(|cv| apply_order(cv.id));
+
+        let sent = send_control_values(controls, values, client, false).await;
+        report.applied = sent.applied.iter().map(|id| id.to_string()).collect();
+        for (id, e) in sent.failed {
+            report.failed.insert(id.to_string(), e);
+        }
+        report
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::radar::settings::test_controls as controls;
+
+    fn preset(json: &str) -> Preset {
+        serde_json::from_str(json).unwrap()
+    }
+
+    #[test]
+    fn test_apply_order() {
+        let mut ids = vec![
+            ControlId::Rain,
+            ControlId::SpokeProcessing,
+            ControlId::Sea,
+            ControlId::Gain,
+            ControlId::Range,
+            ControlId::Power,
+        ];
+        ids.sort_by_key(|id| apply_order(*id));
+        assert_eq!(
+            ids,
+            vec![
+                ControlId::Range,
+                ControlId::Gain,
+                ControlId::Sea,
+                ControlId::Rain,
+                ControlId::Power,
+                ControlId::SpokeProcessing,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_normalize() {
+        let controls = controls();
+        let p = preset(
+            r#"{"controls": {"Range": {"value": 1, "units": "nm", "allowed": true}, "spokeProcessing": {"value": 2}}}"#,
+        )
+        .normalize(&controls)
+        .unwrap();
+        assert_eq!(
+            p.controls.keys().collect::<Vec<_>>(),
+            vec!["range", "spokeProcessing"]
+        );
+        assert_eq!(p.controls["range"].value, Some(serde_json::json!(1852.0)));
+        assert_eq!(p.controls["range"].allowed, None);
+
+        let e = preset(r#"{"controls": {"gain": {"value": 50}}}"#)
+            .normalize(&controls)
+            .unwrap_err();
+        assert_eq!(e.to_string(), "No such control 'gain'");
+        assert!(
+            preset(r#"{"controls": {"spokes": {"value": 2048}}}"#)
+                .normalize(&controls)
+                .is_err()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_apply() {
+        let controls = controls();
+        let p =
+            preset(r#"{"controls": {"spokeProcessing": {"value": 3}, "range": {"value": 1852}}}"#);
+        let report = p.apply(&controls, None).await;
+
+        // Internal controls are set right away, the range needs a radar
+        assert_eq!(report.applied, vec!["spokeProcessing"]);
+        assert_eq!(report.failed["range"], "Shutdown");
+        assert_eq!(controls.spoke_processing(), 3);
+    }
+}
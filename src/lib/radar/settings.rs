@@ -17,6 +17,7 @@ use thiserror::Error;
 use utoipa::ToSchema;
 
 use super::NAUTICAL_MILE;
+use super::lock::{ControlLock, ControlLocks};
 use super::range::Range;
 use super::units::Units;
 use crate::Cli;
@@ -566,6 +567,24 @@ impl ControlId {
             ControlId::EchoFormat => ControlDestination::Command,
         }
     }
+
+    /// Whether a change by a client is stored in the radar's persistent data
+    pub fn needs_persistence(&self) -> bool {
+        matches!(
+            self,
+            ControlId::GuardZone1
+                | ControlId::GuardZone2
+                | ControlId::ExclusionZone1
+                | ControlId::ExclusionZone2
+                | ControlId::ExclusionZone3
+                | ControlId::ExclusionZone4
+                | ControlId::ExclusionRect1
+                | ControlId::ExclusionRect2
+                | ControlId::ExclusionRect3
+                | ControlId::ExclusionRect4
+                | ControlId::UserName
+        )
+    }
 }
 ///
 /// Radars have settings. There are some common ones that every radar supports:
@@ -606,6 +625,8 @@ pub struct Controls {
     sk_client_tx: tokio::sync::broadcast::Sender<SignalKDelta>,
     #[serde(skip)]
     control_update_tx: tokio::sync::broadcast::Sender<ControlUpdate>,
+    #[serde(skip)]
+    locks: ControlLocks,
 }
 
 impl Controls {
@@ -717,6 +738,7 @@ impl Controls {
             all_clients_tx,
             sk_client_tx,
             control_update_tx,
+            locks: ControlLocks::default(),
         }
     }
 }
@@ -972,27 +994,19 @@ impl SharedControls {
     // Some controls are handled internally, some in the data handler for a radar and the
     // rest are settings that need to be sent to the radar.
     //
+    // `client` is the name the client uses for control locks, if it has one.
+    //
     pub fn process_client_request(
         &self,
         control_value: ControlValue,
+        client: Option<&str>,
         reply_tx: tokio::sync::mpsc::Sender<ControlValue>,
     ) -> Result<(), RadarError> {
+        self.check_lock(client, control_value.id)?;
         match self.get(&control_value.id) {
             Some(c) => {
                 let cv_orig = control_value.clone();
-                let (units, value) =
-                    Self::convert_to_wire_number(control_value.units, control_value.value, &c)?;
-                let end_value =
-                    Self::convert_f64_to_wire(control_value.units, control_value.end_value, &c)?;
-                let auto_value =
-                    Self::convert_f64_to_wire(control_value.units, control_value.auto_value, &c)?;
-                let cv = ControlValue {
-                    units,
-                    value,
-                    auto_value,
-                    end_value,
-                    ..control_value
-                };
+                let cv = Self::check_client_request(control_value, &c)?;
                 log::info!(
                     "Client request to update {:?} to {:?} wire {:?}",
                     ControlValue::from(&c, None),
@@ -1000,21 +1014,6 @@ impl SharedControls {
                     cv
                 );
 
-                // Reject values not in the valid set (e.g. sparse capability bitmasks)
-                if let Some(ref valid_values) = c.item.valid_values {
-                    if let Some(ref v) = cv.value {
-                        if let Some(f) = v.as_f64() {
-                            let i = f as i32;
-                            if !valid_values.contains(&i) {
-                                return Err(RadarError::ControlError(ControlError::Invalid(
-                                    cv.id,
-                                    format!("{}", i),
-                                )));
-                            }
-                        }
-                    }
-                }
-
                 // Handle zone controls specially - they have multiple values and are stored directly
                 // This applies to both Internal and Target destinations (guard zones are Target)
                 if c.item.data_type == ControlDataType::Zone {
@@ -1068,6 +1067,161 @@ impl SharedControls {
         }
     }
 
+    // check_client_request()
+    //
+    // Check a client request against the definition of control `c`, the same
+    // way that the control itself checks values in Control::set(), and return
+    // the request with its values converted to wire units.
+    //
+    fn check_client_request(
+        control_value: ControlValue,
+        c: &Control,
+    ) -> Result<ControlValue, RadarError> {
+        let id = control_value.id;
+        if id.get_destination() == ControlDestination::ReadOnly || c.item.is_read_only {
+            return Err(RadarError::CannotSetControlId(id));
+        }
+        if control_value.auto.is_some() && c.item.automatic.is_none() {
+            return Err(RadarError::ControlError(ControlError::NoAuto(id)));
+        }
+
+        let (units, value) =
+            Self::convert_to_wire_number(control_value.units, control_value.value, c)?;
+        let end_value = Self::convert_f64_to_wire(control_value.units, control_value.end_value, c)?;
+        let auto_value =
+            Self::convert_f64_to_wire(control_value.units, control_value.auto_value, c)?;
+        let cv = ControlValue {
+            units,
+            value,
+            auto_value,
+            end_value,
+            ..control_value
+        };
+
+        // Only changing auto or enabled needs no further checks
+        if matches!(
+            c.item.data_type,
+            ControlDataType::Number | ControlDataType::Enum
+        ) && let Some(wire_value) = cv.value.as_ref().and_then(|v| v.as_f64())
+        {
+            // Reject values not in the valid set (e.g. sparse capability bitmasks)
+            if let Some(ref valid_values) = c.item.valid_values
+                && !valid_values.contains(&(wire_value as i32))
+            {
+                return Err(RadarError::ControlError(ControlError::Invalid(
+                    id,
+                    format!("{}", wire_value as i32),
+                )));
+            }
+            let value = c
+                .item
+                .wire_units
+                .map(|u| u.to_si(wire_value).1)
+                .unwrap_or(wire_value);
+            c.item.check_range(value)?;
+        }
+        Ok(cv)
+    }
+
+    // validate_client_request()
+    //
+    // Check a client request like process_client_request() does, but without
+    // changing or sending anything. This lets a batch of requests be checked
+    // before the first one is sent to the radar.
+    //
+    pub fn validate_client_request(
+        &self,
+        control_value: &ControlValue,
+        client: Option<&str>,
+    ) -> Result<(), RadarError> {
+        let id = control_value.id;
+        self.check_lock(client, id)?;
+        let c = self.get(&id).ok_or(RadarError::CannotSetControlId(id))?;
+        Self::check_client_request(control_value.clone(), &c).map(|_| ())
+    }
+
+    // ******* CONTROL LOCKS
+
+    /// Can `client` change control `control_id`, or has another client locked it?
+    pub fn check_lock(
+        &self,
+        client: Option<&str>,
+        control_id: ControlId,
+    ) -> Result<(), RadarError> {
+        self.expire_locks();
+        let locked = self.controls.read().unwrap();
+        locked.locks.check(client, control_id)
+    }
+
+    pub fn get_locks(&self) -> Vec<ControlLock> {
+        self.expire_locks();
+        let locked = self.controls.read().unwrap();
+        locked.locks.get()
+    }
+
+    ///
+    /// Claim or renew a lock on `category`, or on all controls when `None`,
+    /// for `lease`. All clients are told about the new set of locks. Locks
+    /// whose lease ran out are dropped when the locks are next looked at.
+    ///
+    pub fn claim_lock(
+        &self,
+        client: &str,
+        category: Option<Category>,
+        lease: std::time::Duration,
+    ) -> Result<ControlLock, RadarError> {
+        self.expire_locks();
+        let lock = {
+            let mut locked = self.controls.write().unwrap();
+            locked.locks.claim(client, category, lease, Utc::now())?
+        };
+        log::info!(
+            "Radar {} controls {:?} locked by '{}' until {}",
+            self.controls.read().unwrap().radar_id,
+            category,
+            client,
+            lock.expires
+        );
+        self.send_locks_to_all_clients();
+        Ok(lock)
+    }
+
+    pub fn release_lock(&self, client: &str, category: Option<Category>) -> Result<(), RadarError> {
+        self.expire_locks();
+        let released = {
+            let mut locked = self.controls.write().unwrap();
+            locked.locks.release(client, category)?
+        };
+        if released {
+            log::info!("Controls {:?} released by '{}'", category, client);
+            self.send_locks_to_all_clients();
+        }
+        Ok(())
+    }
+
+    fn expire_locks(&self) {
+        let now = Utc::now();
+        if !self.controls.read().unwrap().locks.has_expired(now) {
+            return;
+        }
+        let expired = {
+            let mut locked = self.controls.write().unwrap();
+            locked.locks.expire(now)
+        };
+        if expired {
+            self.send_locks_to_all_clients();
+        }
+    }
+
+    fn send_locks_to_all_clients(&self) {
+        let locked = self.controls.read().unwrap();
+        let mut sk_delta = SignalKDelta::new();
+        sk_delta.add_locks_update(&locked.radar_id, &locked.locks.get());
+        if let Some(sk_delta) = sk_delta.build() {
+            let _ = locked.sk_client_tx.send(sk_delta);
+        }
+    }
+
     pub fn control_update_subscribe(&self) -> tokio::sync::broadcast::Receiver<ControlUpdate> {
         let locked = self.controls.read().unwrap();
 
@@ -2863,7 +3017,7 @@ impl Control {
         }
 
         let wire_value = value;
-        let mut value = self
+        let value = self
             .item
             .wire_units
             .map(|u| u.to_si(value).1)
@@ -2877,27 +3031,7 @@ impl Control {
         );
 
         // RANGE MAPPING
-        if let (Some(min_value), Some(max_value)) = (self.item.min_value, self.item.max_value) {
-            if self.item.wire_offset.unwrap_or(0.) == -1.
-                && value > max_value
-                && value <= 2. * max_value
-            {
-                // debug!("{} value {} -> ", self.item.control_id, value);
-                value -= 2. * max_value;
-                // debug!("{} ..... {}", self.item.control_id, value);
-            }
-
-            if value < min_value {
-                return Err(ControlError::TooLow(self.item.control_id, value, min_value));
-            }
-            if value > max_value {
-                return Err(ControlError::TooHigh(
-                    self.item.control_id,
-                    value,
-                    max_value,
-                ));
-            }
-        }
+        let mut value = self.item.check_range(value)?;
 
         let step = self.item.step_value.unwrap_or(1.0);
         match step {
@@ -3357,9 +3491,32 @@ impl ControlDefinition {
             max_distance: None,
         }
     }
+
+    ///
+    /// Check that `value`, in SI units, is within the range of the control.
+    /// Controls with a wire offset of -1 also take values up to twice the
+    /// maximum, which wrap around to negative values; the value is returned
+    /// wrapped.
+    ///
+    fn check_range(&self, mut value: f64) -> Result<f64, ControlError> {
+        if let (Some(min_value), Some(max_value)) = (self.min_value, self.max_value) {
+            if self.wire_offset.unwrap_or(0.) == -1. && value > max_value && value <= 2. * max_value
+            {
+                value -= 2. * max_value;
+            }
+
+            if value < min_value {
+                return Err(ControlError::TooLow(self.control_id, value, min_value));
+            }
+            if value > max_value {
+                return Err(ControlError::TooHigh(self.control_id, value, max_value));
+            }
+        }
+        Ok(value)
+    }
 }
 
-#[derive(Clone, Debug, Serialize, ToSchema)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
 #[serde(rename_all = "camelCase")]
 pub enum Category {
     Base,
@@ -3471,10 +3628,18 @@ impl Serialize for ControlId {
     }
 }
 
+/// The controls of radar `nav1234` with the default command line, for tests
 #[cfg(test)]
-mod test {
+pub(crate) fn test_controls() -> SharedControls {
     use clap::Parser;
 
+    let args = Cli::parse_from(["my_program"]);
+    let tx = tokio::sync::broadcast::Sender::new(1);
+    SharedControls::new("nav1234".to_string(), tx, &args, HashMap::new())
+}
+
+#[cfg(test)]
+mod test {
     use super::*;
 
     #[test]
@@ -3534,9 +3699,7 @@ mod test {
 
     #[test]
     fn control_range_values() {
-        let args = Cli::parse_from(["my_program"]);
-        let tx = tokio::sync::broadcast::Sender::new(1);
-        let controls = SharedControls::new("nav1234".to_string(), tx, &args, HashMap::new());
+        let controls = test_controls();
 
         assert!(controls.set(&ControlId::TargetTrails, 0., None).is_ok());
         assert_eq!(
@@ -0,0 +1,852 @@
+//! A rendered radar picture, for clients that cannot draw the spokes themselves.
+//!
+//! `CommonRadar` keeps the latest spoke for every angle, so there is always a
+//! full revolution to draw. `render()` turns that into a PNG of the plan
+//! position indicator, head up or north up, with optional overlays.
+//!
+//! Drawing takes a while for large pictures, so it works on a copy of the
+//! revolution in a blocking task, and `record()` can go on with the next
+//! spokes in the meantime.
+
+use std::collections::HashMap;
+use std::f64::consts::{PI, TAU};
+use std::fmt::Write as _;
+use std::io::Write;
+use std::sync::{Arc, Mutex};
+
+use flate2::Compression;
+use flate2::write::ZlibEncoder;
+use serde::Deserialize;
+use utoipa::ToSchema;
+
+use super::GeoPosition;
+use super::target::{METERS_PER_DEGREE_LATITUDE, meters_per_degree_longitude};
+use super::trail::cartesian::PolarToCartesianLookup;
+use crate::config::GuardZone;
+
+pub const DEFAULT_IMAGE_SIZE: u32 = 512;
+pub const MAX_IMAGE_SIZE: u32 = 2048;
+
+/// Width and height of a map tile
+pub const TILE_SIZE: u32 = 256;
+pub const MAX_ZOOM: u8 = 22;
+
+/// Stop caching tiles of a revolution when a client asks for this many
+const MAX_CACHED_TILES: usize = 256;
+
+/// Number of range rings drawn by the `rings` overlay
+const RANGE_RINGS: u32 = 4;
+
+const BACKGROUND: [u8; 4] = [0, 0, 0, 255];
+const RING_COLOR: [u8; 4] = [255, 255, 255, 96];
+const GUARD_ZONE_COLOR: [u8; 4] = [255, 215, 0, 192];
+const TARGET_COLOR: [u8; 4] = [0, 255, 255, 255];
+
+/// Which way is up in the picture
+#[derive(Deserialize, Clone, Copy, Debug, Default, PartialEq, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum Orientation {
+    /// True north up; needs a heading, otherwise the picture is head up
+    #[default]
+    North,
+    /// The bow of the boat up
+    Head,
+}
+
+#[derive(Clone, Debug)]
+struct StoredSpoke {
+    bearing: Option<u32>,
+    range: u32,
+    data: Vec<u8>,
+}
+
+impl StoredSpoke {
+    /// The pixel value at `distance` in meters
+    fn value_at(&self, distance: f64) -> Option<u8> {
+        if self.range == 0 {
+            return None;
+        }
+        let index = (distance / self.range as f64 * self.data.len() as f64) as usize;
+        self.data.get(index).copied()
+    }
+}
+
+/// A lookup table for a number of spokes and a radius in pixels
+type CachedLookup = Option<(usize, usize, Arc<PolarToCartesianLookup>)>;
+
+///
+/// The latest spoke at each angle of a radar, shared by all clones of its
+/// `RadarInfo`.
+///
+#[derive(Clone)]
+pub struct SharedSnapshot {
+    inner: Arc<Mutex<Snapshot>>,
+    /// The lookup of the last image, as clients tend to ask for the same size
+    lookup: Arc<Mutex<CachedLookup>>,
+}
+
+impl std::fmt::Debug for SharedSnapshot {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SharedSnapshot").finish_non_exhaustive()
+    }
+}
+
+#[derive(Debug, Default)]
+struct Snapshot {
+    /// Shared with the copies that are being drawn, see `copy()`
+    spokes: Vec<Option<Arc<StoredSpoke>>>,
+    /// Angle of the spoke that was recorded last
+    latest: usize,
+    /// Counts the revolutions, so tiles of an old one are not cached
+    revolution: u64,
+    /// Map tiles of this revolution
+    tiles: HashMap<(u8, u32, u32), Vec<u8>>,
+}
+
+impl Snapshot {
+    /// The spokes to draw, without the tiles
+    fn copy(&self) -> Snapshot {
+        Snapshot {
+            spokes: self.spokes.clone(),
+            latest: self.latest,
+            revolution: self.revolution,
+            tiles: HashMap::new(),
+        }
+    }
+
+    /// The heading follows from the bearing and angle of the latest spoke
+    fn heading(&self) -> Option<f64> {
+        let spokes_per_revolution = self.spokes.len();
+        let bearing = self.spokes.get(self.latest)?.as_ref()?.bearing?;
+        let offset =
+            (bearing as usize + spokes_per_revolution - self.latest) % spokes_per_revolution;
+        Some(offset as f64 * TAU / spokes_per_revolution as f64)
+    }
+
+    ///
+    /// Which spoke to draw in each direction, by true bearing when `north_up`
+    /// or else by angle from the bow, filling the gaps left by radars that do
+    /// not send every spoke. Returns `None` when there are no spokes yet.
+    ///
+    fn picture(&self, north_up: bool) -> Option<Vec<Option<&StoredSpoke>>> {
+        let spokes_per_revolution = self.spokes.len();
+        let mut picture: Vec<Option<&StoredSpoke>> = vec![None; spokes_per_revolution];
+        for (angle, spoke) in self.spokes.iter().enumerate() {
+            if let Some(spoke) = spoke.as_deref() {
+                let direction = match (north_up, spoke.bearing) {
+                    (true, Some(bearing)) => bearing as usize % spokes_per_revolution,
+                    _ => angle,
+                };
+                picture[direction] = Some(spoke);
+            }
+        }
+        if picture.iter().all(|s| s.is_none()) {
+            return None;
+        }
+        let max_gap = (spokes_per_revolution / 128).max(1);
+        let mut last = None;
+        let mut gap = 0;
+        for i in 0..spokes_per_revolution * 2 {
+            let direction = i % spokes_per_revolution;
+            match picture[direction] {
+                Some(spoke) => {
+                    last = Some(spoke);
+                    gap = 0;
+                }
+                None if gap < max_gap && i >= spokes_per_revolution => {
+                    picture[direction] = last;
+                    gap += 1;
+                }
+                None => gap += 1,
+            }
+        }
+        Some(picture)
+    }
+}
+
+/// The pixel value at `angle` (radians clockwise) and `distance` in meters
+fn value_at(picture: &[Option<&StoredSpoke>], angle: f64, distance: f64) -> Option<u8> {
+    let direction = (angle.rem_euclid(TAU) / TAU * picture.len() as f64) as usize % picture.len();
+    picture[direction]?.value_at(distance)
+}
+
+/// `color` over an opaque `background`
+fn over(background: [u8; 4], color: [u8; 4]) -> [u8; 4] {
+    let alpha = color[3] as u32;
+    let mut pixel = background;
+    for c in 0..3 {
+        pixel[c] = ((color[c] as u32 * alpha + background[c] as u32 * (255 - alpha)) / 255) as u8;
+    }
+    pixel
+}
+
+/// What to draw
+#[derive(Clone, Debug)]
+pub struct ImageOptions {
+    /// Width and height in pixels
+    pub size: u32,
+    pub orientation: Orientation,
+    /// Range at the edge of the picture in meters, or that of the latest spoke
+    pub range: Option<f64>,
+    pub range_rings: bool,
+    /// Guard zones, with angles relative to the bow
+    pub guard_zones: Vec<GuardZone>,
+    /// Targets as true bearing in radians and distance in meters
+    pub targets: Vec<(f64, f64)>,
+}
+
+/// An RGBA image
+struct Canvas {
+    size: u32,
+    pixels: Vec<u8>,
+}
+
+impl Canvas {
+    fn new(size: u32) -> Self {
+        Canvas {
+            size,
+            pixels: vec![0; (size * size * 4) as usize],
+        }
+    }
+
+    /// Set the pixel at `x`, `y` to `color`, if it is in the picture
+    fn set(&mut self, x: i64, y: i64, color: [u8; 4]) {
+        if x < 0 || y < 0 || x >= self.size as i64 || y >= self.size as i64 {
+            return;
+        }
+        let i = ((y as u32 * self.size + x as u32) * 4) as usize;
+        self.pixels[i..i + 4].copy_from_slice(&color);
+    }
+
+    /// Blend `color` over the pixel at `x`, `y`, if it is in the picture
+    fn blend(&mut self, x: i64, y: i64, color: [u8; 4]) {
+        if x < 0 || y < 0 || x >= self.size as i64 || y >= self.size as i64 {
+            return;
+        }
+        let i = ((y as u32 * self.size + x as u32) * 4) as usize;
+        let pixel = &mut self.pixels[i..i + 4];
+        let alpha = color[3] as u32;
+        for c in 0..3 {
+            pixel[c] = ((color[c] as u32 * alpha + pixel[c] as u32 * (255 - alpha)) / 255) as u8;
+        }
+        pixel[3] = pixel[3].max(color[3]);
+    }
+
+    /// Plot at `angle` (clockwise from up) and `radius` pixels from the center
+    fn plot(&mut self, angle: f64, radius: f64, color: [u8; 4]) {
+        let center = self.size as f64 / 2.;
+        let x = center + radius * angle.sin();
+        let y = center - radius * angle.cos();
+        self.blend(x.floor() as i64, y.floor() as i64, color);
+    }
+
+    fn arc(&mut self, start: f64, end: f64, radius: f64, color: [u8; 4]) {
+        let steps = ((end - start) * radius).abs().ceil().max(1.) as usize;
+        for i in 0..=steps {
+            self.plot(
+                start + (end - start) * i as f64 / steps as f64,
+                radius,
+                color,
+            );
+        }
+    }
+
+    fn radial(&mut self, angle: f64, from: f64, to: f64, color: [u8; 4]) {
+        let mut radius = from;
+        while radius <= to {
+            self.plot(angle, radius, color);
+            radius += 1.;
+        }
+    }
+}
+
+impl Snapshot {
+    ///
+    /// Draw the revolution as a PNG, with the colors of the legend in
+    /// `palette`. Each spoke fills the sector up to the next one, using the
+    /// points of `lookup`, which must have as many spokes as the picture and
+    /// the radius of the image.
+    ///
+    fn render(
+        &self,
+        lookup: &PolarToCartesianLookup,
+        palette: &[[u8; 4]],
+        options: &ImageOptions,
+    ) -> Option<Vec<u8>> {
+        let heading = self.heading();
+        let north_up = options.orientation == Orientation::North && heading.is_some();
+        let picture = self.picture(north_up)?;
+
+        let size = options.size.clamp(1, MAX_IMAGE_SIZE);
+        let radius = size as f64 / 2.;
+        let range = options
+            .range
+            .filter(|r| *r > 0.)
+            .or_else(|| {
+                picture
+                    .iter()
+                    .flatten()
+                    .map(|s| s.range as f64)
+                    .reduce(f64::max)
+            })
+            .unwrap_or(1.);
+        let meters_per_pixel = range / radius;
+
+        let colors: Vec<[u8; 4]> = palette.iter().map(|c| over(BACKGROUND, *c)).collect();
+        let mut canvas = Canvas::new(size);
+        let spokes = picture.len();
+        for (direction, spoke) in picture.iter().enumerate() {
+            let next = (direction + 1) % spokes;
+            for r in 0..size as usize / 2 {
+                let color = spoke
+                    .and_then(|s| s.value_at(r as f64 * meters_per_pixel))
+                    .and_then(|v| colors.get(v as usize))
+                    .unwrap_or(&BACKGROUND);
+                // The lookup has angle 0 along x, in the picture that is up.
+                // Draw 2x2 pixels from this spoke to the next, so that no
+                // pixel is left out between the spokes or the radii.
+                let from = lookup.get_point(direction, r);
+                let to = lookup.get_point(next, r);
+                let (dx, dy) = (to.x - from.x, to.y - from.y);
+                let steps = dx.abs().max(dy.abs()).ceil().max(1.) as usize;
+                for i in 0..steps {
+                    let f = i as f64 / steps as f64;
+                    let x = (radius + from.y + dy * f - 0.5).floor() as i64;
+                    let y = (radius - from.x - dx * f - 0.5).floor() as i64;
+                    canvas.set(x, y, *color);
+                    canvas.set(x + 1, y, *color);
+                    canvas.set(x, y + 1, *color);
+                    canvas.set(x + 1, y + 1, *color);
+                }
+            }
+        }
+
+        // Overlay angles are clockwise from up
+        let rotation = if north_up { heading.unwrap_or(0.) } else { 0. };
+
+        if options.range_rings {
+            for ring in 1..=RANGE_RINGS {
+                let r = radius * ring as f64 / RANGE_RINGS as f64 - 0.5;
+                canvas.arc(0., TAU, r, RING_COLOR);
+            }
+            // Heading line, pointing to the bow
+            canvas.radial(rotation, 0., radius, RING_COLOR);
+        }
+
+        for zone in options.guard_zones.iter().filter(|z| z.enabled) {
+            let start = zone.start_angle + rotation;
+            let mut end = zone.end_angle + rotation;
+            if end <= start {
+                end += TAU;
+            }
+            let inner = zone.start_distance / meters_per_pixel;
+            let outer = zone.end_distance / meters_per_pixel;
+            canvas.arc(start, end, inner, GUARD_ZONE_COLOR);
+            canvas.arc(start, end, outer, GUARD_ZONE_COLOR);
+            canvas.radial(start, inner, outer, GUARD_ZONE_COLOR);
+            canvas.radial(end, inner, outer, GUARD_ZONE_COLOR);
+        }
+
+        // Targets have a true bearing, so without a heading we cannot place them
+        if let Some(heading) = heading {
+            for (bearing, distance) in &options.targets {
+                let angle = if north_up {
+                    *bearing
+                } else {
+                    bearing - heading
+                };
+                let r = distance / meters_per_pixel;
+                let center = radius;
+                let x = (center + r * angle.sin()).floor() as i64;
+                let y = (center - r * angle.cos()).floor() as i64;
+                for d in -3..=3 {
+                    canvas.blend(x + d, y - 3, TARGET_COLOR);
+                    canvas.blend(x + d, y + 3, TARGET_COLOR);
+                    canvas.blend(x - 3, y + d, TARGET_COLOR);
+                    canvas.blend(x + 3, y + d, TARGET_COLOR);
+                }
+            }
+        }
+
+        Some(encode_png(size, &canvas.pixels))
+    }
+
+    /// Draw Web Mercator tile `x`, `y` at zoom level `z`, see `render_tile()`
+    fn render_tile(
+        &self,
+        palette: &[[u8; 4]],
+        position: &GeoPosition,
+        z: u8,
+        x: u32,
+        y: u32,
+    ) -> Option<Vec<u8>> {
+        self.heading()?;
+        let picture = self.picture(true)?;
+        let max_range = picture.iter().flatten().map(|s| s.range).max()? as f64;
+
+        let meters_per_degree_lon = meters_per_degree_longitude(&position.lat());
+        let tiles = (1u64 << z) as f64;
+        let mut canvas = Canvas::new(TILE_SIZE);
+        for py in 0..TILE_SIZE {
+            let n = PI * (1. - 2. * (y as f64 + (py as f64 + 0.5) / TILE_SIZE as f64) / tiles);
+            let lat = n.sinh().atan().to_degrees();
+            let north = (lat - position.lat()) * METERS_PER_DEGREE_LATITUDE;
+            for px in 0..TILE_SIZE {
+                let lon = (x as f64 + (px as f64 + 0.5) / TILE_SIZE as f64) / tiles * 360. - 180.;
+                let east = (lon - position.lon()) * meters_per_degree_lon;
+                let distance = east.hypot(north);
+                if distance > max_range {
+                    continue;
+                }
+                if let Some(color) = value_at(&picture, east.atan2(north), distance)
+                    .filter(|v| *v > 0)
+                    .and_then(|v| palette.get(v as usize))
+                {
+                    canvas.blend(px as i64, py as i64, *color);
+                }
+            }
+        }
+        Some(encode_png(TILE_SIZE, &canvas.pixels))
+    }
+}
+
+impl SharedSnapshot {
+    pub(crate) fn new(spokes_per_revolution: u16) -> Self {
+        SharedSnapshot {
+            inner: Arc::new(Mutex::new(Snapshot {
+                spokes: vec![None; spokes_per_revolution as usize],
+                ..Snapshot::default()
+            })),
+            lookup: Arc::new(Mutex::new(None)),
+        }
+    }
+
+    /// Keep the spoke at `angle`, replacing the one of the previous revolution
+    pub(crate) fn record(&self, angle: u32, bearing: Option<u32>, range: u32, data: &[u8]) {
+        let mut snapshot = self.inner.lock().unwrap();
+        let angle = angle as usize;
+        if angle < snapshot.latest {
+            // A new revolution, so the tiles of the last one are out of date
+            snapshot.tiles.clear();
+            snapshot.revolution += 1;
+        }
+        snapshot.latest = angle;
+        let spokes = &mut snapshot.spokes;
+        if angle >= spokes.len() {
+            spokes.resize(angle + 1, None);
+        }
+        match &mut spokes[angle] {
+            Some(spoke) => {
+                // Reuses the buffer, unless a copy is being drawn
+                let spoke = Arc::make_mut(spoke);
+                spoke.bearing = bearing;
+                spoke.range = range;
+                spoke.data.clear();
+                spoke.data.extend_from_slice(data);
+            }
+            None => {
+                spokes[angle] = Some(Arc::new(StoredSpoke {
+                    bearing,
+                    range,
+                    data: data.to_vec(),
+                }))
+            }
+        }
+    }
+
+    /// The range of the longest spoke in meters
+    pub fn range(&self) -> Option<u32> {
+        let snapshot = self.inner.lock().unwrap();
+        snapshot.spokes.iter().flatten().map(|s| s.range).max()
+    }
+
+    /// The lookup for `spokes` spokes and `radius` pixels, made when needed
+    fn lookup(&self, spokes: usize, radius: usize) -> Arc<PolarToCartesianLookup> {
+        let mut cached = self.lookup.lock().unwrap();
+        match &*cached {
+            Some((s, r, lookup)) if *s == spokes && *r == radius => lookup.clone(),
+            _ => {
+                let lookup = Arc::new(PolarToCartesianLookup::new(spokes, radius));
+                *cached = Some((spokes, radius, lookup.clone()));
+                lookup
+            }
+        }
+    }
+
+    ///
+    /// Render the latest revolution as a PNG, with the colors of the legend in
+    /// `palette`. Returns `None` when no spokes have been received yet.
+    ///
+    pub async fn render(&self, palette: Vec<[u8; 4]>, options: ImageOptions) -> Option<Vec<u8>> {
+        let snapshot = self.inner.lock().unwrap().copy();
+        let shared = self.clone();
+        tokio::task::spawn_blocking(move || {
+            let size = options.size.clamp(1, MAX_IMAGE_SIZE) as usize;
+            let lookup = shared.lookup(snapshot.spokes.len(), size / 2);
+            snapshot.render(&lookup, &palette, &options)
+        })
+        .await
+        .ok()
+        .flatten()
+    }
+
+    ///
+    /// Render Web Mercator tile `x`, `y` at zoom level `z` of the radar at
+    /// `position`, transparent where there is no return. Returns `None` when
+    /// there are no spokes with a true bearing, as these cannot be placed on
+    /// a chart. Tiles are cached until the next revolution.
+    ///
+    pub async fn render_tile(
+        &self,
+        palette: Vec<[u8; 4]>,
+        position: GeoPosition,
+        z: u8,
+        x: u32,
+        y: u32,
+    ) -> Option<Vec<u8>> {
+        let snapshot = {
+            let snapshot = self.inner.lock().unwrap();
+            if let Some(tile) = snapshot.tiles.get(&(z, x, y)) {
+                return Some(tile.clone());
+            }
+            snapshot.copy()
+        };
+        let revolution = snapshot.revolution;
+        let png =
+            tokio::task::spawn_blocking(move || snapshot.render_tile(&palette, &position, z, x, y))
+                .await
+                .ok()
+                .flatten()?;
+
+        let mut snapshot = self.inner.lock().unwrap();
+        if snapshot.revolution == revolution && snapshot.tiles.len() < MAX_CACHED_TILES {
+            snapshot.tiles.insert((z, x, y), png.clone());
+        }
+        Some(png)
+    }
+}
+
+fn xml_escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+///
+/// A minimal WMTS capabilities document for the tiles of radar `layer`,
+/// found at `tile_url/{z}/{x}/{y}.png`. The radar covers `range` meters
+/// around `position`, if known.
+///
+pub fn wmts_capabilities(
+    layer: &str,
+    tile_url: &str,
+    position: Option<GeoPosition>,
+    range: Option<u32>,
+) -> String {
+    let (lower, upper) = match (position, range) {
+        (Some(p), Some(range)) => {
+            let lat = range as f64 / METERS_PER_DEGREE_LATITUDE;
+            let lon = range as f64 / meters_per_degree_longitude(&p.lat()).max(1.);
+            (
+                format!("{:.6} {:.6}", p.lon() - lon, p.lat() - lat),
+                format!("{:.6} {:.6}", p.lon() + lon, p.lat() + lat),
+            )
+        }
+        _ => ("-180 -85.051129".to_string(), "180 85.051129".to_string()),
+    };
+    let layer = xml_escape(layer);
+
+    let mut matrices = String::new();
+    for z in 0..=MAX_ZOOM {
+        let tiles = 1u64 << z;
+        let _ = write!(
+            matrices,
+            r#"
+      <TileMatrix>
+        <ows:Identifier>{z}</ows:Identifier>
+        <ScaleDenominator>{}</ScaleDenominator>
+        <TopLeftCorner>-20037508.3427892 20037508.3427892</TopLeftCorner>
+        <TileWidth>{TILE_SIZE}</TileWidth>
+        <TileHeight>{TILE_SIZE}</TileHeight>
+        <MatrixWidth>{tiles}</MatrixWidth>
+        <MatrixHeight>{tiles}</MatrixHeight>
+      </TileMatrix>"#,
+            559082264.0287178 / tiles as f64
+        );
+    }
+
+    format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<Capabilities xmlns="http://www.opengis.net/wmts/1.0" xmlns:ows="http://www.opengis.net/ows/1.1" version="1.0.0">
+  <ows:ServiceIdentification>
+    <ows:Title>Mayara radar</ows:Title>
+    <ows:ServiceType>OGC WMTS</ows:ServiceType>
+    <ows:ServiceTypeVersion>1.0.0</ows:ServiceTypeVersion>
+  </ows:ServiceIdentification>
+  <Contents>
+    <Layer>
+      <ows:Title>Radar {layer}</ows:Title>
+      <ows:WGS84BoundingBox>
+        <ows:LowerCorner>{lower}</ows:LowerCorner>
+        <ows:UpperCorner>{upper}</ows:UpperCorner>
+      </ows:WGS84BoundingBox>
+      <ows:Identifier>{layer}</ows:Identifier>
+      <Style isDefault="true">
+        <ows:Identifier>default</ows:Identifier>
+      </Style>
+      <Format>image/png</Format>
+      <TileMatrixSetLink>
+        <TileMatrixSet>GoogleMapsCompatible</TileMatrixSet>
+      </TileMatrixSetLink>
+      <ResourceURL format="image/png" resourceType="tile" template="{}/{{TileMatrix}}/{{TileCol}}/{{TileRow}}.png"/>
+    </Layer>
+    <TileMatrixSet>
+      <ows:Identifier>GoogleMapsCompatible</ows:Identifier>
+      <ows:SupportedCRS>urn:ogc:def:crs:EPSG::3857</ows:SupportedCRS>
+      <WellKnownScaleSet>urn:ogc:def:wkss:OGC:1.0:GoogleMapsCompatible</WellKnownScaleSet>{matrices}
+    </TileMatrixSet>
+  </Contents>
+</Capabilities>
+"#,
+        xml_escape(tile_url)
+    )
+}
+
+fn png_chunk(out: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    out.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    out.extend_from_slice(kind);
+    out.extend_from_slice(data);
+    let mut crc = flate2::Crc::new();
+    crc.update(kind);
+    crc.update(data);
+    out.extend_from_slice(&crc.sum().to_be_bytes());
+}
+
+/// Encode a square RGBA image as PNG
+fn encode_png(size: u32, pixels: &[u8]) -> Vec<u8> {
+    let mut header = Vec::with_capacity(13);
+    header.extend_from_slice(&size.to_be_bytes());
+    header.extend_from_slice(&size.to_be_bytes());
+    header.extend_from_slice(&[8, 6, 0, 0, 0]); // 8 bit RGBA, no interlace
+
+    // Each row starts with the filter type, we use none
+    let mut encoder = ZlibEncoder::new(Vec::new(), Compression::fast());
+    for row in pixels.chunks((size * 4) as usize) {
+        let _ = encoder.write_all(&[0]);
+        let _ = encoder.write_all(row);
+    }
+    let data = encoder.finish().unwrap_or_default();
+
+    let mut out = Vec::with_capacity(data.len() + 64);
+    out.extend_from_slice(b"\x89PNG\r\n\x1a\n");
+    png_chunk(&mut out, b"IHDR", &header);
+    png_chunk(&mut out, b"IDAT", &data);
+    png_chunk(&mut out, b"IEND", &[]);
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::read::ZlibDecoder;
+    use std::io::Read;
+
+    const PALETTE: [[u8; 4]; 2] = [[0, 0, 0, 0], [255, 0, 0, 255]];
+
+    /// The chunks of a PNG as (type, data), checking their CRC
+    fn chunks(png: &[u8]) -> Vec<(String, Vec<u8>)> {
+        assert_eq!(&png[0..8], b"\x89PNG\r\n\x1a\n");
+        let mut chunks = Vec::new();
+        let mut offset = 8;
+        while offset < png.len() {
+            let len = u32::from_be_bytes(png[offset..offset + 4].try_into().unwrap()) as usize;
+            let kind = &png[offset + 4..offset + 8];
+            let data = &png[offset + 8..offset + 8 + len];
+            let mut crc = flate2::Crc::new();
+            crc.update(kind);
+            crc.update(data);
+            let sum =
+                u32::from_be_bytes(png[offset + 8 + len..offset + 12 + len].try_into().unwrap());
+            assert_eq!(crc.sum(), sum);
+            chunks.push((String::from_utf8(kind.to_vec()).unwrap(), data.to_vec()));
+            offset += 12 + len;
+        }
+        chunks
+    }
+
+    /// Decode the RGBA pixels of a PNG written by `encode_png`
+    fn decode(png: &[u8]) -> (u32, Vec<u8>) {
+        let chunks = chunks(png);
+        assert_eq!(chunks[0].0, "IHDR");
+        let size = u32::from_be_bytes(chunks[0].1[0..4].try_into().unwrap());
+        let mut raw = Vec::new();
+        ZlibDecoder::new(&chunks[1].1[..])
+            .read_to_end(&mut raw)
+            .unwrap();
+        assert_eq!(chunks[2].0, "IEND");
+        let pixels = raw
+            .chunks((size * 4 + 1) as usize)
+            .flat_map(|row| row[1..].to_vec())
+            .collect();
+        (size, pixels)
+    }
+
+    fn pixel(pixels: &[u8], size: u32, x: u32, y: u32) -> [u8; 4] {
+        let i = ((y * size + x) * 4) as usize;
+        pixels[i..i + 4].try_into().unwrap()
+    }
+
+    fn options(orientation: Orientation) -> ImageOptions {
+        ImageOptions {
+            size: 64,
+            orientation,
+            range: None,
+            range_rings: false,
+            guard_zones: Vec::new(),
+            targets: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_encode_png() {
+        let png = encode_png(2, &[1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16]);
+        let (size, pixels) = decode(&png);
+        assert_eq!(size, 2);
+        assert_eq!(pixels, (1..=16).collect::<Vec<u8>>());
+    }
+
+    async fn render(snapshot: &SharedSnapshot, options: ImageOptions) -> Option<(u32, Vec<u8>)> {
+        let png = snapshot.render(PALETTE.to_vec(), options).await?;
+        Some(decode(&png))
+    }
+
+    #[tokio::test]
+    async fn test_render() {
+        let snapshot = SharedSnapshot::new(64);
+        assert!(
+            render(&snapshot, options(Orientation::Head))
+                .await
+                .is_none()
+        );
+
+        // A strong return within 45 degrees of dead ahead, with the boat heading east
+        for angle in 0..64 {
+            let value = if !(8..56).contains(&angle) { 1 } else { 0 };
+            snapshot.record(angle, Some((angle + 16) % 64), 1000, &[value; 10]);
+        }
+
+        let (size, pixels) = render(&snapshot, options(Orientation::Head)).await.unwrap();
+        assert_eq!(size, 64);
+        assert_eq!(pixel(&pixels, size, 36, 10), [255, 0, 0, 255]); // ahead is up
+        assert_eq!(pixel(&pixels, size, 54, 40), BACKGROUND); // starboard
+        assert_eq!(pixel(&pixels, size, 0, 0)[3], 0); // outside the circle
+        // No pixel inside the circle is left out
+        for y in 0..size {
+            for x in 0..size {
+                let r = (x as f64 + 0.5 - 32.).hypot(y as f64 + 0.5 - 32.);
+                if r < 31. {
+                    assert_eq!(pixel(&pixels, size, x, y)[3], 255, "{} {}", x, y);
+                }
+            }
+        }
+
+        let (size, pixels) = render(&snapshot, options(Orientation::North))
+            .await
+            .unwrap();
+        assert_eq!(pixel(&pixels, size, 36, 10), BACKGROUND);
+        assert_eq!(pixel(&pixels, size, 54, 40), [255, 0, 0, 255]); // east is right
+    }
+
+    #[tokio::test]
+    async fn test_render_tile() {
+        let snapshot = SharedSnapshot::new(4);
+        let position = GeoPosition::new(0., 0.);
+        for angle in 0..4 {
+            snapshot.record(angle, None, 1000, &[1; 10]);
+        }
+        // Without true bearings the radar picture cannot be placed on a chart
+        assert!(
+            snapshot
+                .render_tile(PALETTE.to_vec(), position, 16, 32768, 32767)
+                .await
+                .is_none()
+        );
+
+        // A return to the north east, with the boat heading north
+        for angle in 0..4 {
+            let value = if angle == 0 { 1 } else { 0 };
+            snapshot.record(angle, Some(angle), 500, &[value; 10]);
+        }
+        // The tile just north east of the radar
+        let png = snapshot
+            .render_tile(PALETTE.to_vec(), position, 16, 32768, 32767)
+            .await
+            .unwrap();
+        let (size, pixels) = decode(&png);
+        assert_eq!(size, TILE_SIZE);
+        assert_eq!(pixel(&pixels, size, 10, 200), [255, 0, 0, 255]);
+        assert_eq!(pixel(&pixels, size, 250, 10)[3], 0); // beyond the range
+        // The tile just north west has no return
+        let png = snapshot
+            .render_tile(PALETTE.to_vec(), position, 16, 32767, 32767)
+            .await
+            .unwrap();
+        let (size, pixels) = decode(&png);
+        assert_eq!(pixel(&pixels, size, 245, 200)[3], 0);
+
+        assert_eq!(snapshot.inner.lock().unwrap().tiles.len(), 2);
+        snapshot.record(0, Some(0), 1000, &[0; 10]);
+        assert!(snapshot.inner.lock().unwrap().tiles.is_empty());
+    }
+
+    #[test]
+    fn test_wmts_capabilities() {
+        let xml = wmts_capabilities(
+            "nav1034A",
+            "http://localhost:6502/tiles?a&b",
+            Some(GeoPosition::new(52., 4.)),
+            Some(1852),
+        );
+        assert!(xml.contains("<ows:Identifier>nav1034A</ows:Identifier>"));
+        assert!(xml.contains(
+            r#"template="http://localhost:6502/tiles?a&amp;b/{TileMatrix}/{TileCol}/{TileRow}.png""#
+        ));
+        assert!(xml.contains("<ows:LowerCorner>3.972929 51.983333</ows:LowerCorner>"));
+        assert_eq!(xml.matches("<TileMatrix>").count(), MAX_ZOOM as usize + 1);
+        assert!(xml.contains("<MatrixWidth>4194304</MatrixWidth>"));
+
+        let xml = wmts_capabilities("nav1034A", "/tiles", None, None);
+        assert!(xml.contains("<ows:LowerCorner>-180 -85.051129</ows:LowerCorner>"));
+    }
+
+    #[tokio::test]
+    async fn test_render_range() {
+        let snapshot = SharedSnapshot::new(64);
+        for angle in 0..64 {
+            // Return only in the outer half of the spoke
+            let mut data = [0; 10];
+            data[5..].fill(1);
+            snapshot.record(angle, None, 1000, &data);
+        }
+        // North up without heading is head up
+        let mut options = options(Orientation::North);
+        let (size, pixels) = render(&snapshot, options.clone()).await.unwrap();
+        assert_eq!(pixel(&pixels, size, 32, 2), [255, 0, 0, 255]);
+        assert_eq!(pixel(&pixels, size, 32, 20), BACKGROUND);
+
+        // Zoomed in to 500 m the return is beyond the edge
+        options.range = Some(500.);
+        let (size, pixels) = render(&snapshot, options).await.unwrap();
+        assert_eq!(pixel(&pixels, size, 32, 2), BACKGROUND);
+    }
+
+    #[tokio::test]
+    async fn test_record_while_drawing() {
+        let snapshot = SharedSnapshot::new(4);
+        snapshot.record(0, None, 1000, &[1; 10]);
+        let copy = snapshot.inner.lock().unwrap().copy();
+        snapshot.record(0, None, 1000, &[0; 10]);
+        assert_eq!(copy.spokes[0].as_ref().unwrap().data, [1; 10]);
+    }
+}
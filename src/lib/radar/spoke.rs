@@ -23,7 +23,12 @@ pub(crate) fn to_protobuf_spoke(
     let heading = if heading.is_some() {
         heading.map(|h| (((h / 2) + angle) % spokes_per_revolution) as u32)
     } else {
-        let heading = crate::navdata::get_heading_true();
+        // Use the heading at the time the spoke was received, so that
+        // spokes on a yawing boat are not smeared
+        let heading = time.map_or_else(
+            crate::navdata::get_heading_true,
+            crate::navdata::get_heading_true_at,
+        );
         heading.map(|h| {
             (((h * spokes_per_revolution as f64 / TAU) as u16 + angle)
                 % spokes_per_revolution) as u32
@@ -35,7 +40,10 @@ pub(crate) fn to_protobuf_spoke(
     spoke.angle = angle as u32;
     spoke.bearing = heading;
 
-    (spoke.lat, spoke.lon) = crate::navdata::get_position();
+    (spoke.lat, spoke.lon) = time.map_or_else(
+        crate::navdata::get_position,
+        crate::navdata::get_position_at,
+    );
     spoke.time = time;
     spoke.data = generic_spoke;
 
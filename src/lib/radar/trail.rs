@@ -1,7 +1,7 @@
 use cartesian::PolarToCartesianLookup;
 use ndarray::{Array2, s};
 
-mod cartesian;
+pub(crate) mod cartesian;
 use super::settings::{ControlError, ControlId, ControlValue, SharedControls};
 use super::{RadarError, RadarInfo};
 use crate::TargetMode;
@@ -196,7 +196,11 @@ impl TrailBuffer {
 
         if let Some(bearing) = spoke.bearing {
             self.have_heading = true;
-            self.update_true_trails(spoke.range, bearing as u16, &mut spoke.data);
+            // Without a (recent) position the true trails cannot be moved
+            // along with the ship, so leave them as they are until it is back.
+            if spoke.lat.is_some() && spoke.lon.is_some() {
+                self.update_true_trails(spoke.range, bearing as u16, &mut spoke.data);
+            }
         } else {
             self.have_heading = false;
         }
@@ -1,6 +1,5 @@
 use ndarray::{Array2, ArrayBase, Dim, OwnedRepr};
 
-#[allow(dead_code)]
 #[derive(Clone, Copy)]
 pub struct Point {
     pub x: f64,
@@ -50,7 +49,6 @@ impl PolarToCartesianLookup {
         }
     }
 
-    #[allow(dead_code)]
     pub fn get_point(&self, angle: usize, radius: usize) -> &Point {
         let angle = (angle + self.spokes_per_revolution) % self.spokes_per_revolution;
         &self.xy[[angle, radius]]
@@ -196,9 +196,11 @@ pub async fn start_recording(
     let message_rx = radar_info.message_tx.subscribe();
 
     let path_clone = path.clone();
+    let radar_key = radar_key.to_string();
     tokio::spawn(async move {
         recording_task(
             mrr_writer,
+            radar_key,
             message_rx,
             stop_flag,
             frame_count,
@@ -214,6 +216,7 @@ pub async fn start_recording(
 
 async fn recording_task(
     mut writer: MrrWriter<BufWriter<File>>,
+    radar_key: String,
     mut message_rx: broadcast::Receiver<Vec<u8>>,
     stop_flag: Arc<AtomicBool>,
     frame_count: Arc<AtomicU32>,
@@ -258,6 +261,7 @@ async fn recording_task(
             }
             Ok(Err(broadcast::error::RecvError::Lagged(n))) => {
                 warn!("Recording lagged, missed {} messages", n);
+                crate::metrics::lagged("recorder", &radar_key, n);
             }
             Ok(Err(broadcast::error::RecvError::Closed)) => {
                 info!("Radar broadcast channel closed");
@@ -309,6 +313,7 @@ pub fn brand_to_id(brand: Brand) -> u32 {
         Brand::Raymarine => 4,
         Brand::Emulator => 5,
         Brand::Playback => 6,
+        Brand::Asterix => 7,
     }
 }
 
@@ -320,6 +325,7 @@ pub fn id_to_brand(id: u32) -> Option<Brand> {
         4 => Some(Brand::Raymarine),
         5 => Some(Brand::Emulator),
         6 => Some(Brand::Playback),
+        7 => Some(Brand::Asterix),
         _ => None,
     }
 }
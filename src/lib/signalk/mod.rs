@@ -0,0 +1,96 @@
+//! Connecting to an upstream Signal K server.
+//!
+//! Normally mayara is the server that GUI clients talk to. The modules here
+//! do the reverse: they connect to a Signal K server as a client, over a
+//! WebSocket, authenticated with an access token that the server
+//! administrator handed out for mayara.
+
+use tokio::net::TcpStream;
+use tokio_tungstenite::tungstenite::client::IntoClientRequest;
+use tokio_tungstenite::tungstenite::http::{HeaderValue, header::AUTHORIZATION};
+use tokio_tungstenite::{MaybeTlsStream, WebSocketStream};
+
+use crate::radar::RadarError;
+
+pub mod provider;
+pub mod publish;
+
+pub(crate) type SignalKStream = WebSocketStream<MaybeTlsStream<TcpStream>>;
+
+///
+/// The WebSocket URL of `path` on the `--signalk-server`, which can be given
+/// as `host:port` or as a URL; any path in it is replaced. Our WebSocket
+/// client has no TLS, so `https` and `wss` servers are refused.
+///
+pub(crate) fn server_url(server: &str, path: &str) -> Result<String, String> {
+    let rest = match server.split_once("://") {
+        Some(("http" | "ws", rest)) => rest,
+        Some(("https" | "wss", _)) => {
+            return Err(format!(
+                "'{}' needs TLS, which is not supported; use ws:// or http://",
+                server
+            ));
+        }
+        Some((scheme, _)) => {
+            return Err(format!("'{}' has unknown scheme '{}'", server, scheme));
+        }
+        None => server,
+    };
+    let host = rest.split('/').next().unwrap_or_default();
+    Ok(format!("ws://{}{}", host, path))
+}
+
+///
+/// Open a WebSocket to `path` on the Signal K server, sending the token as
+/// a bearer token when we have one.
+///
+pub(crate) async fn connect(
+    server: &str,
+    path: &str,
+    token: Option<&str>,
+) -> Result<SignalKStream, RadarError> {
+    let url = server_url(server, path).map_err(RadarError::SignalK)?;
+    let mut request = url
+        .as_str()
+        .into_client_request()
+        .map_err(|e| RadarError::SignalK(format!("{}: {}", url, e)))?;
+    if let Some(token) = token {
+        let value = HeaderValue::from_str(&format!("Bearer {}", token))
+            .map_err(|e| RadarError::SignalK(format!("invalid token: {}", e)))?;
+        request.headers_mut().insert(AUTHORIZATION, value);
+    }
+
+    let (stream, _) = tokio_tungstenite::connect_async(request)
+        .await
+        .map_err(|e| RadarError::SignalK(format!("{}: {}", url, e)))?;
+    Ok(stream)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_server_url() {
+        let path = "/signalk/v1/stream?subscribe=none";
+        assert_eq!(
+            server_url("192.168.1.10:3000", path).unwrap(),
+            "ws://192.168.1.10:3000/signalk/v1/stream?subscribe=none"
+        );
+        assert_eq!(
+            server_url("ws://signalk.local:3000/", path).unwrap(),
+            "ws://signalk.local:3000/signalk/v1/stream?subscribe=none"
+        );
+        assert_eq!(
+            server_url("http://signalk.local", path).unwrap(),
+            "ws://signalk.local/signalk/v1/stream?subscribe=none"
+        );
+        assert_eq!(
+            server_url("ws://signalk.local:3000/signalk/v1/stream", "/plugins/x").unwrap(),
+            "ws://signalk.local:3000/plugins/x"
+        );
+        assert!(server_url("https://signalk.local", path).is_err());
+        assert!(server_url("wss://signalk.local:3443", path).is_err());
+        assert!(server_url("ftp://signalk.local", path).is_err());
+    }
+}
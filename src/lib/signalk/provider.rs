@@ -0,0 +1,515 @@
+//! Acting as a radar provider for an upstream Signal K server.
+//!
+//! Signal K servers only take PUTs through handlers that plugins register, so
+//! mayara connects to the plugin in `signalk-plugin/` at `PROVIDER_PATH`. The
+//! plugin puts our deltas into the server's data model and registers a PUT
+//! handler for each control. Clients then only need to know the Signal K
+//! server. Over the one WebSocket we:
+//! - advertise the active radars, again whenever that list changes;
+//! - send the control values, control definitions and targets of those
+//!   radars as Signal K deltas;
+//! - apply control PUTs that the server forwards, and reply with a status;
+//! - relay the spokes of the radars that the server subscribes to, as binary
+//!   frames of the radar id length (one byte), the radar id and the
+//!   `RadarMessage` that a spoke WebSocket would send.
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::net::Ipv4Addr;
+use std::time::Duration;
+
+use futures::{SinkExt, StreamExt};
+use serde::{Deserialize, Serialize};
+use tokio::sync::{broadcast, mpsc};
+use tokio::task::JoinHandle;
+use tokio_graceful_shutdown::SubsystemHandle;
+use tokio_tungstenite::tungstenite::Message;
+
+use crate::radar::settings::{BareControlValue, ControlValue};
+use crate::radar::{RadarError, RadarInfo, SharedRadars};
+use crate::stream::SignalKDelta;
+use crate::util::{Connection, RADAR_POLL_INTERVAL, reconnect};
+use crate::{PACKAGE, VERSION};
+
+/// Where the mayara plugin on the Signal K server accepts providers
+const PROVIDER_PATH: &str = "/plugins/mayara/provider";
+
+/// The client name of control changes from the Signal K server, for control locks
+const SIGNALK_CLIENT: &str = "signalk";
+
+/// Most controls only reply on error, so a PUT is fine when there is no reply by then
+const CONTROL_REPLY_TIMEOUT: Duration = Duration::from_millis(100);
+
+/// Messages queued for the server; spokes wait when the connection is slow
+const OUTGOING_QUEUE_LEN: usize = 64;
+
+/// A radar as advertised to the Signal K server
+#[derive(Serialize, Clone, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+struct ProviderRadar {
+    name: String,
+    brand: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    model: Option<String>,
+    spokes_per_revolution: u16,
+    max_spoke_len: u16,
+    pixel_values: u8,
+    radar_ip_address: Ipv4Addr,
+}
+
+impl From<&RadarInfo> for ProviderRadar {
+    fn from(info: &RadarInfo) -> Self {
+        ProviderRadar {
+            name: info.controls.user_name(),
+            brand: info.brand.to_string(),
+            model: info.controls.model_name(),
+            spokes_per_revolution: info.spokes_per_revolution,
+            max_spoke_len: info.max_spoke_len,
+            pixel_values: info.pixel_values,
+            radar_ip_address: *info.addr.ip(),
+        }
+    }
+}
+
+/// Messages from mayara to the Signal K server
+#[derive(Serialize, Debug)]
+#[serde(
+    tag = "type",
+    rename_all = "camelCase",
+    rename_all_fields = "camelCase"
+)]
+enum ProviderMessage {
+    /// The active radars, by radar id
+    Radars {
+        provider: &'static str,
+        version: &'static str,
+        radars: BTreeMap<String, ProviderRadar>,
+    },
+    /// Control values, control definitions and targets
+    Delta { delta: SignalKDelta },
+    /// The result of a `put` request
+    Reply {
+        request_id: String,
+        status_code: u16,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        message: Option<String>,
+    },
+}
+
+impl ProviderMessage {
+    fn to_message(&self) -> Option<Message> {
+        serde_json::to_string(self)
+            .ok()
+            .map(|json| Message::Text(json.into()))
+    }
+}
+
+/// Requests from the Signal K server to mayara
+#[derive(Deserialize, Debug)]
+#[serde(
+    tag = "type",
+    rename_all = "camelCase",
+    rename_all_fields = "camelCase"
+)]
+enum ServerRequest {
+    /// Set a control, as `PUT .../radars/{radar_id}/controls/{control_id}` would
+    Put {
+        request_id: String,
+        radar_id: String,
+        control_id: String,
+        value: BareControlValue,
+    },
+    /// Start or stop relaying the spokes of a radar
+    Spokes { radar_id: String, subscribe: bool },
+}
+
+///
+/// Binary frame for a `RadarMessage` of radar `key`. Our radar keys are
+/// short ASCII strings, so the length always fits in a byte.
+///
+fn spoke_frame(key: &str, message: &[u8]) -> Vec<u8> {
+    let key = &key.as_bytes()[..key.len().min(u8::MAX as usize)];
+    let mut frame = Vec::with_capacity(1 + key.len() + message.len());
+    frame.push(key.len() as u8);
+    frame.extend_from_slice(key);
+    frame.extend_from_slice(message);
+    frame
+}
+
+///
+/// The subsystem that keeps a connection to the Signal K server and serves
+/// the requests that come in over it.
+///
+pub struct SignalKProvider {
+    server: String,
+    token: Option<String>,
+    radars: SharedRadars,
+}
+
+impl SignalKProvider {
+    pub fn new(server: String, token: Option<String>, radars: SharedRadars) -> Self {
+        SignalKProvider {
+            server,
+            token,
+            radars,
+        }
+    }
+
+    pub async fn run(self, subsys: SubsystemHandle) -> Result<(), RadarError> {
+        reconnect(&subsys, self).await;
+        log::debug!("Signal K provider shutdown requested");
+        Ok(())
+    }
+
+    async fn provide(
+        &self,
+        subsys: &SubsystemHandle,
+        stream: super::SignalKStream,
+    ) -> Result<(), RadarError> {
+        let (mut write, mut read) = stream.split();
+        let (out_tx, mut out_rx) = mpsc::channel::<Message>(OUTGOING_QUEUE_LEN);
+        let mut sk_client_rx = self.radars.new_sk_client_subscription();
+        let mut interval = tokio::time::interval(RADAR_POLL_INTERVAL);
+        let mut advertised: Option<BTreeMap<String, ProviderRadar>> = None;
+        let mut meta_sent: HashSet<String> = HashSet::new();
+        let mut spoke_relays: HashMap<String, JoinHandle<()>> = HashMap::new();
+
+        let r = 'serve: loop {
+            // Deltas are written here rather than queued, so they are never
+            // dropped in favour of spokes
+            let mut outgoing: Vec<Message> = Vec::new();
+            tokio::select! { biased;
+                _ = subsys.on_shutdown_requested() => {
+                    let _ = write.send(Message::Close(None)).await;
+                    break Err(RadarError::Shutdown);
+                },
+                r = read.next() => {
+                    match r {
+                        Some(Ok(Message::Text(text))) => {
+                            self.handle_request(&text, &out_tx, &mut spoke_relays);
+                        }
+                        Some(Ok(Message::Close(_))) | None => {
+                            break Err(RadarError::SignalK(format!("{} closed the connection", self.server)));
+                        }
+                        Some(Err(e)) => {
+                            break Err(RadarError::SignalK(format!("{}: {}", self.server, e)));
+                        }
+                        Some(Ok(_)) => {}
+                    }
+                },
+                Some(message) = out_rx.recv() => {
+                    outgoing.push(message);
+                },
+                _ = interval.tick() => {
+                    let radars: BTreeMap<String, ProviderRadar> = self
+                        .radars
+                        .get_active()
+                        .iter()
+                        .map(|info| (info.key(), ProviderRadar::from(info)))
+                        .collect();
+                    if advertised.as_ref() == Some(&radars) {
+                        continue;
+                    }
+                    log::debug!("Advertising {} radars to {}", radars.len(), self.server);
+                    spoke_relays.retain(|key, relay| {
+                        if !radars.contains_key(key) {
+                            relay.abort();
+                        }
+                        radars.contains_key(key)
+                    });
+                    let new_radars: Vec<String> = radars
+                        .keys()
+                        .filter(|key| !advertised.as_ref().is_some_and(|a| a.contains_key(*key)))
+                        .cloned()
+                        .collect();
+                    let message = ProviderMessage::Radars {
+                        provider: PACKAGE,
+                        version: VERSION,
+                        radars: radars.clone(),
+                    };
+                    advertised = Some(radars);
+                    outgoing.extend(message.to_message());
+
+                    // Newly found radars start with all their control values
+                    let mut delta = SignalKDelta::new();
+                    delta.add_meta_updates(&self.radars, &mut meta_sent);
+                    for key in new_radars {
+                        if let Some(info) = self.radars.get_by_key(&key) {
+                            delta.add_updates(info.controls.get_radar_control_values());
+                        }
+                    }
+                    if let Some(delta) = delta.build() {
+                        outgoing.extend(ProviderMessage::Delta { delta }.to_message());
+                    }
+                },
+                r = sk_client_rx.recv() => {
+                    match r {
+                        Ok(mut delta) => {
+                            delta.retain_radar_values();
+                            delta.add_meta_from_updates(&self.radars, &mut meta_sent);
+                            if let Some(delta) = delta.build() {
+                                outgoing.extend(ProviderMessage::Delta { delta }.to_message());
+                            }
+                        }
+                        Err(broadcast::error::RecvError::Lagged(n)) => {
+                            log::warn!("Signal K provider lagged, skipped {} updates", n);
+                        }
+                        Err(broadcast::error::RecvError::Closed) => {
+                            break Err(RadarError::Shutdown);
+                        }
+                    }
+                },
+            }
+            for message in outgoing {
+                if let Err(e) = write.send(message).await {
+                    break 'serve Err(RadarError::SignalK(format!("{}: {}", self.server, e)));
+                }
+            }
+        };
+
+        for relay in spoke_relays.values() {
+            relay.abort();
+        }
+        r
+    }
+
+    fn handle_request(
+        &self,
+        text: &str,
+        out_tx: &mpsc::Sender<Message>,
+        spoke_relays: &mut HashMap<String, JoinHandle<()>>,
+    ) {
+        let request = match serde_json::from_str::<ServerRequest>(text) {
+            Ok(request) => request,
+            Err(e) => {
+                log::debug!("Ignoring Signal K server request '{}': {}", text, e);
+                return;
+            }
+        };
+        log::debug!("Signal K server request {:?}", request);
+
+        match request {
+            ServerRequest::Put {
+                request_id,
+                radar_id,
+                control_id,
+                value,
+            } => {
+                let radars = self.radars.clone();
+                let out_tx = out_tx.clone();
+                tokio::spawn(async move {
+                    let (status_code, message) = match put_control(
+                        &radars,
+                        &radar_id,
+                        &control_id,
+                        value,
+                        Some(SIGNALK_CLIENT),
+                    )
+                    .await
+                    {
+                        Ok(()) => (200, None),
+                        Err(e) => e,
+                    };
+                    let reply = ProviderMessage::Reply {
+                        request_id,
+                        status_code,
+                        message,
+                    };
+                    if let Some(message) = reply.to_message() {
+                        let _ = out_tx.send(message).await;
+                    }
+                });
+            }
+            ServerRequest::Spokes {
+                radar_id,
+                subscribe: true,
+            } => {
+                if spoke_relays
+                    .get(&radar_id)
+                    .is_some_and(|r| !r.is_finished())
+                {
+                    return;
+                }
+                let Some(info) = self.radars.get_by_key(&radar_id) else {
+                    log::debug!(
+                        "Signal K server asks for spokes of unknown radar {}",
+                        radar_id
+                    );
+                    return;
+                };
+                let relay = tokio::spawn(relay_spokes(
+                    radar_id.clone(),
+                    info.message_tx.subscribe(),
+                    out_tx.clone(),
+                ));
+                spoke_relays.insert(radar_id, relay);
+            }
+            ServerRequest::Spokes {
+                radar_id,
+                subscribe: false,
+            } => {
+                if let Some(relay) = spoke_relays.remove(&radar_id) {
+                    relay.abort();
+                }
+            }
+        }
+    }
+}
+
+impl Connection for SignalKProvider {
+    type Stream = super::SignalKStream;
+
+    async fn connect(&self) -> Result<super::SignalKStream, RadarError> {
+        super::connect(&self.server, PROVIDER_PATH, self.token.as_deref()).await
+    }
+
+    async fn serve(
+        &mut self,
+        subsys: &SubsystemHandle,
+        stream: super::SignalKStream,
+    ) -> Result<(), RadarError> {
+        log::info!("Providing radars to Signal K server {}", self.server);
+        self.provide(subsys, stream).await
+    }
+}
+
+///
+/// Set a control the same way the REST API does: the radar only replies when
+/// the change failed. Errors come with the HTTP status code for the reply.
+/// `client` is the name that control locks are checked against.
+///
+pub(crate) async fn put_control(
+    radars: &SharedRadars,
+    radar_id: &str,
+    control_id: &str,
+    value: BareControlValue,
+    client: Option<&str>,
+) -> Result<(), (u16, Option<String>)> {
+    let error = |e: RadarError| (e.status_code().as_u16(), Some(e.to_string()));
+    let info = radars
+        .get_by_key(radar_id)
+        .ok_or_else(|| error(RadarError::NoSuchRadar(radar_id.to_string())))?;
+    let control = info
+        .controls
+        .get_by_id(control_id)
+        .ok_or_else(|| error(RadarError::InvalidControlId(control_id.to_string())))?;
+    let control_value = ControlValue::from_request(control.item().control_id, value);
+    let id = control_value.id;
+
+    let (reply_tx, mut reply_rx) = mpsc::channel(1);
+    info.controls
+        .process_client_request(control_value, client, reply_tx)
+        .map_err(error)?;
+
+    if id.needs_persistence() {
+        radars.save_persistence(radar_id);
+    }
+
+    if let Ok(Some(cv)) = tokio::time::timeout(CONTROL_REPLY_TIMEOUT, reply_rx.recv()).await
+        && cv.error.is_some()
+    {
+        return Err((400, cv.error));
+    }
+    Ok(())
+}
+
+async fn relay_spokes(
+    key: String,
+    mut radar_message_rx: broadcast::Receiver<Vec<u8>>,
+    out_tx: mpsc::Sender<Message>,
+) {
+    log::debug!("Relaying spokes of {} to the Signal K server", key);
+    loop {
+        match radar_message_rx.recv().await {
+            Ok(message) => {
+                let frame = spoke_frame(&key, &message);
+                if out_tx.send(Message::Binary(frame.into())).await.is_err() {
+                    break;
+                }
+            }
+            Err(broadcast::error::RecvError::Lagged(n)) => {
+                log::debug!("Spoke relay of {} lagged by {} messages, resuming", key, n);
+            }
+            Err(broadcast::error::RecvError::Closed) => {
+                break;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_spoke_frame() {
+        assert_eq!(
+            spoke_frame("nav1", &[8, 1]),
+            vec![4, b'n', b'a', b'v', b'1', 8, 1]
+        );
+    }
+
+    #[test]
+    fn test_server_request() {
+        let request: ServerRequest = serde_json::from_value(json!({
+            "type": "put",
+            "requestId": "42",
+            "radarId": "nav1034A",
+            "controlId": "gain",
+            "value": { "value": 50, "auto": false }
+        }))
+        .unwrap();
+        match request {
+            ServerRequest::Put {
+                request_id,
+                radar_id,
+                control_id,
+                value,
+            } => {
+                assert_eq!(request_id, "42");
+                assert_eq!(radar_id, "nav1034A");
+                assert_eq!(control_id, "gain");
+                assert_eq!(value.value, Some(json!(50)));
+                assert_eq!(value.auto, Some(false));
+            }
+            _ => panic!("not a put: {:?}", request),
+        }
+
+        let request: ServerRequest = serde_json::from_value(json!({
+            "type": "spokes",
+            "radarId": "nav1034A",
+            "subscribe": true
+        }))
+        .unwrap();
+        assert!(matches!(
+            request,
+            ServerRequest::Spokes {
+                subscribe: true,
+                ..
+            }
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_put_control_status() {
+        let radars = SharedRadars::new();
+        let value: BareControlValue = serde_json::from_value(json!({ "value": 50 })).unwrap();
+        assert_eq!(
+            put_control(&radars, "nav1034A", "gain", value, Some(SIGNALK_CLIENT)).await,
+            Err((404, Some("No such radar with id 'nav1034A'".to_string())))
+        );
+    }
+
+    #[test]
+    fn test_reply() {
+        let reply = ProviderMessage::Reply {
+            request_id: "42".to_string(),
+            status_code: 200,
+            message: None,
+        };
+        assert_eq!(
+            serde_json::to_value(&reply).unwrap(),
+            json!({ "type": "reply", "requestId": "42", "statusCode": 200 })
+        );
+    }
+}
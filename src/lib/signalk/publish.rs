@@ -0,0 +1,271 @@
+//! Publishing ARPA targets and alarms to an upstream Signal K server.
+//!
+//! Listens to the target updates that the `TrackerManager` sends to Signal K
+//! clients and turns each target into a vessel of its own, in context
+//! `vessels.urn:mrn:radar:<radar>:<target>`, with position, COG, SOG and
+//! closest approach. Chart plotters connected to the Signal K server then
+//! show the radar targets next to the AIS targets.
+//!
+//! The alarms of `crate::alarm` are sent as notifications of our own vessel,
+//! e.g. `notifications.navigation.closestApproach.<urn>`.
+
+use futures::{SinkExt, StreamExt};
+use serde_json::{Value, json};
+use tokio::sync::broadcast;
+use tokio_graceful_shutdown::SubsystemHandle;
+use tokio_tungstenite::tungstenite::Message;
+
+use crate::PACKAGE;
+use crate::alarm::{TargetAlarms, target_urn};
+use crate::radar::RadarError;
+use crate::stream::{SignalKDelta, opt_f64};
+use crate::util::{Connection, reconnect};
+
+/// Context of our own vessel, where the alarms go
+const SELF_CONTEXT: &str = "vessels.self";
+
+/// The stream endpoint of a Signal K server, without the initial subscription
+/// to our own vessel; we only send.
+const STREAM_PATH: &str = "/signalk/v1/stream?subscribe=none";
+
+/// The values we send for each target, cleared when the target is deleted
+const TARGET_PATHS: [&str; 4] = [
+    "navigation.position",
+    "navigation.courseOverGroundTrue",
+    "navigation.speedOverGround",
+    "navigation.closestApproach",
+];
+
+///
+/// Converts target updates into Signal K deltas for the upstream server,
+/// with the alarms they raise or clear as notifications.
+///
+#[derive(Default)]
+pub(crate) struct TargetDeltas {
+    alarms: TargetAlarms,
+}
+
+impl TargetDeltas {
+    pub(crate) fn new() -> Self {
+        Self::default()
+    }
+
+    /// Return the deltas for all target updates in `delta`
+    pub(crate) fn process_delta(&mut self, delta: &SignalKDelta) -> Vec<SignalKDelta> {
+        let mut deltas = Vec::new();
+        let mut notifications = SignalKDelta::new_for_context(SELF_CONTEXT);
+        for (radar_id, target_id, value) in delta.target_updates() {
+            deltas.push(self.process_target(radar_id, target_id, value, &mut notifications));
+        }
+        if let Some(notifications) = notifications.build() {
+            deltas.push(notifications);
+        }
+        deltas
+    }
+
+    fn process_target(
+        &mut self,
+        radar_id: &str,
+        target_id: u64,
+        value: &Value,
+        notifications: &mut SignalKDelta,
+    ) -> SignalKDelta {
+        for alarm in self.alarms.process_target(radar_id, target_id, value) {
+            notifications.add_notification(
+                &format!("notifications.{}", alarm.path),
+                alarm.state.as_str(),
+                &alarm.message,
+            );
+        }
+        target_delta(radar_id, target_id, value)
+    }
+}
+
+/// The delta for a single target in its own vessel context
+fn target_delta(radar_id: &str, target_id: u64, value: &Value) -> SignalKDelta {
+    let context = format!("vessels.{}", target_urn(radar_id, target_id));
+    let mut delta = SignalKDelta::new_for_context(&context);
+
+    if value.is_null() {
+        for path in TARGET_PATHS {
+            delta.add_navigation_loss(path, PACKAGE);
+        }
+        return delta;
+    }
+
+    if let (Some(lat), Some(lon)) = (
+        opt_f64(value, "position", "latitude"),
+        opt_f64(value, "position", "longitude"),
+    ) {
+        delta.add_navigation_position(lat, lon, PACKAGE);
+    }
+    if let Some(course) = opt_f64(value, "motion", "course") {
+        delta.add_navigation_update("navigation.courseOverGroundTrue", course, PACKAGE);
+    }
+    if let Some(speed) = opt_f64(value, "motion", "speed") {
+        delta.add_navigation_update("navigation.speedOverGround", speed, PACKAGE);
+    }
+    match (
+        opt_f64(value, "danger", "cpa"),
+        opt_f64(value, "danger", "tcpa"),
+    ) {
+        (Some(cpa), Some(tcpa)) => delta.add_navigation_value(
+            "navigation.closestApproach",
+            json!({ "distance": cpa, "timeTo": tcpa }),
+            PACKAGE,
+        ),
+        _ => delta.add_navigation_loss("navigation.closestApproach", PACKAGE),
+    }
+    delta
+}
+
+///
+/// The subsystem that keeps a connection to the Signal K server and sends
+/// the deltas to it.
+///
+pub struct SignalKPublisher {
+    server: String,
+    token: Option<String>,
+    sk_client_rx: broadcast::Receiver<SignalKDelta>,
+    deltas: TargetDeltas,
+}
+
+impl SignalKPublisher {
+    pub fn new(
+        server: String,
+        token: Option<String>,
+        sk_client_rx: broadcast::Receiver<SignalKDelta>,
+    ) -> Self {
+        SignalKPublisher {
+            server,
+            token,
+            sk_client_rx,
+            deltas: TargetDeltas::new(),
+        }
+    }
+
+    pub async fn run(self, subsys: SubsystemHandle) -> Result<(), RadarError> {
+        reconnect(&subsys, self).await;
+        log::debug!("Signal K publisher shutdown requested");
+        Ok(())
+    }
+}
+
+impl Connection for SignalKPublisher {
+    type Stream = super::SignalKStream;
+
+    async fn connect(&self) -> Result<super::SignalKStream, RadarError> {
+        super::connect(&self.server, STREAM_PATH, self.token.as_deref()).await
+    }
+
+    async fn serve(
+        &mut self,
+        subsys: &SubsystemHandle,
+        stream: super::SignalKStream,
+    ) -> Result<(), RadarError> {
+        log::info!("Publishing ARPA targets to Signal K server {}", self.server);
+        // Start afresh, skipping what was queued while we were not connected
+        self.sk_client_rx = self.sk_client_rx.resubscribe();
+        self.deltas = TargetDeltas::new();
+
+        let (mut write, mut read) = stream.split();
+        loop {
+            tokio::select! { biased;
+                _ = subsys.on_shutdown_requested() => {
+                    let _ = write.send(Message::Close(None)).await;
+                    return Err(RadarError::Shutdown);
+                },
+                // Read what the server sends, so pings are answered and we notice a close
+                r = read.next() => {
+                    match r {
+                        Some(Ok(Message::Close(_))) | None => {
+                            return Err(RadarError::SignalK(format!("{} closed the connection", self.server)));
+                        }
+                        Some(Err(e)) => {
+                            return Err(RadarError::SignalK(format!("{}: {}", self.server, e)));
+                        }
+                        Some(Ok(_)) => {}
+                    }
+                },
+                r = self.sk_client_rx.recv() => {
+                    match r {
+                        Ok(delta) => {
+                            for delta in self.deltas.process_delta(&delta) {
+                                let Ok(json) = serde_json::to_string(&delta) else {
+                                    continue;
+                                };
+                                write
+                                    .send(Message::Text(json.into()))
+                                    .await
+                                    .map_err(|e| RadarError::SignalK(format!("{}: {}", self.server, e)))?;
+                            }
+                        }
+                        Err(broadcast::error::RecvError::Lagged(n)) => {
+                            log::warn!("Signal K publisher lagged, skipped {} target updates", n);
+                        }
+                        Err(broadcast::error::RecvError::Closed) => {
+                            return Err(RadarError::Shutdown);
+                        }
+                    }
+                },
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stream::test_target as target;
+
+    #[test]
+    fn test_target_delta() {
+        let json =
+            serde_json::to_value(target_delta("nav1", 7, &target("tracking", 2000., 600., 0)))
+                .unwrap();
+        assert_eq!(json["context"], "vessels.urn:mrn:radar:nav1:7");
+        let updates = json["updates"].as_array().unwrap();
+        assert_eq!(updates.len(), 4);
+        assert_eq!(updates[0]["$source"], PACKAGE);
+        assert_eq!(updates[0]["values"][0]["path"], "navigation.position");
+        assert_eq!(updates[0]["values"][0]["value"]["latitude"], 52.5);
+        assert_eq!(
+            updates[1]["values"][0]["path"],
+            "navigation.courseOverGroundTrue"
+        );
+        assert_eq!(updates[2]["values"][0]["value"], 5.0);
+        assert_eq!(
+            updates[3]["values"][0]["value"],
+            json!({ "distance": 2000., "timeTo": 600. })
+        );
+
+        let json = serde_json::to_value(target_delta("nav1", 7, &Value::Null)).unwrap();
+        let updates = json["updates"].as_array().unwrap();
+        assert_eq!(updates.len(), TARGET_PATHS.len());
+        assert!(updates.iter().all(|u| u["values"][0]["value"].is_null()));
+    }
+
+    #[test]
+    fn test_notifications() {
+        let mut deltas = TargetDeltas::new();
+        let mut notifications = SignalKDelta::new_for_context(SELF_CONTEXT);
+        deltas.process_target(
+            "nav1",
+            7,
+            &target("tracking", 300., 600., 1),
+            &mut notifications,
+        );
+
+        let json = serde_json::to_value(notifications).unwrap();
+        assert_eq!(json["context"], SELF_CONTEXT);
+        assert_eq!(
+            json["updates"][0]["values"][0]["path"],
+            "notifications.navigation.closestApproach.urn:mrn:radar:nav1:7"
+        );
+        assert_eq!(json["updates"][0]["values"][0]["value"]["state"], "alarm");
+        assert_eq!(
+            json["updates"][1]["values"][0]["path"],
+            "notifications.radar.nav1.guardZone1"
+        );
+    }
+}
@@ -12,6 +12,7 @@ use wildmatch::WildMatch;
 
 use crate::{
     PACKAGE,
+    radar::lock::ControlLock,
     radar::settings::{BareControlValue, Control, ControlDefinition, ControlId, RadarControlValue},
     radar::target::ArpaTargetApi,
     radar::{RadarError, SharedRadars},
@@ -30,6 +31,9 @@ use crate::{
     }]
 }))]
 pub struct SignalKDelta {
+    /// Signal K context the values belong to; omitted for our own clients
+    #[serde(skip_serializing_if = "Option::is_none")]
+    context: Option<String>,
     /// Array of update batches, each containing changed control values
     updates: Vec<DeltaUpdate>,
 }
@@ -37,6 +41,16 @@ pub struct SignalKDelta {
 impl SignalKDelta {
     pub fn new() -> SignalKDelta {
         Self {
+            context: None,
+            updates: Vec::new(),
+        }
+    }
+
+    /// A delta for another context than our own vessel, e.g. "vessels.urn:mrn:radar:nav1:1",
+    /// as sent to an upstream Signal K server.
+    pub fn new_for_context(context: &str) -> SignalKDelta {
+        Self {
+            context: Some(context.to_string()),
             updates: Vec::new(),
         }
     }
@@ -113,8 +127,8 @@ impl SignalKDelta {
         self.updates.push(delta_update);
     }
 
-    /// Add a navigation update to the delta message.
-    pub fn add_navigation_update(&mut self, path: &str, value: f64, source: &str) {
+    /// Add a navigation value, in Signal K format, to the delta message.
+    pub fn add_navigation_value(&mut self, path: &str, value: serde_json::Value, source: &str) {
         let delta_update = DeltaUpdate {
             timestamp: Some(Utc::now()),
             source: Some(source.to_string()),
@@ -127,6 +141,25 @@ impl SignalKDelta {
         self.updates.push(delta_update);
     }
 
+    /// Add a navigation update to the delta message.
+    pub fn add_navigation_update(&mut self, path: &str, value: f64, source: &str) {
+        self.add_navigation_value(path, value.into(), source);
+    }
+
+    /// Add a navigation position update to the delta message.
+    pub fn add_navigation_position(&mut self, lat: f64, lon: f64, source: &str) {
+        self.add_navigation_value(
+            "navigation.position",
+            serde_json::json!({ "latitude": lat, "longitude": lon }),
+            source,
+        );
+    }
+
+    /// Add a navigation update that reports the value is no longer available.
+    pub fn add_navigation_loss(&mut self, path: &str, source: &str) {
+        self.add_navigation_value(path, serde_json::Value::Null, source);
+    }
+
     /// Add an AIS vessel update to the delta message.
     pub fn add_ais_vessel_update(&mut self, path: &str, vessel: &crate::ais::AisVesselApi) {
         let value = serde_json::to_value(vessel).unwrap_or(serde_json::Value::Null);
@@ -142,6 +175,44 @@ impl SignalKDelta {
         self.updates.push(delta_update);
     }
 
+    /// Add a notification, e.g. "notifications.navigation.closestApproach.<id>".
+    /// A state other than "normal" asks the receiver to show and sound the alarm.
+    pub fn add_notification(&mut self, path: &str, state: &str, message: &str) {
+        let method: &[&str] = if state == "normal" {
+            &[]
+        } else {
+            &["visual", "sound"]
+        };
+        let delta_update = DeltaUpdate {
+            timestamp: Some(Utc::now()),
+            source: Some(PACKAGE.to_string()),
+            meta: Vec::new(),
+            values: vec![DeltaValue::Notification {
+                path: path.to_string(),
+                value: serde_json::json!({
+                    "state": state,
+                    "method": method,
+                    "message": message,
+                }),
+            }],
+        };
+        self.updates.push(delta_update);
+    }
+
+    /// Add the current control locks of a radar, an empty array when there are none.
+    pub fn add_locks_update(&mut self, radar_id: &str, locks: &[ControlLock]) {
+        let delta_update = DeltaUpdate {
+            timestamp: Some(Utc::now()),
+            source: Some(PACKAGE.to_string()),
+            meta: Vec::new(),
+            values: vec![DeltaValue::Locks {
+                path: format!("radars.{}.locks", radar_id),
+                value: serde_json::to_value(locks).unwrap_or(serde_json::Value::Null),
+            }],
+        };
+        self.updates.push(delta_update);
+    }
+
     pub fn add_meta_for_control(&mut self, radar_id: &str, control: &Control) {
         let mut meta = Vec::new();
         let path = format!("radars.{}.controls.{}", radar_id, control.item().control_id);
@@ -165,6 +236,32 @@ impl SignalKDelta {
         }
     }
 
+    /// Keep only the values of our radars (controls and targets), dropping
+    /// navigation and AIS data that a Signal K server already has.
+    pub fn retain_radar_values(&mut self) {
+        for update in self.updates.iter_mut() {
+            update.values.retain(|dv| dv.path().starts_with("radars."));
+        }
+        self.updates
+            .retain(|update| !update.values.is_empty() || !update.meta.is_empty());
+    }
+
+    /// Iterate over the target updates in this delta as (radar_id, target_id, value).
+    /// A `Null` value means the target was deleted.
+    pub fn target_updates(&self) -> impl Iterator<Item = (&str, u64, &serde_json::Value)> {
+        self.updates
+            .iter()
+            .flat_map(|update| update.values.iter())
+            .filter_map(|dv| match dv {
+                DeltaValue::Target { path, value } => {
+                    let (radar_id, target_id) =
+                        path.strip_prefix("radars.")?.rsplit_once(".targets.")?;
+                    Some((radar_id, target_id.parse().ok()?, value))
+                }
+                _ => None,
+            })
+    }
+
     pub fn build(self) -> Option<Self> {
         if self.updates.len() > 0 {
             return Some(self);
@@ -173,6 +270,32 @@ impl SignalKDelta {
     }
 }
 
+/// The number `object.field` of a target value from `target_updates`, e.g. `danger.cpa`
+pub(crate) fn opt_f64(value: &serde_json::Value, object: &str, field: &str) -> Option<f64> {
+    value.get(object)?.get(field)?.as_f64()
+}
+
+/// A target value as `target_updates` gives it, for tests
+#[cfg(test)]
+pub(crate) fn test_target(status: &str, cpa: f64, tcpa: f64, zone: u8) -> serde_json::Value {
+    serde_json::json!({
+        "id": 7,
+        "status": status,
+        "position": {
+            "bearing": 1.0,
+            "distance": 1852,
+            "latitude": 52.5,
+            "longitude": 4.25
+        },
+        "motion": { "course": 3.0, "speed": 5.0 },
+        "danger": { "cpa": cpa, "tcpa": tcpa },
+        "acquisition": "auto",
+        "sourceZone": zone,
+        "firstSeen": "2024-01-15T10:29:00.000Z",
+        "lastSeen": "2024-01-15T10:30:05.250Z"
+    })
+}
+
 /// A batch of control value updates within a SignalKDelta message
 #[derive(Serialize, Clone, Debug, ToSchema)]
 struct DeltaUpdate {
@@ -196,7 +319,7 @@ struct DeltaUpdate {
     values: Vec<DeltaValue>,
 }
 
-/// A single value update (control, target, navigation, or AIS)
+/// A single value update (control, target, navigation, AIS or notification)
 #[derive(Serialize, Clone, Debug, ToSchema)]
 #[serde(untagged)]
 enum DeltaValue {
@@ -219,8 +342,10 @@ enum DeltaValue {
     Navigation {
         /// Full path to the navigation data (e.g., "navigation.headingTrue")
         path: String,
-        /// Navigation value (radians for heading, m/s for speed, etc.)
-        value: f64,
+        /// Navigation value (radians for heading, m/s for speed, an object with
+        /// latitude and longitude for position), or null when the value is no
+        /// longer available
+        value: serde_json::Value,
     },
     /// AIS vessel update (structured data from AIS store)
     Ais {
@@ -229,6 +354,20 @@ enum DeltaValue {
         /// Structured vessel data
         value: serde_json::Value,
     },
+    /// The control locks of a radar
+    Locks {
+        /// Full path to the locks (e.g., "radars.nav1034A.locks")
+        path: String,
+        /// Array of locks, with holder, category and expiry
+        value: serde_json::Value,
+    },
+    /// Alarm or other notification
+    Notification {
+        /// Full path to the notification (e.g., "notifications.radar.nav1034A.guardZone1")
+        path: String,
+        /// Notification with state, method and message
+        value: serde_json::Value,
+    },
 }
 
 impl DeltaValue {
@@ -238,6 +377,8 @@ impl DeltaValue {
             DeltaValue::Target { path, .. } => path,
             DeltaValue::Navigation { path, .. } => path,
             DeltaValue::Ais { path, .. } => path,
+            DeltaValue::Locks { path, .. } => path,
+            DeltaValue::Notification { path, .. } => path,
         }
     }
 }
@@ -601,6 +742,11 @@ impl ActiveSubscriptions {
             return self.is_subscribed_vessel_path(path);
         }
 
+        // Lock changes are rare and every client that sets controls needs them
+        if path.ends_with(".locks") {
+            return true;
+        }
+
         // Handle control paths (existing logic)
         let (radar_id, control_id) = extract_path(path);
         let control_id = match ControlId::from_str(control_id) {
@@ -971,4 +1117,55 @@ mod test {
         .unwrap();
         assert_eq!(subs.navigation_subscriptions, vec!["navigation.position"]);
     }
+
+    #[test]
+    fn navigation_loss_serializes_as_null() {
+        let mut delta = SignalKDelta::new();
+        delta.add_navigation_update("navigation.headingTrue", 1.5, "nmea0183");
+        delta.add_navigation_loss("navigation.headingTrue", "nmea0183");
+        let json = serde_json::to_value(&delta).unwrap();
+        let updates = json["updates"].as_array().unwrap();
+        assert_eq!(updates[0]["values"][0]["value"], 1.5);
+        assert_eq!(updates[1]["$source"], "nmea0183");
+        assert_eq!(updates[1]["values"][0]["path"], "navigation.headingTrue");
+        assert!(updates[1]["values"][0]["value"].is_null());
+    }
+
+    #[test]
+    fn context_and_notification() {
+        let json = serde_json::to_value(SignalKDelta::new()).unwrap();
+        assert!(json.get("context").is_none());
+
+        let mut delta = SignalKDelta::new_for_context("vessels.self");
+        delta.add_notification("notifications.radar.nav1.guardZone1", "alarm", "Target");
+        delta.add_notification("notifications.radar.nav1.guardZone1", "normal", "Clear");
+        let json = serde_json::to_value(&delta).unwrap();
+        assert_eq!(json["context"], "vessels.self");
+        let updates = json["updates"].as_array().unwrap();
+        assert_eq!(
+            updates[0]["values"][0]["path"],
+            "notifications.radar.nav1.guardZone1"
+        );
+        assert_eq!(updates[0]["values"][0]["value"]["state"], "alarm");
+        assert_eq!(
+            updates[0]["values"][0]["value"]["method"],
+            serde_json::json!(["visual", "sound"])
+        );
+        assert_eq!(
+            updates[1]["values"][0]["value"]["method"],
+            serde_json::json!([])
+        );
+    }
+
+    #[test]
+    fn retain_radar_values_drops_navigation() {
+        let mut delta = SignalKDelta::new();
+        delta.add_navigation_update("navigation.headingTrue", 1.5, "nmea0183");
+        delta.add_target_update("nav1", 5, None);
+        delta.retain_radar_values();
+        let json = serde_json::to_value(&delta).unwrap();
+        let updates = json["updates"].as_array().unwrap();
+        assert_eq!(updates.len(), 1);
+        assert_eq!(updates[0]["values"][0]["path"], "radars.nav1.targets.5");
+    }
 }
@@ -1,6 +1,73 @@
 // Various common functions
 
 use std::fmt;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use tokio_graceful_shutdown::SubsystemHandle;
+
+use crate::radar::RadarError;
+
+/// How often clients of the radars look for radars that came or went
+pub(crate) const RADAR_POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// How long to wait before connecting again after a connection went away
+pub(crate) const RECONNECT_INTERVAL: Duration = Duration::from_secs(5);
+
+///
+/// A connection that `reconnect` keeps going: `connect` opens it, `serve`
+/// uses it until it ends. `serve` watches for shutdown itself, so that it
+/// can close the connection properly, and then returns `RadarError::Shutdown`.
+///
+pub(crate) trait Connection {
+    type Stream: Send;
+
+    fn connect(&self) -> impl Future<Output = Result<Self::Stream, RadarError>> + Send;
+
+    fn serve(
+        &mut self,
+        subsys: &SubsystemHandle,
+        stream: Self::Stream,
+    ) -> impl Future<Output = Result<(), RadarError>> + Send;
+}
+
+///
+/// Keep `connection` going until shutdown is requested: connect, serve the
+/// connection until it ends, wait `RECONNECT_INTERVAL` and start again.
+/// Errors are logged.
+///
+pub(crate) async fn reconnect<C: Connection>(subsys: &SubsystemHandle, mut connection: C) {
+    loop {
+        let r = tokio::select! { biased;
+            _ = subsys.on_shutdown_requested() => {
+                break;
+            },
+            r = connection.connect() => r,
+        };
+        let r = match r {
+            Ok(stream) => connection.serve(subsys, stream).await,
+            Err(e) => Err(e),
+        };
+        match r {
+            Err(RadarError::Shutdown) => break,
+            Err(e) => log::warn!("{}", e),
+            Ok(()) => {}
+        }
+        tokio::select! { biased;
+            _ = subsys.on_shutdown_requested() => {
+                break;
+            },
+            _ = tokio::time::sleep(RECONNECT_INTERVAL) => {},
+        }
+    }
+}
+
+/// The current time in milliseconds since the Unix epoch
+pub(crate) fn now_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0)
+}
 
 pub(crate) fn c_string(bytes: &[u8]) -> Option<&str> {
     let bytes_without_null = match bytes.iter().position(|&b| b == 0) {
@@ -189,6 +189,101 @@ async fn test_get_capabilities() {
     assert!(caps["legend"].is_object());
 }
 
+#[tokio::test]
+#[ignore = "requires running server"]
+async fn test_get_diagnostics() {
+    let id = first_radar_id().await;
+    let json = get_json(&format!(
+        "/signalk/v2/api/vessels/self/radars/{}/diagnostics",
+        id
+    ))
+    .await;
+
+    for field in [
+        "connectionState",
+        "sockets",
+        "rotation",
+        "spokes",
+        "commands",
+    ] {
+        assert!(
+            json.get(field).is_some(),
+            "Missing diagnostics field: {}",
+            field
+        );
+    }
+    assert!(json["sockets"].is_object());
+    assert!(json["rotation"]["revolutions"].is_number());
+    assert!(json["spokes"]["spokesPerRevolution"].is_number());
+    assert!(json["spokes"]["missing"].is_number());
+    assert!(json["commands"]["errors"].is_number());
+}
+
+#[tokio::test]
+#[ignore = "requires running server"]
+async fn test_get_image() {
+    let id = first_radar_id().await;
+    let response = get_response(&format!(
+        "/signalk/v2/api/vessels/self/radars/{}/image.png?size=256&orientation=head&overlays=rings,guardzones",
+        id
+    ))
+    .await;
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.headers()["content-type"], "image/png");
+    let body = response.bytes().await.unwrap();
+    assert_eq!(&body[0..8], b"\x89PNG\r\n\x1a\n");
+    // Width and height in the IHDR chunk
+    assert_eq!(&body[16..24], &[0, 0, 1, 0, 0, 0, 1, 0]);
+
+    let response = get_response(&format!(
+        "/signalk/v2/api/vessels/self/radars/{}/image.png?overlays=compass",
+        id
+    ))
+    .await;
+    assert_eq!(response.status(), 400);
+}
+
+#[tokio::test]
+#[ignore = "requires running server"]
+async fn test_get_tiles() {
+    let id = first_radar_id().await;
+    let response = get_response(&format!("/signalk/v2/api/vessels/self/radars/{}/wmts", id)).await;
+    assert_eq!(response.status(), 200);
+    let xml = response.text().await.unwrap();
+    assert!(xml.contains("<TileMatrixSet>"));
+    assert!(xml.contains(&format!("radars/{}/tiles/{{TileMatrix}}", id)));
+
+    // 503 without a radar position or heading
+    let response = get_response(&format!(
+        "/signalk/v2/api/vessels/self/radars/{}/tiles/0/0/0.png",
+        id
+    ))
+    .await;
+    assert!(response.status() == 200 || response.status() == 503);
+
+    let response = get_response(&format!(
+        "/signalk/v2/api/vessels/self/radars/{}/tiles/1/2/0.png",
+        id
+    ))
+    .await;
+    assert_eq!(response.status(), 400);
+}
+
+#[tokio::test]
+#[ignore = "requires running server"]
+async fn test_login_rejected() {
+    let url = format!("{}/signalk/v1/auth/login", base_url());
+    let body = serde_json::json!({"username": "nobody", "password": "wrong"});
+    let response = reqwest::Client::new()
+        .post(&url)
+        .json(&body)
+        .send()
+        .await
+        .unwrap();
+    // 404 when authentication is not enabled
+    assert!(response.status() == 401 || response.status() == 404);
+}
+
 #[tokio::test]
 #[ignore = "requires running server"]
 async fn test_capabilities_controls_structure() {
@@ -382,6 +477,160 @@ async fn test_set_invalid_control() {
     assert_eq!(response.status(), 404);
 }
 
+#[tokio::test]
+#[ignore = "requires running server"]
+async fn test_set_control_values() {
+    let id = first_radar_id().await;
+    let path = format!("/signalk/v2/api/vessels/self/radars/{}/controls", id);
+
+    let response = put_json(
+        &path,
+        &serde_json::json!({"controls": [
+            {"id": "spokeProcessing", "value": 1},
+            {"id": "gain", "value": 60}
+        ]}),
+    )
+    .await;
+    assert_eq!(response.status(), 200);
+    let report: Value = response.json().await.unwrap();
+    assert_eq!(report["success"], true);
+    assert_eq!(report["results"][1]["id"], "gain");
+    assert_eq!(report["results"][1]["status"], "applied");
+
+    // One invalid value and nothing is sent
+    let response = put_json(
+        &path,
+        &serde_json::json!({"controls": [
+            {"id": "spokeProcessing", "value": 2},
+            {"id": "gain", "value": 999}
+        ]}),
+    )
+    .await;
+    assert_eq!(response.status(), 400);
+    let report: Value = response.json().await.unwrap();
+    assert_eq!(report["results"][0]["status"], "notApplied");
+    assert_eq!(report["results"][1]["status"], "failed");
+
+    let json = get_json(&format!("{}/spokeProcessing", path)).await;
+    assert_eq!(json["value"], 1);
+
+    // A control given twice is rejected as well
+    let response = put_json(
+        &path,
+        &serde_json::json!({"controls": [
+            {"id": "spokeProcessing", "value": 2},
+            {"id": "spokeProcessing", "value": 3}
+        ]}),
+    )
+    .await;
+    assert_eq!(response.status(), 400);
+}
+
+// ============================================================================
+// GET/PUT/DELETE /signalk/v2/api/vessels/self/radars/{radar_id}/locks
+// ============================================================================
+
+#[tokio::test]
+#[ignore = "requires running server"]
+async fn test_control_locks() {
+    let id = first_radar_id().await;
+    let locks = format!("/signalk/v2/api/vessels/self/radars/{}/locks", id);
+    let gain = format!("/signalk/v2/api/vessels/self/radars/{}/controls/gain", id);
+
+    let response = put_json(
+        &locks,
+        &serde_json::json!({"client": "helm", "category": "base", "lease": 60}),
+    )
+    .await;
+    assert_eq!(response.status(), 200);
+    let lock: Value = response.json().await.unwrap();
+    assert_eq!(lock["holder"], "helm");
+    assert_eq!(lock["category"], "base");
+
+    let json = get_json(&locks).await;
+    assert_eq!(json.as_array().unwrap().len(), 1);
+
+    // Another client cannot claim or change the locked controls
+    let response = put_json(&locks, &serde_json::json!({"client": "phone"})).await;
+    assert_eq!(response.status(), 409);
+
+    let client = reqwest::Client::new();
+    let url = format!("{}{}", base_url(), gain);
+    let body = serde_json::json!({"value": 50});
+    for (name, status) in [("phone", 409), ("helm", 200)] {
+        let response = client
+            .put(&url)
+            .header("X-Mayara-Client", name)
+            .json(&body)
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), status, "PUT gain as '{}'", name);
+    }
+
+    let response = client
+        .delete(format!("{}{}?client=helm&category=base", base_url(), locks))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 200);
+
+    let json = get_json(&locks).await;
+    assert!(json.as_array().unwrap().is_empty());
+}
+
+// ============================================================================
+// /signalk/v2/api/vessels/self/radars/{radar_id}/presets
+// ============================================================================
+
+#[tokio::test]
+#[ignore = "requires running server"]
+async fn test_control_presets() {
+    let id = first_radar_id().await;
+    let presets = format!("/signalk/v2/api/vessels/self/radars/{}/presets", id);
+    let preset = format!("{}/test", presets);
+
+    let response = put_json(
+        &preset,
+        &serde_json::json!({
+            "controls": {"spokeProcessing": {"value": 1}},
+            "capture": ["range"]
+        }),
+    )
+    .await;
+    assert_eq!(response.status(), 200);
+    let json: Value = response.json().await.unwrap();
+    assert!(json["controls"]["range"]["value"].is_number());
+    assert_eq!(json["controls"]["spokeProcessing"]["value"], 1);
+
+    let json = get_json(&presets).await;
+    assert!(json.get("test").is_some());
+
+    let response = put_json(
+        &format!("{}/bad", presets),
+        &serde_json::json!({"controls": {"nonexistent": {"value": 1}}}),
+    )
+    .await;
+    assert_eq!(response.status(), 404);
+
+    let client = reqwest::Client::new();
+    let response = client
+        .post(format!("{}{}/apply", base_url(), preset))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 200);
+    let report: Value = response.json().await.unwrap();
+    assert_eq!(report["applied"][0], "range", "Range goes first");
+    assert!(report["failed"].as_object().unwrap().is_empty());
+
+    let url = format!("{}{}", base_url(), preset);
+    let response = client.delete(&url).send().await.unwrap();
+    assert_eq!(response.status(), 200);
+    let response = client.delete(&url).send().await.unwrap();
+    assert_eq!(response.status(), 404);
+}
+
 // ============================================================================
 // GET /signalk/v2/api/vessels/self/radars/{radar_id}/targets
 // ============================================================================
@@ -479,6 +728,36 @@ async fn test_delete_target() {
     }
 }
 
+// ============================================================================
+// GET /signalk/v2/api/vessels/self/navigation
+// ============================================================================
+
+#[tokio::test]
+#[ignore = "requires running server"]
+async fn test_get_navigation() {
+    let json = get_json("/signalk/v2/api/vessels/self/navigation").await;
+
+    let navigation = json.as_object().unwrap();
+    for path in [
+        "navigation.headingTrue",
+        "navigation.position",
+        "navigation.courseOverGroundTrue",
+        "navigation.speedOverGround",
+    ] {
+        let value = navigation
+            .get(path)
+            .unwrap_or_else(|| panic!("Missing '{}'", path));
+        assert!(value["valid"].is_boolean(), "'{}' has no validity", path);
+        assert!(value.get("value").is_some(), "'{}' has no value", path);
+    }
+
+    // The emulator provides its own heading and position
+    let heading = &navigation["navigation.headingTrue"];
+    assert_eq!(heading["valid"], true);
+    assert_eq!(heading["source"], "emulator");
+    assert!(heading["age"].as_f64().unwrap() >= 0.0);
+}
+
 // ============================================================================
 // GET /signalk/v2/api/vessels/self/radars/resources/openapi.json
 // ============================================================================
@@ -495,3 +774,43 @@ async fn test_openapi_spec() {
     let paths = json["paths"].as_object().unwrap();
     assert!(paths.contains_key("/signalk/v2/api/vessels/self/radars"));
 }
+
+// ============================================================================
+// GET /metrics
+// ============================================================================
+
+#[tokio::test]
+#[ignore = "requires running server"]
+async fn test_metrics() {
+    let response = get_response("/metrics").await;
+    assert_eq!(response.status(), 200);
+    let content_type = response.headers()["content-type"]
+        .to_str()
+        .unwrap()
+        .to_string();
+    assert!(content_type.starts_with("text/plain"));
+
+    let body = response.text().await.unwrap();
+    for metric in [
+        "mayara_radars",
+        "mayara_radar_spokes_total",
+        "mayara_radar_revolutions_per_second",
+        "mayara_broadcast_lagged_total",
+        "mayara_malformed_packets_total",
+        "mayara_websocket_clients",
+        "mayara_navigation_age_seconds",
+    ] {
+        assert!(
+            body.contains(&format!("# TYPE {} ", metric)),
+            "Missing '{}'",
+            metric
+        );
+    }
+
+    // The emulator sends spokes
+    let radar_id = first_radar_id().await;
+    assert!(body.contains(&format!(
+        "mayara_radar_spokes_total{{radar=\"{}\"}}",
+        radar_id
+    )));
+}
@@ -75,6 +75,16 @@ export function getInterfacesUrl() {
   return STANDALONE_INTERFACES_API;
 }
 
+/**
+ * Go to the login page when the server requires authentication
+ * @param {Response} response - Response to an API request
+ */
+function redirectToLogin(response) {
+  if (response.status === 401) {
+    window.location.href = "/gui/login.html";
+  }
+}
+
 /**
  * Fetch list of radar IDs
  * @returns {Promise<string[]>} Array of radar IDs
@@ -83,6 +93,7 @@ export async function fetchRadarIds() {
   await detectMode();
 
   const response = await fetch(getRadarsPath());
+  redirectToLogin(response);
   const data = await response.json();
 
   return Object.keys(data);
@@ -96,6 +107,7 @@ export async function fetchRadars() {
   await detectMode();
 
   const response = await fetch(getRadarsPath());
+  redirectToLogin(response);
   return response.json();
 }
 